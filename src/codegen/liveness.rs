@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+use crate::ir::{BlockId, Function, Instruction, Terminator, Value, ValueId};
+
+// something in a function that reads or writes values
+pub(crate) enum Point<'a> {
+    // where the parameters arrive
+    Entry,
+    Instruction(&'a Instruction),
+    Terminator(&'a Terminator)
+}
+
+impl Point<'_> {
+    pub(crate) fn uses(&self) -> Vec<ValueId> {
+        let operands = match self {
+            Point::Entry => vec![],
+            Point::Instruction(instruction) => instruction.operands(),
+            Point::Terminator(terminator) => terminator.operands()
+        };
+
+        operands.into_iter().filter_map(|operand| match operand {
+            Value::Ref(id) => Some(*id),
+            _ => None
+        }).collect()
+    }
+
+    pub(crate) fn defs(&self, function: &Function) -> Vec<ValueId> {
+        match self {
+            Point::Entry => function.params.clone(),
+            Point::Instruction(instruction) => instruction.result().into_iter().collect(),
+            Point::Terminator(_) => vec![]
+        }
+    }
+}
+
+// the positions from a value's definition to its last use. Point k reads its operands
+// at 2k and writes its result at 2k + 1, so a value can share a register with one
+// defined where it dies.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Interval {
+    pub(crate) start: usize,
+    pub(crate) end: usize
+}
+
+impl Interval {
+    fn touch(interval: &mut Option<Interval>, position: usize) {
+        *interval = Some(match interval {
+            Some(interval) => Interval { start: interval.start.min(position), end: interval.end.max(position) },
+            None => Interval { start: position, end: position }
+        });
+    }
+
+    // whether the value has to survive point `point`, so anything the point clobbers would destroy it
+    pub(crate) fn crosses(&self, point: usize) -> bool {
+        self.start <= 2 * point && self.end > 2 * point
+    }
+}
+
+// which values are live where, for a function's blocks laid out in `layout` order
+pub(crate) struct Liveness<'a> {
+    // every point in layout order, starting with the entry
+    pub(crate) points: Vec<Point<'a>>,
+    // each block with its first and last point, the last being its terminator
+    pub(crate) blocks: Vec<(BlockId, usize, usize)>,
+    pub(crate) live_out: HashMap<BlockId, HashSet<ValueId>>,
+    // for every value, None if it's never defined in the layout
+    pub(crate) intervals: Vec<Option<Interval>>
+}
+
+impl<'a> Liveness<'a> {
+    pub(crate) fn compute(function: &'a Function, layout: &[BlockId]) -> Liveness<'a> {
+        let mut points = vec![Point::Entry];
+        let mut blocks = vec![];
+
+        for id in layout {
+            let block = function.block(*id);
+            let first = points.len();
+
+            points.extend(block.instructions.iter().map(Point::Instruction));
+            points.extend(block.terminator.iter().map(Point::Terminator));
+            blocks.push((*id, first, points.len() - 1));
+        }
+
+        // values each block reads before writing them, and the values it writes
+        let mut uses: HashMap<BlockId, HashSet<ValueId>> = HashMap::new();
+        let mut defs: HashMap<BlockId, HashSet<ValueId>> = HashMap::new();
+        for (id, first, last) in &blocks {
+            let (block_uses, block_defs) = (uses.entry(*id).or_default(), defs.entry(*id).or_default());
+
+            for point in &points[*first..=*last] {
+                block_uses.extend(point.uses().into_iter().filter(|value| !block_defs.contains(value)));
+                block_defs.extend(point.defs(function));
+            }
+        }
+
+        let mut live_in: HashMap<BlockId, HashSet<ValueId>> = layout.iter().map(|id| (*id, HashSet::new())).collect();
+        let mut live_out: HashMap<BlockId, HashSet<ValueId>> = live_in.clone();
+
+        // backwards dataflow, visiting blocks in reverse layout order so most values settle in one pass
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for id in layout.iter().rev() {
+                let out: HashSet<ValueId> = function.block(*id).successors().iter()
+                    .filter_map(|succ| live_in.get(succ))
+                    .flatten()
+                    .copied()
+                    .collect();
+
+                let mut new_in = uses[id].clone();
+                new_in.extend(out.iter().filter(|value| !defs[id].contains(value)));
+
+                if new_in.len() != live_in[id].len() {
+                    changed = true;
+                    live_in.insert(*id, new_in);
+                }
+                live_out.insert(*id, out);
+            }
+        }
+
+        let mut intervals = vec![None; function.values.len()];
+        for (k, point) in points.iter().enumerate() {
+            for value in point.uses() {
+                Interval::touch(&mut intervals[value.0], 2 * k);
+            }
+            for value in point.defs(function) {
+                Interval::touch(&mut intervals[value.0], 2 * k + 1);
+            }
+        }
+
+        for (id, first, last) in &blocks {
+            for value in &live_in[id] {
+                Interval::touch(&mut intervals[value.0], 2 * first);
+            }
+            for value in &live_out[id] {
+                Interval::touch(&mut intervals[value.0], 2 * last + 1);
+            }
+        }
+
+        Liveness {
+            points,
+            blocks,
+            live_out,
+            intervals
+        }
+    }
+}
@@ -1,8 +1,71 @@
-use crate::ir::TranslationUnit;
-
-pub mod x64_elf;
-
-pub trait Codegen {
-    type OutputFormat;
-    fn compile_translation_unit(&mut self, translation_unit: TranslationUnit) -> Self::OutputFormat;
+use crate::error::ChairError;
+use crate::ir::TranslationUnit;
+use crate::ir::opt::OptLevel;
+
+pub(crate) mod liveness;
+pub mod x64;
+pub mod x64_elf;
+
+pub trait Codegen {
+    type OutputFormat;
+    fn compile_translation_unit(&mut self, translation_unit: TranslationUnit) -> Result<Self::OutputFormat, ChairError>;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RegisterAllocator {
+    // fast, for unoptimized builds
+    #[default]
+    LinearScan,
+    // iterated register coalescing, slower but with fewer moves and spills
+    GraphColoring
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Addressing {
+    // rip-relative lea, so objects work wherever they're loaded, like in PIE executables
+    #[default]
+    RipRelative,
+    // rip-relative, but with the addresses of globals other objects can see loaded from the GOT, so shared
+    // libraries can export them and see the executable's copy when it has one
+    Got,
+    // movabs of full 64-bit addresses, for code linked at a fixed address with its data more than 2 GiB away
+    Absolute
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CodegenOptions {
+    pub register_allocator: RegisterAllocator,
+    // how code refers to constants, globals and jump tables
+    pub addressing: Addressing,
+    // keeps rbp pointing at the frame so debuggers and profilers can walk the stack
+    pub frame_pointer: bool,
+    // lets leaf functions keep their locals below rsp without moving it, which kernels can't allow
+    pub red_zone: bool
+}
+
+impl Default for CodegenOptions {
+    fn default() -> CodegenOptions {
+        CodegenOptions {
+            register_allocator: RegisterAllocator::default(),
+            addressing: Addressing::default(),
+            frame_pointer: true,
+            red_zone: true
+        }
+    }
+}
+
+impl CodegenOptions {
+    // what code generation does at `level`
+    pub fn for_level(level: OptLevel) -> CodegenOptions {
+        let register_allocator = match level {
+            OptLevel::O0 | OptLevel::O1 => RegisterAllocator::LinearScan,
+            OptLevel::O2 => RegisterAllocator::GraphColoring
+        };
+
+        CodegenOptions {
+            register_allocator,
+            frame_pointer: level != OptLevel::O2,
+            ..CodegenOptions::default()
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,726 @@
+// numbered the way the encoding numbers them
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Reg {
+    Rax, Rcx, Rdx, Rbx, Rsp, Rbp, Rsi, Rdi,
+    R8, R9, R10, R11, R12, R13, R14, R15
+}
+
+impl Reg {
+    pub const ALL: [Reg; 16] = [
+        Reg::Rax, Reg::Rcx, Reg::Rdx, Reg::Rbx, Reg::Rsp, Reg::Rbp, Reg::Rsi, Reg::Rdi,
+        Reg::R8, Reg::R9, Reg::R10, Reg::R11, Reg::R12, Reg::R13, Reg::R14, Reg::R15
+    ];
+
+    pub fn number(self) -> u8 {
+        self as u8
+    }
+
+    // the name of the register's low `size` bytes, like `eax` or `r8b`
+    pub fn name(self, size: Size) -> String {
+        const LEGACY: [[&str; 4]; 8] = [
+            ["al", "ax", "eax", "rax"], ["cl", "cx", "ecx", "rcx"], ["dl", "dx", "edx", "rdx"], ["bl", "bx", "ebx", "rbx"],
+            ["spl", "sp", "esp", "rsp"], ["bpl", "bp", "ebp", "rbp"], ["sil", "si", "esi", "rsi"], ["dil", "di", "edi", "rdi"]
+        ];
+
+        match (self.number(), size) {
+            (n @ 0..8, size) => LEGACY[n as usize][size as usize].to_owned(),
+            (n, Size::Byte) => format!("r{}b", n),
+            (n, Size::Word) => format!("r{}w", n),
+            (n, Size::Dword) => format!("r{}d", n),
+            (n, Size::Qword) => format!("r{}", n)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Xmm {
+    Xmm0, Xmm1, Xmm2, Xmm3, Xmm4, Xmm5, Xmm6, Xmm7,
+    Xmm8, Xmm9, Xmm10, Xmm11, Xmm12, Xmm13, Xmm14, Xmm15
+}
+
+impl Xmm {
+    pub const ALL: [Xmm; 16] = [
+        Xmm::Xmm0, Xmm::Xmm1, Xmm::Xmm2, Xmm::Xmm3, Xmm::Xmm4, Xmm::Xmm5, Xmm::Xmm6, Xmm::Xmm7,
+        Xmm::Xmm8, Xmm::Xmm9, Xmm::Xmm10, Xmm::Xmm11, Xmm::Xmm12, Xmm::Xmm13, Xmm::Xmm14, Xmm::Xmm15
+    ];
+
+    pub fn number(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Size {
+    Byte,
+    Word,
+    Dword,
+    Qword
+}
+
+impl Size {
+    pub fn bytes(self) -> usize {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+            Size::Dword => 4,
+            Size::Qword => 8
+        }
+    }
+}
+
+// what a branch, call or rip-relative operand refers to: a label the caller places itself,
+// or an entry in the caller's symbol table
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    Label(usize),
+    Symbol(usize)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mem {
+    // `[base + index * scale + disp]`, where the scale is 1, 2, 4 or 8 and rsp can't be an index
+    Based {
+        base: Option<Reg>,
+        index: Option<(Reg, u8)>,
+        disp: i32
+    },
+    // `[rip + target + addend]`
+    Rip {
+        target: Target,
+        addend: i64
+    }
+}
+
+impl Mem {
+    // `[base + disp]`
+    pub fn base(base: Reg, disp: i32) -> Mem {
+        Mem::Based { base: Some(base), index: None, disp }
+    }
+
+    // `[base + index * scale + disp]`
+    pub fn indexed(base: Reg, index: Reg, scale: u8, disp: i32) -> Mem {
+        Mem::Based { base: Some(base), index: Some((index, scale)), disp }
+    }
+
+    // `[rip + target]`
+    pub fn rip(target: Target) -> Mem {
+        Mem::Rip { target, addend: 0 }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+    Reg(Reg),
+    Mem(Mem),
+    Imm(i64)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum XmmOperand {
+    Xmm(Xmm),
+    Mem(Mem)
+}
+
+// an immediate for `movabs`, either a number or the address of a target
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Imm64 {
+    Value(i64),
+    Address {
+        target: Target,
+        addend: i64
+    }
+}
+
+// condition codes for `jcc`, `setcc` and `cmovcc`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cond {
+    Overflow, NoOverflow, Below, AboveEqual, Equal, NotEqual, BelowEqual, Above,
+    Sign, NoSign, Parity, NoParity, Less, GreaterEqual, LessEqual, Greater
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AluOp {
+    Add, Or, Adc, Sbb, And, Sub, Xor, Cmp
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShiftOp {
+    Shl,
+    Shr,
+    Sar
+}
+
+// operand combinations the hardware can't encode, like memory-to-memory moves, make `encode` panic
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum X64Inst {
+    Mov { size: Size, dst: Operand, src: Operand },
+    // `mov reg, imm64`
+    MovAbs { dst: Reg, src: Imm64 },
+    // zero extends a byte or word into `dst`
+    Movzx { src_size: Size, dst: Reg, src: Operand },
+    // sign extends a byte, word or dword into `dst`, which is `size` wide
+    Movsx { size: Size, src_size: Size, dst: Reg, src: Operand },
+    Lea { dst: Reg, src: Mem },
+    Alu { op: AluOp, size: Size, dst: Operand, src: Operand },
+    Test { size: Size, dst: Operand, src: Operand },
+    Imul { size: Size, dst: Reg, src: Operand },
+    // `imul dst, src, imm`
+    ImulImm { size: Size, dst: Reg, src: Operand, imm: i32 },
+    // shifts by `amount`, or by cl if there isn't one
+    Shift { op: ShiftOp, size: Size, dst: Operand, amount: Option<u8> },
+    Neg { size: Size, dst: Operand },
+    Not { size: Size, dst: Operand },
+    // `div` or `idiv` of rdx:rax
+    Div { size: Size, signed: bool, src: Operand },
+    // sign extends rax into rdx:rax
+    Cqo,
+    Setcc { cond: Cond, dst: Operand },
+    Cmov { cond: Cond, size: Size, dst: Reg, src: Operand },
+    Push(Operand),
+    Pop(Reg),
+    Jmp(Target),
+    Jcc { cond: Cond, target: Target },
+    JmpIndirect(Operand),
+    Call(Target),
+    CallIndirect(Operand),
+    Ret,
+    Leave,
+    Syscall,
+    Ud2,
+    Nop,
+    // `movss` or `movsd`
+    MovFloat { double: bool, dst: XmmOperand, src: XmmOperand },
+    // `movq xmm, reg`
+    MovToXmm { dst: Xmm, src: Reg },
+    // `movq reg, xmm`
+    MovFromXmm { dst: Reg, src: Xmm },
+    // `movaps`, for copying between xmm registers
+    Movaps { dst: Xmm, src: Xmm },
+    // bytes that are already encoded
+    Bytes(Vec<u8>)
+}
+
+// how a fixup's field is filled in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FixupKind {
+    // a 4-byte displacement of data, `target + addend - offset`
+    Relative32,
+    // a 4-byte displacement of a jump or call, `target + addend - offset`
+    Branch32,
+    // an 8-byte address, `target + addend`
+    Absolute64
+}
+
+// a field in the encoded bytes that depends on where `target` ends up
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fixup {
+    pub offset: usize,
+    pub target: Target,
+    pub kind: FixupKind,
+    pub addend: i64
+}
+
+// encodes `instructions` one after another
+pub fn assemble(instructions: &[X64Inst]) -> (Vec<u8>, Vec<Fixup>) {
+    let mut code = vec![];
+    let mut fixups = vec![];
+
+    for instruction in instructions {
+        instruction.encode(&mut code, &mut fixups);
+    }
+
+    (code, fixups)
+}
+
+// what goes in the ModRM r/m field
+#[derive(Clone, Copy)]
+enum Rm {
+    Reg(u8),
+    Mem(Mem)
+}
+
+// one instruction's worth of encoding
+struct Encoding {
+    prefix: Option<u8>,
+    rex_w: bool,
+    // byte registers 4-7 mean spl, bpl, sil and dil with a REX prefix, and ah, ch, dh and bh without one
+    force_rex: bool,
+    opcode: Vec<u8>,
+    // a register added to the last opcode byte, like in push and mov reg, imm
+    opcode_reg: Option<u8>,
+    modrm: Option<(u8, Rm)>,
+    imm: Vec<u8>
+}
+
+impl Encoding {
+    fn new(opcode: &[u8]) -> Encoding {
+        Encoding {
+            prefix: None,
+            rex_w: false,
+            force_rex: false,
+            opcode: opcode.to_vec(),
+            opcode_reg: None,
+            modrm: None,
+            imm: vec![]
+        }
+    }
+
+    // an instruction that works on `size`, sets the operand size prefix or REX.W for it
+    fn sized(size: Size, opcode: &[u8]) -> Encoding {
+        let mut encoding = Encoding::new(opcode);
+        encoding.prefix = (size == Size::Word).then_some(0x66);
+        encoding.rex_w = size == Size::Qword;
+        encoding
+    }
+
+    fn modrm(mut self, reg: u8, rm: Rm) -> Encoding {
+        self.modrm = Some((reg, rm));
+        self
+    }
+
+    fn opcode_reg(mut self, reg: u8) -> Encoding {
+        self.opcode_reg = Some(reg);
+        self
+    }
+
+    fn imm(mut self, imm: i64, size: usize) -> Encoding {
+        self.imm = imm.to_le_bytes()[..size].to_vec();
+        self
+    }
+
+    fn force_rex(mut self, force: bool) -> Encoding {
+        self.force_rex |= force;
+        self
+    }
+
+    // returns where the immediate starts
+    fn emit(self, code: &mut Vec<u8>, fixups: &mut Vec<Fixup>) -> usize {
+        code.extend(self.prefix);
+
+        let (r, x, b) = match self.modrm {
+            Some((reg, Rm::Reg(rm))) => (reg >> 3, 0, rm >> 3),
+            Some((reg, Rm::Mem(Mem::Based { base, index, .. }))) => {
+                (reg >> 3, index.map_or(0, |(index, _)| index.number() >> 3), base.map_or(0, |base| base.number() >> 3))
+            },
+            Some((reg, Rm::Mem(Mem::Rip { .. }))) => (reg >> 3, 0, 0),
+            None => (0, 0, self.opcode_reg.unwrap_or(0) >> 3)
+        };
+
+        if self.rex_w || self.force_rex || r | x | b != 0 {
+            code.push(0x40 | ((self.rex_w as u8) << 3) | (r << 2) | (x << 1) | b);
+        }
+
+        code.extend(&self.opcode);
+        if let Some(reg) = self.opcode_reg {
+            *code.last_mut().unwrap() += reg & 7;
+        }
+
+        match self.modrm {
+            Some((reg, Rm::Reg(rm))) => code.push(0xC0 | ((reg & 7) << 3) | (rm & 7)),
+            Some((reg, Rm::Mem(mem))) => emit_mem(code, fixups, reg & 7, mem, self.imm.len()),
+            None => ()
+        }
+
+        let imm_start = code.len();
+        code.extend(self.imm);
+        imm_start
+    }
+}
+
+// the ModRM, SIB and displacement for a memory operand, followed by `imm_size` bytes of immediate
+fn emit_mem(code: &mut Vec<u8>, fixups: &mut Vec<Fixup>, reg: u8, mem: Mem, imm_size: usize) {
+    match mem {
+        Mem::Rip { target, addend } => {
+            code.push((reg << 3) | 0b101);
+
+            // the displacement is from the end of the instruction
+            fixups.push(Fixup {
+                offset: code.len(),
+                target,
+                kind: FixupKind::Relative32,
+                addend: addend - 4 - imm_size as i64
+            });
+            code.extend([0; 4]);
+        },
+        Mem::Based { base, index, disp } => {
+            let index = index.map(|(index, scale)| {
+                assert!(index != Reg::Rsp, "rsp can't be an index register");
+                let scale = match scale {
+                    1 => 0,
+                    2 => 1,
+                    4 => 2,
+                    8 => 3,
+                    _ => panic!("Invalid scale {}", scale)
+                };
+                (index.number() & 7, scale)
+            });
+
+            let Some(base) = base else {
+                // no base is a SIB with base 101 and mod 00, which takes a disp32
+                let (index, scale) = index.unwrap_or((0b100, 0));
+                code.push((reg << 3) | 0b100);
+                code.push((scale << 6) | (index << 3) | 0b101);
+                code.extend(disp.to_le_bytes());
+                return;
+            };
+
+            // rbp and r13 with mod 00 mean RIP-relative or no base, so they always take a displacement
+            let base = base.number() & 7;
+            let mode = if disp == 0 && base != 0b101 {
+                0b00
+            } else if i8::try_from(disp).is_ok() {
+                0b01
+            } else {
+                0b10
+            };
+
+            // rsp and r12 as the r/m field mean a SIB follows
+            if index.is_some() || base == 0b100 {
+                let (index, scale) = index.unwrap_or((0b100, 0));
+                code.push((mode << 6) | (reg << 3) | 0b100);
+                code.push((scale << 6) | (index << 3) | base);
+            } else {
+                code.push((mode << 6) | (reg << 3) | base);
+            }
+
+            match mode {
+                0b01 => code.push(disp as u8),
+                0b10 => code.extend(disp.to_le_bytes()),
+                _ => ()
+            }
+        }
+    }
+}
+
+fn rm(operand: &Operand) -> Rm {
+    match operand {
+        Operand::Reg(reg) => Rm::Reg(reg.number()),
+        Operand::Mem(mem) => Rm::Mem(*mem),
+        Operand::Imm(_) => panic!("An immediate can't be a register or memory operand")
+    }
+}
+
+fn xmm_rm(operand: &XmmOperand) -> Rm {
+    match operand {
+        XmmOperand::Xmm(xmm) => Rm::Reg(xmm.number()),
+        XmmOperand::Mem(mem) => Rm::Mem(*mem)
+    }
+}
+
+fn is_byte_reg_with_rex(size: Size, operand: &Operand) -> bool {
+    matches!(operand, Operand::Reg(reg) if size == Size::Byte && (4..8).contains(&reg.number()))
+}
+
+// the immediate of an instruction that takes at most an imm32, sign extended to 64 bits
+fn imm32(size: Size, imm: i64) -> (i64, usize) {
+    let bytes = size.bytes().min(4);
+
+    let fits = match size {
+        Size::Byte => i8::try_from(imm).is_ok() || u8::try_from(imm).is_ok(),
+        Size::Word => i16::try_from(imm).is_ok() || u16::try_from(imm).is_ok(),
+        Size::Dword => i32::try_from(imm).is_ok() || u32::try_from(imm).is_ok(),
+        Size::Qword => i32::try_from(imm).is_ok()
+    };
+    assert!(fits, "Immediate {} doesn't fit in {:?}", imm, size);
+
+    (imm, bytes)
+}
+
+impl X64Inst {
+    // appends the encoding to `code`, and a fixup to `fixups` for every field that refers to a target
+    pub fn encode(&self, code: &mut Vec<u8>, fixups: &mut Vec<Fixup>) {
+        let byte_op = |size: Size, byte: u8, other: u8| if size == Size::Byte { byte } else { other };
+
+        let encoding = match self {
+            X64Inst::Mov { size, dst, src } => match (dst, src) {
+                // mov r32, imm32 zero extends, so only 64-bit moves take the sign extended form
+                (Operand::Reg(dst), Operand::Imm(imm)) if *size != Size::Qword => {
+                    let (imm, bytes) = imm32(*size, *imm);
+                    Encoding::sized(*size, &[byte_op(*size, 0xB0, 0xB8)]).opcode_reg(dst.number()).imm(imm, bytes)
+                        .force_rex(is_byte_reg_with_rex(*size, &Operand::Reg(*dst)))
+                },
+                (_, Operand::Imm(imm)) => {
+                    let (imm, bytes) = imm32(*size, *imm);
+                    Encoding::sized(*size, &[byte_op(*size, 0xC6, 0xC7)]).modrm(0, rm(dst)).imm(imm, bytes)
+                },
+                (Operand::Mem(_), Operand::Mem(_)) => panic!("mov can't copy memory to memory"),
+                (_, Operand::Reg(src)) => Encoding::sized(*size, &[byte_op(*size, 0x88, 0x89)]).modrm(src.number(), rm(dst))
+                    .force_rex(is_byte_reg_with_rex(*size, &Operand::Reg(*src)) || is_byte_reg_with_rex(*size, dst)),
+                (Operand::Reg(dst), _) => Encoding::sized(*size, &[byte_op(*size, 0x8A, 0x8B)]).modrm(dst.number(), rm(src))
+                    .force_rex(is_byte_reg_with_rex(*size, &Operand::Reg(*dst))),
+                (Operand::Imm(_), _) => panic!("mov can't write to an immediate")
+            },
+            X64Inst::MovAbs { dst, src } => {
+                let encoding = Encoding::sized(Size::Qword, &[0xB8]).opcode_reg(dst.number());
+
+                match src {
+                    Imm64::Value(imm) => encoding.imm(*imm, 8),
+                    Imm64::Address { target, addend } => {
+                        let offset = encoding.imm(0, 8).emit(code, fixups);
+                        fixups.push(Fixup { offset, target: *target, kind: FixupKind::Absolute64, addend: *addend });
+                        return;
+                    }
+                }
+            },
+            X64Inst::Movzx { src_size, dst, src } => {
+                let opcode = match src_size {
+                    Size::Byte => 0xB6,
+                    Size::Word => 0xB7,
+                    _ => panic!("movzx extends bytes and words, 32-bit moves zero extend by themselves")
+                };
+                Encoding::new(&[0x0F, opcode]).modrm(dst.number(), rm(src)).force_rex(is_byte_reg_with_rex(*src_size, src))
+            },
+            X64Inst::Movsx { size, src_size, dst, src } => {
+                let opcode: &[u8] = match src_size {
+                    Size::Byte => &[0x0F, 0xBE],
+                    Size::Word => &[0x0F, 0xBF],
+                    Size::Dword => &[0x63],
+                    Size::Qword => panic!("movsx can't extend a qword")
+                };
+                Encoding::sized(*size, opcode).modrm(dst.number(), rm(src)).force_rex(is_byte_reg_with_rex(*src_size, src))
+            },
+            X64Inst::Lea { dst, src } => Encoding::sized(Size::Qword, &[0x8D]).modrm(dst.number(), Rm::Mem(*src)),
+            X64Inst::Alu { op, size, dst, src } => {
+                let op = *op as u8;
+
+                match (dst, src) {
+                    (_, Operand::Imm(imm)) => {
+                        let (imm, bytes) = imm32(*size, *imm);
+
+                        if *size != Size::Byte && i8::try_from(imm).is_ok() {
+                            Encoding::sized(*size, &[0x83]).modrm(op, rm(dst)).imm(imm, 1)
+                        } else if *dst == Operand::Reg(Reg::Rax) {
+                            Encoding::sized(*size, &[(op << 3) + byte_op(*size, 4, 5)]).imm(imm, bytes)
+                        } else {
+                            Encoding::sized(*size, &[byte_op(*size, 0x80, 0x81)]).modrm(op, rm(dst)).imm(imm, bytes)
+                                .force_rex(is_byte_reg_with_rex(*size, dst))
+                        }
+                    },
+                    (Operand::Mem(_), Operand::Mem(_)) => panic!("Arithmetic can't have two memory operands"),
+                    (_, Operand::Reg(src)) => Encoding::sized(*size, &[(op << 3) + byte_op(*size, 0, 1)]).modrm(src.number(), rm(dst))
+                        .force_rex(is_byte_reg_with_rex(*size, &Operand::Reg(*src)) || is_byte_reg_with_rex(*size, dst)),
+                    (Operand::Reg(dst), _) => Encoding::sized(*size, &[(op << 3) + byte_op(*size, 2, 3)]).modrm(dst.number(), rm(src))
+                        .force_rex(is_byte_reg_with_rex(*size, &Operand::Reg(*dst))),
+                    (Operand::Imm(_), _) => panic!("Arithmetic can't write to an immediate")
+                }
+            },
+            X64Inst::Test { size, dst, src } => match src {
+                Operand::Imm(imm) => {
+                    let (imm, bytes) = imm32(*size, *imm);
+
+                    if *dst == Operand::Reg(Reg::Rax) {
+                        Encoding::sized(*size, &[byte_op(*size, 0xA8, 0xA9)]).imm(imm, bytes)
+                    } else {
+                        Encoding::sized(*size, &[byte_op(*size, 0xF6, 0xF7)]).modrm(0, rm(dst)).imm(imm, bytes)
+                            .force_rex(is_byte_reg_with_rex(*size, dst))
+                    }
+                },
+                Operand::Reg(src) => Encoding::sized(*size, &[byte_op(*size, 0x84, 0x85)]).modrm(src.number(), rm(dst))
+                    .force_rex(is_byte_reg_with_rex(*size, &Operand::Reg(*src)) || is_byte_reg_with_rex(*size, dst)),
+                Operand::Mem(_) => panic!("test takes a register or an immediate as its second operand")
+            },
+            X64Inst::Imul { size, dst, src } => Encoding::sized(*size, &[0x0F, 0xAF]).modrm(dst.number(), rm(src)),
+            X64Inst::ImulImm { size, dst, src, imm } => {
+                if i8::try_from(*imm).is_ok() {
+                    Encoding::sized(*size, &[0x6B]).modrm(dst.number(), rm(src)).imm(*imm as i64, 1)
+                } else {
+                    Encoding::sized(*size, &[0x69]).modrm(dst.number(), rm(src)).imm(*imm as i64, size.bytes().min(4))
+                }
+            },
+            X64Inst::Shift { op, size, dst, amount } => {
+                let op = match op {
+                    ShiftOp::Shl => 4,
+                    ShiftOp::Shr => 5,
+                    ShiftOp::Sar => 7
+                };
+
+                let encoding = match amount {
+                    None => Encoding::sized(*size, &[byte_op(*size, 0xD2, 0xD3)]).modrm(op, rm(dst)),
+                    Some(1) => Encoding::sized(*size, &[byte_op(*size, 0xD0, 0xD1)]).modrm(op, rm(dst)),
+                    Some(amount) => Encoding::sized(*size, &[byte_op(*size, 0xC0, 0xC1)]).modrm(op, rm(dst)).imm(*amount as i64, 1)
+                };
+                encoding.force_rex(is_byte_reg_with_rex(*size, dst))
+            },
+            X64Inst::Not { size, dst } => Encoding::sized(*size, &[byte_op(*size, 0xF6, 0xF7)]).modrm(2, rm(dst))
+                .force_rex(is_byte_reg_with_rex(*size, dst)),
+            X64Inst::Neg { size, dst } => Encoding::sized(*size, &[byte_op(*size, 0xF6, 0xF7)]).modrm(3, rm(dst))
+                .force_rex(is_byte_reg_with_rex(*size, dst)),
+            X64Inst::Div { size, signed, src } => Encoding::sized(*size, &[byte_op(*size, 0xF6, 0xF7)]).modrm(if *signed { 7 } else { 6 }, rm(src))
+                .force_rex(is_byte_reg_with_rex(*size, src)),
+            X64Inst::Cqo => Encoding::sized(Size::Qword, &[0x99]),
+            X64Inst::Setcc { cond, dst } => Encoding::new(&[0x0F, 0x90 + *cond as u8]).modrm(0, rm(dst))
+                .force_rex(is_byte_reg_with_rex(Size::Byte, dst)),
+            X64Inst::Cmov { cond, size, dst, src } => Encoding::sized(*size, &[0x0F, 0x40 + *cond as u8]).modrm(dst.number(), rm(src)),
+            X64Inst::Push(operand) => match operand {
+                Operand::Reg(reg) => Encoding::new(&[0x50]).opcode_reg(reg.number()),
+                Operand::Imm(imm) if i8::try_from(*imm).is_ok() => Encoding::new(&[0x6A]).imm(*imm, 1),
+                Operand::Imm(imm) => Encoding::new(&[0x68]).imm(imm32(Size::Qword, *imm).0, 4),
+                Operand::Mem(_) => Encoding::new(&[0xFF]).modrm(6, rm(operand))
+            },
+            X64Inst::Pop(reg) => Encoding::new(&[0x58]).opcode_reg(reg.number()),
+            X64Inst::Jmp(target) => return branch(code, fixups, &[0xE9], *target),
+            X64Inst::Jcc { cond, target } => return branch(code, fixups, &[0x0F, 0x80 + *cond as u8], *target),
+            X64Inst::Call(target) => return branch(code, fixups, &[0xE8], *target),
+            X64Inst::JmpIndirect(operand) => Encoding::new(&[0xFF]).modrm(4, rm(operand)),
+            X64Inst::CallIndirect(operand) => Encoding::new(&[0xFF]).modrm(2, rm(operand)),
+            X64Inst::Ret => Encoding::new(&[0xC3]),
+            X64Inst::Leave => Encoding::new(&[0xC9]),
+            X64Inst::Syscall => Encoding::new(&[0x0F, 0x05]),
+            X64Inst::Ud2 => Encoding::new(&[0x0F, 0x0B]),
+            X64Inst::Nop => Encoding::new(&[0x90]),
+            X64Inst::MovFloat { double, dst, src } => {
+                let mut encoding = match (dst, src) {
+                    (XmmOperand::Xmm(dst), _) => Encoding::new(&[0x0F, 0x10]).modrm(dst.number(), xmm_rm(src)),
+                    (XmmOperand::Mem(_), XmmOperand::Xmm(src)) => Encoding::new(&[0x0F, 0x11]).modrm(src.number(), xmm_rm(dst)),
+                    (XmmOperand::Mem(_), XmmOperand::Mem(_)) => panic!("movss and movsd can't copy memory to memory")
+                };
+                encoding.prefix = Some(if *double { 0xF2 } else { 0xF3 });
+                encoding
+            },
+            X64Inst::MovToXmm { dst, src } => {
+                let mut encoding = Encoding::sized(Size::Qword, &[0x0F, 0x6E]).modrm(dst.number(), Rm::Reg(src.number()));
+                encoding.prefix = Some(0x66);
+                encoding
+            },
+            X64Inst::MovFromXmm { dst, src } => {
+                let mut encoding = Encoding::sized(Size::Qword, &[0x0F, 0x7E]).modrm(src.number(), Rm::Reg(dst.number()));
+                encoding.prefix = Some(0x66);
+                encoding
+            },
+            X64Inst::Movaps { dst, src } => Encoding::new(&[0x0F, 0x28]).modrm(dst.number(), Rm::Reg(src.number())),
+            X64Inst::Bytes(bytes) => {
+                code.extend(bytes);
+                return;
+            }
+        };
+
+        encoding.emit(code, fixups);
+    }
+
+    // the instruction in Intel syntax, calling `name` for what each target is called
+    pub fn to_intel(&self, name: &dyn Fn(Target) -> String) -> String {
+        let op = |operand: &Operand, size: Size| match operand {
+            Operand::Reg(reg) => reg.name(size),
+            Operand::Mem(mem) => format!("{} {}", ptr(size), intel_mem(mem, name)),
+            Operand::Imm(imm) => imm.to_string()
+        };
+        let xmm_op = |operand: &XmmOperand, double: bool| match operand {
+            XmmOperand::Xmm(xmm) => format!("xmm{}", xmm.number()),
+            XmmOperand::Mem(mem) => format!("{} {}", ptr(if double { Size::Qword } else { Size::Dword }), intel_mem(mem, name))
+        };
+
+        match self {
+            X64Inst::Mov { size, dst, src } => format!("mov {}, {}", op(dst, *size), op(src, *size)),
+            X64Inst::MovAbs { dst, src: Imm64::Value(imm) } => format!("movabs {}, {}", dst.name(Size::Qword), imm),
+            X64Inst::MovAbs { dst, src: Imm64::Address { target, addend } } => {
+                format!("movabs {}, {}{}", dst.name(Size::Qword), name(*target), intel_addend(*addend))
+            },
+            X64Inst::Movzx { src_size, dst, src } => format!("movzx {}, {}", dst.name(Size::Dword), op(src, *src_size)),
+            X64Inst::Movsx { size, src_size: Size::Dword, dst, src } => format!("movsxd {}, {}", dst.name(*size), op(src, Size::Dword)),
+            X64Inst::Movsx { size, src_size, dst, src } => format!("movsx {}, {}", dst.name(*size), op(src, *src_size)),
+            X64Inst::Lea { dst, src } => format!("lea {}, {}", dst.name(Size::Qword), intel_mem(src, name)),
+            X64Inst::Alu { op: alu, size, dst, src } => {
+                let mnemonic = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"][*alu as usize];
+                format!("{} {}, {}", mnemonic, op(dst, *size), op(src, *size))
+            },
+            X64Inst::Test { size, dst, src } => format!("test {}, {}", op(dst, *size), op(src, *size)),
+            X64Inst::Imul { size, dst, src } => format!("imul {}, {}", dst.name(*size), op(src, *size)),
+            X64Inst::ImulImm { size, dst, src, imm } => format!("imul {}, {}, {}", dst.name(*size), op(src, *size), imm),
+            X64Inst::Shift { op: shift, size, dst, amount } => {
+                let mnemonic = match shift {
+                    ShiftOp::Shl => "shl",
+                    ShiftOp::Shr => "shr",
+                    ShiftOp::Sar => "sar"
+                };
+                let amount = amount.map(|amount| amount.to_string()).unwrap_or_else(|| "cl".to_owned());
+                format!("{} {}, {}", mnemonic, op(dst, *size), amount)
+            },
+            X64Inst::Neg { size, dst } => format!("neg {}", op(dst, *size)),
+            X64Inst::Not { size, dst } => format!("not {}", op(dst, *size)),
+            X64Inst::Div { size, signed, src } => format!("{} {}", if *signed { "idiv" } else { "div" }, op(src, *size)),
+            X64Inst::Cqo => "cqo".to_owned(),
+            X64Inst::Setcc { cond, dst } => format!("set{} {}", cond.suffix(), op(dst, Size::Byte)),
+            X64Inst::Cmov { cond, size, dst, src } => format!("cmov{} {}, {}", cond.suffix(), dst.name(*size), op(src, *size)),
+            X64Inst::Push(operand) => format!("push {}", op(operand, Size::Qword)),
+            X64Inst::Pop(reg) => format!("pop {}", reg.name(Size::Qword)),
+            X64Inst::Jmp(target) => format!("jmp {}", name(*target)),
+            X64Inst::Jcc { cond, target } => format!("j{} {}", cond.suffix(), name(*target)),
+            X64Inst::JmpIndirect(operand) => format!("jmp {}", op(operand, Size::Qword)),
+            X64Inst::Call(target) => format!("call {}", name(*target)),
+            X64Inst::CallIndirect(operand) => format!("call {}", op(operand, Size::Qword)),
+            X64Inst::Ret => "ret".to_owned(),
+            X64Inst::Leave => "leave".to_owned(),
+            X64Inst::Syscall => "syscall".to_owned(),
+            X64Inst::Ud2 => "ud2".to_owned(),
+            X64Inst::Nop => "nop".to_owned(),
+            X64Inst::MovFloat { double, dst, src } => {
+                format!("{} {}, {}", if *double { "movsd" } else { "movss" }, xmm_op(dst, *double), xmm_op(src, *double))
+            },
+            X64Inst::MovToXmm { dst, src } => format!("movq xmm{}, {}", dst.number(), src.name(Size::Qword)),
+            X64Inst::MovFromXmm { dst, src } => format!("movq {}, xmm{}", dst.name(Size::Qword), src.number()),
+            X64Inst::Movaps { dst, src } => format!("movaps xmm{}, xmm{}", dst.number(), src.number()),
+            X64Inst::Bytes(bytes) => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+                format!(".byte {}", bytes.join(", "))
+            }
+        }
+    }
+}
+
+impl Cond {
+    fn suffix(self) -> &'static str {
+        [
+            "o", "no", "b", "ae", "e", "ne", "be", "a",
+            "s", "ns", "p", "np", "l", "ge", "le", "g"
+        ][self as usize]
+    }
+}
+
+fn ptr(size: Size) -> &'static str {
+    match size {
+        Size::Byte => "byte ptr",
+        Size::Word => "word ptr",
+        Size::Dword => "dword ptr",
+        Size::Qword => "qword ptr"
+    }
+}
+
+fn intel_addend(addend: i64) -> String {
+    match addend {
+        0 => String::new(),
+        addend if addend < 0 => format!(" - {}", addend.unsigned_abs()),
+        addend => format!(" + {}", addend)
+    }
+}
+
+fn intel_mem(mem: &Mem, name: &dyn Fn(Target) -> String) -> String {
+    match mem {
+        Mem::Rip { target, addend } => format!("[rip + {}{}]", name(*target), intel_addend(*addend)),
+        Mem::Based { base, index, disp } => {
+            let mut parts = vec![];
+            if let Some(base) = base {
+                parts.push(base.name(Size::Qword));
+            }
+            if let Some((index, scale)) = index {
+                parts.push(format!("{}*{}", index.name(Size::Qword), scale));
+            }
+
+            match (parts.is_empty(), *disp) {
+                (true, disp) => format!("[{}]", disp),
+                (false, disp) => format!("[{}{}]", parts.join(" + "), intel_addend(disp as i64))
+            }
+        }
+    }
+}
+
+// a jump or call with a rel32 to `target`
+fn branch(code: &mut Vec<u8>, fixups: &mut Vec<Fixup>, opcode: &[u8], target: Target) {
+    code.extend(opcode);
+    fixups.push(Fixup {
+        offset: code.len(),
+        target,
+        kind: FixupKind::Branch32,
+        addend: -4
+    });
+    code.extend([0; 4]);
+}
@@ -0,0 +1,545 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::codegen::liveness::{Liveness, Point};
+use crate::codegen::x64::asm::{Reg, Xmm};
+use crate::codegen::x64::regalloc::{Allocation, GPRS, Location, RegSet, XMMS, candidates, clobbers, reg_bit, used_callee_saved};
+use crate::codegen::x64_elf::{ArgLocation, arg_locations};
+use crate::ir::{BlockId, Function, Instruction, Terminator, Value, ValueId};
+
+// nodes below this are the physical registers, numbered like the bits of a RegSet, and the rest are values
+const PRECOLORED: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum NodeState {
+    Precolored,
+    // not defined in the function's layout, so never allocated
+    Unused,
+    Simplify,
+    Freeze,
+    Spill,
+    Selected,
+    Coalesced,
+    Colored,
+    Spilled
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MoveState {
+    Worklist,
+    Active,
+    Coalesced,
+    Constrained,
+    Frozen
+}
+
+// the interference graph with George and Appel's worklists
+struct Graph<'a> {
+    function: &'a Function,
+    state: Vec<NodeState>,
+    // every interference, including those between int and float nodes that only spill slots care about
+    edges: HashSet<(usize, usize)>,
+    // the neighbours of values in the same class, physical registers don't keep theirs
+    neighbours: Vec<Vec<usize>>,
+    degree: Vec<usize>,
+    alias: Vec<usize>,
+    color: Vec<Option<Location>>,
+    // how expensive spilling each node is, uses and definitions weighted by loop depth
+    cost: Vec<f64>,
+    // as (destination, source)
+    moves: Vec<(usize, usize)>,
+    move_state: Vec<MoveState>,
+    node_moves: Vec<Vec<usize>>,
+    simplify: BTreeSet<usize>,
+    freeze: BTreeSet<usize>,
+    spill: BTreeSet<usize>,
+    worklist_moves: BTreeSet<usize>,
+    select: Vec<usize>
+}
+
+fn node(value: ValueId) -> usize {
+    PRECOLORED + value.0
+}
+
+fn register_node(location: Location) -> usize {
+    reg_bit(location).trailing_zeros() as usize
+}
+
+// Chaitin-Briggs coloring with George and Appel's iterated register coalescing, which merges
+// values joined by moves whenever that can't make the graph harder to color
+pub(crate) fn graph_coloring(function: &Function, liveness: &Liveness, layout: &[BlockId]) -> Allocation {
+    let nodes = PRECOLORED + function.values.len();
+
+    let mut graph = Graph {
+        function,
+        state: (0..nodes).map(|node| if node < PRECOLORED { NodeState::Precolored } else { NodeState::Unused }).collect(),
+        edges: HashSet::new(),
+        neighbours: vec![vec![]; nodes],
+        degree: vec![0; nodes],
+        alias: (0..nodes).collect(),
+        color: (0..nodes).map(|node| (node < PRECOLORED).then(|| physical(node))).collect(),
+        cost: vec![0.0; nodes],
+        moves: vec![],
+        move_state: vec![],
+        node_moves: vec![vec![]; nodes],
+        simplify: BTreeSet::new(),
+        freeze: BTreeSet::new(),
+        spill: BTreeSet::new(),
+        worklist_moves: BTreeSet::new(),
+        select: vec![]
+    };
+
+    graph.build(liveness, &loop_depths(function, layout));
+    graph.make_worklists(liveness);
+
+    loop {
+        if let Some(node) = graph.simplify.pop_first() {
+            graph.simplify_node(node);
+        } else if let Some(index) = graph.worklist_moves.pop_first() {
+            graph.coalesce(index);
+        } else if let Some(node) = graph.freeze.pop_first() {
+            graph.simplify.insert(node);
+            graph.freeze_moves(node);
+        } else if !graph.spill.is_empty() {
+            graph.select_spill();
+        } else {
+            break;
+        }
+    }
+
+    graph.assign_colors();
+    graph.allocation()
+}
+
+fn physical(node: usize) -> Location {
+    if node < 16 {
+        Location::Reg(Reg::ALL[node])
+    } else {
+        Location::Xmm(Xmm::ALL[node - 16])
+    }
+}
+
+impl Graph<'_> {
+    fn is_float(&self, node: usize) -> bool {
+        if node < PRECOLORED {
+            node >= 16
+        } else {
+            self.function.values[node - PRECOLORED].is_float()
+        }
+    }
+
+    // how many registers a node's class has
+    fn k(&self, node: usize) -> usize {
+        if self.is_float(node) { XMMS.len() } else { GPRS.len() }
+    }
+
+    fn add_edge(&mut self, u: usize, v: usize) {
+        if u == v || self.edges.contains(&(u, v)) {
+            return;
+        }
+
+        self.edges.insert((u, v));
+        self.edges.insert((v, u));
+
+        // values of different classes never compete for a register, only for a spill slot
+        if self.is_float(u) != self.is_float(v) {
+            return;
+        }
+
+        for (from, to) in [(u, v), (v, u)] {
+            if self.state[from] != NodeState::Precolored {
+                self.neighbours[from].push(to);
+                self.degree[from] += 1;
+            }
+        }
+    }
+
+    fn add_move(&mut self, dst: usize, src: usize) {
+        if dst == src || self.is_float(dst) != self.is_float(src) {
+            return;
+        }
+
+        let index = self.moves.len();
+        self.moves.push((dst, src));
+        self.move_state.push(MoveState::Worklist);
+        self.node_moves[dst].push(index);
+        self.node_moves[src].push(index);
+        self.worklist_moves.insert(index);
+    }
+
+    // walks every block backwards from the values live out of it, making each definition interfere
+    // with everything live after it and each value live across a point with the registers it clobbers
+    fn build(&mut self, liveness: &Liveness, depths: &HashMap<BlockId, u32>) {
+        let allocatable = GPRS.iter().map(|reg| reg_bit(Location::Reg(*reg))).chain(XMMS.iter().map(|xmm| reg_bit(Location::Xmm(*xmm)))).fold(0, |set, bit| set | bit);
+
+        for (i, (block, first, last)) in liveness.blocks.iter().enumerate() {
+            let weight = 10f64.powi(depths[block].min(8) as i32);
+            let mut live: BTreeSet<usize> = liveness.live_out[block].iter().map(|value| node(*value)).collect();
+
+            // the entry point comes right before the first block
+            let first = if i == 0 { 0 } else { *first };
+
+            for point in liveness.points[first..=*last].iter().rev() {
+                let defs: Vec<usize> = point.defs(self.function).into_iter().map(node).collect();
+                let uses: Vec<usize> = point.uses().into_iter().map(node).collect();
+
+                // a cast's result can share its operand's register, since extending a value in place keeps its low bits
+                let copied = match point {
+                    Point::Instruction(Instruction::Cast { value: Value::Ref(value), .. }) => Some(node(*value)),
+                    _ => None
+                };
+
+                let clobbered: RegSet = clobbers(point) & allocatable;
+                for value in live.iter().copied().filter(|value| !defs.contains(value)).collect::<Vec<_>>() {
+                    for register in (0..PRECOLORED).filter(|register| clobbered & (1 << register) != 0) {
+                        self.add_edge(value, register);
+                    }
+                }
+
+                for def in &defs {
+                    for value in live.iter().chain(defs.iter()).copied().collect::<Vec<_>>() {
+                        if Some(value) != copied {
+                            self.add_edge(*def, value);
+                        }
+                    }
+                }
+
+                for value in defs.iter().chain(uses.iter()) {
+                    self.cost[*value] += weight;
+                }
+
+                for def in &defs {
+                    live.remove(def);
+                }
+                live.extend(uses);
+
+                self.add_moves(point, copied);
+            }
+        }
+    }
+
+    // the copies a point makes, between values or into and out of the registers the ABI fixes
+    fn add_moves(&mut self, point: &Point, copied: Option<usize>) {
+        let function = self.function;
+        let arg_node = |location: &ArgLocation| match location {
+            ArgLocation::Int(reg) => Some(register_node(Location::Reg(*reg))),
+            ArgLocation::Sse(xmm) => Some(register_node(Location::Xmm(*xmm))),
+            ArgLocation::Stack => None
+        };
+        let xmm0 = register_node(Location::Xmm(Xmm::Xmm0));
+
+        match point {
+            Point::Entry => {
+                let types: Vec<_> = function.params.iter().map(|param| function.values[param.0].clone()).collect();
+                for (param, location) in function.params.iter().zip(arg_locations(&types)) {
+                    if let Some(register) = arg_node(&location) {
+                        self.add_move(node(*param), register);
+                    }
+                }
+            },
+            Point::Instruction(Instruction::Cast { result, .. }) => {
+                if let Some(value) = copied {
+                    self.add_move(node(*result), value);
+                }
+            },
+            Point::Instruction(Instruction::Call { result, args, .. }) => {
+                let types: Vec<_> = args.iter().map(|arg| function.value_type(arg)).collect();
+                for (arg, location) in args.iter().zip(arg_locations(&types)) {
+                    if let (Value::Ref(value), Some(register)) = (arg, arg_node(&location)) {
+                        self.add_move(register, node(*value));
+                    }
+                }
+
+                if let Some(result) = result {
+                    self.add_move(node(*result), xmm0);
+                }
+            },
+            Point::Terminator(Terminator::Return(Some(Value::Ref(value)))) => self.add_move(xmm0, node(*value)),
+            _ => ()
+        }
+    }
+
+    fn make_worklists(&mut self, liveness: &Liveness) {
+        for (value, interval) in liveness.intervals.iter().enumerate() {
+            if interval.is_none() {
+                continue;
+            }
+
+            let node = node(ValueId(value));
+            if self.degree[node] >= self.k(node) {
+                self.state[node] = NodeState::Spill;
+                self.spill.insert(node);
+            } else if self.is_move_related(node) {
+                self.state[node] = NodeState::Freeze;
+                self.freeze.insert(node);
+            } else {
+                self.state[node] = NodeState::Simplify;
+                self.simplify.insert(node);
+            }
+        }
+    }
+
+    fn adjacent(&self, node: usize) -> Vec<usize> {
+        self.neighbours[node].iter().copied()
+            .filter(|neighbour| !matches!(self.state[*neighbour], NodeState::Selected | NodeState::Coalesced))
+            .collect()
+    }
+
+    // the moves of `node` that might still be coalesced
+    fn pending_moves(&self, node: usize) -> Vec<usize> {
+        self.node_moves[node].iter().copied()
+            .filter(|index| matches!(self.move_state[*index], MoveState::Worklist | MoveState::Active))
+            .collect()
+    }
+
+    fn is_move_related(&self, node: usize) -> bool {
+        !self.pending_moves(node).is_empty()
+    }
+
+    fn simplify_node(&mut self, node: usize) {
+        self.state[node] = NodeState::Selected;
+        self.select.push(node);
+
+        for neighbour in self.adjacent(node) {
+            self.decrement_degree(neighbour);
+        }
+    }
+
+    fn decrement_degree(&mut self, node: usize) {
+        if self.state[node] == NodeState::Precolored {
+            return;
+        }
+
+        self.degree[node] -= 1;
+        if self.degree[node] + 1 != self.k(node) {
+            return;
+        }
+
+        // the node just became colorable, which might make moves around it coalescable too
+        let mut nodes = self.adjacent(node);
+        nodes.push(node);
+        self.enable_moves(&nodes);
+
+        self.spill.remove(&node);
+        if self.is_move_related(node) {
+            self.state[node] = NodeState::Freeze;
+            self.freeze.insert(node);
+        } else {
+            self.state[node] = NodeState::Simplify;
+            self.simplify.insert(node);
+        }
+    }
+
+    fn enable_moves(&mut self, nodes: &[usize]) {
+        for node in nodes {
+            for index in self.pending_moves(*node) {
+                if self.move_state[index] == MoveState::Active {
+                    self.move_state[index] = MoveState::Worklist;
+                    self.worklist_moves.insert(index);
+                }
+            }
+        }
+    }
+
+    fn alias(&self, mut node: usize) -> usize {
+        while self.state[node] == NodeState::Coalesced {
+            node = self.alias[node];
+        }
+        node
+    }
+
+    fn add_worklist(&mut self, node: usize) {
+        if self.state[node] != NodeState::Precolored && !self.is_move_related(node) && self.degree[node] < self.k(node) {
+            self.freeze.remove(&node);
+            self.state[node] = NodeState::Simplify;
+            self.simplify.insert(node);
+        }
+    }
+
+    // George's test, for coalescing into a physical register
+    fn george(&self, neighbour: usize, register: usize) -> bool {
+        self.degree[neighbour] < self.k(neighbour) || self.state[neighbour] == NodeState::Precolored || self.edges.contains(&(neighbour, register))
+    }
+
+    // Briggs's test: the merged node has fewer than k significant neighbours
+    fn briggs(&self, u: usize, v: usize) -> bool {
+        let neighbours: BTreeSet<usize> = self.adjacent(u).into_iter().chain(self.adjacent(v)).collect();
+        let significant = neighbours.iter().filter(|neighbour| {
+            self.state[**neighbour] == NodeState::Precolored || self.degree[**neighbour] >= self.k(**neighbour)
+        }).count();
+
+        significant < self.k(u)
+    }
+
+    fn coalesce(&mut self, index: usize) {
+        let (x, y) = self.moves[index];
+        let (x, y) = (self.alias(x), self.alias(y));
+        let (u, v) = if self.state[y] == NodeState::Precolored { (y, x) } else { (x, y) };
+
+        if u == v {
+            self.move_state[index] = MoveState::Coalesced;
+            self.add_worklist(u);
+        } else if self.state[v] == NodeState::Precolored || self.edges.contains(&(u, v)) {
+            self.move_state[index] = MoveState::Constrained;
+            self.add_worklist(u);
+            self.add_worklist(v);
+        } else if (self.state[u] == NodeState::Precolored && self.adjacent(v).iter().all(|neighbour| self.george(*neighbour, u)))
+            || (self.state[u] != NodeState::Precolored && self.briggs(u, v)) {
+            self.move_state[index] = MoveState::Coalesced;
+            self.combine(u, v);
+            self.add_worklist(u);
+        } else {
+            self.move_state[index] = MoveState::Active;
+        }
+    }
+
+    fn combine(&mut self, u: usize, v: usize) {
+        self.freeze.remove(&v);
+        self.spill.remove(&v);
+        self.state[v] = NodeState::Coalesced;
+        self.alias[v] = u;
+        self.cost[u] += self.cost[v];
+
+        let moves = self.node_moves[v].clone();
+        self.node_moves[u].extend(moves);
+        self.enable_moves(&[v]);
+
+        for neighbour in self.adjacent(v) {
+            self.add_edge(neighbour, u);
+            self.decrement_degree(neighbour);
+        }
+
+        if self.state[u] == NodeState::Freeze && self.degree[u] >= self.k(u) {
+            self.freeze.remove(&u);
+            self.state[u] = NodeState::Spill;
+            self.spill.insert(u);
+        }
+    }
+
+    // gives up on coalescing the moves of `node`
+    fn freeze_moves(&mut self, node: usize) {
+        for index in self.pending_moves(node) {
+            let (x, y) = self.moves[index];
+            let other = if self.alias(y) == self.alias(node) { self.alias(x) } else { self.alias(y) };
+
+            self.move_state[index] = MoveState::Frozen;
+            if self.state[other] == NodeState::Freeze && !self.is_move_related(other) && self.degree[other] < self.k(other) {
+                self.freeze.remove(&other);
+                self.state[other] = NodeState::Simplify;
+                self.simplify.insert(other);
+            }
+        }
+    }
+
+    // picks the cheapest node to spill for the registers it would free up, optimistically hoping it still gets a color
+    fn select_spill(&mut self) {
+        let node = *self.spill.iter()
+            .min_by(|a, b| (self.cost[**a] / self.degree[**a] as f64).total_cmp(&(self.cost[**b] / self.degree[**b] as f64)))
+            .unwrap();
+
+        self.spill.remove(&node);
+        self.state[node] = NodeState::Simplify;
+        self.simplify.insert(node);
+        self.freeze_moves(node);
+    }
+
+    fn assign_colors(&mut self) {
+        while let Some(node) = self.select.pop() {
+            let mut free = candidates(self.function, ValueId(node - PRECOLORED));
+            for neighbour in &self.neighbours[node] {
+                let neighbour = self.alias(*neighbour);
+                if matches!(self.state[neighbour], NodeState::Colored | NodeState::Precolored) {
+                    free.retain(|color| Some(*color) != self.color[neighbour]);
+                }
+            }
+
+            if free.is_empty() {
+                self.state[node] = NodeState::Spilled;
+                continue;
+            }
+
+            // a register a frozen move partner already has makes the move disappear
+            let partner = self.node_moves[node].iter()
+                .map(|index| {
+                    let (x, y) = self.moves[*index];
+                    if self.alias(x) == node { self.alias(y) } else { self.alias(x) }
+                })
+                .filter_map(|partner| self.color[partner])
+                .find(|color| free.contains(color));
+
+            self.state[node] = NodeState::Colored;
+            self.color[node] = Some(partner.unwrap_or(free[0]));
+        }
+    }
+
+    fn allocation(&self) -> Allocation {
+        let mut locations = vec![None; self.function.values.len()];
+        let mut slots: Vec<Option<usize>> = vec![None; self.state.len()];
+        let mut spill_slots = 0;
+
+        for (value, location) in locations.iter_mut().enumerate() {
+            let node = self.alias(node(ValueId(value)));
+
+            *location = match self.state[node] {
+                NodeState::Colored | NodeState::Precolored => self.color[node],
+                NodeState::Spilled => {
+                    // spilled values share slots as long as nothing coalesced into them interferes
+                    if slots[node].is_none() {
+                        let taken: HashSet<usize> = self.edges.iter()
+                            .filter(|(u, _)| self.alias(*u) == node)
+                            .filter_map(|(_, v)| slots[self.alias(*v)])
+                            .collect();
+                        let slot = (0..).find(|slot| !taken.contains(slot)).unwrap();
+                        spill_slots = spill_slots.max(slot + 1);
+                        slots[node] = Some(slot);
+                    }
+                    slots[node].map(Location::Spill)
+                },
+                _ => None
+            };
+        }
+
+        Allocation {
+            callee_saved: used_callee_saved(&locations),
+            locations,
+            spill_slots
+        }
+    }
+}
+
+// how many loops each block is in. A loop is an edge back to a block earlier in `layout`, which is in
+// reverse postorder, together with the blocks that reach the edge without passing through its header.
+fn loop_depths(function: &Function, layout: &[BlockId]) -> HashMap<BlockId, u32> {
+    let position: HashMap<BlockId, usize> = layout.iter().enumerate().map(|(i, block)| (*block, i)).collect();
+    let mut predecessors: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+    let mut latches: Vec<(BlockId, Vec<BlockId>)> = vec![];
+
+    for block in layout {
+        for successor in function.block(*block).successors() {
+            predecessors.entry(successor).or_default().push(*block);
+
+            if position[&successor] <= position[block] {
+                match latches.iter_mut().find(|(header, _)| *header == successor) {
+                    Some((_, blocks)) => blocks.push(*block),
+                    None => latches.push((successor, vec![*block]))
+                }
+            }
+        }
+    }
+
+    let mut depths: HashMap<BlockId, u32> = layout.iter().map(|block| (*block, 0)).collect();
+    for (header, blocks) in latches {
+        let mut body = HashSet::from([header]);
+        let mut stack = blocks;
+
+        while let Some(block) = stack.pop() {
+            if body.insert(block) {
+                stack.extend(predecessors.get(&block).into_iter().flatten());
+            }
+        }
+
+        for block in body {
+            *depths.get_mut(&block).unwrap() += 1;
+        }
+    }
+
+    depths
+}
@@ -0,0 +1,105 @@
+use crate::codegen::CodegenOptions;
+use crate::codegen::liveness::{Liveness, Point};
+use crate::codegen::x64::asm::{Mem, Reg};
+use crate::codegen::x64::regalloc::Allocation;
+use crate::codegen::x64_elf::{ArgLocation, arg_locations};
+use crate::ir::{Function, Instruction};
+
+// how much of the stack below rsp System V leaves alone for leaf functions
+const RED_ZONE: usize = 128;
+
+// where a function keeps things on the stack. Offsets are from the canonical frame address,
+// the 16-byte aligned rsp from before the call pushed the return address, so stack arguments
+// are at 0, 8, ... and everything the function owns is below -8.
+//
+//   cfa - 8        return address
+//   cfa - 16       the caller's rbp, with a frame pointer
+//   ...            callee-saved registers, pushed in `saved` order
+//   ...            spill slots, then stack slots
+//   rsp + ...      outgoing stack arguments, at the bottom so calls don't move rsp
+#[derive(Default)]
+pub(crate) struct Frame {
+    // whether rbp points at the saved rbp, otherwise everything is addressed from rsp
+    pub(crate) frame_pointer: bool,
+    pub(crate) saved: Vec<Reg>,
+    // how far the prologue lowers rsp after its pushes, 0 when a leaf function fits in the red zone
+    pub(crate) size: usize,
+    pub(crate) spill_slots: Vec<i32>,
+    pub(crate) stack_slots: Vec<i32>,
+    // bytes from the return address down to rsp after the pushes
+    pushed: usize
+}
+
+impl Frame {
+    // lays out `function`'s frame, failing with the size of frames too big for 32-bit displacements
+    pub(crate) fn layout(function: &Function, liveness: &Liveness, allocation: &Allocation, options: &CodegenOptions) -> Result<Frame, usize> {
+        let mut pushed = 8;
+        if options.frame_pointer {
+            pushed += 8;
+        }
+        pushed += allocation.callee_saved.len() * 8;
+
+        let mut used = pushed;
+        let spill_slots: Vec<usize> = (0..allocation.spill_slots).map(|_| {
+            used += 8;
+            used
+        }).collect();
+
+        let stack_slots: Vec<usize> = function.stack_slots.iter().map(|slot| {
+            used = (used + slot.ty.size()).next_multiple_of(slot.align.max(slot.ty.align()));
+            used
+        }).collect();
+
+        // stack arguments of the call passing the most of them
+        let mut leaf = true;
+        let mut outgoing = 0;
+        for point in &liveness.points {
+            match point {
+                Point::Instruction(Instruction::Call { args, .. }) => {
+                    let types: Vec<_> = args.iter().map(|arg| function.value_type(arg)).collect();
+                    let stack_args = arg_locations(&types).iter().filter(|location| matches!(location, ArgLocation::Stack)).count();
+                    outgoing = outgoing.max(stack_args * 8);
+                    leaf = false;
+                },
+                // inline assembly might call something
+                Point::Instruction(Instruction::Asm(_)) => leaf = false,
+                _ => ()
+            }
+        }
+
+        // calls need rsp 16-byte aligned, which the cfa is
+        let locals = used - pushed;
+        let size = if leaf && options.red_zone && locals <= RED_ZONE {
+            0
+        } else {
+            (used + outgoing).next_multiple_of(16) - pushed
+        };
+
+        if pushed + size > i32::MAX as usize {
+            return Err(pushed + size);
+        }
+
+        Ok(Frame {
+            frame_pointer: options.frame_pointer,
+            saved: allocation.callee_saved.clone(),
+            size,
+            spill_slots: spill_slots.into_iter().map(|used| -(used as i32)).collect(),
+            stack_slots: stack_slots.into_iter().map(|used| -(used as i32)).collect(),
+            pushed
+        })
+    }
+
+    // the memory at `offset` from the cfa
+    pub(crate) fn mem(&self, offset: i32) -> Mem {
+        if self.frame_pointer {
+            Mem::base(Reg::Rbp, offset + 16)
+        } else {
+            Mem::base(Reg::Rsp, offset + (self.pushed + self.size) as i32)
+        }
+    }
+
+    // where the `index`th stack argument of a call goes
+    pub(crate) fn outgoing(&self, index: usize) -> Mem {
+        Mem::base(Reg::Rsp, 8 * index as i32)
+    }
+}
@@ -0,0 +1,92 @@
+use crate::codegen::liveness::Liveness;
+use crate::codegen::x64::regalloc::{Allocation, Location, RegSet, blocked, candidates, clobbers, hints, reg_bit, used_callee_saved};
+use crate::ir::{Function, ValueId};
+
+// hands out spill slots, reusing those of values that died before the next one is born
+struct SpillSlots {
+    count: usize,
+    // slots nothing uses anymore, with the position their last value died at
+    free: Vec<(usize, usize)>
+}
+
+impl SpillSlots {
+    fn take(&mut self, start: usize) -> usize {
+        match self.free.iter().position(|(_, died)| *died < start) {
+            Some(index) => self.free.swap_remove(index).0,
+            None => {
+                self.count += 1;
+                self.count - 1
+            }
+        }
+    }
+}
+
+// Poletto and Sarkar's linear scan: walks the live intervals in order of their start, giving each
+// a free register and spilling whichever live value ends last when there isn't one
+pub(crate) fn linear_scan(function: &Function, liveness: &Liveness) -> Allocation {
+    let intervals = &liveness.intervals;
+    let clobbers: Vec<RegSet> = liveness.points.iter().map(clobbers).collect();
+    let hints = hints(function, liveness);
+
+    let mut order: Vec<ValueId> = (0..function.values.len()).filter(|value| intervals[*value].is_some()).map(ValueId).collect();
+    order.sort_by_key(|value| intervals[value.0].unwrap().start);
+
+    let mut locations = vec![None; function.values.len()];
+    let mut slots = SpillSlots { count: 0, free: vec![] };
+    // values that are still live, in registers and in spill slots
+    let mut active: Vec<ValueId> = vec![];
+    let mut spilled: Vec<ValueId> = vec![];
+
+    for value in order {
+        let interval = intervals[value.0].unwrap();
+        let end = |value: &ValueId| intervals[value.0].unwrap().end;
+
+        active.retain(|live| end(live) >= interval.start);
+        spilled.retain(|live| {
+            if end(live) >= interval.start {
+                return true;
+            }
+            if let Some(Location::Spill(slot)) = locations[live.0] {
+                slots.free.push((slot, end(live)));
+            }
+            false
+        });
+
+        let blocked = blocked(&interval, &clobbers);
+        let candidates: Vec<Location> = candidates(function, value).into_iter().filter(|location| reg_bit(*location) & blocked == 0).collect();
+        let used = active.iter().fold(0, |used, live| used | reg_bit(locations[live.0].unwrap()));
+        let free: Vec<Location> = candidates.iter().copied().filter(|location| reg_bit(*location) & used == 0).collect();
+
+        if let Some(location) = hints[value.0].filter(|hint| free.contains(hint)).or(free.first().copied()) {
+            locations[value.0] = Some(location);
+            active.push(value);
+            continue;
+        }
+
+        // a value that lives longer in a register this one could use gives it up
+        let victim = active.iter().copied()
+            .filter(|live| candidates.contains(&locations[live.0].unwrap()))
+            .max_by_key(end)
+            .filter(|live| end(live) > interval.end);
+
+        let spill = match victim {
+            Some(victim) => {
+                locations[value.0] = locations[victim.0];
+                active.retain(|live| *live != victim);
+                active.push(value);
+                victim
+            },
+            None => value
+        };
+
+        let slot = slots.take(intervals[spill.0].unwrap().start);
+        locations[spill.0] = Some(Location::Spill(slot));
+        spilled.push(spill);
+    }
+
+    Allocation {
+        callee_saved: used_callee_saved(&locations),
+        locations,
+        spill_slots: slots.count
+    }
+}
@@ -0,0 +1,5 @@
+pub mod asm;
+pub(crate) mod coloring;
+pub(crate) mod frame;
+pub(crate) mod linear_scan;
+pub(crate) mod regalloc;
@@ -0,0 +1,128 @@
+use crate::codegen::liveness::{Interval, Liveness, Point};
+use crate::codegen::x64::asm::{Reg, Xmm};
+use crate::codegen::x64_elf::{ArgLocation, arg_locations};
+use crate::ir::{BinaryOp, Function, Instruction, Terminator, Value, ValueId};
+
+// where a value lives for its whole lifetime
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Location {
+    Reg(Reg),
+    Xmm(Xmm),
+    // an 8-byte slot in the frame's spill area
+    Spill(usize)
+}
+
+pub(crate) struct Allocation {
+    // None for values that are never defined
+    pub(crate) locations: Vec<Option<Location>>,
+    pub(crate) spill_slots: usize,
+    // the callee-saved registers the function writes, which it has to restore before returning
+    pub(crate) callee_saved: Vec<Reg>
+}
+
+// rax and r11 are the code generator's scratch registers and xmm15 its float scratch, so none of
+// them are handed out. Caller-saved registers come first so leaf functions rarely save anything.
+pub(crate) const GPRS: [Reg; 12] = [
+    Reg::Rcx, Reg::Rdx, Reg::Rsi, Reg::Rdi, Reg::R8, Reg::R9, Reg::R10,
+    Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15
+];
+pub(crate) const CALLEE_SAVED: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+pub(crate) const XMMS: [Xmm; 15] = [
+    Xmm::Xmm0, Xmm::Xmm1, Xmm::Xmm2, Xmm::Xmm3, Xmm::Xmm4, Xmm::Xmm5, Xmm::Xmm6, Xmm::Xmm7,
+    Xmm::Xmm8, Xmm::Xmm9, Xmm::Xmm10, Xmm::Xmm11, Xmm::Xmm12, Xmm::Xmm13, Xmm::Xmm14
+];
+
+// a set of registers, general purpose ones in the low 16 bits and xmms in the high 16
+pub(crate) type RegSet = u32;
+
+pub(crate) fn reg_bit(location: Location) -> RegSet {
+    match location {
+        Location::Reg(reg) => 1 << reg.number(),
+        Location::Xmm(xmm) => 1 << (16 + xmm.number()),
+        Location::Spill(_) => 0
+    }
+}
+
+// everything System V lets a callee overwrite
+const CALLER_SAVED: RegSet = 0xFFFF_0000 | 1 << Reg::Rax as u8 | 1 << Reg::Rcx as u8 | 1 << Reg::Rdx as u8
+    | 1 << Reg::Rsi as u8 | 1 << Reg::Rdi as u8 | 1 << Reg::R8 as u8 | 1 << Reg::R9 as u8 | 1 << Reg::R10 as u8 | 1 << Reg::R11 as u8;
+
+// the registers a point overwrites besides the scratch ones, so values live across it can't be in them
+pub(crate) fn clobbers(point: &Point) -> RegSet {
+    let Point::Instruction(instruction) = point else {
+        return 0;
+    };
+
+    match instruction {
+        Instruction::Call { .. } => CALLER_SAVED,
+        // the amount of a variable shift has to be in cl
+        Instruction::Binary { op: BinaryOp::Shl | BinaryOp::LShr | BinaryOp::AShr, .. } => reg_bit(Location::Reg(Reg::Rcx)),
+        // division takes its dividend in rdx:rax
+        Instruction::Binary { op: BinaryOp::SDiv | BinaryOp::UDiv | BinaryOp::SRem | BinaryOp::URem, .. } => reg_bit(Location::Reg(Reg::Rdx)),
+        // inline assembly could do anything
+        Instruction::Asm(_) => RegSet::MAX,
+        _ => 0
+    }
+}
+
+// the registers `value` mustn't be in because something clobbers them while it's live
+pub(crate) fn blocked(interval: &Interval, clobbers: &[RegSet]) -> RegSet {
+    (interval.start / 2..=interval.end / 2)
+        .filter(|point| interval.crosses(*point))
+        .fold(0, |blocked, point| blocked | clobbers[point])
+}
+
+// the registers a value of `function` can go in, in order of preference
+pub(crate) fn candidates(function: &Function, value: ValueId) -> Vec<Location> {
+    if function.values[value.0].is_float() {
+        XMMS.iter().map(|xmm| Location::Xmm(*xmm)).collect()
+    } else {
+        GPRS.iter().map(|reg| Location::Reg(*reg)).collect()
+    }
+}
+
+// the register each value would like to be in, to save moves into argument and return registers
+pub(crate) fn hints(function: &Function, liveness: &Liveness) -> Vec<Option<Location>> {
+    let mut hints = vec![None; function.values.len()];
+
+    let mut hint = |value: &Value, location: &ArgLocation| {
+        if let Value::Ref(id) = value {
+            let hint = match location {
+                ArgLocation::Int(reg) => Location::Reg(*reg),
+                ArgLocation::Sse(xmm) => Location::Xmm(*xmm),
+                ArgLocation::Stack => return
+            };
+            hints[id.0].get_or_insert(hint);
+        }
+    };
+
+    for point in &liveness.points {
+        match point {
+            Point::Entry => {
+                let types: Vec<_> = function.params.iter().map(|param| function.values[param.0].clone()).collect();
+                for (param, location) in function.params.iter().zip(arg_locations(&types)) {
+                    hint(&Value::Ref(*param), &location);
+                }
+            },
+            Point::Instruction(Instruction::Call { args, .. }) => {
+                let types: Vec<_> = args.iter().map(|arg| function.value_type(arg)).collect();
+                for (arg, location) in args.iter().zip(arg_locations(&types)) {
+                    hint(arg, &location);
+                }
+            },
+            Point::Terminator(Terminator::Return(Some(value))) if function.value_type(value).is_float() => {
+                hint(value, &ArgLocation::Sse(Xmm::Xmm0));
+            },
+            _ => ()
+        }
+    }
+
+    hints
+}
+
+// the callee-saved registers `locations` uses
+pub(crate) fn used_callee_saved(locations: &[Option<Location>]) -> Vec<Reg> {
+    CALLEE_SAVED.iter().copied()
+        .filter(|reg| locations.contains(&Some(Location::Reg(*reg))))
+        .collect()
+}
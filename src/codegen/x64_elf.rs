@@ -1,321 +1,1232 @@
-use std::collections::HashMap;
-use crate::codegen::Codegen;
-use crate::ir::{Block, Function, Instruction, Terminator, TranslationUnit, Value};
-use crate::outputs::elf::{ElfFile, ElfHeader, ElfRelocation, ElfSectionHeader, ElfSymbol};
-use crate::outputs::serialization::Serializable;
-
-enum Section {
-    Text,
-    Rodata
-}
-
-struct Relocation {
-    src_symbol: usize,
-    dst_section: Section,
-    dst_offset: usize
-}
-
-struct Symbol {
-    section: Section,
-    offset: usize,
-    size: usize,
-    name: Option<String>,
-    elf_type: u8
-}
-
-pub struct CompilerX64Elf {
-    pub(crate) text: Vec<u8>,
-    pub(crate) rodata: Vec<u8>,
-    pub(crate) relocations: Vec<Relocation>,
-    pub(crate) symbols: Vec<Symbol>
-}
-
-impl CompilerX64Elf {
-
-    pub fn new() -> CompilerX64Elf {
-        CompilerX64Elf { 
-            text: vec![],
-            rodata: vec![],
-            relocations: vec![],
-            symbols: vec![]
-        }
-    }
-
-    fn compile_block(&mut self, block: &Block) {
-        for instr in block.instructions.iter() {
-            self.compile_instruction(instr);
-        }
-
-        self.compile_terminator(block.terminator.clone().expect("Attempt to compile block with no terminator"));
-
-    }
-
-    fn compile_terminator(&mut self, terminator: Terminator) {
-        match terminator {
-            Terminator::Return => {
-                self.text.extend(vec![0xC3]);
-            },
-            Terminator::Jump(_) => {
-                let vec: Vec<u8> = Vec::new();
-                self.text.extend(vec);
-            },
-        }
-    }
-
-    fn compile_instruction(&mut self, instruction: &Instruction) {
-        match instruction {
-            Instruction::Asm(x) => {
-                self.text.extend(x)
-            },
-
-            Instruction::AsmValue(val) => {
-                self.compile_value(val)
-            }
-        }
-    }
-
-    fn compile_function(&mut self, function: &Function) {
-        self.compile_block(&*function.start_block)
-    }
-
-    fn compile_value(&mut self, value: &Value) {
-        match value {
-            Value::Const(val) => {
-                self.text.extend(val.serialize(false))
-            },
-            Value::ConstRef(val) => {
-                self.symbols.push(Symbol {
-                    section: Section::Rodata,
-                    size: 8,
-                    offset: self.rodata.len(),
-                    name: None,
-                    elf_type: 1
-                });
-
-                self.relocations.push(Relocation {
-                    src_symbol: self.symbols.len(),
-                    dst_section: Section::Text,
-                    dst_offset: self.text.len()
-                });
-
-                self.rodata.extend(val.serialize(false));
-                self.text.extend(vec![0, 0, 0, 0, 0, 0, 0, 0]);
-            }
-        }
-    }
-}
-
-impl Codegen for CompilerX64Elf {
-    type OutputFormat = ElfFile;
-    fn compile_translation_unit(&mut self, translation_unit: TranslationUnit) -> ElfFile {
-        for (name, func) in &translation_unit.functions {
-
-            let function_start = self.text.len();
-            self.compile_function(&func);
-
-            self.symbols.push(Symbol {
-                offset: function_start,
-                elf_type: 2,
-                size: self.text.len() - function_start,
-                section: Section::Text,
-                name: Some(name.to_string())
-            })
-        }
-
-        let section_names: Vec<String> = vec!["".to_owned(), ".text".to_owned(), ".shstrtab".to_owned(), ".symtab".to_owned(), ".strtab".to_owned(), ".rodata".to_owned(), ".rel.text".to_owned()];
-
-        let mut elf = ElfFile {
-            elf_header: ElfHeader {
-                e_ident_magic: [0x7F, 0x45, 0x4c, 0x46],
-                e_ident_class: 2,
-                e_ident_data: 1,
-                e_ident_version: 1,
-                e_ident_abi: 3,
-                e_ident_abi_version: 67,
-                e_ident_pad: [0,0,0,0,0,0,0],
-                e_type: 1,
-                e_machine: 0x3E,
-                e_version: 1,
-                e_entry: 0,
-                e_phoff: 0,
-                e_shoff: 0x40,
-                e_flags: 0,
-                e_ehsize: 0x40,
-                e_phentsize: 0x38,
-                e_phnum: 0,
-                e_shentsize: 0x40,
-                e_shnum: 7,
-                e_shstrndx: 2
-            },
-            elf_program_headers: vec![],
-            elf_section_headers: vec![
-                ElfSectionHeader {
-                    sh_name: 0,
-                    sh_type: 0,
-                    sh_flags: 0,
-                    sh_addr: 0,
-                    sh_offset: 0,
-                    sh_size: 0,
-                    sh_link: 0,
-                    sh_info: 0,
-                    sh_addralign: 0,
-                    sh_entsize: 0
-                },
-                ElfSectionHeader {
-                    sh_name: 1,
-                    sh_type: 1,
-                    sh_flags: 2 | 4,
-                    //sh_addr: 0x40000000,
-                    sh_addr: 0,
-                    sh_offset: 0x80,
-                    sh_size: self.text.len() as u64,
-                    sh_link: 0,
-                    sh_info: 0,
-                    sh_addralign: 0,
-                    sh_entsize: 0
-                },
-                ElfSectionHeader {
-                    sh_name: 7,
-                    sh_type: 3,
-                    sh_flags: 0x20,
-                    sh_addr: 0,
-                    sh_size: section_names.iter().fold(0, |acc, x|
-                        acc + (x.to_string().serialized_length() as u64)
-                    ),
-                    sh_info: 0,
-                    sh_link: 0,
-                    sh_offset: 0,
-                    sh_addralign: 0,
-                    sh_entsize: 0
-                },
-                ElfSectionHeader {
-                    sh_name: 17,
-                    sh_type: 2,
-                    sh_flags: 0,
-                    sh_addr: 0,
-                    sh_offset: 0,
-                    sh_entsize: 0x18,
-                    sh_link: 4,
-                    sh_size: 0,
-                    sh_info: 1,
-                    sh_addralign: 0,
-                },
-                ElfSectionHeader {
-                    sh_name: 25,
-                    sh_type: 3,
-                    sh_flags: 0x20,
-                    sh_addr: 0,
-                    sh_size: 0,
-                    sh_info: 0,
-                    sh_link: 0,
-                    sh_entsize: 0,
-                    sh_addralign: 0,
-                    sh_offset: 0
-                },
-                ElfSectionHeader {
-                    sh_name: 33,
-                    sh_type: 1,
-                    sh_flags: 2,
-                    sh_addr: 0,
-                    sh_addralign: 0,
-                    sh_link: 0,
-                    sh_info: 0,
-                    sh_size: self.rodata.len() as u64,
-                    sh_offset: 0,
-                    sh_entsize: 0,
-                },
-                ElfSectionHeader {
-                    sh_name: 41,
-                    sh_type: 9,
-                    sh_flags: 0,
-                    sh_addr: 0,
-                    sh_offset: 0,
-                    sh_size: self.relocations.len() as u64 * 16,
-                    sh_link: 3,
-                    sh_info: 1,
-                    sh_addralign: 0,
-                    sh_entsize: 16,
-                }
-            ],
-            data: vec![]
-        };
-
-        let header_len = elf.serialized_length();
-        elf.elf_section_headers[1].sh_offset = header_len as u64;
-
-        elf.data.extend_from_slice(&self.text);
-
-        let header_text_len = elf.serialized_length();
-
-        elf.data.extend(section_names.iter().flat_map(|section_name| {section_name.to_string().serialize(false)}));
-        elf.elf_section_headers[2].sh_offset = header_text_len as u64;
-
-        let symbol_table_start = elf.serialized_length();
-
-        elf.elf_section_headers[3].sh_offset = symbol_table_start as u64;
-        let mut symbol_table: Vec<ElfSymbol> = vec![
-            ElfSymbol {
-                st_name: 0,
-                st_info: 0,
-                st_other: 0,
-                st_size: 0,
-                st_shndx: 0,
-                st_value: 0
-            }
-        ];
-        let mut symbol_table_names: Vec<String> = vec!["".to_owned()];
-
-        for sym in self.symbols.iter() {
-            let mut name_idx = 0;
-
-            let shndx = match sym.section {
-                Section::Text => 1,
-                Section::Rodata => 5
-            };
-
-            match &sym.name {
-                Some(x) => {
-                    name_idx = symbol_table_names.serialized_length();
-                    symbol_table_names.push(x.to_string());
-                }
-                _ => (),
-            }
-
-            let symbol = ElfSymbol {
-                st_name: name_idx as u32,
-                st_info: (1<<4)+(sym.elf_type&0xf),
-                st_other: 0,
-                st_shndx: shndx,
-                st_size: sym.size as u64,
-                st_value: sym.offset as u64
-            };
-
-            symbol_table.push(symbol);
-        }
-
-        elf.elf_section_headers[3].sh_size = symbol_table.iter().fold(0, |acc, x|
-            acc + (x.serialized_length() as u64));
-
-        elf.data.extend(symbol_table.iter().flat_map(|symbol| {symbol.serialize(false)}));
-
-        elf.elf_section_headers[4].sh_offset = elf.serialized_length() as u64;
-        elf.elf_section_headers[4].sh_size = symbol_table_names.iter().fold(0, |acc, x| acc + (x.to_string().serialized_length() as u64));
-
-        elf.data.extend(symbol_table_names.iter().flat_map(|symbol_name| {symbol_name.to_string().serialize(false)}));
-        elf.elf_section_headers[5].sh_offset = elf.serialized_length() as u64;
-        elf.data.extend_from_slice(&self.rodata);
-
-        elf.elf_section_headers[6].sh_offset = elf.serialized_length() as u64;
-
-        elf.data.extend(
-            self.relocations.iter().map(|reloc| {ElfRelocation{
-                r_offset: reloc.dst_offset as u64,
-                r_info: ((reloc.src_symbol<<32) + (1&0xffffffff)) as u64
-            }}).collect::<Vec<ElfRelocation>>().serialize(false)
-        );
-
-
-
-        elf
-    }
-}
+use std::collections::HashMap;
+use crate::codegen::{Addressing, Codegen, CodegenOptions, RegisterAllocator};
+use crate::codegen::liveness::Liveness;
+use crate::codegen::x64::asm::{AluOp, Cond, Fixup, FixupKind, Imm64, Mem, Operand, Reg, ShiftOp, Size, Target, X64Inst, Xmm, XmmOperand};
+use crate::codegen::x64::coloring::graph_coloring;
+use crate::codegen::x64::frame::Frame;
+use crate::codegen::x64::linear_scan::linear_scan;
+use crate::codegen::x64::regalloc::Location;
+use crate::error::{ChairError, ErrorContext};
+use crate::ir::{BinaryOp, Block, BlockId, CastOp, ConstValue, Function, Global, Instruction, IntCondition, Terminator, TranslationUnit, Value, ValueId};
+use crate::ir::types::Type;
+use crate::ir::verify::verify_translation_unit;
+use crate::outputs::elf::{ElfFile, ElfObjectBuilder, ElfSymbolId, EM_X86_64, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, R_X86_64_32S, R_X86_64_64, R_X86_64_GOTPCREL, R_X86_64_PC32, R_X86_64_PLT32, R_X86_64_REX_GOTPCRELX, SHT_PROGBITS, STB_GLOBAL, STB_LOCAL, STT_FUNC, STT_NOTYPE, STT_OBJECT};
+use crate::outputs::serialization::Serializable;
+
+pub(crate) enum Section {
+    Text,
+    Rodata,
+    Data,
+    Bss,
+    Undefined
+}
+
+// a reference from `section` at `offset` to an entry in `symbols`
+pub(crate) struct Relocation {
+    symbol: usize,
+    section: Section,
+    offset: usize,
+    kind: u32,
+    addend: i64
+}
+
+const TARGET: &str = "x86_64-elf";
+
+// a rel32 displacement in .text that must point at the start of a block
+pub(crate) struct BlockFixup {
+    offset: usize,
+    target: BlockId,
+    addend: i64
+}
+
+#[derive(Clone, Copy)]
+enum Extend {
+    Sign,
+    Zero
+}
+
+const RAX: Reg = Reg::Rax;
+const RCX: Reg = Reg::Rcx;
+const RDX: Reg = Reg::Rdx;
+const RBP: Reg = Reg::Rbp;
+const RSP: Reg = Reg::Rsp;
+// scratch registers the allocator never hands out, next to rax
+const R11: Reg = Reg::R11;
+const XMM_SCRATCH: Xmm = Xmm::Xmm15;
+
+// System V AMD64 argument registers
+pub(crate) const INT_ARG_REGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+const SSE_ARG_REGS: usize = 8;
+
+pub(crate) enum ArgLocation {
+    Int(Reg),
+    Sse(Xmm),
+    Stack
+}
+
+// where a value lives while its function runs
+#[derive(Clone, Copy)]
+pub(crate) enum Home {
+    Reg(Reg),
+    Xmm(Xmm),
+    // at this offset from the canonical frame address
+    Frame(i32)
+}
+
+// switches with at least this many cases that fill at least a third of their range get a jump table
+const JUMP_TABLE_MIN_CASES: usize = 4;
+const JUMP_TABLE_MIN_DENSITY: usize = 3;
+
+pub(crate) struct Symbol {
+    section: Section,
+    offset: usize,
+    size: usize,
+    name: Option<String>,
+    elf_type: u8
+}
+
+pub struct CompilerX64Elf {
+    pub(crate) options: CodegenOptions,
+    pub(crate) text: Vec<u8>,
+    pub(crate) rodata: Vec<u8>,
+    pub(crate) data: Vec<u8>,
+    pub(crate) bss_size: usize,
+    pub(crate) data_align: usize,
+    pub(crate) bss_align: usize,
+    pub(crate) relocations: Vec<Relocation>,
+    pub(crate) symbols: Vec<Symbol>,
+    pub(crate) block_offsets: HashMap<BlockId, usize>,
+    pub(crate) block_fixups: Vec<BlockFixup>,
+    pub(crate) block_symbols: Vec<(usize, BlockId)>,
+    // None for values that are never live
+    pub(crate) value_homes: Vec<Option<Home>>,
+    pub(crate) frame: Frame,
+    pub(crate) function_symbols: HashMap<String, usize>,
+    pub(crate) global_symbols: HashMap<String, usize>,
+    // rel32s in .text to functions that are in .text too, as (offset, symbol)
+    pub(crate) call_fixups: Vec<(usize, usize)>,
+    // what's being compiled, for errors
+    pub(crate) context: ErrorContext,
+    // the code in Intel syntax, when it's asked for
+    pub(crate) listing: Option<String>
+}
+
+impl CompilerX64Elf {
+
+    pub fn new() -> CompilerX64Elf {
+        CompilerX64Elf::with_options(CodegenOptions::default())
+    }
+
+    pub fn with_options(options: CodegenOptions) -> CompilerX64Elf {
+        CompilerX64Elf {
+            options,
+            text: vec![],
+            rodata: vec![],
+            data: vec![],
+            bss_size: 0,
+            data_align: 1,
+            bss_align: 1,
+            relocations: vec![],
+            symbols: vec![],
+            block_offsets: HashMap::new(),
+            block_fixups: vec![],
+            block_symbols: vec![],
+            value_homes: vec![],
+            frame: Frame::default(),
+            function_symbols: HashMap::new(),
+            global_symbols: HashMap::new(),
+            call_fixups: vec![],
+            context: ErrorContext::default(),
+            listing: None
+        }
+    }
+
+    fn invalid_ir(&self, message: String) -> ChairError {
+        ChairError::InvalidIr {
+            context: self.context.clone(),
+            message
+        }
+    }
+
+    fn unsupported(&self, feature: String) -> ChairError {
+        ChairError::Unsupported {
+            target: TARGET,
+            context: self.context.clone(),
+            feature
+        }
+    }
+
+    fn list(&mut self, line: String) {
+        if let Some(listing) = &mut self.listing {
+            listing.push_str(&line);
+            listing.push('\n');
+        }
+    }
+
+    // labels are blocks, anonymous symbols are constants and jump tables
+    fn target_name(&self, target: Target) -> String {
+        match target {
+            Target::Label(block) => format!("bb{}", block),
+            Target::Symbol(symbol) => match &self.symbols[symbol].name {
+                Some(name) => name.clone(),
+                None => format!(".L{}", symbol)
+            }
+        }
+    }
+
+    // a rel32 at `offset` to `target + addend`, usually from the end of its 4 bytes with an addend of -4
+    fn displacement(&self, offset: usize, target: usize, addend: i64, name: impl FnOnce() -> String) -> Result<i32, ChairError> {
+        let displacement = target as i64 + addend - offset as i64;
+
+        i32::try_from(displacement).map_err(|_| ChairError::RelocationOverflow {
+            context: self.context.clone(),
+            target: name(),
+            value: displacement,
+            bits: 32
+        })
+    }
+
+    fn emit(&mut self, instruction: X64Inst) {
+        self.emit_relocated(instruction, None);
+    }
+
+    // encodes `instruction` into .text, turning its fixups into block fixups, call fixups and relocations,
+    // where `relocation` replaces the kind of the relocations against symbols
+    fn emit_relocated(&mut self, instruction: X64Inst, relocation: Option<u32>) {
+        if self.listing.is_some() {
+            let suffix = if relocation == Some(R_X86_64_REX_GOTPCRELX) { "@GOTPCREL" } else { "" };
+            let line = instruction.to_intel(&|target| self.target_name(target) + suffix);
+            self.list(format!("    {}", line));
+        }
+
+        let mut fixups = vec![];
+        instruction.encode(&mut self.text, &mut fixups);
+
+        for Fixup { offset, target, kind, addend } in fixups {
+            match (target, kind, relocation) {
+                (Target::Label(block), _, _) => self.block_fixups.push(BlockFixup { offset, target: BlockId(block), addend }),
+                (Target::Symbol(symbol), _, Some(relocation)) => self.add_relocation(Section::Text, offset, symbol, relocation, addend),
+                (Target::Symbol(symbol), FixupKind::Branch32, None) if !matches!(self.symbols[symbol].section, Section::Undefined) => {
+                    self.call_fixups.push((offset, symbol));
+                },
+                (Target::Symbol(symbol), FixupKind::Branch32, None) => self.add_relocation(Section::Text, offset, symbol, R_X86_64_PLT32, addend),
+                (Target::Symbol(symbol), FixupKind::Relative32, None) => self.add_relocation(Section::Text, offset, symbol, R_X86_64_PC32, addend),
+                (Target::Symbol(symbol), FixupKind::Absolute64, None) => self.add_relocation(Section::Text, offset, symbol, R_X86_64_64, addend)
+            }
+        }
+    }
+
+    fn compile_block(&mut self, function: &Function, block: &Block, next_block: Option<BlockId>) -> Result<(), ChairError> {
+        for instr in block.instructions.iter() {
+            self.compile_instruction(function, instr)?;
+        }
+
+        let terminator = block.terminator.as_ref().ok_or_else(|| self.invalid_ir("block has no terminator".to_owned()))?;
+        self.compile_terminator(function, terminator, next_block)
+    }
+
+    fn compile_terminator(&mut self, function: &Function, terminator: &Terminator, next_block: Option<BlockId>) -> Result<(), ChairError> {
+        match terminator {
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    let ty = function.value_type(value);
+
+                    if ty.is_float() {
+                        self.load_float(function, Xmm::Xmm0, value)?;
+                    } else {
+                        self.load_value(function, RAX, value, arg_extend(&ty))?;
+                    }
+                }
+
+                self.emit_epilogue();
+            },
+            Terminator::Jump(target) => {
+                self.emit_jump(*target, next_block);
+            },
+            Terminator::Branch { cond, if_true, if_false } => {
+                self.load_value(function, RAX, cond, Extend::Zero)?;
+                self.emit(X64Inst::Test { size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Reg(RAX) });
+
+                if next_block == Some(*if_true) {
+                    self.emit(X64Inst::Jcc { cond: Cond::Equal, target: block_label(*if_false) });
+                } else {
+                    self.emit(X64Inst::Jcc { cond: Cond::NotEqual, target: block_label(*if_true) });
+                    self.emit_jump(*if_false, next_block);
+                }
+            },
+            Terminator::Switch { value, cases, default } => {
+                self.load_value(function, RAX, value, Extend::Sign)?;
+
+                let bits = function.value_type(value).bits();
+                let cases: Vec<(i64, BlockId)> = cases.iter().map(|(case, target)| {
+                    (extend_const(*case, bits, Extend::Sign), *target)
+                }).collect();
+
+                if Self::use_jump_table(&cases) {
+                    self.compile_jump_table(&cases, *default);
+                } else {
+                    for (case, target) in cases {
+                        self.emit_cmp_rax(case);
+                        self.emit(X64Inst::Jcc { cond: Cond::Equal, target: block_label(target) });
+                    }
+                    self.emit_jump(*default, next_block);
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn use_jump_table(cases: &[(i64, BlockId)]) -> bool {
+        if cases.len() < JUMP_TABLE_MIN_CASES {
+            return false;
+        }
+
+        let min = cases.iter().map(|(case, _)| *case).min().unwrap();
+        let max = cases.iter().map(|(case, _)| *case).max().unwrap();
+
+        match (max as i128 - min as i128 + 1).try_into() {
+            Ok(range) => {
+                let range: usize = range;
+                range <= cases.len() * JUMP_TABLE_MIN_DENSITY
+            },
+            Err(_) => false
+        }
+    }
+
+    // expects the switch value in rax
+    fn compile_jump_table(&mut self, cases: &[(i64, BlockId)], default: BlockId) {
+        let min = cases.iter().map(|(case, _)| *case).min().unwrap();
+        let max = cases.iter().map(|(case, _)| *case).max().unwrap();
+        let range = (max - min + 1) as usize;
+
+        if min != 0 {
+            self.emit(X64Inst::MovAbs { dst: R11, src: Imm64::Value(min) });
+            self.emit(X64Inst::Alu { op: AluOp::Sub, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Reg(R11) });
+        }
+
+        self.emit_cmp_rax(range as i64 - 1);
+        self.emit(X64Inst::Jcc { cond: Cond::Above, target: block_label(default) });
+
+        // rip-relative tables hold each target's distance from the table, so they don't need relocating at load time
+        let entry_size = match self.options.addressing {
+            Addressing::RipRelative | Addressing::Got => 4,
+            Addressing::Absolute => 8
+        };
+
+        self.rodata.resize(self.rodata.len().next_multiple_of(entry_size), 0);
+        let table_start = self.rodata.len();
+        let mut entries = vec![default; range];
+        for (case, target) in cases.iter().rev() {
+            entries[(*case - min) as usize] = *target;
+        }
+
+        for (index, target) in entries.into_iter().enumerate() {
+            self.symbols.push(Symbol {
+                section: Section::Text,
+                size: 0,
+                offset: 0,
+                name: None,
+                elf_type: STT_NOTYPE
+            });
+            self.block_symbols.push((self.symbols.len() - 1, target));
+
+            match self.options.addressing {
+                Addressing::RipRelative | Addressing::Got => self.emit_relocation(Section::Rodata, self.symbols.len() - 1, R_X86_64_PC32, (index * 4) as i64),
+                Addressing::Absolute => self.emit_relocation(Section::Rodata, self.symbols.len() - 1, R_X86_64_64, 0)
+            }
+        }
+
+        self.symbols.push(Symbol {
+            section: Section::Rodata,
+            size: range * entry_size,
+            offset: table_start,
+            name: None,
+            elf_type: STT_OBJECT
+        });
+
+        self.emit_address(R11, self.symbols.len() - 1);
+        match self.options.addressing {
+            Addressing::RipRelative | Addressing::Got => {
+                self.emit(X64Inst::Movsx { size: Size::Qword, src_size: Size::Dword, dst: RAX, src: Operand::Mem(Mem::indexed(R11, RAX, 4, 0)) });
+                self.emit(X64Inst::Alu { op: AluOp::Add, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Reg(R11) });
+                self.emit(X64Inst::JmpIndirect(Operand::Reg(RAX)));
+            },
+            Addressing::Absolute => self.emit(X64Inst::JmpIndirect(Operand::Mem(Mem::indexed(R11, RAX, 8, 0))))
+        }
+    }
+
+    // puts the address of `symbols[symbol]` in `reg`
+    fn emit_address(&mut self, reg: Reg, symbol: usize) {
+        let target = Target::Symbol(symbol);
+        // anonymous symbols are local, so only named ones can be preempted
+        let exported = self.symbols[symbol].name.is_some();
+
+        match self.options.addressing {
+            Addressing::Got if exported => {
+                // linkers relax this back into a lea when the symbol turns out to be defined in the same module
+                let load = X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(reg), src: Operand::Mem(Mem::rip(target)) };
+                self.emit_relocated(load, Some(R_X86_64_REX_GOTPCRELX));
+            },
+            Addressing::RipRelative | Addressing::Got => self.emit(X64Inst::Lea { dst: reg, src: Mem::rip(target) }),
+            Addressing::Absolute => self.emit(X64Inst::MovAbs { dst: reg, src: Imm64::Address { target, addend: 0 } })
+        }
+    }
+
+    fn emit_jump(&mut self, target: BlockId, next_block: Option<BlockId>) {
+        if next_block != Some(target) {
+            self.emit(X64Inst::Jmp(block_label(target)));
+        }
+    }
+
+    fn emit_cmp_rax(&mut self, imm: i64) {
+        if i32::try_from(imm).is_ok() {
+            self.emit(X64Inst::Alu { op: AluOp::Cmp, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Imm(imm) });
+        } else {
+            self.emit(X64Inst::MovAbs { dst: R11, src: Imm64::Value(imm) });
+            self.emit(X64Inst::Alu { op: AluOp::Cmp, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Reg(R11) });
+        }
+    }
+
+    fn home(&self, id: ValueId) -> Result<Home, ChairError> {
+        self.value_homes[id.0].ok_or_else(|| self.invalid_ir(format!("%{} is used but never defined", id.0)))
+    }
+
+    // loads `value` into `reg`, extended from its type to the full 64 bits
+    fn load_value(&mut self, function: &Function, reg: Reg, value: &Value, extend: Extend) -> Result<(), ChairError> {
+        let bits = function.value_type(value).bits();
+
+        match value {
+            Value::Const(val) => {
+                let num = val.as_i64().ok_or_else(|| self.unsupported(format!("loading a {} constant into a register", val.get_type())))?;
+                self.emit(mov_imm(reg, extend_const(num, bits, extend)));
+            },
+            Value::ConstRef(val) => {
+                let symbol = self.add_constant(val);
+                self.emit_address(reg, symbol);
+            },
+            Value::Ref(id) => match self.home(*id)? {
+                Home::Reg(src) => {
+                    if src != reg {
+                        self.emit(X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(reg), src: Operand::Reg(src) });
+                    }
+                    self.emit_extend(reg, bits, extend);
+                },
+                Home::Xmm(src) => {
+                    self.emit(X64Inst::MovFromXmm { dst: reg, src });
+                    self.emit_extend(reg, bits, extend);
+                },
+                Home::Frame(offset) => {
+                    let slot = Operand::Mem(self.frame.mem(offset));
+
+                    let instruction = match (bits, extend) {
+                        (1 | 8, Extend::Zero) => X64Inst::Movzx { src_size: Size::Byte, dst: reg, src: slot },
+                        (1 | 8, Extend::Sign) => X64Inst::Movsx { size: Size::Qword, src_size: Size::Byte, dst: reg, src: slot },
+                        (16, Extend::Zero) => X64Inst::Movzx { src_size: Size::Word, dst: reg, src: slot },
+                        (16, Extend::Sign) => X64Inst::Movsx { size: Size::Qword, src_size: Size::Word, dst: reg, src: slot },
+                        (32, Extend::Zero) => X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(reg), src: slot },
+                        (32, Extend::Sign) => X64Inst::Movsx { size: Size::Qword, src_size: Size::Dword, dst: reg, src: slot },
+                        (64, _) => X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(reg), src: slot },
+                        _ => return Err(self.unsupported(format!("loading a {} into a register", function.value_type(value))))
+                    };
+                    self.emit(instruction);
+
+                    // an i1 is only guaranteed to have its lowest bit set correctly
+                    if bits == 1 {
+                        self.emit_extend(reg, bits, extend);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // values in registers only have their low bits set, like values in memory
+    fn store_value(&mut self, function: &Function, reg: Reg, id: ValueId) -> Result<(), ChairError> {
+        let Some(home) = self.value_homes[id.0] else {
+            return Ok(());
+        };
+
+        match home {
+            Home::Reg(dst) if dst != reg => self.emit(X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(dst), src: Operand::Reg(reg) }),
+            Home::Reg(_) => (),
+            Home::Xmm(dst) => self.emit(X64Inst::MovToXmm { dst, src: reg }),
+            Home::Frame(offset) => {
+                let size = match function.values[id.0].size() {
+                    1 => Size::Byte,
+                    2 => Size::Word,
+                    4 => Size::Dword,
+                    8 => Size::Qword,
+                    _ => return Err(self.unsupported(format!("storing a {} from a register", function.values[id.0])))
+                };
+
+                self.emit(X64Inst::Mov { size, dst: Operand::Mem(self.frame.mem(offset)), src: Operand::Reg(reg) });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_float(&mut self, function: &Function, xmm: Xmm, value: &Value) -> Result<(), ChairError> {
+        match value {
+            Value::Ref(id) => match self.home(*id)? {
+                Home::Xmm(src) if src != xmm => self.emit(X64Inst::Movaps { dst: xmm, src }),
+                Home::Xmm(_) => (),
+                Home::Reg(src) => self.emit(X64Inst::MovToXmm { dst: xmm, src }),
+                Home::Frame(offset) => {
+                    let double = function.values[id.0] != Type::F32;
+                    self.emit(X64Inst::MovFloat { double, dst: XmmOperand::Xmm(xmm), src: XmmOperand::Mem(self.frame.mem(offset)) });
+                }
+            },
+            _ => {
+                self.load_value(function, RAX, value, Extend::Zero)?;
+                self.emit(X64Inst::MovToXmm { dst: xmm, src: RAX });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn store_float(&mut self, function: &Function, xmm: Xmm, id: ValueId) {
+        match self.value_homes[id.0] {
+            Some(Home::Xmm(dst)) if dst != xmm => self.emit(X64Inst::Movaps { dst, src: xmm }),
+            Some(Home::Reg(dst)) => self.emit(X64Inst::MovFromXmm { dst, src: xmm }),
+            Some(Home::Frame(offset)) => {
+                let double = function.values[id.0] != Type::F32;
+                self.emit(X64Inst::MovFloat { double, dst: XmmOperand::Mem(self.frame.mem(offset)), src: XmmOperand::Xmm(xmm) });
+            },
+            Some(Home::Xmm(_)) | None => ()
+        }
+    }
+
+    // extends the low `bits` of `reg` over the rest of it
+    fn emit_extend(&mut self, reg: Reg, bits: u32, extend: Extend) {
+        let src = Operand::Reg(reg);
+
+        match (bits, extend) {
+            (1, _) => {
+                self.emit(X64Inst::Alu { op: AluOp::And, size: Size::Dword, dst: src, src: Operand::Imm(1) });
+
+                if let Extend::Sign = extend {
+                    self.emit(X64Inst::Neg { size: Size::Qword, dst: src });
+                }
+            },
+            (8, Extend::Zero) => self.emit(X64Inst::Movzx { src_size: Size::Byte, dst: reg, src }),
+            (8, Extend::Sign) => self.emit(X64Inst::Movsx { size: Size::Qword, src_size: Size::Byte, dst: reg, src }),
+            (16, Extend::Zero) => self.emit(X64Inst::Movzx { src_size: Size::Word, dst: reg, src }),
+            (16, Extend::Sign) => self.emit(X64Inst::Movsx { size: Size::Qword, src_size: Size::Word, dst: reg, src }),
+            (32, Extend::Zero) => self.emit(X64Inst::Mov { size: Size::Dword, dst: src, src }),
+            (32, Extend::Sign) => self.emit(X64Inst::Movsx { size: Size::Qword, src_size: Size::Dword, dst: reg, src }),
+            _ => ()
+        }
+    }
+
+    // copies between registers as if all the copies happened at once, breaking cycles with the scratch registers
+    fn emit_parallel_moves(&mut self, mut moves: Vec<(Location, Location)>) {
+        moves.retain(|(dst, src)| dst != src);
+
+        while !moves.is_empty() {
+            // a move whose destination no other move still has to read
+            match moves.iter().position(|(dst, _)| !moves.iter().any(|(_, src)| src == dst)) {
+                Some(index) => {
+                    let (dst, src) = moves.remove(index);
+                    self.emit_move(dst, src);
+                },
+                None => {
+                    let (_, src) = moves[0];
+                    let scratch = match src {
+                        Location::Xmm(_) => Location::Xmm(XMM_SCRATCH),
+                        _ => Location::Reg(R11)
+                    };
+
+                    self.emit_move(scratch, src);
+                    for (_, pending) in moves.iter_mut().filter(|(_, pending)| *pending == src) {
+                        *pending = scratch;
+                    }
+                }
+            }
+        }
+    }
+
+    fn emit_move(&mut self, dst: Location, src: Location) {
+        match (dst, src) {
+            (Location::Reg(dst), Location::Reg(src)) => self.emit(X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(dst), src: Operand::Reg(src) }),
+            (Location::Xmm(dst), Location::Xmm(src)) => self.emit(X64Inst::Movaps { dst, src }),
+            _ => unreachable!("Moves are between registers of the same class")
+        }
+    }
+
+    fn compile_call(&mut self, function: &Function, result: Option<ValueId>, callee: &str, args: &[Value]) -> Result<(), ChairError> {
+        let types: Vec<Type> = args.iter().map(|arg| function.value_type(arg)).collect();
+        let locations = self.classify_args(&types)?;
+
+        let stack_args: Vec<&Value> = args.iter().zip(locations.iter())
+            .filter(|(_, location)| matches!(location, ArgLocation::Stack))
+            .map(|(arg, _)| arg)
+            .collect();
+
+        // the frame has room for them at the bottom, so rsp stays where the frame's addresses expect it
+        for (index, arg) in stack_args.iter().enumerate() {
+            self.load_value(function, RAX, arg, arg_extend(&function.value_type(arg)))?;
+            self.emit(X64Inst::Mov { size: Size::Qword, dst: Operand::Mem(self.frame.outgoing(index)), src: Operand::Reg(RAX) });
+        }
+
+        // arguments already in registers are shuffled into place first, since loading the
+        // others could overwrite them
+        let mut moves = vec![];
+        for (arg, location) in args.iter().zip(locations.iter()) {
+            let home = match arg {
+                Value::Ref(id) => self.value_homes[id.0],
+                _ => None
+            };
+
+            match (location, home) {
+                (ArgLocation::Int(reg), Some(Home::Reg(src))) => moves.push((Location::Reg(*reg), Location::Reg(src))),
+                (ArgLocation::Sse(xmm), Some(Home::Xmm(src))) => moves.push((Location::Xmm(*xmm), Location::Xmm(src))),
+                _ => ()
+            }
+        }
+        self.emit_parallel_moves(moves);
+
+        let mut sse_count = 0;
+        for ((arg, ty), location) in args.iter().zip(types.iter()).zip(locations.iter()) {
+            let home = match arg {
+                Value::Ref(id) => self.value_homes[id.0],
+                _ => None
+            };
+
+            match (location, home) {
+                (ArgLocation::Int(reg), Some(Home::Reg(_))) => self.emit_extend(*reg, ty.bits(), arg_extend(ty)),
+                (ArgLocation::Int(reg), _) => self.load_value(function, *reg, arg, arg_extend(ty))?,
+                (ArgLocation::Sse(_), Some(Home::Xmm(_))) => sse_count += 1,
+                (ArgLocation::Sse(xmm), _) => {
+                    self.load_float(function, *xmm, arg)?;
+                    sse_count += 1;
+                },
+                (ArgLocation::Stack, _) => ()
+            }
+        }
+
+        // variadic callees read the number of vector registers used from al
+        self.emit(X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(RAX), src: Operand::Imm(sse_count) });
+
+        let callee_symbol = *self.function_symbols.get(callee).ok_or_else(|| self.invalid_ir(format!("call to unknown function @{}", callee)))?;
+        self.emit(X64Inst::Call(Target::Symbol(callee_symbol)));
+
+        if let Some(result) = result {
+            if function.values[result.0].is_float() {
+                self.store_float(function, Xmm::Xmm0, result);
+            } else {
+                self.store_value(function, RAX, result)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn patch_block_fixups(&mut self) -> Result<(), ChairError> {
+        for fixup in std::mem::take(&mut self.block_fixups) {
+            let target = self.block_offsets[&fixup.target];
+            let displacement = self.displacement(fixup.offset, target, fixup.addend, || format!("bb{}", fixup.target.0))?;
+
+            self.text[fixup.offset..fixup.offset + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+
+        for (symbol, target) in self.block_symbols.drain(..) {
+            self.symbols[symbol].offset = self.block_offsets[&target];
+        }
+
+        Ok(())
+    }
+
+    fn compile_instruction(&mut self, function: &Function, instruction: &Instruction) -> Result<(), ChairError> {
+        match instruction {
+            Instruction::Asm(x) => {
+                self.emit(X64Inst::Bytes(x.clone()))
+            },
+
+            Instruction::AsmValue(val) => {
+                self.compile_value(val)?
+            },
+
+            Instruction::Binary { result, op, lhs, rhs } => {
+                let extend = match op {
+                    BinaryOp::SDiv | BinaryOp::SRem | BinaryOp::AShr => Extend::Sign,
+                    _ => Extend::Zero
+                };
+
+                // shift amounts have to be in cl, and the allocator keeps rcx free of anything live across shifts
+                let rhs_reg = match op {
+                    BinaryOp::Shl | BinaryOp::LShr | BinaryOp::AShr => RCX,
+                    _ => R11
+                };
+
+                self.load_value(function, RAX, lhs, extend)?;
+                self.load_value(function, rhs_reg, rhs, extend)?;
+
+                let mut result_reg = RAX;
+                let (rax, rhs) = (Operand::Reg(RAX), Operand::Reg(rhs_reg));
+                let alu = |op| X64Inst::Alu { op, size: Size::Qword, dst: rax, src: rhs };
+                let shift = |op| X64Inst::Shift { op, size: Size::Qword, dst: rax, amount: None };
+
+                match op {
+                    BinaryOp::Add => self.emit(alu(AluOp::Add)),
+                    BinaryOp::Sub => self.emit(alu(AluOp::Sub)),
+                    BinaryOp::Mul => self.emit(X64Inst::Imul { size: Size::Qword, dst: RAX, src: rhs }),
+                    BinaryOp::And => self.emit(alu(AluOp::And)),
+                    BinaryOp::Or => self.emit(alu(AluOp::Or)),
+                    BinaryOp::Xor => self.emit(alu(AluOp::Xor)),
+                    BinaryOp::Shl => self.emit(shift(ShiftOp::Shl)),
+                    BinaryOp::LShr => self.emit(shift(ShiftOp::Shr)),
+                    BinaryOp::AShr => self.emit(shift(ShiftOp::Sar)),
+                    BinaryOp::SDiv | BinaryOp::SRem => {
+                        self.emit(X64Inst::Cqo);
+                        self.emit(X64Inst::Div { size: Size::Qword, signed: true, src: rhs });
+                    },
+                    BinaryOp::UDiv | BinaryOp::URem => {
+                        self.emit(X64Inst::Alu { op: AluOp::Xor, size: Size::Dword, dst: Operand::Reg(RDX), src: Operand::Reg(RDX) });
+                        self.emit(X64Inst::Div { size: Size::Qword, signed: false, src: rhs });
+                    }
+                }
+
+                if matches!(op, BinaryOp::SRem | BinaryOp::URem) {
+                    result_reg = RDX;
+                }
+
+                self.store_value(function, result_reg, *result)?;
+            },
+
+            Instruction::ICmp { result, cond, lhs, rhs } => {
+                let (extend, cond) = match cond {
+                    IntCondition::Eq => (Extend::Zero, Cond::Equal),
+                    IntCondition::Ne => (Extend::Zero, Cond::NotEqual),
+                    IntCondition::SLt => (Extend::Sign, Cond::Less),
+                    IntCondition::SLe => (Extend::Sign, Cond::LessEqual),
+                    IntCondition::SGt => (Extend::Sign, Cond::Greater),
+                    IntCondition::SGe => (Extend::Sign, Cond::GreaterEqual),
+                    IntCondition::ULt => (Extend::Zero, Cond::Below),
+                    IntCondition::ULe => (Extend::Zero, Cond::BelowEqual),
+                    IntCondition::UGt => (Extend::Zero, Cond::Above),
+                    IntCondition::UGe => (Extend::Zero, Cond::AboveEqual)
+                };
+
+                self.load_value(function, RAX, lhs, extend)?;
+                self.load_value(function, R11, rhs, extend)?;
+
+                self.emit(X64Inst::Alu { op: AluOp::Cmp, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Reg(R11) });
+                self.emit(X64Inst::Setcc { cond, dst: Operand::Reg(RAX) });
+                self.emit(X64Inst::Movzx { src_size: Size::Byte, dst: RAX, src: Operand::Reg(RAX) });
+
+                self.store_value(function, RAX, *result)?;
+            },
+
+            Instruction::Call { result, callee, args } => {
+                self.compile_call(function, *result, callee, args)?;
+            },
+
+            Instruction::GlobalAddr { result, global } => {
+                let global_symbol = *self.global_symbols.get(global).ok_or_else(|| self.invalid_ir(format!("reference to unknown global @{}", global)))?;
+
+                self.emit_address(RAX, global_symbol);
+                self.store_value(function, RAX, *result)?;
+            },
+
+            Instruction::StackAddr { result, slot } => {
+                self.emit(X64Inst::Lea { dst: RAX, src: self.frame.mem(self.frame.stack_slots[slot.0]) });
+                self.store_value(function, RAX, *result)?;
+            },
+
+            Instruction::Load { result, ptr, .. } => {
+                self.load_value(function, R11, ptr, Extend::Zero)?;
+                let src = Operand::Mem(Mem::base(R11, 0));
+
+                let instruction = match function.values[result.0].size() {
+                    1 => X64Inst::Movzx { src_size: Size::Byte, dst: RAX, src },
+                    2 => X64Inst::Movzx { src_size: Size::Word, dst: RAX, src },
+                    4 => X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(RAX), src },
+                    8 => X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(RAX), src },
+                    _ => return Err(self.unsupported(format!("loading a {}", function.values[result.0])))
+                };
+                self.emit(instruction);
+
+                self.store_value(function, RAX, *result)?;
+            },
+
+            Instruction::Store { value, ptr, .. } => {
+                let size = function.value_type(value).size();
+
+                self.load_value(function, R11, ptr, Extend::Zero)?;
+                self.load_value(function, RAX, value, Extend::Zero)?;
+
+                let size = match size {
+                    1 => Size::Byte,
+                    2 => Size::Word,
+                    4 => Size::Dword,
+                    8 => Size::Qword,
+                    _ => return Err(self.unsupported(format!("storing a {}", function.value_type(value))))
+                };
+                self.emit(X64Inst::Mov { size, dst: Operand::Mem(Mem::base(R11, 0)), src: Operand::Reg(RAX) });
+            },
+
+            Instruction::Gep { result, ty, ptr, indices } => {
+                self.load_value(function, RAX, ptr, Extend::Zero)?;
+
+                let mut current = ty.clone();
+                let mut const_offset: i64 = 0;
+
+                for (i, index) in indices.iter().enumerate() {
+                    // the first index steps over whole `ty`s, the rest step into the aggregate
+                    let stride = match (i, &current) {
+                        (0, _) => current.size(),
+                        (_, Type::Array(elem, _)) => {
+                            current = (**elem).clone();
+                            current.size()
+                        },
+                        (_, Type::Struct(fields)) => {
+                            let field = match index {
+                                Value::Const(val) => val.as_i64().filter(|field| *field >= 0 && (*field as usize) < fields.len()),
+                                _ => None
+                            };
+                            let field = field.ok_or_else(|| self.invalid_ir("struct indices must be constant field numbers".to_owned()))? as usize;
+
+                            const_offset += current.struct_layout().0[field] as i64;
+                            current = fields[field].clone();
+                            continue;
+                        },
+                        _ => return Err(self.invalid_ir(format!("gep can't index into {}", current)))
+                    };
+
+                    match index {
+                        Value::Const(val) => {
+                            let bits = val.get_type().bits();
+                            let num = val.as_i64().ok_or_else(|| self.invalid_ir("gep indices must be integers".to_owned()))?;
+                            let num = extend_const(num, bits, Extend::Sign);
+                            const_offset += num * stride as i64;
+                        },
+                        _ => {
+                            let stride = i32::try_from(stride).map_err(|_| self.unsupported(format!("a gep stride of {} bytes", stride)))?;
+                            self.load_value(function, R11, index, Extend::Sign)?;
+                            self.emit(X64Inst::ImulImm { size: Size::Qword, dst: R11, src: Operand::Reg(R11), imm: stride });
+                            self.emit(X64Inst::Alu { op: AluOp::Add, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Reg(R11) });
+                        }
+                    }
+                }
+
+                if const_offset != 0 {
+                    if i32::try_from(const_offset).is_ok() {
+                        self.emit(X64Inst::Alu { op: AluOp::Add, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Imm(const_offset) });
+                    } else {
+                        self.emit(X64Inst::MovAbs { dst: R11, src: Imm64::Value(const_offset) });
+                        self.emit(X64Inst::Alu { op: AluOp::Add, size: Size::Qword, dst: Operand::Reg(RAX), src: Operand::Reg(R11) });
+                    }
+                }
+
+                self.store_value(function, RAX, *result)?;
+            },
+
+            Instruction::Cast { result, op, value, .. } => {
+                let extend = match op {
+                    CastOp::SExt => Extend::Sign,
+                    CastOp::ZExt | CastOp::Trunc => Extend::Zero
+                };
+
+                // casts into a register happen in place, which is free when the allocator gave the operand the same one
+                match self.value_homes[result.0] {
+                    Some(Home::Reg(reg)) => self.load_value(function, reg, value, extend)?,
+                    _ => {
+                        self.load_value(function, RAX, value, extend)?;
+                        self.store_value(function, RAX, *result)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_function(&mut self, function: &Function) -> Result<(), ChairError> {
+        self.context = ErrorContext {
+            function: Some(function.name.clone()),
+            block: None
+        };
+        self.block_offsets.clear();
+
+        // slots are placed from the canonical frame address, which is only 16-byte aligned
+        if let Some(slot) = function.stack_slots.iter().find(|slot| slot.align > 16) {
+            return Err(self.unsupported(format!("a stack slot aligned to {} bytes", slot.align)));
+        }
+
+        let layout = function.reverse_postorder();
+        let liveness = Liveness::compute(function, &layout);
+        let allocation = match self.options.register_allocator {
+            RegisterAllocator::LinearScan => linear_scan(function, &liveness),
+            RegisterAllocator::GraphColoring => graph_coloring(function, &liveness, &layout)
+        };
+
+        self.frame = Frame::layout(function, &liveness, &allocation, &self.options)
+            .map_err(|size| self.unsupported(format!("a {}-byte stack frame", size)))?;
+
+        self.value_homes = allocation.locations.iter().map(|location| location.map(|location| match location {
+            Location::Reg(reg) => Home::Reg(reg),
+            Location::Xmm(xmm) => Home::Xmm(xmm),
+            Location::Spill(slot) => Home::Frame(self.frame.spill_slots[slot])
+        })).collect();
+
+        self.emit_prologue();
+
+        let param_types: Vec<Type> = function.params.iter().map(|param| function.values[param.0].clone()).collect();
+        let locations = self.classify_args(&param_types)?;
+
+        // parameters that were spilled are stored before the others are shuffled over their argument registers
+        let mut moves = vec![];
+        for (param, location) in function.params.iter().zip(locations.iter()) {
+            match (location, self.value_homes[param.0]) {
+                (ArgLocation::Int(reg), Some(Home::Reg(dst))) => moves.push((Location::Reg(dst), Location::Reg(*reg))),
+                (ArgLocation::Sse(xmm), Some(Home::Xmm(dst))) => moves.push((Location::Xmm(dst), Location::Xmm(*xmm))),
+                (ArgLocation::Int(reg), _) => self.store_value(function, *reg, *param)?,
+                (ArgLocation::Sse(xmm), _) => self.store_float(function, *xmm, *param),
+                (ArgLocation::Stack, _) => ()
+            }
+        }
+        self.emit_parallel_moves(moves);
+
+        // stack arguments are right above the return address, and spilled ones are used in place
+        let mut stack_offset = 0;
+        for (param, location) in function.params.iter().zip(locations.iter()) {
+            if !matches!(location, ArgLocation::Stack) {
+                continue;
+            }
+
+            let slot = self.frame.mem(stack_offset);
+            match self.value_homes[param.0] {
+                Some(Home::Reg(reg)) => self.emit(X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(reg), src: Operand::Mem(slot) }),
+                Some(Home::Xmm(xmm)) => self.emit(X64Inst::MovFloat { double: true, dst: XmmOperand::Xmm(xmm), src: XmmOperand::Mem(slot) }),
+                Some(Home::Frame(_)) => self.value_homes[param.0] = Some(Home::Frame(stack_offset)),
+                None => ()
+            }
+            stack_offset += 8;
+        }
+
+        for (i, id) in layout.iter().enumerate() {
+            self.context.block = Some(*id);
+            self.block_offsets.insert(*id, self.text.len());
+            self.list(format!("bb{}:", id.0));
+            self.compile_block(function, function.block(*id), layout.get(i + 1).copied())?;
+        }
+        self.context.block = None;
+
+        self.patch_block_fixups()
+    }
+
+    fn emit_prologue(&mut self) {
+        if self.frame.frame_pointer {
+            self.emit(X64Inst::Push(Operand::Reg(RBP)));
+            self.emit(X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(RBP), src: Operand::Reg(RSP) });
+        }
+        for reg in self.frame.saved.clone() {
+            self.emit(X64Inst::Push(Operand::Reg(reg)));
+        }
+        if self.frame.size > 0 {
+            self.emit(X64Inst::Alu { op: AluOp::Sub, size: Size::Qword, dst: Operand::Reg(RSP), src: Operand::Imm(self.frame.size as i64) });
+        }
+    }
+
+    // undoes the prologue at every return
+    fn emit_epilogue(&mut self) {
+        if self.frame.size > 0 {
+            self.emit(X64Inst::Alu { op: AluOp::Add, size: Size::Qword, dst: Operand::Reg(RSP), src: Operand::Imm(self.frame.size as i64) });
+        }
+        for reg in self.frame.saved.clone().into_iter().rev() {
+            self.emit(X64Inst::Pop(reg));
+        }
+        if self.frame.frame_pointer {
+            self.emit(X64Inst::Pop(RBP));
+        }
+        self.emit(X64Inst::Ret);
+    }
+
+    // records a relocation against `symbols[symbol]` at the end of `section` and leaves room for it
+    fn emit_relocation(&mut self, section: Section, symbol: usize, kind: u32, addend: i64) {
+        let bytes = match section {
+            Section::Text => &mut self.text,
+            Section::Rodata => &mut self.rodata,
+            Section::Data => &mut self.data,
+            Section::Bss | Section::Undefined => unreachable!("Relocations need a section with contents")
+        };
+
+        let offset = bytes.len();
+        let size = match kind {
+            R_X86_64_64 => 8,
+            R_X86_64_PC32 | R_X86_64_PLT32 | R_X86_64_GOTPCREL | R_X86_64_32S => 4,
+            _ => unreachable!("Unknown relocation type {}", kind)
+        };
+        bytes.resize(offset + size, 0);
+
+        self.add_relocation(section, offset, symbol, kind, addend);
+    }
+
+    fn add_relocation(&mut self, section: Section, offset: usize, symbol: usize, kind: u32, addend: i64) {
+        self.relocations.push(Relocation {
+            symbol,
+            section,
+            offset,
+            kind,
+            addend
+        });
+    }
+
+    // constants go to .rodata, all-zero globals to .bss and everything else to .data
+    fn compile_global(&mut self, global: &Global) {
+        let size = global.ty.size();
+        let align = global.ty.align();
+
+        let mut bytes = global.init.as_ref().map(|init| init.serialize(false)).unwrap_or_default();
+        bytes.resize(size, 0);
+
+        let (section, offset) = if global.constant {
+            self.rodata.resize(self.rodata.len().next_multiple_of(align), 0);
+            let offset = self.rodata.len();
+            self.rodata.extend(bytes);
+            (Section::Rodata, offset)
+        } else if bytes.iter().all(|byte| *byte == 0) {
+            self.bss_size = self.bss_size.next_multiple_of(align);
+            self.bss_align = self.bss_align.max(align);
+            let offset = self.bss_size;
+            self.bss_size += size;
+            (Section::Bss, offset)
+        } else {
+            self.data.resize(self.data.len().next_multiple_of(align), 0);
+            self.data_align = self.data_align.max(align);
+            let offset = self.data.len();
+            self.data.extend(bytes);
+            (Section::Data, offset)
+        };
+
+        self.symbols.push(Symbol {
+            section,
+            offset,
+            size,
+            name: Some(global.name.to_string()),
+            elf_type: STT_OBJECT
+        });
+        self.global_symbols.insert(global.name.to_string(), self.symbols.len() - 1);
+    }
+
+    fn compile_value(&mut self, value: &Value) -> Result<(), ChairError> {
+        match value {
+            Value::Ref(_) => {
+                return Err(self.invalid_ir("asm_value needs a constant".to_owned()));
+            },
+            Value::Const(val) => {
+                self.emit(X64Inst::Bytes(val.serialize(false)))
+            },
+            // the surrounding asm has to match the addressing: a rip-relative disp32 that ends its instruction,
+            // like lea's, or the 8-byte immediate of a movabs when addressing is absolute
+            Value::ConstRef(val) => {
+                let symbol = self.add_constant(val);
+                let name = self.target_name(Target::Symbol(symbol));
+
+                if self.options.addressing == Addressing::Absolute {
+                    self.list(format!("    .quad {}", name));
+                    self.emit_relocation(Section::Text, symbol, R_X86_64_64, 0);
+                } else {
+                    self.list(format!("    .long {} - . - 4", name));
+                    self.emit_relocation(Section::Text, symbol, R_X86_64_PC32, -4);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // puts `val` in .rodata behind an anonymous symbol
+    fn add_constant(&mut self, val: &ConstValue) -> usize {
+        let ty = val.get_type();
+        self.rodata.resize(self.rodata.len().next_multiple_of(ty.align()), 0);
+
+        self.symbols.push(Symbol {
+            section: Section::Rodata,
+            size: ty.size(),
+            offset: self.rodata.len(),
+            name: None,
+            elf_type: STT_OBJECT
+        });
+
+        self.rodata.extend(val.serialize(false));
+        self.symbols.len() - 1
+    }
+
+    fn classify_args(&self, types: &[Type]) -> Result<Vec<ArgLocation>, ChairError> {
+        match types.iter().find(|ty| ty.is_aggregate()) {
+            Some(ty) => Err(self.unsupported(format!("passing a {} by value", ty))),
+            None => Ok(arg_locations(types))
+        }
+    }
+}
+
+// where System V passes arguments of `types`, which mustn't be aggregates
+pub(crate) fn arg_locations(types: &[Type]) -> Vec<ArgLocation> {
+    let mut int_count = 0;
+    let mut sse_count = 0;
+
+    types.iter().map(|ty| {
+        if ty.is_float() && sse_count < SSE_ARG_REGS {
+            sse_count += 1;
+            ArgLocation::Sse(Xmm::ALL[sse_count - 1])
+        } else if !ty.is_float() && int_count < INT_ARG_REGS.len() {
+            int_count += 1;
+            ArgLocation::Int(INT_ARG_REGS[int_count - 1])
+        } else {
+            ArgLocation::Stack
+        }
+    }).collect()
+}
+
+fn block_label(block: BlockId) -> Target {
+    Target::Label(block.0)
+}
+
+// the shortest mov of `imm` into `reg`
+fn mov_imm(reg: Reg, imm: i64) -> X64Inst {
+    if u32::try_from(imm).is_ok() {
+        X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(reg), src: Operand::Imm(imm) }
+    } else if i32::try_from(imm).is_ok() {
+        X64Inst::Mov { size: Size::Qword, dst: Operand::Reg(reg), src: Operand::Imm(imm) }
+    } else {
+        X64Inst::MovAbs { dst: reg, src: Imm64::Value(imm) }
+    }
+}
+
+// C callers expect bools zero-extended and other small integers sign-extended
+fn arg_extend(ty: &Type) -> Extend {
+    match ty {
+        Type::I1 => Extend::Zero,
+        _ => Extend::Sign
+    }
+}
+
+// extends the low `bits` of `num` to 64 bits
+fn extend_const(num: i64, bits: u32, extend: Extend) -> i64 {
+    if bits >= 64 {
+        return num;
+    }
+
+    let shift = 64 - bits;
+
+    match extend {
+        Extend::Sign => (num << shift) >> shift,
+        Extend::Zero => (((num as u64) << shift) >> shift) as i64
+    }
+}
+
+impl Default for CompilerX64Elf {
+    fn default() -> CompilerX64Elf {
+        CompilerX64Elf::new()
+    }
+}
+
+impl Codegen for CompilerX64Elf {
+    type OutputFormat = ElfFile;
+    fn compile_translation_unit(&mut self, translation_unit: TranslationUnit) -> Result<ElfFile, ChairError> {
+        Ok(self.compile_to_builder(translation_unit)?.build())
+    }
+}
+
+impl CompilerX64Elf {
+    // compiles straight to a static executable that starts at the function `entry`, without a linker.
+    // Everything called has to be defined in the translation unit.
+    pub fn compile_executable(&mut self, translation_unit: TranslationUnit, entry: &str) -> Result<ElfFile, ChairError> {
+        self.compile_to_builder(translation_unit)?.build_executable(entry)
+    }
+
+    // the code for `translation_unit` in Intel syntax, one function after another
+    pub fn compile_listing(&mut self, translation_unit: TranslationUnit) -> Result<String, ChairError> {
+        self.listing = Some(String::new());
+        self.compile_to_builder(translation_unit)?;
+        Ok(self.listing.take().unwrap_or_default())
+    }
+
+    fn compile_to_builder(&mut self, translation_unit: TranslationUnit) -> Result<ElfObjectBuilder, ChairError> {
+        // everything below indexes by the ids in the IR, so it must be well formed first
+        verify_translation_unit(&translation_unit)?;
+
+        // function symbols come first so calls can find functions that aren't compiled yet
+        for function in &translation_unit.functions {
+            self.symbols.push(Symbol {
+                offset: 0,
+                elf_type: STT_FUNC,
+                size: 0,
+                section: Section::Text,
+                name: Some(function.name.clone())
+            });
+            self.function_symbols.insert(function.name.clone(), self.symbols.len() - 1);
+        }
+
+        for (name, _) in &translation_unit.declarations {
+            if self.function_symbols.contains_key(name) {
+                continue;
+            }
+
+            self.symbols.push(Symbol {
+                offset: 0,
+                elf_type: STT_NOTYPE,
+                size: 0,
+                section: Section::Undefined,
+                name: Some(name.to_string())
+            });
+            self.function_symbols.insert(name.to_string(), self.symbols.len() - 1);
+        }
+
+        for global in &translation_unit.globals {
+            self.compile_global(global);
+        }
+
+        for function in &translation_unit.functions {
+            let function_start = self.text.len();
+            self.list(format!("{}:", function.name));
+            self.compile_function(function)?;
+
+            let symbol = &mut self.symbols[self.function_symbols[&function.name]];
+            symbol.offset = function_start;
+            symbol.size = self.text.len() - function_start;
+        }
+
+        self.context = ErrorContext::default();
+
+        // calls to functions in this .text are resolved here instead of by the linker
+        for (offset, callee) in std::mem::take(&mut self.call_fixups) {
+            let target = self.symbols[callee].offset;
+            let displacement = self.displacement(offset, target, -4, || format!("@{}", self.symbols[callee].name.as_deref().unwrap_or_default()))?;
+
+            self.text[offset..offset + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+
+        let mut object = ElfObjectBuilder::new(EM_X86_64);
+        let text = object.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, std::mem::take(&mut self.text));
+        let rodata = object.add_section(".rodata", SHT_PROGBITS, SHF_ALLOC, 8, std::mem::take(&mut self.rodata));
+        let data = object.add_section(".data", SHT_PROGBITS, SHF_WRITE | SHF_ALLOC, self.data_align as u64, std::mem::take(&mut self.data));
+        let bss = object.add_nobits_section(".bss", SHF_WRITE | SHF_ALLOC, self.bss_align as u64, self.bss_size as u64);
+
+        let section_id = |section: &Section| match section {
+            Section::Text => Some(text),
+            Section::Rodata => Some(rodata),
+            Section::Data => Some(data),
+            Section::Bss => Some(bss),
+            Section::Undefined => None
+        };
+
+        // anonymous symbols are only referenced from this object, so they're local
+        let symbol_ids: Vec<ElfSymbolId> = self.symbols.iter().map(|symbol| {
+            let binding = if symbol.name.is_some() { STB_GLOBAL } else { STB_LOCAL };
+            object.add_symbol(symbol.name.as_deref(), section_id(&symbol.section), symbol.offset as u64, symbol.size as u64, binding, symbol.elf_type)
+        }).collect();
+
+        for reloc in &self.relocations {
+            let section = section_id(&reloc.section).expect("Relocation in an undefined section");
+            object.add_relocation(section, reloc.offset as u64, symbol_ids[reloc.symbol], reloc.kind, reloc.addend);
+        }
+
+        Ok(object)
+    }
+}
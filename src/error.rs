@@ -0,0 +1,133 @@
+use std::fmt;
+use std::io;
+
+use crate::ir::BlockId;
+use crate::ir::text::ParseError;
+use crate::ir::verify::VerifyError;
+use crate::outputs::serialization::DeserializeError;
+
+// where in the IR an error happened, as far as it's known
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub function: Option<String>,
+    pub block: Option<BlockId>
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(function) = &self.function {
+            write!(f, "in @{}", function)?;
+            if let Some(block) = self.block {
+                write!(f, ", bb{}", block.0)?;
+            }
+            f.write_str(": ")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ChairError {
+    Parse(ParseError),
+    Verify(Vec<VerifyError>),
+    // an object file that couldn't be read
+    Deserialize(DeserializeError),
+    // IR that codegen can't make sense of, normally caught by the verifier first
+    InvalidIr {
+        context: ErrorContext,
+        message: String
+    },
+    Unsupported {
+        target: &'static str,
+        context: ErrorContext,
+        feature: String
+    },
+    // a displacement or address that doesn't fit in the field it has to be written to
+    RelocationOverflow {
+        context: ErrorContext,
+        target: String,
+        value: i64,
+        bits: u32
+    },
+    // an object file that can't be linked
+    InvalidObject {
+        object: String,
+        message: String
+    },
+    // a symbol defined by more than one object
+    DuplicateSymbol {
+        symbol: String,
+        objects: Vec<String>
+    },
+    // a symbol that's referenced but never defined, with the objects that reference it when there are several
+    UndefinedSymbol {
+        symbol: String,
+        objects: Vec<String>
+    },
+    Io {
+        path: String,
+        error: io::Error
+    }
+}
+
+impl fmt::Display for ChairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChairError::Parse(error) => write!(f, "{}", error),
+            ChairError::Verify(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("\n")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            },
+            ChairError::Deserialize(error) => write!(f, "invalid ELF file {}", error),
+            ChairError::InvalidIr { context, message } => write!(f, "{}invalid IR: {}", context, message),
+            ChairError::Unsupported { target, context, feature } => write!(f, "{}{} isn't supported for {}", context, feature, target),
+            ChairError::RelocationOverflow { context, target, value, bits } => {
+                write!(f, "{}reference to {} needs {}, which doesn't fit in {} bits", context, target, value, bits)
+            },
+            ChairError::InvalidObject { object, message } => write!(f, "{}: {}", object, message),
+            ChairError::DuplicateSymbol { symbol, objects } => write!(f, "duplicate symbol {}, defined in {}", symbol, objects.join(", ")),
+            ChairError::UndefinedSymbol { symbol, objects } => {
+                write!(f, "undefined symbol {}", symbol)?;
+                if !objects.is_empty() {
+                    write!(f, ", referenced from {}", objects.join(", "))?;
+                }
+                Ok(())
+            },
+            ChairError::Io { path, error } => write!(f, "{}: {}", path, error)
+        }
+    }
+}
+
+impl std::error::Error for ChairError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChairError::Parse(error) => Some(error),
+            ChairError::Deserialize(error) => Some(error),
+            ChairError::Io { error, .. } => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<ParseError> for ChairError {
+    fn from(error: ParseError) -> ChairError {
+        ChairError::Parse(error)
+    }
+}
+
+impl From<Vec<VerifyError>> for ChairError {
+    fn from(errors: Vec<VerifyError>) -> ChairError {
+        ChairError::Verify(errors)
+    }
+}
+
+impl From<DeserializeError> for ChairError {
+    fn from(error: DeserializeError) -> ChairError {
+        ChairError::Deserialize(error)
+    }
+}
@@ -0,0 +1,229 @@
+use crate::error::{ChairError, ErrorContext};
+use crate::ir::{BinaryOp, Block, BlockId, CastOp, ConstValue, Function, Global, Instruction, IntCondition, StackSlotId, Terminator, TranslationUnit, Value};
+use crate::ir::types::{Signature, Type};
+
+/// Builds a [`TranslationUnit`] one function, declaration and global at a time.
+///
+/// ```
+/// use chair::ir::{Builder, Value};
+/// use chair::ir::types::{Signature, Type};
+///
+/// let mut builder = Builder::new("example");
+///
+/// let mut function = builder.function("answer", Signature::new(vec![], Type::I64));
+/// let entry = function.create_block();
+/// function.switch_to_block(entry)?;
+/// function.ret(Some(Value::const_i64(42)))?;
+/// function.finish();
+///
+/// let translation_unit = builder.finish();
+/// # Ok::<(), chair::ChairError>(())
+/// ```
+pub struct Builder {
+    translation_unit: TranslationUnit
+}
+
+impl Builder {
+    /// Starts an empty translation unit called `name`.
+    pub fn new(name: &str) -> Builder {
+        Builder {
+            translation_unit: TranslationUnit::new(name)
+        }
+    }
+
+    /// Declares a function defined outside this translation unit, so it can be called.
+    pub fn declare_function(&mut self, name: &str, signature: Signature) {
+        self.translation_unit.declare_function(name, signature);
+    }
+
+    /// Adds a global variable. `init` of `None` zero-initializes it, `constant` globals are read-only.
+    pub fn add_global(&mut self, name: &str, ty: Type, init: Option<ConstValue>, constant: bool) {
+        self.translation_unit.add_global(Global::new(name, ty, init, constant));
+    }
+
+    /// Starts defining a function. It's added to the translation unit by [`FunctionBuilder::finish`].
+    pub fn function(&mut self, name: &str, signature: Signature) -> FunctionBuilder<'_> {
+        FunctionBuilder {
+            builder: self,
+            function: Function::new(name, signature),
+            current_block: None
+        }
+    }
+
+    /// Returns the finished translation unit.
+    pub fn finish(self) -> TranslationUnit {
+        self.translation_unit
+    }
+}
+
+/// Builds the body of one function. Instructions are appended to the current block,
+/// chosen with [`FunctionBuilder::switch_to_block`].
+///
+/// Methods return [`ChairError::InvalidIr`] when there's no current block, or when they're given a
+/// parameter, value or block this function doesn't have. Anything else wrong is left to the verifier.
+#[must_use = "the function is only added to the translation unit by `finish`"]
+pub struct FunctionBuilder<'a> {
+    builder: &'a mut Builder,
+    function: Function,
+    current_block: Option<BlockId>
+}
+
+impl FunctionBuilder<'_> {
+    fn error(&self, message: String) -> ChairError {
+        ChairError::InvalidIr {
+            context: ErrorContext {
+                function: Some(self.function.name.clone()),
+                block: self.current_block
+            },
+            message
+        }
+    }
+
+    /// The function's `index`th parameter.
+    pub fn param(&self, index: usize) -> Result<Value, ChairError> {
+        match self.function.params.get(index) {
+            Some(param) => Ok(Value::Ref(*param)),
+            None => Err(self.error(format!("there's no parameter {}", index)))
+        }
+    }
+
+    /// The type of `value` in this function.
+    pub fn value_type(&self, value: &Value) -> Result<Type, ChairError> {
+        match value {
+            Value::Ref(id) if id.0 >= self.function.values.len() => Err(self.error(format!("%{} isn't a value of this function", id.0))),
+            _ => Ok(self.function.value_type(value))
+        }
+    }
+
+    /// Reserves a piece of the stack frame that lives for the whole function.
+    pub fn add_stack_slot(&mut self, ty: Type, align: usize) -> StackSlotId {
+        self.function.add_stack_slot(ty, align)
+    }
+
+    /// Creates an empty block. The first block created is where the function starts.
+    pub fn create_block(&mut self) -> BlockId {
+        self.function.add_block(Block::new())
+    }
+
+    /// Makes `block` the one new instructions and terminators go into.
+    pub fn switch_to_block(&mut self, block: BlockId) -> Result<(), ChairError> {
+        if block.0 >= self.function.blocks.len() {
+            return Err(self.error(format!("bb{} isn't a block of this function", block.0)));
+        }
+
+        self.current_block = Some(block);
+        Ok(())
+    }
+
+    /// The block new instructions go into, if one has been chosen.
+    pub fn current_block(&self) -> Option<BlockId> {
+        self.current_block
+    }
+
+    fn block(&self) -> Result<BlockId, ChairError> {
+        self.current_block.ok_or_else(|| self.error("there's no block to insert into, call switch_to_block first".to_owned()))
+    }
+
+    /// Emits raw machine code.
+    pub fn asm(&mut self, bytes: Vec<u8>) -> Result<(), ChairError> {
+        let block = self.block()?;
+        self.function.block_mut(block).add_instruction(Instruction::Asm(bytes));
+        Ok(())
+    }
+
+    /// Emits the bytes of a constant, or the address of a [`Value::ConstRef`], into the machine code.
+    pub fn asm_value(&mut self, value: Value) -> Result<(), ChairError> {
+        let block = self.block()?;
+        self.function.block_mut(block).add_instruction(Instruction::AsmValue(value));
+        Ok(())
+    }
+
+    /// `lhs op rhs`, typed like `lhs`.
+    pub fn binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, ChairError> {
+        let block = self.block()?;
+        self.value_type(&lhs)?;
+        Ok(self.function.binary(block, op, lhs, rhs))
+    }
+
+    /// Compares two integers or pointers, giving an `i1`.
+    pub fn icmp(&mut self, cond: IntCondition, lhs: Value, rhs: Value) -> Result<Value, ChairError> {
+        let block = self.block()?;
+        Ok(self.function.icmp(block, cond, lhs, rhs))
+    }
+
+    /// Converts an integer to `ty`.
+    pub fn cast(&mut self, op: CastOp, value: Value, ty: Type) -> Result<Value, ChairError> {
+        let block = self.block()?;
+        Ok(self.function.cast(block, op, value, ty))
+    }
+
+    /// Calls a function defined or declared in this translation unit.
+    /// Returns the result, or `None` when the callee returns void.
+    pub fn call(&mut self, callee: &str, signature: &Signature, args: Vec<Value>) -> Result<Option<Value>, ChairError> {
+        let block = self.block()?;
+        Ok(self.function.call(block, callee, signature, args))
+    }
+
+    /// The address of a global.
+    pub fn global_addr(&mut self, global: &str) -> Result<Value, ChairError> {
+        let block = self.block()?;
+        Ok(self.function.global_addr(block, global))
+    }
+
+    /// The address of a stack slot.
+    pub fn stack_addr(&mut self, slot: StackSlotId) -> Result<Value, ChairError> {
+        let block = self.block()?;
+        Ok(self.function.stack_addr(block, slot))
+    }
+
+    /// Loads a `ty` from `ptr`.
+    pub fn load(&mut self, ty: Type, ptr: Value, align: usize) -> Result<Value, ChairError> {
+        let block = self.block()?;
+        Ok(self.function.load(block, ty, ptr, align))
+    }
+
+    /// Stores `value` to `ptr`.
+    pub fn store(&mut self, value: Value, ptr: Value, align: usize) -> Result<(), ChairError> {
+        let block = self.block()?;
+        self.function.store(block, value, ptr, align);
+        Ok(())
+    }
+
+    /// The address of `ptr[indices[0]][indices[1]]...`, where `ptr` points at a `ty`.
+    /// Struct indices have to be constants.
+    pub fn gep(&mut self, ty: Type, ptr: Value, indices: Vec<Value>) -> Result<Value, ChairError> {
+        let block = self.block()?;
+        Ok(self.function.gep(block, ty, ptr, indices))
+    }
+
+    fn terminate(&mut self, terminator: Terminator) -> Result<(), ChairError> {
+        let block = self.block()?;
+        self.function.block_mut(block).set_terminator(terminator);
+        Ok(())
+    }
+
+    /// Ends the current block with a jump to `target`.
+    pub fn jump(&mut self, target: BlockId) -> Result<(), ChairError> {
+        self.terminate(Terminator::Jump(target))
+    }
+
+    /// Ends the current block with a jump to `if_true` or `if_false` depending on an `i1`.
+    pub fn branch(&mut self, cond: Value, if_true: BlockId, if_false: BlockId) -> Result<(), ChairError> {
+        self.terminate(Terminator::Branch { cond, if_true, if_false })
+    }
+
+    /// Ends the current block with a jump to the case matching `value`, or `default`.
+    pub fn switch(&mut self, value: Value, cases: Vec<(i64, BlockId)>, default: BlockId) -> Result<(), ChairError> {
+        self.terminate(Terminator::Switch { value, cases, default })
+    }
+
+    /// Ends the current block by returning from the function.
+    pub fn ret(&mut self, value: Option<Value>) -> Result<(), ChairError> {
+        self.terminate(Terminator::Return(value))
+    }
+
+    /// Adds the function to the translation unit.
+    pub fn finish(self) {
+        self.builder.translation_unit.add_function(self.function);
+    }
+}
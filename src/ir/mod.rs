@@ -1,120 +1,573 @@
-use std::collections::HashMap;
-
-use crate::outputs::serialization::{Serializable, ToBytes};
-
-pub mod sample;
-
-pub struct TranslationUnit {
-    name: String,
-    pub(crate) functions: HashMap<String, Function>,
-    pub(crate) globals: HashMap<String, Value>
-}
-
-impl TranslationUnit {
-    fn new(name: &'static str) -> TranslationUnit {
-        TranslationUnit {
-            name: name.to_owned(),
-            functions: HashMap::new(),
-            globals: HashMap::new()
-        }
-    }
-
-    fn add_function(&mut self, function: Function) {
-        self.functions.insert(function.name.to_string(), function);
-    }
-}
-
-pub struct Function {
-    pub(crate) name: String,
-    pub(crate) start_block: Box<Block>
-}
-
-impl Function {
-    fn new(name: &'static str, start_block: Block) -> Function {
-        Function {
-            name: name.to_owned(),
-            start_block: Box::from(start_block)
-        }
-    }
-}
-
-#[derive(Clone)]
-pub struct Block { 
-    pub(crate) instructions: Vec<Instruction>,
-    pub(crate) terminator: Option<Terminator> 
-}
-
-impl Block {
-    fn new() -> Block {
-        Block {
-            instructions: vec![],
-            terminator: None
-        }
-    }
-
-    fn from(instructions: Vec<Instruction>, terminator: Terminator) -> Block {
-        Block {
-            instructions: instructions,
-            terminator: Some(terminator)
-        }
-    }
-
-    fn set_terminator(&mut self, terminator: Terminator) {
-        self.terminator = Some(terminator);
-    }
-
-    fn add_instruction(&mut self, instruction: Instruction) {
-        self.instructions.push(instruction);
-    }
-}
-
-#[derive(Clone)]
-pub enum Instruction {
-    Asm(Vec<u8>),
-    AsmValue(Value)
-}
-
-#[derive(Clone)]
-pub enum ConstValue {
-    UInt8(u8),
-    Int64(i64),
-    Array(Vec<ConstValue>)
-}
-
-impl Serializable for ConstValue {
-    fn serialize(&self, big_endian: bool) -> Vec<u8> {
-        match self {
-            Self::UInt8(num) => {num.to_bytes(big_endian)}
-            Self::Int64(num) => {num.to_bytes(big_endian)}
-            Self::Array(vals) => {vals.serialize(big_endian)}
-        }
-    }
-}
-
-#[derive(Clone)]
-pub enum Value {
-    Const(ConstValue),
-    ConstRef(ConstValue)
-}
-
-
-impl Value {
-    fn const_i64(num: i64) -> Value {
-        Value::Const(ConstValue::Int64(num))
-    }
-
-    fn const_str(string: String) -> Value {
-        Value::ConstRef(
-            ConstValue::Array(
-                string.serialize(false).into_iter().map(|x| ConstValue::UInt8(x)).collect()
-            )
-        )
-    }
-}
-
-#[derive(Clone)]
-pub enum Terminator {
-    Jump(Box<Block>),
-    Return
-}
-
+use crate::outputs::serialization::{Serializable, ToBytes};
+
+pub mod builder;
+pub mod opt;
+pub mod sample;
+pub mod text;
+pub mod types;
+pub mod verify;
+
+use crate::ir::types::{Signature, Type};
+
+pub use crate::ir::builder::{Builder, FunctionBuilder};
+
+pub struct TranslationUnit {
+    name: String,
+    // everything is kept in the order it was added so the output doesn't depend on hashing
+    pub(crate) functions: Vec<Function>,
+    // functions defined outside this translation unit
+    pub(crate) declarations: Vec<(String, Signature)>,
+    pub(crate) globals: Vec<Global>
+}
+
+impl TranslationUnit {
+    fn new(name: &str) -> TranslationUnit {
+        TranslationUnit {
+            name: name.to_owned(),
+            functions: vec![],
+            declarations: vec![],
+            globals: vec![]
+        }
+    }
+
+    fn add_function(&mut self, function: Function) {
+        self.functions.push(function);
+    }
+
+    fn declare_function(&mut self, name: &str, signature: Signature) {
+        self.declarations.push((name.to_owned(), signature));
+    }
+
+    fn add_global(&mut self, global: Global) {
+        self.globals.push(global);
+    }
+
+    pub(crate) fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    pub(crate) fn declaration(&self, name: &str) -> Option<&Signature> {
+        self.declarations.iter().find(|(declared, _)| declared == name).map(|(_, signature)| signature)
+    }
+
+    pub(crate) fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|global| global.name == name)
+    }
+}
+
+pub struct Global {
+    pub(crate) name: String,
+    pub(crate) ty: Type,
+    // None means zero-initialized
+    pub(crate) init: Option<ConstValue>,
+    pub(crate) constant: bool
+}
+
+impl Global {
+    fn new(name: &str, ty: Type, init: Option<ConstValue>, constant: bool) -> Global {
+        Global {
+            name: name.to_owned(),
+            ty,
+            init,
+            constant
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockId(pub(crate) usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ValueId(pub(crate) usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StackSlotId(pub(crate) usize);
+
+pub struct StackSlot {
+    pub(crate) ty: Type,
+    pub(crate) align: usize
+}
+
+pub struct Function {
+    pub(crate) name: String,
+    pub(crate) signature: Signature,
+    pub(crate) params: Vec<ValueId>,
+    pub(crate) blocks: Vec<Block>,
+    pub(crate) start_block: BlockId,
+    pub(crate) values: Vec<Type>,
+    pub(crate) stack_slots: Vec<StackSlot>
+}
+
+impl Function {
+    fn new(name: &str, signature: Signature) -> Function {
+        let mut function = Function {
+            name: name.to_owned(),
+            signature,
+            params: vec![],
+            blocks: vec![],
+            start_block: BlockId(0),
+            values: vec![],
+            stack_slots: vec![]
+        };
+
+        for ty in function.signature.params.clone() {
+            let param = function.new_value(ty);
+            function.params.push(param);
+        }
+
+        function
+    }
+
+    // a piece of the stack frame that lives for the whole function
+    fn add_stack_slot(&mut self, ty: Type, align: usize) -> StackSlotId {
+        self.stack_slots.push(StackSlot { ty, align });
+        StackSlotId(self.stack_slots.len() - 1)
+    }
+
+    fn new_value(&mut self, ty: Type) -> ValueId {
+        self.values.push(ty);
+        ValueId(self.values.len() - 1)
+    }
+
+    pub(crate) fn value_type(&self, value: &Value) -> Type {
+        match value {
+            Value::Const(val) => val.get_type(),
+            Value::ConstRef(_) => Type::Ptr,
+            Value::Ref(id) => self.values[id.0].clone()
+        }
+    }
+
+    // appends `instruction` to `block` and returns its result, typed the way the instruction says
+    fn append(&mut self, block: BlockId, result_type: Type, instruction: impl FnOnce(ValueId) -> Instruction) -> Value {
+        let result = self.new_value(result_type);
+        let instruction = instruction(result);
+        self.block_mut(block).add_instruction(instruction);
+        Value::Ref(result)
+    }
+
+    fn binary(&mut self, block: BlockId, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+        let ty = self.value_type(&lhs);
+        self.append(block, ty, |result| Instruction::Binary { result, op, lhs, rhs })
+    }
+
+    fn icmp(&mut self, block: BlockId, cond: IntCondition, lhs: Value, rhs: Value) -> Value {
+        self.append(block, Type::I1, |result| Instruction::ICmp { result, cond, lhs, rhs })
+    }
+
+    // returns the call's result, or None when the callee returns void
+    fn call(&mut self, block: BlockId, callee: &str, signature: &Signature, args: Vec<Value>) -> Option<Value> {
+        let callee = callee.to_owned();
+
+        if signature.return_type == Type::Void {
+            self.block_mut(block).add_instruction(Instruction::Call { result: None, callee, args });
+            None
+        } else {
+            Some(self.append(block, signature.return_type.clone(), |result| Instruction::Call { result: Some(result), callee, args }))
+        }
+    }
+
+    fn stack_addr(&mut self, block: BlockId, slot: StackSlotId) -> Value {
+        self.append(block, Type::Ptr, |result| Instruction::StackAddr { result, slot })
+    }
+
+    fn load(&mut self, block: BlockId, ty: Type, ptr: Value, align: usize) -> Value {
+        self.append(block, ty, |result| Instruction::Load { result, ptr, align })
+    }
+
+    fn store(&mut self, block: BlockId, value: Value, ptr: Value, align: usize) {
+        self.block_mut(block).add_instruction(Instruction::Store { value, ptr, align });
+    }
+
+    // address of `ptr[indices[0]][indices[1]]...`, where `ptr` points at a `ty`
+    fn gep(&mut self, block: BlockId, ty: Type, ptr: Value, indices: Vec<Value>) -> Value {
+        self.append(block, Type::Ptr, |result| Instruction::Gep { result, ty, ptr, indices })
+    }
+
+    fn global_addr(&mut self, block: BlockId, global: &str) -> Value {
+        let global = global.to_owned();
+        self.append(block, Type::Ptr, |result| Instruction::GlobalAddr { result, global })
+    }
+
+    fn cast(&mut self, block: BlockId, op: CastOp, value: Value, ty: Type) -> Value {
+        self.append(block, ty.clone(), |result| Instruction::Cast { result, op, value, ty })
+    }
+
+    // the first block added becomes the start block
+    fn add_block(&mut self, block: Block) -> BlockId {
+        self.blocks.push(block);
+        BlockId(self.blocks.len() - 1)
+    }
+
+    pub(crate) fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    fn block_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id.0]
+    }
+
+    // blocks reachable from the start block, in reverse postorder
+    pub(crate) fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut postorder = vec![];
+
+        if self.blocks.is_empty() {
+            return postorder;
+        }
+
+        let mut visited = vec![false; self.blocks.len()];
+        let mut stack = vec![(self.start_block, 0)];
+        visited[self.start_block.0] = true;
+
+        while let Some((id, next_succ)) = stack.pop() {
+            let successors = self.block(id).successors();
+
+            if next_succ < successors.len() {
+                stack.push((id, next_succ + 1));
+
+                let succ = successors[next_succ];
+                if !visited[succ.0] {
+                    visited[succ.0] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                postorder.push(id);
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+}
+
+#[derive(Clone)]
+pub struct Block { 
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) terminator: Option<Terminator> 
+}
+
+impl Block {
+    fn new() -> Block {
+        Block {
+            instructions: vec![],
+            terminator: None
+        }
+    }
+
+    fn set_terminator(&mut self, terminator: Terminator) {
+        self.terminator = Some(terminator);
+    }
+
+    fn add_instruction(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+
+    pub(crate) fn successors(&self) -> Vec<BlockId> {
+        match &self.terminator {
+            Some(terminator) => terminator.successors(),
+            None => vec![]
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    SDiv,
+    UDiv,
+    SRem,
+    URem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    LShr,
+    AShr
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IntCondition {
+    Eq,
+    Ne,
+    SLt,
+    SLe,
+    SGt,
+    SGe,
+    ULt,
+    ULe,
+    UGt,
+    UGe
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CastOp {
+    ZExt,
+    SExt,
+    Trunc
+}
+
+#[derive(Clone)]
+pub enum Instruction {
+    Asm(Vec<u8>),
+    AsmValue(Value),
+    Binary {
+        result: ValueId,
+        op: BinaryOp,
+        lhs: Value,
+        rhs: Value
+    },
+    ICmp {
+        result: ValueId,
+        cond: IntCondition,
+        lhs: Value,
+        rhs: Value
+    },
+    Cast {
+        result: ValueId,
+        op: CastOp,
+        value: Value,
+        ty: Type
+    },
+    Call {
+        result: Option<ValueId>,
+        callee: String,
+        args: Vec<Value>
+    },
+    GlobalAddr {
+        result: ValueId,
+        global: String
+    },
+    StackAddr {
+        result: ValueId,
+        slot: StackSlotId
+    },
+    Load {
+        result: ValueId,
+        ptr: Value,
+        align: usize
+    },
+    Store {
+        value: Value,
+        ptr: Value,
+        align: usize
+    },
+    // struct indices have to be constants, array and pointer indices can be any integer
+    Gep {
+        result: ValueId,
+        ty: Type,
+        ptr: Value,
+        indices: Vec<Value>
+    }
+}
+
+impl Instruction {
+    pub(crate) fn result(&self) -> Option<ValueId> {
+        match self {
+            Instruction::Asm(_) | Instruction::AsmValue(_) | Instruction::Store { .. } => None,
+            Instruction::Call { result, .. } => *result,
+            Instruction::Binary { result, .. }
+            | Instruction::ICmp { result, .. }
+            | Instruction::Cast { result, .. }
+            | Instruction::GlobalAddr { result, .. }
+            | Instruction::StackAddr { result, .. }
+            | Instruction::Load { result, .. }
+            | Instruction::Gep { result, .. } => Some(*result)
+        }
+    }
+
+    pub(crate) fn result_mut(&mut self) -> Option<&mut ValueId> {
+        match self {
+            Instruction::Asm(_) | Instruction::AsmValue(_) | Instruction::Store { .. } => None,
+            Instruction::Call { result, .. } => result.as_mut(),
+            Instruction::Binary { result, .. }
+            | Instruction::ICmp { result, .. }
+            | Instruction::Cast { result, .. }
+            | Instruction::GlobalAddr { result, .. }
+            | Instruction::StackAddr { result, .. }
+            | Instruction::Load { result, .. }
+            | Instruction::Gep { result, .. } => Some(result)
+        }
+    }
+
+    pub(crate) fn operands(&self) -> Vec<&Value> {
+        match self {
+            Instruction::Asm(_) | Instruction::GlobalAddr { .. } | Instruction::StackAddr { .. } => vec![],
+            Instruction::AsmValue(value) | Instruction::Cast { value, .. } => vec![value],
+            Instruction::Binary { lhs, rhs, .. } | Instruction::ICmp { lhs, rhs, .. } => vec![lhs, rhs],
+            Instruction::Call { args, .. } => args.iter().collect(),
+            Instruction::Load { ptr, .. } => vec![ptr],
+            Instruction::Store { value, ptr, .. } => vec![value, ptr],
+            Instruction::Gep { ptr, indices, .. } => std::iter::once(ptr).chain(indices).collect()
+        }
+    }
+
+    pub(crate) fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Instruction::Asm(_) | Instruction::GlobalAddr { .. } | Instruction::StackAddr { .. } => vec![],
+            Instruction::AsmValue(value) | Instruction::Cast { value, .. } => vec![value],
+            Instruction::Binary { lhs, rhs, .. } | Instruction::ICmp { lhs, rhs, .. } => vec![lhs, rhs],
+            Instruction::Call { args, .. } => args.iter_mut().collect(),
+            Instruction::Load { ptr, .. } => vec![ptr],
+            Instruction::Store { value, ptr, .. } => vec![value, ptr],
+            Instruction::Gep { ptr, indices, .. } => std::iter::once(ptr).chain(indices).collect()
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum ConstValue {
+    Bool(bool),
+    UInt8(u8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    // elements all have the given type
+    Array(Type, Vec<ConstValue>),
+    Struct(Vec<ConstValue>)
+}
+
+impl ConstValue {
+    // the raw bits of a scalar, as they would sit in a register
+    pub(crate) fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Bool(val) => Some(*val as i64),
+            Self::UInt8(num) => Some(*num as i64),
+            Self::Int16(num) => Some(*num as i64),
+            Self::Int32(num) => Some(*num as i64),
+            Self::Int64(num) => Some(*num),
+            Self::Float32(num) => Some(num.to_bits() as i64),
+            Self::Float64(num) => Some(num.to_bits() as i64),
+            Self::Array(..) | Self::Struct(_) => None
+        }
+    }
+
+    pub(crate) fn get_type(&self) -> Type {
+        match self {
+            Self::Bool(_) => Type::I1,
+            Self::UInt8(_) => Type::I8,
+            Self::Int16(_) => Type::I16,
+            Self::Int32(_) => Type::I32,
+            Self::Int64(_) => Type::I64,
+            Self::Float32(_) => Type::F32,
+            Self::Float64(_) => Type::F64,
+            Self::Array(elem, vals) => Type::Array(Box::new(elem.clone()), vals.len()),
+            Self::Struct(fields) => Type::Struct(fields.iter().map(|field| field.get_type()).collect())
+        }
+    }
+}
+
+impl Serializable for ConstValue {
+    fn serialize(&self, big_endian: bool) -> Vec<u8> {
+        match self {
+            Self::Bool(val) => {(*val as u8).to_bytes(big_endian)}
+            Self::UInt8(num) => {num.to_bytes(big_endian)}
+            Self::Int16(num) => {num.to_bytes(big_endian)}
+            Self::Int32(num) => {num.to_bytes(big_endian)}
+            Self::Int64(num) => {num.to_bytes(big_endian)}
+            Self::Float32(num) => {num.to_bits().to_bytes(big_endian)}
+            Self::Float64(num) => {num.to_bits().to_bytes(big_endian)}
+            Self::Array(_, vals) => {vals.serialize(big_endian)}
+            Self::Struct(fields) => {
+                let (offsets, size) = self.get_type().struct_layout();
+                let mut vec = Vec::with_capacity(size);
+
+                for (field, offset) in fields.iter().zip(offsets) {
+                    vec.resize(offset, 0);
+                    vec.extend(field.serialize(big_endian));
+                }
+
+                vec.resize(size, 0);
+                vec
+            }
+        }
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.get_type().size()
+    }
+}
+
+#[derive(Clone)]
+pub enum Value {
+    Const(ConstValue),
+    ConstRef(ConstValue),
+    Ref(ValueId)
+}
+
+
+impl Value {
+    /// An `i64` constant.
+    pub fn const_i64(num: i64) -> Value {
+        Value::Const(ConstValue::Int64(num))
+    }
+
+    /// The address of a NUL-terminated copy of `string` in read-only data.
+    pub fn const_str(string: String) -> Value {
+        Value::ConstRef(
+            ConstValue::Array(
+                Type::I8,
+                string.serialize(false).into_iter().map(ConstValue::UInt8).collect()
+            )
+        )
+    }
+}
+
+#[derive(Clone)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        cond: Value,
+        if_true: BlockId,
+        if_false: BlockId
+    },
+    Switch {
+        value: Value,
+        cases: Vec<(i64, BlockId)>,
+        default: BlockId
+    },
+    Return(Option<Value>)
+}
+
+impl Terminator {
+    pub(crate) fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { if_true, if_false, .. } => vec![*if_true, *if_false],
+            Terminator::Switch { cases, default, .. } => {
+                let mut successors: Vec<BlockId> = cases.iter().map(|(_, target)| *target).collect();
+                successors.push(*default);
+                successors
+            },
+            Terminator::Return(_) => vec![]
+        }
+    }
+
+    pub(crate) fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch { if_true, if_false, .. } => vec![if_true, if_false],
+            Terminator::Switch { cases, default, .. } => {
+                cases.iter_mut().map(|(_, target)| target).chain(std::iter::once(default)).collect()
+            },
+            Terminator::Return(_) => vec![]
+        }
+    }
+
+    pub(crate) fn operands(&self) -> Vec<&Value> {
+        match self {
+            Terminator::Jump(_) | Terminator::Return(None) => vec![],
+            Terminator::Branch { cond: value, .. } | Terminator::Switch { value, .. } | Terminator::Return(Some(value)) => vec![value]
+        }
+    }
+
+    pub(crate) fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Terminator::Jump(_) | Terminator::Return(None) => vec![],
+            Terminator::Branch { cond: value, .. } | Terminator::Switch { value, .. } | Terminator::Return(Some(value)) => vec![value]
+        }
+    }
+}
+
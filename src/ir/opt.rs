@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use crate::error::ChairError;
+use crate::ir::{BinaryOp, BlockId, CastOp, ConstValue, Function, Instruction, IntCondition, Terminator, TranslationUnit, Value, ValueId};
+use crate::ir::types::Type;
+use crate::ir::verify::verify_translation_unit;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2
+}
+
+// runs the IR passes for `level` over every function. The passes walk the CFG, so it's verified first
+pub fn optimize(translation_unit: &mut TranslationUnit, level: OptLevel) -> Result<(), ChairError> {
+    verify_translation_unit(translation_unit)?;
+
+    if level == OptLevel::O0 {
+        return Ok(());
+    }
+
+    for function in &mut translation_unit.functions {
+        fold_constants(function);
+        fold_branches(function);
+        remove_unreachable_blocks(function);
+        compact_values(function);
+    }
+
+    Ok(())
+}
+
+// replaces integer arithmetic, comparisons and casts of constants with their results
+fn fold_constants(function: &mut Function) {
+    let mut folded: HashMap<ValueId, ConstValue> = HashMap::new();
+
+    loop {
+        let mut changed = false;
+
+        for block in &mut function.blocks {
+            for instruction in &mut block.instructions {
+                substitute(instruction.operands_mut(), &folded);
+            }
+
+            if let Some(terminator) = &mut block.terminator {
+                substitute(terminator.operands_mut(), &folded);
+            }
+
+            block.instructions.retain(|instruction| {
+                match (instruction.result(), fold(instruction, &function.values)) {
+                    (Some(result), Some(val)) => {
+                        folded.insert(result, val);
+                        changed = true;
+                        false
+                    },
+                    _ => true
+                }
+            });
+        }
+
+        if !changed {
+            break;
+        }
+    }
+}
+
+fn substitute(operands: Vec<&mut Value>, folded: &HashMap<ValueId, ConstValue>) {
+    for operand in operands {
+        if let Value::Ref(id) = operand && let Some(val) = folded.get(id) {
+            *operand = Value::Const(val.clone());
+        }
+    }
+}
+
+fn extend(num: i64, bits: u32, signed: bool) -> i64 {
+    if bits >= 64 {
+        return num;
+    }
+
+    let shift = 64 - bits;
+
+    if signed {
+        (num << shift) >> shift
+    } else {
+        (((num as u64) << shift) >> shift) as i64
+    }
+}
+
+fn int_const(ty: &Type, num: i64) -> Option<ConstValue> {
+    match ty {
+        Type::I1 => Some(ConstValue::Bool(num & 1 != 0)),
+        Type::I8 => Some(ConstValue::UInt8(num as u8)),
+        Type::I16 => Some(ConstValue::Int16(num as i16)),
+        Type::I32 => Some(ConstValue::Int32(num as i32)),
+        Type::I64 => Some(ConstValue::Int64(num)),
+        _ => None
+    }
+}
+
+// an integer constant operand, extended to 64 bits the way codegen would load it
+fn const_operand(value: &Value, signed: bool) -> Option<(i64, Type)> {
+    match value {
+        Value::Const(val) if val.get_type().is_integer() => {
+            let ty = val.get_type();
+            Some((extend(val.as_i64()?, ty.bits(), signed), ty))
+        },
+        _ => None
+    }
+}
+
+// computes an instruction's result the same way the generated code would, or None if it can't be known
+fn fold(instruction: &Instruction, values: &[Type]) -> Option<ConstValue> {
+    match instruction {
+        Instruction::Binary { result, op, lhs, rhs } => {
+            let signed = matches!(op, BinaryOp::SDiv | BinaryOp::SRem | BinaryOp::AShr);
+            let (lhs, _) = const_operand(lhs, signed)?;
+            let (rhs, _) = const_operand(rhs, signed)?;
+
+            let num = match op {
+                BinaryOp::Add => lhs.wrapping_add(rhs),
+                BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                BinaryOp::And => lhs & rhs,
+                BinaryOp::Or => lhs | rhs,
+                BinaryOp::Xor => lhs ^ rhs,
+                BinaryOp::Shl => lhs << (rhs & 63),
+                BinaryOp::LShr => ((lhs as u64) >> (rhs & 63)) as i64,
+                BinaryOp::AShr => lhs >> (rhs & 63),
+                // division by zero and overflow trap at runtime, so they're left alone
+                BinaryOp::SDiv => lhs.checked_div(rhs)?,
+                BinaryOp::SRem => lhs.checked_rem(rhs)?,
+                BinaryOp::UDiv => (lhs as u64).checked_div(rhs as u64)? as i64,
+                BinaryOp::URem => (lhs as u64).checked_rem(rhs as u64)? as i64
+            };
+
+            int_const(&values[result.0], num)
+        },
+        Instruction::ICmp { cond, lhs, rhs, .. } => {
+            let signed = matches!(cond, IntCondition::SLt | IntCondition::SLe | IntCondition::SGt | IntCondition::SGe);
+            let (lhs, _) = const_operand(lhs, signed)?;
+            let (rhs, _) = const_operand(rhs, signed)?;
+
+            let result = match cond {
+                IntCondition::Eq => lhs == rhs,
+                IntCondition::Ne => lhs != rhs,
+                IntCondition::SLt => lhs < rhs,
+                IntCondition::SLe => lhs <= rhs,
+                IntCondition::SGt => lhs > rhs,
+                IntCondition::SGe => lhs >= rhs,
+                IntCondition::ULt => (lhs as u64) < rhs as u64,
+                IntCondition::ULe => (lhs as u64) <= rhs as u64,
+                IntCondition::UGt => (lhs as u64) > rhs as u64,
+                IntCondition::UGe => (lhs as u64) >= rhs as u64
+            };
+
+            Some(ConstValue::Bool(result))
+        },
+        Instruction::Cast { op, value, ty, .. } => {
+            let (num, _) = const_operand(value, matches!(op, CastOp::SExt))?;
+            int_const(ty, num)
+        },
+        _ => None
+    }
+}
+
+// turns branches and switches on constants into jumps
+fn fold_branches(function: &mut Function) {
+    for block in &mut function.blocks {
+        let target = match &block.terminator {
+            Some(Terminator::Branch { cond: Value::Const(ConstValue::Bool(cond)), if_true, if_false }) => {
+                if *cond { *if_true } else { *if_false }
+            },
+            Some(Terminator::Switch { value, cases, default }) => {
+                let Some((num, ty)) = const_operand(value, true) else { continue };
+
+                cases.iter()
+                    .find(|(case, _)| extend(*case, ty.bits(), true) == num)
+                    .map_or(*default, |(_, target)| *target)
+            },
+            _ => continue
+        };
+
+        block.terminator = Some(Terminator::Jump(target));
+    }
+}
+
+// drops blocks that can't be reached from the start block, keeping the rest in order
+fn remove_unreachable_blocks(function: &mut Function) {
+    if function.blocks.is_empty() {
+        return;
+    }
+
+    let mut reachable = vec![false; function.blocks.len()];
+    for id in function.reverse_postorder() {
+        reachable[id.0] = true;
+    }
+
+    let mut new_ids = vec![None; function.blocks.len()];
+    let mut next = 0;
+    for (i, reachable) in reachable.iter().enumerate() {
+        if *reachable {
+            new_ids[i] = Some(BlockId(next));
+            next += 1;
+        }
+    }
+
+    let blocks = std::mem::take(&mut function.blocks);
+    function.blocks = blocks.into_iter()
+        .zip(reachable)
+        .filter_map(|(block, reachable)| reachable.then_some(block))
+        .collect();
+
+    for block in &mut function.blocks {
+        if let Some(terminator) = &mut block.terminator {
+            for target in terminator.successors_mut() {
+                *target = new_ids[target.0].unwrap();
+            }
+        }
+    }
+
+    function.start_block = new_ids[function.start_block.0].unwrap();
+}
+
+// renumbers values so the ones still defined are dense again: parameters first, then in block order
+fn compact_values(function: &mut Function) {
+    let mut new_ids: Vec<Option<ValueId>> = vec![None; function.values.len()];
+    let mut values = vec![];
+
+    let mut renumber = |id: &mut ValueId| {
+        values.push(function.values[id.0].clone());
+        new_ids[id.0] = Some(ValueId(values.len() - 1));
+        *id = ValueId(values.len() - 1);
+    };
+
+    for param in &mut function.params {
+        renumber(param);
+    }
+
+    for block in &mut function.blocks {
+        for instruction in &mut block.instructions {
+            if let Some(result) = instruction.result_mut() {
+                renumber(result);
+            }
+        }
+    }
+
+    for block in &mut function.blocks {
+        let operands = block.instructions.iter_mut()
+            .flat_map(|instruction| instruction.operands_mut())
+            .chain(block.terminator.iter_mut().flat_map(|terminator| terminator.operands_mut()));
+
+        for operand in operands {
+            if let Value::Ref(id) = operand {
+                *id = new_ids[id.0].unwrap();
+            }
+        }
+    }
+
+    function.values = values;
+}
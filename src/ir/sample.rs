@@ -1,39 +1,69 @@
-use crate::ir::{Block, Function, Instruction, Terminator, TranslationUnit, Value};
-
-pub fn get_example_translation_unit() -> TranslationUnit {
-    let mut translation_unit = TranslationUnit::new("cook");
-
-    let mut block = Block::new();
-
-    let str = Value::const_str("Hello, World!\n".to_owned());
-
-    block.add_instruction(Instruction::Asm(vec![
-        0x6A, 0x1,                                  // push 1
-        0x58,                                       // pop rax
-        0x6A, 0x1,                                  // push 1
-        0x5F,                                       // pop rdi
-        0x48, 0xbe                                  // put following 64-bit immediate into rsi
-    ]));
-    
-    block.add_instruction(Instruction::AsmValue(str));
-
-    block.add_instruction(Instruction::Asm(vec![
-        0x6a, 14,                                   // push 14
-        0x5a,                                       // pop rdx
-        0x0f, 0x05,                                 // syscall
-        0x6a, 0x3c,                                 // push 60
-        0x58,                                       // pop rax
-        0x48, 0xbf,                                 // put following 64-bit immediate into rdi
-    ]));
-
-    block.add_instruction(Instruction::AsmValue(Value::const_i64(0)));
-
-    block.add_instruction(Instruction::Asm(vec![
-        0x0f, 0x05,                                 // syscall
-    ]));
-
-    block.set_terminator(Terminator::Return);
-    translation_unit.add_function(Function::new("_start", block));
-
-    translation_unit
-}
+use crate::codegen::x64::asm::{assemble, AluOp, Mem, Operand, Reg, Size, Target, X64Inst};
+use crate::error::ChairError;
+use crate::ir::{BinaryOp, Builder, ConstValue, IntCondition, TranslationUnit, Value};
+use crate::ir::types::{Signature, Type};
+
+pub fn get_example_translation_unit() -> Result<TranslationUnit, ChairError> {
+    let mut builder = Builder::new("cook");
+
+    let subtract_signature = Signature::new(vec![Type::I8, Type::I8], Type::I8);
+    let mut subtract = builder.function("subtract", subtract_signature.clone());
+    let body = subtract.create_block();
+    subtract.switch_to_block(body)?;
+    let difference = subtract.binary(BinaryOp::Sub, subtract.param(0)?, subtract.param(1)?)?;
+    subtract.ret(Some(difference))?;
+    subtract.finish();
+
+    let mut function = builder.function("_start", Signature::new(vec![], Type::Void));
+
+    let start = function.create_block();
+    let hello = function.create_block();
+    let dispatch = function.create_block();
+    let exit = function.create_block();
+
+    function.switch_to_block(exit)?;
+    function.asm(encode(&[
+        X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(Reg::Rax), src: Operand::Imm(60) },
+        X64Inst::Alu { op: AluOp::Xor, size: Size::Dword, dst: Operand::Reg(Reg::Rdi), src: Operand::Reg(Reg::Rdi) },
+        X64Inst::Syscall
+    ]))?;
+    function.ret(None)?;
+
+    function.switch_to_block(start)?;
+    let greet = function.icmp(IntCondition::Ne, Value::const_i64(1), Value::const_i64(0))?;
+    function.branch(greet, hello, exit)?;
+
+    function.switch_to_block(hello)?;
+
+    let str = Value::const_str("Hello, World!\n".to_owned());
+
+    function.asm(encode(&[
+        X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(Reg::Rax), src: Operand::Imm(1) },
+        X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(Reg::Rdi), src: Operand::Imm(1) },
+        X64Inst::Mov { size: Size::Dword, dst: Operand::Reg(Reg::Rdx), src: Operand::Imm(14) }
+    ]))?;
+
+    // lea rsi, [rip + disp32], where asm_value fills in the displacement to the string
+    let lea = encode(&[X64Inst::Lea { dst: Reg::Rsi, src: Mem::rip(Target::Symbol(0)) }]);
+    function.asm(lea[..lea.len() - 4].to_vec())?;
+    function.asm_value(str)?;
+
+    function.asm(encode(&[X64Inst::Syscall]))?;
+
+    function.jump(dispatch)?;
+
+    function.switch_to_block(dispatch)?;
+    let choice = function.call("subtract", &subtract_signature, vec![
+        Value::Const(ConstValue::UInt8(5)),
+        Value::Const(ConstValue::UInt8(3))
+    ])?.unwrap();
+
+    function.switch(choice, vec![(0, exit), (1, hello), (2, exit), (3, hello)], exit)?;
+    function.finish();
+
+    Ok(builder.finish())
+}
+
+fn encode(instructions: &[X64Inst]) -> Vec<u8> {
+    assemble(instructions).0
+}
@@ -0,0 +1,212 @@
+use crate::ir::text::ParseError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenKind {
+    Ident(String),
+    Global(String),
+    Local(usize),
+    Slot(usize),
+    Number(String),
+    Str(Vec<u8>),
+    CStr(Vec<u8>),
+    Punct(char),
+    Arrow,
+    Eof
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Token {
+    pub(crate) kind: TokenKind,
+    pub(crate) line: usize,
+    pub(crate) column: usize
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize
+}
+
+impl Lexer<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut string = String::new();
+
+        while let Some(c) = self.peek() {
+            if !pred(c) {
+                break;
+            }
+            string.push(c);
+            self.bump();
+        }
+
+        string
+    }
+
+    fn index(&mut self, sigil: char) -> Result<usize, ParseError> {
+        let digits = self.take_while(|c| c.is_ascii_digit());
+
+        digits.parse().map_err(|_| self.error(format!("expected a number after '{}'", sigil)))
+    }
+
+    // the opening quote has already been consumed
+    fn string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let mut bytes = vec![];
+
+        loop {
+            // reported where the line ends rather than at the start of the next one
+            let Some(c) = self.peek().filter(|c| *c != '\n') else {
+                return Err(self.error("unterminated string".to_owned()));
+            };
+            self.bump();
+
+            match c {
+                '"' => return Ok(bytes),
+                '\\' => {
+                    match self.bump() {
+                        Some('n') => bytes.push(b'\n'),
+                        Some('t') => bytes.push(b'\t'),
+                        Some('"') => bytes.push(b'"'),
+                        Some('\\') => bytes.push(b'\\'),
+                        Some(high) => {
+                            let low = self.bump().unwrap_or(' ');
+                            let byte = u8::from_str_radix(&format!("{}{}", high, low), 16)
+                                .map_err(|_| self.error(format!("invalid escape '\\{}{}'", high, low)))?;
+                            bytes.push(byte);
+                        },
+                        None => return Err(self.error("unterminated string".to_owned()))
+                    }
+                },
+                c => {
+                    let mut buf = [0; 4];
+                    bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+pub(crate) fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut lexer = Lexer {
+        chars: source.chars().peekable(),
+        line: 1,
+        column: 1
+    };
+    let mut tokens = vec![];
+
+    loop {
+        lexer.take_while(|c| c.is_whitespace());
+
+        let (line, column) = (lexer.line, lexer.column);
+
+        let kind = match lexer.peek() {
+            None => TokenKind::Eof,
+            Some(';') => {
+                lexer.take_while(|c| c != '\n');
+                continue;
+            },
+            Some('@') => {
+                lexer.bump();
+
+                if lexer.peek() == Some('"') {
+                    lexer.bump();
+                    let bytes = lexer.string()?;
+                    TokenKind::Global(String::from_utf8(bytes).map_err(|_| lexer.error("name is not valid UTF-8".to_owned()))?)
+                } else {
+                    let name = lexer.take_while(is_name_char);
+                    if name.is_empty() {
+                        return Err(lexer.error("expected a name after '@'".to_owned()));
+                    }
+                    TokenKind::Global(name)
+                }
+            },
+            Some('%') => {
+                lexer.bump();
+                TokenKind::Local(lexer.index('%')?)
+            },
+            Some('$') => {
+                lexer.bump();
+                TokenKind::Slot(lexer.index('$')?)
+            },
+            Some('"') => {
+                lexer.bump();
+                TokenKind::Str(lexer.string()?)
+            },
+            Some('-') => {
+                lexer.bump();
+
+                if lexer.peek() == Some('>') {
+                    lexer.bump();
+                    TokenKind::Arrow
+                } else {
+                    let number = lexer.take_while(|c| c.is_ascii_alphanumeric() || c == '.' || c == '+' || c == '-');
+                    TokenKind::Number(format!("-{}", number))
+                }
+            },
+            Some(c) if c.is_ascii_digit() => {
+                let mut number = String::new();
+
+                while let Some(c) = lexer.peek() {
+                    let exponent_sign = (c == '+' || c == '-') && number.ends_with(['e', 'E']);
+                    if !(c.is_ascii_alphanumeric() || c == '.' || exponent_sign) {
+                        break;
+                    }
+                    number.push(c);
+                    lexer.bump();
+                }
+
+                TokenKind::Number(number)
+            },
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let ident = lexer.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+
+                if ident == "c" && lexer.peek() == Some('"') {
+                    lexer.bump();
+                    TokenKind::CStr(lexer.string()?)
+                } else {
+                    TokenKind::Ident(ident)
+                }
+            },
+            Some(c) if "(){}[],:=".contains(c) => {
+                lexer.bump();
+                TokenKind::Punct(c)
+            },
+            Some(c) => return Err(lexer.error(format!("unexpected character '{}'", c)))
+        };
+
+        let eof = kind == TokenKind::Eof;
+        tokens.push(Token { kind, line, column });
+
+        if eof {
+            return Ok(tokens);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+
+mod lexer;
+pub mod parser;
+mod printer;
+
+// the textual IR format, `.chir`
+//
+// unit "hello"
+//
+// declare @puts(ptr) -> i32
+//
+// const @message: [6 x i8] = [6 x i8] c"hello\00"
+//
+// fn @main() -> i32 {
+// bb0:
+//     %0 = global_addr @message
+//     %1 = call i32 @puts(%0)
+//     ret i32 0
+// }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,737 @@
+use std::collections::HashMap;
+
+use crate::ir::{BinaryOp, Block, BlockId, CastOp, ConstValue, Function, Global, Instruction, IntCondition, StackSlot, StackSlotId, Terminator, TranslationUnit, Value, ValueId};
+use crate::ir::text::ParseError;
+use crate::ir::text::lexer::{tokenize, Token, TokenKind};
+use crate::ir::types::{Signature, Type};
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize
+}
+
+// per-function state while parsing its body
+struct FunctionScope {
+    values: Vec<Option<Type>>,
+    // where each value was first used, for reporting values that are never defined
+    uses: HashMap<usize, (usize, usize)>
+}
+
+impl FunctionScope {
+    fn define(&mut self, id: usize, ty: Type) -> bool {
+        if self.values.len() <= id {
+            self.values.resize(id + 1, None);
+        }
+
+        if self.values[id].is_some() {
+            return false;
+        }
+
+        self.values[id] = Some(ty);
+        true
+    }
+}
+
+pub fn parse_translation_unit(source: &str) -> Result<TranslationUnit, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0
+    };
+
+    parser.translation_unit()
+}
+
+impl Parser {
+    fn peek(&self) -> &TokenKind {
+        &self.tokens[self.pos].kind
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error_at(&self, token: &Token, message: String) -> ParseError {
+        ParseError {
+            line: token.line,
+            column: token.column,
+            message
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        Err(self.error_at(&self.tokens[self.pos], message))
+    }
+
+    fn describe(kind: &TokenKind) -> String {
+        match kind {
+            TokenKind::Ident(ident) => format!("'{}'", ident),
+            TokenKind::Global(name) => format!("'@{}'", name),
+            TokenKind::Local(id) => format!("'%{}'", id),
+            TokenKind::Slot(id) => format!("'${}'", id),
+            TokenKind::Number(number) => format!("'{}'", number),
+            TokenKind::Str(_) | TokenKind::CStr(_) => "string".to_owned(),
+            TokenKind::Punct(c) => format!("'{}'", c),
+            TokenKind::Arrow => "'->'".to_owned(),
+            TokenKind::Eof => "end of file".to_owned()
+        }
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, ParseError> {
+        self.error(format!("expected {}, found {}", expected, Self::describe(self.peek())))
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        *self.peek() == TokenKind::Punct(c)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), TokenKind::Ident(ident) if ident == keyword)
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), ParseError> {
+        if self.is_punct(c) {
+            self.next();
+            Ok(())
+        } else {
+            self.unexpected(&format!("'{}'", c))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.is_keyword(keyword) {
+            self.next();
+            Ok(())
+        } else {
+            self.unexpected(&format!("'{}'", keyword))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            TokenKind::Ident(ident) => {
+                self.next();
+                Ok(ident)
+            },
+            _ => self.unexpected("an identifier")
+        }
+    }
+
+    fn global_name(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            TokenKind::Global(name) => {
+                self.next();
+                Ok(name)
+            },
+            _ => self.unexpected("a global name")
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            TokenKind::Str(bytes) => {
+                let token = self.next();
+                String::from_utf8(bytes).map_err(|_| self.error_at(&token, "string is not valid UTF-8".to_owned()))
+            },
+            _ => self.unexpected("a string")
+        }
+    }
+
+    fn integer<T: std::str::FromStr>(&mut self) -> Result<T, ParseError> {
+        match self.peek().clone() {
+            TokenKind::Number(number) => {
+                let token = self.next();
+                number.parse().map_err(|_| self.error_at(&token, format!("invalid or out of range integer '{}'", number)))
+            },
+            _ => self.unexpected("an integer")
+        }
+    }
+
+    fn block_label(&mut self) -> Result<BlockId, ParseError> {
+        match self.peek().clone() {
+            TokenKind::Ident(ident) if ident.starts_with("bb") && ident.len() > 2 => {
+                let token = self.next();
+                ident[2..].parse().map(BlockId).map_err(|_| self.error_at(&token, format!("invalid block label '{}'", ident)))
+            },
+            _ => self.unexpected("a block label")
+        }
+    }
+
+    fn comma_separated<T>(&mut self, close: char, mut item: impl FnMut(&mut Self) -> Result<T, ParseError>) -> Result<Vec<T>, ParseError> {
+        let mut items = vec![];
+
+        while !self.is_punct(close) {
+            if !items.is_empty() {
+                self.expect_punct(',')?;
+            }
+            items.push(item(self)?);
+        }
+        self.next();
+
+        Ok(items)
+    }
+
+    fn ty(&mut self) -> Result<Type, ParseError> {
+        if self.is_punct('[') {
+            self.next();
+            let len = self.integer()?;
+            self.expect_keyword("x")?;
+            let elem = self.ty()?;
+            self.expect_punct(']')?;
+            return Ok(Type::Array(Box::new(elem), len));
+        }
+
+        if self.is_punct('{') {
+            self.next();
+            let fields = self.comma_separated('}', |parser| parser.ty())?;
+            return Ok(Type::Struct(fields));
+        }
+
+        let ty = match self.peek() {
+            TokenKind::Ident(ident) => match ident.as_str() {
+                "i1" => Type::I1,
+                "i8" => Type::I8,
+                "i16" => Type::I16,
+                "i32" => Type::I32,
+                "i64" => Type::I64,
+                "f32" => Type::F32,
+                "f64" => Type::F64,
+                "ptr" => Type::Ptr,
+                "void" => Type::Void,
+                _ => return self.unexpected("a type")
+            },
+            _ => return self.unexpected("a type")
+        };
+        self.next();
+
+        Ok(ty)
+    }
+
+    fn is_type_start(&self) -> bool {
+        match self.peek() {
+            TokenKind::Ident(ident) => matches!(ident.as_str(), "i1" | "i8" | "i16" | "i32" | "i64" | "f32" | "f64" | "ptr" | "void"),
+            TokenKind::Punct('[') | TokenKind::Punct('{') => true,
+            _ => false
+        }
+    }
+
+    fn float_text(&mut self) -> Result<(Token, String), ParseError> {
+        match self.peek().clone() {
+            TokenKind::Number(number) | TokenKind::Ident(number) => Ok((self.next(), number)),
+            _ => self.unexpected("a floating point number")
+        }
+    }
+
+    // a constant of type `ty`, without the leading type
+    fn literal(&mut self, ty: &Type) -> Result<ConstValue, ParseError> {
+        match ty {
+            Type::I1 => {
+                match self.ident()?.as_str() {
+                    "true" => Ok(ConstValue::Bool(true)),
+                    "false" => Ok(ConstValue::Bool(false)),
+                    _ => {
+                        self.pos -= 1;
+                        self.unexpected("'true' or 'false'")
+                    }
+                }
+            },
+            Type::I8 => {
+                let token = self.tokens[self.pos].clone();
+                let num: i64 = self.integer()?;
+                if !(-128..=255).contains(&num) {
+                    return Err(self.error_at(&token, format!("{} doesn't fit in i8", num)));
+                }
+                Ok(ConstValue::UInt8(num as u8))
+            },
+            Type::I16 => Ok(ConstValue::Int16(self.integer()?)),
+            Type::I32 => Ok(ConstValue::Int32(self.integer()?)),
+            Type::I64 => Ok(ConstValue::Int64(self.integer()?)),
+            // either decimal or the bits in hex, which is how they're printed
+            Type::F32 => {
+                let (token, text) = self.float_text()?;
+                let num = match text.strip_prefix("0x") {
+                    Some(bits) => u32::from_str_radix(bits, 16).ok().map(f32::from_bits),
+                    None => text.parse().ok()
+                };
+                num.map(ConstValue::Float32).ok_or_else(|| self.error_at(&token, format!("invalid f32 '{}'", text)))
+            },
+            Type::F64 => {
+                let (token, text) = self.float_text()?;
+                let num = match text.strip_prefix("0x") {
+                    Some(bits) => u64::from_str_radix(bits, 16).ok().map(f64::from_bits),
+                    None => text.parse().ok()
+                };
+                num.map(ConstValue::Float64).ok_or_else(|| self.error_at(&token, format!("invalid f64 '{}'", text)))
+            },
+            Type::Array(elem, len) => {
+                let token = self.tokens[self.pos].clone();
+
+                let vals = match self.peek().clone() {
+                    TokenKind::CStr(bytes) if **elem == Type::I8 => {
+                        self.next();
+                        bytes.into_iter().map(ConstValue::UInt8).collect()
+                    },
+                    _ => {
+                        self.expect_punct('[')?;
+                        self.comma_separated(']', |parser| parser.literal(elem))?
+                    }
+                };
+
+                if vals.len() != *len {
+                    return Err(self.error_at(&token, format!("expected {} elements, found {}", len, vals.len())));
+                }
+
+                Ok(ConstValue::Array((**elem).clone(), vals))
+            },
+            Type::Struct(fields) => {
+                let token = self.tokens[self.pos].clone();
+                self.expect_punct('{')?;
+
+                let mut field_types = fields.iter();
+                let vals = self.comma_separated('}', |parser| {
+                    match field_types.next() {
+                        Some(field) => parser.literal(field),
+                        None => parser.error(format!("too many fields for {}", ty))
+                    }
+                })?;
+
+                if vals.len() != fields.len() {
+                    return Err(self.error_at(&token, format!("expected {} fields, found {}", fields.len(), vals.len())));
+                }
+
+                Ok(ConstValue::Struct(vals))
+            },
+            Type::Ptr | Type::Void => self.error(format!("there are no constants of type {}", ty))
+        }
+    }
+
+    fn constant(&mut self) -> Result<ConstValue, ParseError> {
+        let ty = self.ty()?;
+        self.literal(&ty)
+    }
+
+    fn value(&mut self, scope: &mut FunctionScope) -> Result<Value, ParseError> {
+        match self.peek().clone() {
+            TokenKind::Local(id) => {
+                let token = self.next();
+                scope.uses.entry(id).or_insert((token.line, token.column));
+                Ok(Value::Ref(ValueId(id)))
+            },
+            TokenKind::Ident(ident) if ident == "ref" => {
+                self.next();
+                Ok(Value::ConstRef(self.constant()?))
+            },
+            _ if self.is_type_start() => Ok(Value::Const(self.constant()?)),
+            _ => self.unexpected("a value")
+        }
+    }
+
+    fn define(&self, scope: &mut FunctionScope, token: &Token, id: usize, ty: Type) -> Result<ValueId, ParseError> {
+        if !scope.define(id, ty) {
+            return Err(self.error_at(token, format!("%{} is defined more than once", id)));
+        }
+        Ok(ValueId(id))
+    }
+
+    fn align(&mut self) -> Result<usize, ParseError> {
+        self.expect_punct(',')?;
+        self.expect_keyword("align")?;
+        self.integer()
+    }
+
+    fn translation_unit(&mut self) -> Result<TranslationUnit, ParseError> {
+        self.expect_keyword("unit")?;
+
+        let mut translation_unit = TranslationUnit {
+            name: self.string()?,
+            functions: vec![],
+            declarations: vec![],
+            globals: vec![]
+        };
+
+        loop {
+            let token = self.tokens[self.pos].clone();
+
+            match self.peek() {
+                TokenKind::Eof => return Ok(translation_unit),
+                TokenKind::Ident(ident) if ident == "declare" => {
+                    self.next();
+                    let name = self.global_name()?;
+                    self.expect_punct('(')?;
+                    let params = self.comma_separated(')', |parser| parser.ty())?;
+                    let signature = Signature::new(params, self.return_type()?);
+
+                    if translation_unit.declaration(&name).is_some() {
+                        return Err(self.error_at(&token, format!("@{} is declared more than once", name)));
+                    }
+                    translation_unit.declarations.push((name, signature));
+                },
+                TokenKind::Ident(ident) if ident == "global" || ident == "const" => {
+                    let constant = ident == "const";
+                    self.next();
+                    let name = self.global_name()?;
+                    self.expect_punct(':')?;
+                    let ty = self.ty()?;
+
+                    let init = if self.is_punct('=') {
+                        self.next();
+                        Some(self.constant()?)
+                    } else {
+                        None
+                    };
+
+                    if translation_unit.global(&name).is_some() {
+                        return Err(self.error_at(&token, format!("@{} is defined more than once", name)));
+                    }
+                    translation_unit.globals.push(Global { name, ty, init, constant });
+                },
+                TokenKind::Ident(ident) if ident == "fn" => {
+                    self.next();
+                    let function = self.function()?;
+
+                    if translation_unit.function(&function.name).is_some() {
+                        return Err(self.error_at(&token, format!("@{} is defined more than once", function.name)));
+                    }
+                    translation_unit.functions.push(function);
+                },
+                _ => return self.unexpected("'declare', 'global', 'const' or 'fn'")
+            }
+        }
+    }
+
+    fn return_type(&mut self) -> Result<Type, ParseError> {
+        if *self.peek() != TokenKind::Arrow {
+            return self.unexpected("'->'");
+        }
+        self.next();
+        self.ty()
+    }
+
+    fn function(&mut self) -> Result<Function, ParseError> {
+        let name_token = self.tokens[self.pos].clone();
+        let name = self.global_name()?;
+        let mut scope = FunctionScope {
+            values: vec![],
+            uses: HashMap::new()
+        };
+
+        self.expect_punct('(')?;
+        let mut params = vec![];
+        let mut param_types = vec![];
+
+        while !self.is_punct(')') {
+            if !params.is_empty() {
+                self.expect_punct(',')?;
+            }
+
+            let token = self.next();
+            match token.kind {
+                TokenKind::Local(id) if id == params.len() => {
+                    self.expect_punct(':')?;
+                    let ty = self.ty()?;
+                    params.push(self.define(&mut scope, &token, id, ty.clone())?);
+                    param_types.push(ty);
+                },
+                _ => return Err(self.error_at(&token, format!("expected parameter %{}", params.len())))
+            }
+        }
+        self.next();
+
+        let mut function = Function {
+            name,
+            signature: Signature::new(param_types, self.return_type()?),
+            params,
+            blocks: vec![],
+            start_block: BlockId(0),
+            values: vec![],
+            stack_slots: vec![]
+        };
+
+        self.expect_punct('{')?;
+
+        while let TokenKind::Slot(id) = *self.peek() {
+            let token = self.next();
+            if id != function.stack_slots.len() {
+                return Err(self.error_at(&token, format!("expected stack slot ${}", function.stack_slots.len())));
+            }
+
+            self.expect_punct('=')?;
+            self.expect_keyword("slot")?;
+            let ty = self.ty()?;
+            let align = self.align()?;
+            function.stack_slots.push(StackSlot { ty, align });
+        }
+
+        while !self.is_punct('}') {
+            let token = self.tokens[self.pos].clone();
+            let label = self.block_label()?;
+            if label.0 != function.blocks.len() {
+                return Err(self.error_at(&token, format!("expected block bb{}", function.blocks.len())));
+            }
+            self.expect_punct(':')?;
+
+            let block = self.block(&mut scope, &function)?;
+            function.blocks.push(block);
+        }
+        self.next();
+
+        for (id, ty) in scope.values.into_iter().enumerate() {
+            match ty {
+                Some(ty) => function.values.push(ty),
+                None => {
+                    return Err(match scope.uses.get(&id) {
+                        Some(&(line, column)) => ParseError { line, column, message: format!("%{} is used but never defined", id) },
+                        None => self.error_at(&name_token, format!("%{} is never defined, values must be numbered without gaps", id))
+                    });
+                }
+            }
+        }
+
+        if let Some((id, (line, column))) = scope.uses.iter().filter(|(id, _)| **id >= function.values.len()).min_by_key(|(id, _)| **id) {
+            return Err(ParseError {
+                line: *line,
+                column: *column,
+                message: format!("%{} is used but never defined", id)
+            });
+        }
+
+        Ok(function)
+    }
+
+    fn is_block_end(&self) -> bool {
+        match self.peek() {
+            TokenKind::Punct('}') => true,
+            TokenKind::Ident(ident) => ident.starts_with("bb") && self.tokens[self.pos + 1].kind == TokenKind::Punct(':'),
+            _ => false
+        }
+    }
+
+    fn block(&mut self, scope: &mut FunctionScope, function: &Function) -> Result<Block, ParseError> {
+        let mut block = Block::new();
+
+        while !self.is_block_end() {
+            if let TokenKind::Ident(ident) = self.peek().clone()
+                && let Some(terminator) = self.terminator(&ident, scope)? {
+                block.set_terminator(terminator);
+                break;
+            }
+
+            let instruction = self.instruction(scope, function)?;
+            block.add_instruction(instruction);
+        }
+
+        Ok(block)
+    }
+
+    fn terminator(&mut self, keyword: &str, scope: &mut FunctionScope) -> Result<Option<Terminator>, ParseError> {
+        let terminator = match keyword {
+            "jmp" => {
+                self.next();
+                Terminator::Jump(self.block_label()?)
+            },
+            "br" => {
+                self.next();
+                let cond = self.value(scope)?;
+                self.expect_punct(',')?;
+                let if_true = self.block_label()?;
+                self.expect_punct(',')?;
+                let if_false = self.block_label()?;
+                Terminator::Branch { cond, if_true, if_false }
+            },
+            "switch" => {
+                self.next();
+                let value = self.value(scope)?;
+                self.expect_punct(',')?;
+                let default = self.block_label()?;
+                self.expect_punct('[')?;
+                let cases = self.comma_separated(']', |parser| {
+                    let case = parser.integer()?;
+                    parser.expect_punct(':')?;
+                    Ok((case, parser.block_label()?))
+                })?;
+                Terminator::Switch { value, cases, default }
+            },
+            "ret" => {
+                self.next();
+                if self.is_block_end() {
+                    Terminator::Return(None)
+                } else {
+                    Terminator::Return(Some(self.value(scope)?))
+                }
+            },
+            _ => return Ok(None)
+        };
+
+        Ok(Some(terminator))
+    }
+
+    fn call_args(&mut self, scope: &mut FunctionScope) -> Result<(String, Vec<Value>), ParseError> {
+        let callee = self.global_name()?;
+        self.expect_punct('(')?;
+        let args = self.comma_separated(')', |parser| parser.value(scope))?;
+        Ok((callee, args))
+    }
+
+    fn instruction(&mut self, scope: &mut FunctionScope, function: &Function) -> Result<Instruction, ParseError> {
+        let token = self.next();
+
+        let (result_token, result) = match token.kind {
+            TokenKind::Local(id) => {
+                self.expect_punct('=')?;
+                (token.clone(), id)
+            },
+            TokenKind::Ident(ref ident) => {
+                return match ident.as_str() {
+                    "asm" => {
+                        let hex = self.string()?;
+                        let bytes: Result<Vec<u8>, _> = hex.split_whitespace().map(|byte| u8::from_str_radix(byte, 16)).collect();
+                        bytes.map(Instruction::Asm).map_err(|_| self.error_at(&token, "asm expects a string of hex bytes".to_owned()))
+                    },
+                    "asm_value" => Ok(Instruction::AsmValue(self.value(scope)?)),
+                    "store" => {
+                        let value = self.value(scope)?;
+                        self.expect_punct(',')?;
+                        let ptr = self.value(scope)?;
+                        let align = self.align()?;
+                        Ok(Instruction::Store { value, ptr, align })
+                    },
+                    "call" => {
+                        self.expect_keyword("void")?;
+                        let (callee, args) = self.call_args(scope)?;
+                        Ok(Instruction::Call { result: None, callee, args })
+                    },
+                    _ => Err(self.error_at(&token, format!("unknown instruction '{}'", ident)))
+                };
+            },
+            _ => return Err(self.error_at(&token, format!("expected an instruction, found {}", Self::describe(&token.kind))))
+        };
+
+        let op_token = self.tokens[self.pos].clone();
+        let op = self.ident()?;
+
+        if let Some(binary) = parse_binary_op(&op) {
+            let ty = self.ty()?;
+            let lhs = self.value(scope)?;
+            self.expect_punct(',')?;
+            let rhs = self.value(scope)?;
+            let result = self.define(scope, &result_token, result, ty)?;
+            return Ok(Instruction::Binary { result, op: binary, lhs, rhs });
+        }
+
+        if let Some(cast) = parse_cast_op(&op) {
+            let value = self.value(scope)?;
+            self.expect_keyword("to")?;
+            let ty = self.ty()?;
+            let result = self.define(scope, &result_token, result, ty.clone())?;
+            return Ok(Instruction::Cast { result, op: cast, value, ty });
+        }
+
+        let instruction = match op.as_str() {
+            "icmp" => {
+                let cond_token = self.tokens[self.pos].clone();
+                let cond = parse_int_condition(&self.ident()?)
+                    .ok_or_else(|| self.error_at(&cond_token, "unknown icmp condition".to_owned()))?;
+                let lhs = self.value(scope)?;
+                self.expect_punct(',')?;
+                let rhs = self.value(scope)?;
+                let result = self.define(scope, &result_token, result, Type::I1)?;
+                Instruction::ICmp { result, cond, lhs, rhs }
+            },
+            "call" => {
+                let ty = self.ty()?;
+                let (callee, args) = self.call_args(scope)?;
+                let result = self.define(scope, &result_token, result, ty)?;
+                Instruction::Call { result: Some(result), callee, args }
+            },
+            "global_addr" => {
+                let global = self.global_name()?;
+                let result = self.define(scope, &result_token, result, Type::Ptr)?;
+                Instruction::GlobalAddr { result, global }
+            },
+            "stack_addr" => {
+                let slot_token = self.next();
+                let slot = match slot_token.kind {
+                    TokenKind::Slot(id) if id < function.stack_slots.len() => StackSlotId(id),
+                    TokenKind::Slot(id) => return Err(self.error_at(&slot_token, format!("unknown stack slot ${}", id))),
+                    _ => return Err(self.error_at(&slot_token, "expected a stack slot".to_owned()))
+                };
+                let result = self.define(scope, &result_token, result, Type::Ptr)?;
+                Instruction::StackAddr { result, slot }
+            },
+            "load" => {
+                let ty = self.ty()?;
+                self.expect_punct(',')?;
+                let ptr = self.value(scope)?;
+                let align = self.align()?;
+                let result = self.define(scope, &result_token, result, ty)?;
+                Instruction::Load { result, ptr, align }
+            },
+            "gep" => {
+                let ty = self.ty()?;
+                self.expect_punct(',')?;
+                let ptr = self.value(scope)?;
+                let mut indices = vec![];
+                while self.is_punct(',') {
+                    self.next();
+                    indices.push(self.value(scope)?);
+                }
+                let result = self.define(scope, &result_token, result, Type::Ptr)?;
+                Instruction::Gep { result, ty, ptr, indices }
+            },
+            _ => return Err(self.error_at(&op_token, format!("unknown instruction '{}'", op)))
+        };
+
+        Ok(instruction)
+    }
+}
+
+fn parse_binary_op(name: &str) -> Option<BinaryOp> {
+    let op = match name {
+        "add" => BinaryOp::Add,
+        "sub" => BinaryOp::Sub,
+        "mul" => BinaryOp::Mul,
+        "sdiv" => BinaryOp::SDiv,
+        "udiv" => BinaryOp::UDiv,
+        "srem" => BinaryOp::SRem,
+        "urem" => BinaryOp::URem,
+        "and" => BinaryOp::And,
+        "or" => BinaryOp::Or,
+        "xor" => BinaryOp::Xor,
+        "shl" => BinaryOp::Shl,
+        "lshr" => BinaryOp::LShr,
+        "ashr" => BinaryOp::AShr,
+        _ => return None
+    };
+
+    Some(op)
+}
+
+fn parse_cast_op(name: &str) -> Option<CastOp> {
+    match name {
+        "zext" => Some(CastOp::ZExt),
+        "sext" => Some(CastOp::SExt),
+        "trunc" => Some(CastOp::Trunc),
+        _ => None
+    }
+}
+
+fn parse_int_condition(name: &str) -> Option<IntCondition> {
+    let cond = match name {
+        "eq" => IntCondition::Eq,
+        "ne" => IntCondition::Ne,
+        "slt" => IntCondition::SLt,
+        "sle" => IntCondition::SLe,
+        "sgt" => IntCondition::SGt,
+        "sge" => IntCondition::SGe,
+        "ult" => IntCondition::ULt,
+        "ule" => IntCondition::ULe,
+        "ugt" => IntCondition::UGt,
+        "uge" => IntCondition::UGe,
+        _ => return None
+    };
+
+    Some(cond)
+}
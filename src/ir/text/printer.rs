@@ -0,0 +1,299 @@
+use std::fmt::{self, Display, Formatter, Write};
+
+use crate::ir::{BinaryOp, Block, CastOp, ConstValue, Function, Instruction, IntCondition, Terminator, TranslationUnit, Value};
+use crate::ir::types::Type;
+
+pub(crate) fn binary_op_name(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Sub => "sub",
+        BinaryOp::Mul => "mul",
+        BinaryOp::SDiv => "sdiv",
+        BinaryOp::UDiv => "udiv",
+        BinaryOp::SRem => "srem",
+        BinaryOp::URem => "urem",
+        BinaryOp::And => "and",
+        BinaryOp::Or => "or",
+        BinaryOp::Xor => "xor",
+        BinaryOp::Shl => "shl",
+        BinaryOp::LShr => "lshr",
+        BinaryOp::AShr => "ashr"
+    }
+}
+
+pub(crate) fn int_condition_name(cond: IntCondition) -> &'static str {
+    match cond {
+        IntCondition::Eq => "eq",
+        IntCondition::Ne => "ne",
+        IntCondition::SLt => "slt",
+        IntCondition::SLe => "sle",
+        IntCondition::SGt => "sgt",
+        IntCondition::SGe => "sge",
+        IntCondition::ULt => "ult",
+        IntCondition::ULe => "ule",
+        IntCondition::UGt => "ugt",
+        IntCondition::UGe => "uge"
+    }
+}
+
+pub(crate) fn cast_op_name(op: CastOp) -> &'static str {
+    match op {
+        CastOp::ZExt => "zext",
+        CastOp::SExt => "sext",
+        CastOp::Trunc => "trunc"
+    }
+}
+
+fn write_string(f: &mut impl Write, bytes: &[u8]) -> fmt::Result {
+    f.write_char('"')?;
+
+    for byte in bytes {
+        match byte {
+            b'"' => f.write_str("\\\"")?,
+            b'\\' => f.write_str("\\\\")?,
+            0x20..=0x7E => f.write_char(*byte as char)?,
+            _ => write!(f, "\\{:02x}", byte)?
+        }
+    }
+
+    f.write_char('"')
+}
+
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+}
+
+fn write_global_name(f: &mut impl Write, name: &str) -> fmt::Result {
+    if is_plain_name(name) {
+        write!(f, "@{}", name)
+    } else {
+        f.write_char('@')?;
+        write_string(f, name.as_bytes())
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::I1 => f.write_str("i1"),
+            Type::I8 => f.write_str("i8"),
+            Type::I16 => f.write_str("i16"),
+            Type::I32 => f.write_str("i32"),
+            Type::I64 => f.write_str("i64"),
+            Type::F32 => f.write_str("f32"),
+            Type::F64 => f.write_str("f64"),
+            Type::Ptr => f.write_str("ptr"),
+            Type::Void => f.write_str("void"),
+            Type::Array(elem, len) => write!(f, "[{} x {}]", len, elem),
+            Type::Struct(fields) => {
+                f.write_char('{')?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", field)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+// the literal part of a constant, without its type. Floats are written as their bits so NaN payloads and signs survive
+fn write_literal(f: &mut impl Write, value: &ConstValue) -> fmt::Result {
+    match value {
+        ConstValue::Bool(val) => write!(f, "{}", val),
+        ConstValue::UInt8(num) => write!(f, "{}", num),
+        ConstValue::Int16(num) => write!(f, "{}", num),
+        ConstValue::Int32(num) => write!(f, "{}", num),
+        ConstValue::Int64(num) => write!(f, "{}", num),
+        ConstValue::Float32(num) => write!(f, "0x{:08X}", num.to_bits()),
+        ConstValue::Float64(num) => write!(f, "0x{:016X}", num.to_bits()),
+        ConstValue::Array(Type::I8, vals) if vals.iter().all(|val| matches!(val, ConstValue::UInt8(_))) => {
+            let bytes: Vec<u8> = vals.iter().map(|val| val.as_i64().unwrap() as u8).collect();
+            f.write_char('c')?;
+            write_string(f, &bytes)
+        },
+        ConstValue::Array(_, vals) | ConstValue::Struct(vals) => {
+            let (open, close) = if let ConstValue::Array(..) = value { ('[', ']') } else { ('{', '}') };
+
+            f.write_char(open)?;
+            for (i, val) in vals.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_literal(f, val)?;
+            }
+            f.write_char(close)
+        }
+    }
+}
+
+impl Display for ConstValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.get_type())?;
+        write_literal(f, self)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Const(val) => write!(f, "{}", val),
+            Value::ConstRef(val) => write!(f, "ref {}", val),
+            Value::Ref(id) => write!(f, "%{}", id.0)
+        }
+    }
+}
+
+fn write_instruction(f: &mut impl Write, function: &Function, instruction: &Instruction) -> fmt::Result {
+    match instruction {
+        Instruction::Asm(bytes) => {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            write!(f, "asm \"{}\"", hex.join(" "))
+        },
+        Instruction::AsmValue(value) => write!(f, "asm_value {}", value),
+        Instruction::Binary { result, op, lhs, rhs } => {
+            write!(f, "%{} = {} {} {}, {}", result.0, binary_op_name(*op), function.values[result.0], lhs, rhs)
+        },
+        Instruction::ICmp { result, cond, lhs, rhs } => {
+            write!(f, "%{} = icmp {} {}, {}", result.0, int_condition_name(*cond), lhs, rhs)
+        },
+        Instruction::Cast { result, op, value, ty } => {
+            write!(f, "%{} = {} {} to {}", result.0, cast_op_name(*op), value, ty)
+        },
+        Instruction::Call { result, callee, args } => {
+            match result {
+                Some(result) => write!(f, "%{} = call {} ", result.0, function.values[result.0])?,
+                None => f.write_str("call void ")?
+            }
+
+            write_global_name(f, callee)?;
+            f.write_char('(')?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", arg)?;
+            }
+            f.write_char(')')
+        },
+        Instruction::GlobalAddr { result, global } => {
+            write!(f, "%{} = global_addr ", result.0)?;
+            write_global_name(f, global)
+        },
+        Instruction::StackAddr { result, slot } => write!(f, "%{} = stack_addr ${}", result.0, slot.0),
+        Instruction::Load { result, ptr, align } => {
+            write!(f, "%{} = load {}, {}, align {}", result.0, function.values[result.0], ptr, align)
+        },
+        Instruction::Store { value, ptr, align } => write!(f, "store {}, {}, align {}", value, ptr, align),
+        Instruction::Gep { result, ty, ptr, indices } => {
+            write!(f, "%{} = gep {}, {}", result.0, ty, ptr)?;
+            for index in indices {
+                write!(f, ", {}", index)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_terminator(f: &mut impl Write, terminator: &Terminator) -> fmt::Result {
+    match terminator {
+        Terminator::Jump(target) => write!(f, "jmp bb{}", target.0),
+        Terminator::Branch { cond, if_true, if_false } => write!(f, "br {}, bb{}, bb{}", cond, if_true.0, if_false.0),
+        Terminator::Switch { value, cases, default } => {
+            write!(f, "switch {}, bb{} [", value, default.0)?;
+            for (i, (case, target)) in cases.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}: bb{}", case, target.0)?;
+            }
+            f.write_char(']')
+        },
+        Terminator::Return(None) => f.write_str("ret"),
+        Terminator::Return(Some(value)) => write!(f, "ret {}", value)
+    }
+}
+
+fn write_block(f: &mut impl Write, function: &Function, index: usize, block: &Block) -> fmt::Result {
+    writeln!(f, "bb{}:", index)?;
+
+    for instruction in block.instructions.iter() {
+        f.write_str("    ")?;
+        write_instruction(f, function, instruction)?;
+        f.write_char('\n')?;
+    }
+
+    if let Some(terminator) = &block.terminator {
+        f.write_str("    ")?;
+        write_terminator(f, terminator)?;
+        f.write_char('\n')?;
+    }
+
+    Ok(())
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("fn ")?;
+        write_global_name(f, &self.name)?;
+        f.write_char('(')?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "%{}: {}", param.0, self.values[param.0])?;
+        }
+        writeln!(f, ") -> {} {{", self.signature.return_type)?;
+
+        for (i, slot) in self.stack_slots.iter().enumerate() {
+            writeln!(f, "    ${} = slot {}, align {}", i, slot.ty, slot.align)?;
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            write_block(f, self, i, block)?;
+        }
+
+        f.write_str("}\n")
+    }
+}
+
+// declarations, then globals, then functions, each in the order they were added
+impl Display for TranslationUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("unit ")?;
+        write_string(f, self.name.as_bytes())?;
+        f.write_char('\n')?;
+
+        for (name, signature) in &self.declarations {
+            f.write_str("\ndeclare ")?;
+            write_global_name(f, name)?;
+            f.write_char('(')?;
+            for (i, param) in signature.params.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", param)?;
+            }
+            writeln!(f, ") -> {}", signature.return_type)?;
+        }
+
+        for global in &self.globals {
+            f.write_str(if global.constant { "\nconst " } else { "\nglobal " })?;
+            write_global_name(f, &global.name)?;
+            write!(f, ": {}", global.ty)?;
+
+            if let Some(init) = &global.init {
+                write!(f, " = {}", init)?;
+            }
+            f.write_char('\n')?;
+        }
+
+        for function in &self.functions {
+            write!(f, "\n{}", function)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Type {
+    I1,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Ptr,
+    Array(Box<Type>, usize),
+    Struct(Vec<Type>),
+    Void
+}
+
+impl Type {
+    pub fn size(&self) -> usize {
+        match self {
+            Type::I1 | Type::I8 => 1,
+            Type::I16 => 2,
+            Type::I32 | Type::F32 => 4,
+            Type::I64 | Type::F64 | Type::Ptr => 8,
+            Type::Array(elem, len) => elem.size() * len,
+            Type::Struct(_) => {
+                let (_, size) = self.struct_layout();
+                size
+            },
+            Type::Void => 0
+        }
+    }
+
+    pub fn align(&self) -> usize {
+        match self {
+            Type::Array(elem, _) => elem.align(),
+            Type::Struct(fields) => fields.iter().map(|field| field.align()).max().unwrap_or(1),
+            Type::Void => 1,
+            _ => self.size()
+        }
+    }
+
+    // width of a scalar as it sits in a register
+    pub(crate) fn bits(&self) -> u32 {
+        match self {
+            Type::I1 => 1,
+            _ => self.size() as u32 * 8
+        }
+    }
+
+    pub(crate) fn is_integer(&self) -> bool {
+        matches!(self, Type::I1 | Type::I8 | Type::I16 | Type::I32 | Type::I64)
+    }
+
+    pub(crate) fn is_float(&self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    pub(crate) fn is_aggregate(&self) -> bool {
+        matches!(self, Type::Array(..) | Type::Struct(_))
+    }
+
+    // offsets of every field of a struct and its total size, with C padding rules
+    pub(crate) fn struct_layout(&self) -> (Vec<usize>, usize) {
+        let fields = match self {
+            Type::Struct(fields) => fields,
+            _ => panic!("Attempt to lay out non-struct type as a struct")
+        };
+
+        let mut offsets = vec![];
+        let mut offset: usize = 0;
+
+        for field in fields {
+            offset = offset.next_multiple_of(field.align());
+            offsets.push(offset);
+            offset += field.size();
+        }
+
+        (offsets, offset.next_multiple_of(self.align()))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Signature {
+    pub(crate) params: Vec<Type>,
+    pub(crate) return_type: Type
+}
+
+impl Signature {
+    pub fn new(params: Vec<Type>, return_type: Type) -> Signature {
+        Signature {
+            params,
+            return_type
+        }
+    }
+}
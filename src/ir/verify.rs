@@ -0,0 +1,658 @@
+use std::fmt;
+
+use crate::ir::{BlockId, CastOp, Function, Instruction, StackSlotId, Terminator, TranslationUnit, Value, ValueId};
+use crate::ir::types::{Signature, Type};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub function: Option<String>,
+    pub block: Option<BlockId>,
+    pub kind: VerifyErrorKind
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    MissingTerminator,
+    UndefinedValue(ValueId),
+    UseBeforeDef(ValueId),
+    MultipleDefinitions(ValueId),
+    TypeMismatch {
+        context: String,
+        expected: String,
+        found: Type
+    },
+    WrongArgumentCount {
+        callee: String,
+        expected: usize,
+        found: usize
+    },
+    UnknownCallTarget(String),
+    UnknownGlobal(String),
+    UnknownStackSlot(StackSlotId),
+    UnknownBlock(BlockId),
+    InvalidGepIndex(String),
+    DuplicateSymbol(String),
+    UnreachableReferencedBlock(BlockId),
+    NonConstantAsmValue,
+    EmptyFunction,
+    SwitchCaseOutOfRange {
+        value: i64,
+        ty: Type
+    },
+    DuplicateSwitchCase(i64),
+    InvalidAlignment(usize)
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(function) = &self.function {
+            write!(f, "in @{}", function)?;
+            if let Some(block) = self.block {
+                write!(f, ", bb{}", block.0)?;
+            }
+            f.write_str(": ")?;
+        }
+
+        match &self.kind {
+            VerifyErrorKind::MissingTerminator => write!(f, "block has no terminator"),
+            VerifyErrorKind::UndefinedValue(id) => write!(f, "%{} is never defined", id.0),
+            VerifyErrorKind::UseBeforeDef(id) => write!(f, "%{} is used before its definition", id.0),
+            VerifyErrorKind::MultipleDefinitions(id) => write!(f, "%{} is defined more than once", id.0),
+            VerifyErrorKind::TypeMismatch { context, expected, found } => write!(f, "{} should be {}, found {}", context, expected, found),
+            VerifyErrorKind::WrongArgumentCount { callee, expected, found } => write!(f, "@{} takes {} arguments, found {}", callee, expected, found),
+            VerifyErrorKind::UnknownCallTarget(callee) => write!(f, "call to unknown function @{}", callee),
+            VerifyErrorKind::UnknownGlobal(global) => write!(f, "reference to unknown global @{}", global),
+            VerifyErrorKind::UnknownStackSlot(slot) => write!(f, "reference to unknown stack slot ${}", slot.0),
+            VerifyErrorKind::UnknownBlock(block) => write!(f, "jump to unknown block bb{}", block.0),
+            VerifyErrorKind::InvalidGepIndex(message) => write!(f, "invalid gep index: {}", message),
+            VerifyErrorKind::DuplicateSymbol(name) => write!(f, "@{} is defined more than once", name),
+            VerifyErrorKind::UnreachableReferencedBlock(block) => write!(f, "bb{} is jumped to but unreachable from the start block", block.0),
+            VerifyErrorKind::NonConstantAsmValue => write!(f, "asm_value needs a constant"),
+            VerifyErrorKind::EmptyFunction => write!(f, "function has no blocks"),
+            VerifyErrorKind::SwitchCaseOutOfRange { value, ty } => write!(f, "switch case {} doesn't fit in {}", value, ty),
+            VerifyErrorKind::DuplicateSwitchCase(value) => write!(f, "switch case {} appears more than once", value),
+            VerifyErrorKind::InvalidAlignment(align) => write!(f, "alignment {} isn't a power of two", align)
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+// checks everything codegen assumes about a translation unit, returning every problem found
+pub fn verify_translation_unit(translation_unit: &TranslationUnit) -> Result<(), Vec<VerifyError>> {
+    let mut errors = vec![];
+
+    let mut names: Vec<&String> = translation_unit.functions.iter().map(|function| &function.name)
+        .chain(translation_unit.declarations.iter().map(|(name, _)| name))
+        .chain(translation_unit.globals.iter().map(|global| &global.name))
+        .collect();
+    names.sort();
+
+    for pair in names.windows(2) {
+        if pair[0] == pair[1] {
+            errors.push(VerifyError {
+                function: None,
+                block: None,
+                kind: VerifyErrorKind::DuplicateSymbol(pair[0].clone())
+            });
+        }
+    }
+
+    for (name, signature) in &translation_unit.declarations {
+        for kind in signature_errors(signature, &format!("declaration of @{}", name)) {
+            errors.push(VerifyError { function: None, block: None, kind });
+        }
+    }
+
+    for global in &translation_unit.globals {
+        if let Some(init) = &global.init && init.get_type() != global.ty {
+            errors.push(VerifyError {
+                function: None,
+                block: None,
+                kind: VerifyErrorKind::TypeMismatch {
+                    context: format!("initializer of @{}", global.name),
+                    expected: global.ty.to_string(),
+                    found: init.get_type()
+                }
+            });
+        }
+    }
+
+    for function in &translation_unit.functions {
+        FunctionVerifier {
+            translation_unit,
+            function,
+            block: None,
+            defs: vec![None; function.values.len()],
+            idoms: vec![],
+            errors: &mut errors
+        }.verify();
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+struct FunctionVerifier<'a> {
+    translation_unit: &'a TranslationUnit,
+    function: &'a Function,
+    block: Option<BlockId>,
+    // where each value is defined, as (block, instruction index); parameters come before the first instruction
+    defs: Vec<Option<(BlockId, isize)>>,
+    // immediate dominator of each reachable block, the start block is its own
+    idoms: Vec<Option<BlockId>>,
+    errors: &'a mut Vec<VerifyError>
+}
+
+impl FunctionVerifier<'_> {
+    fn error(&mut self, kind: VerifyErrorKind) {
+        self.errors.push(VerifyError {
+            function: Some(self.function.name.clone()),
+            block: self.block,
+            kind
+        });
+    }
+
+    fn expect_type(&mut self, context: impl FnOnce() -> String, expected: &Type, found: Type) {
+        if *expected != found {
+            self.error(VerifyErrorKind::TypeMismatch {
+                context: context(),
+                expected: expected.to_string(),
+                found
+            });
+        }
+    }
+
+    fn expect_kind(&mut self, context: impl FnOnce() -> String, expected: &str, found: Type, ok: bool) {
+        if !ok {
+            self.error(VerifyErrorKind::TypeMismatch {
+                context: context(),
+                expected: expected.to_owned(),
+                found
+            });
+        }
+    }
+
+    // the type of `value`, or None if it refers to a value that doesn't exist, which `verify_uses` reports
+    fn value_type(&self, value: &Value) -> Option<Type> {
+        match value {
+            Value::Ref(id) if id.0 >= self.function.values.len() => None,
+            _ => Some(self.function.value_type(value))
+        }
+    }
+
+    fn verify_align(&mut self, align: usize) {
+        if !align.is_power_of_two() {
+            self.error(VerifyErrorKind::InvalidAlignment(align));
+        }
+    }
+
+    fn define(&mut self, id: ValueId, block: BlockId, index: isize) {
+        match self.defs.get(id.0) {
+            None => self.error(VerifyErrorKind::UndefinedValue(id)),
+            Some(Some(_)) => self.error(VerifyErrorKind::MultipleDefinitions(id)),
+            Some(None) => self.defs[id.0] = Some((block, index))
+        }
+    }
+
+    fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+
+            match self.idoms[b.0] {
+                Some(idom) if idom != b => b = idom,
+                _ => return false
+            }
+        }
+    }
+
+    fn verify(&mut self) {
+        let function = self.function;
+
+        for kind in signature_errors(&function.signature, &format!("signature of @{}", function.name)) {
+            self.error(kind);
+        }
+
+        for slot in &function.stack_slots {
+            self.verify_align(slot.align);
+        }
+
+        if function.blocks.is_empty() {
+            self.error(VerifyErrorKind::EmptyFunction);
+            return;
+        }
+
+        if function.start_block.0 >= function.blocks.len() {
+            self.error(VerifyErrorKind::UnknownBlock(function.start_block));
+            return;
+        }
+
+        let mut targets_valid = true;
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            self.block = Some(BlockId(i));
+
+            match &block.terminator {
+                None => self.error(VerifyErrorKind::MissingTerminator),
+                Some(terminator) => {
+                    for target in terminator.successors() {
+                        if target.0 >= function.blocks.len() {
+                            self.error(VerifyErrorKind::UnknownBlock(target));
+                            targets_valid = false;
+                        }
+                    }
+                }
+            }
+        }
+        self.block = None;
+
+        // the CFG can't be walked with dangling edges
+        if !targets_valid {
+            return;
+        }
+
+        let rpo = function.reverse_postorder();
+        self.compute_dominators(&rpo);
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            if self.idoms[i].is_some() {
+                continue;
+            }
+
+            let referenced = function.blocks.iter().any(|other| {
+                other.successors().contains(&BlockId(i))
+            });
+
+            if referenced {
+                self.block = Some(BlockId(i));
+                self.error(VerifyErrorKind::UnreachableReferencedBlock(BlockId(i)));
+                self.block = None;
+            }
+
+            // unreachable blocks are never compiled, but their definitions still count
+            for (index, instruction) in block.instructions.iter().enumerate() {
+                if let Some(result) = instruction.result() {
+                    self.define(result, BlockId(i), index as isize);
+                }
+            }
+        }
+
+        for (param, expected) in function.params.iter().zip(&function.signature.params) {
+            self.define(*param, function.start_block, -1);
+
+            if let Some(ty) = function.values.get(param.0) {
+                self.expect_type(|| format!("parameter %{}", param.0), expected, ty.clone());
+            }
+        }
+
+        // definitions are collected up front so a use of a later value reads as use-before-def, not undefined
+        for &id in &rpo {
+            for (index, instruction) in function.block(id).instructions.iter().enumerate() {
+                if let Some(result) = instruction.result() {
+                    self.define(result, id, index as isize);
+                }
+            }
+        }
+
+        for &id in &rpo {
+            self.block = Some(id);
+            let block = function.block(id);
+
+            for (index, instruction) in block.instructions.iter().enumerate() {
+                self.verify_uses(instruction.operands(), id, index as isize);
+                self.verify_instruction(instruction);
+            }
+
+            if let Some(terminator) = &block.terminator {
+                self.verify_uses(terminator.operands(), id, block.instructions.len() as isize);
+                self.verify_terminator(terminator);
+            }
+        }
+        self.block = None;
+
+        for id in 0..function.values.len() {
+            if self.defs[id].is_none() {
+                self.error(VerifyErrorKind::UndefinedValue(ValueId(id)));
+            }
+        }
+    }
+
+    // Cooper, Harvey and Kennedy's iterative algorithm, over blocks in reverse postorder
+    fn compute_dominators(&mut self, rpo: &[BlockId]) {
+        let function = self.function;
+        let mut order = vec![usize::MAX; function.blocks.len()];
+        for (i, id) in rpo.iter().enumerate() {
+            order[id.0] = i;
+        }
+
+        let mut preds = vec![vec![]; function.blocks.len()];
+        for &id in rpo {
+            for succ in function.block(id).successors() {
+                preds[succ.0].push(id);
+            }
+        }
+
+        self.idoms = vec![None; function.blocks.len()];
+        self.idoms[function.start_block.0] = Some(function.start_block);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for &id in &rpo[1..] {
+                let mut new_idom: Option<BlockId> = None;
+
+                for &pred in &preds[id.0] {
+                    if self.idoms[pred.0].is_none() {
+                        continue;
+                    }
+
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(mut a) => {
+                            let mut b = pred;
+                            while a != b {
+                                while order[a.0] > order[b.0] {
+                                    a = self.idoms[a.0].unwrap();
+                                }
+                                while order[b.0] > order[a.0] {
+                                    b = self.idoms[b.0].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+
+                if new_idom.is_some() && self.idoms[id.0] != new_idom {
+                    self.idoms[id.0] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn verify_uses(&mut self, operands: Vec<&Value>, block: BlockId, index: isize) {
+        for operand in operands {
+            let Value::Ref(id) = operand else { continue };
+
+            match self.defs.get(id.0).copied() {
+                None | Some(None) => self.error(VerifyErrorKind::UndefinedValue(*id)),
+                Some(Some((def_block, def_index))) => {
+                    let available = if def_block == block {
+                        def_index < index
+                    } else {
+                        self.idoms[def_block.0].is_some() && self.dominates(def_block, block)
+                    };
+
+                    if !available {
+                        self.error(VerifyErrorKind::UseBeforeDef(*id));
+                    }
+                }
+            }
+        }
+    }
+
+    fn result_type(&self, result: ValueId) -> Option<Type> {
+        self.function.values.get(result.0).cloned()
+    }
+
+    fn verify_instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Asm(_) => {},
+            Instruction::AsmValue(value) => {
+                if let Value::Ref(_) = value {
+                    self.error(VerifyErrorKind::NonConstantAsmValue);
+                }
+            },
+            Instruction::Binary { result, lhs, rhs, .. } => {
+                let (Some(lhs_ty), Some(rhs_ty)) = (self.value_type(lhs), self.value_type(rhs)) else { return };
+
+                let ok = lhs_ty.is_integer() || lhs_ty == Type::Ptr;
+                self.expect_kind(|| "binary operand".to_owned(), "an integer or pointer", lhs_ty.clone(), ok);
+                self.expect_type(|| "right hand side of binary operation".to_owned(), &lhs_ty, rhs_ty);
+
+                if let Some(ty) = self.result_type(*result) {
+                    self.expect_type(|| format!("result %{}", result.0), &lhs_ty, ty);
+                }
+            },
+            Instruction::ICmp { result, lhs, rhs, .. } => {
+                let (Some(lhs_ty), Some(rhs_ty)) = (self.value_type(lhs), self.value_type(rhs)) else { return };
+
+                let ok = lhs_ty.is_integer() || lhs_ty == Type::Ptr;
+                self.expect_kind(|| "icmp operand".to_owned(), "an integer or pointer", lhs_ty.clone(), ok);
+                self.expect_type(|| "right hand side of icmp".to_owned(), &lhs_ty, rhs_ty);
+
+                if let Some(ty) = self.result_type(*result) {
+                    self.expect_type(|| format!("result %{}", result.0), &Type::I1, ty);
+                }
+            },
+            Instruction::Cast { result, op, value, ty } => {
+                let Some(value_ty) = self.value_type(value) else { return };
+
+                self.expect_kind(|| "cast operand".to_owned(), "an integer", value_ty.clone(), value_ty.is_integer());
+                self.expect_kind(|| "cast target".to_owned(), "an integer", ty.clone(), ty.is_integer());
+
+                if value_ty.is_integer() && ty.is_integer() {
+                    let ok = match op {
+                        CastOp::ZExt | CastOp::SExt => ty.bits() > value_ty.bits(),
+                        CastOp::Trunc => ty.bits() < value_ty.bits()
+                    };
+                    let expected = if matches!(op, CastOp::Trunc) { "a narrower integer" } else { "a wider integer" };
+                    self.expect_kind(|| format!("cast target of {}", value_ty), expected, ty.clone(), ok);
+                }
+
+                if let Some(result_ty) = self.result_type(*result) {
+                    self.expect_type(|| format!("result %{}", result.0), ty, result_ty);
+                }
+            },
+            Instruction::Call { result, callee, args } => {
+                let signature = match self.translation_unit.function(callee) {
+                    Some(function) => &function.signature,
+                    None => match self.translation_unit.declaration(callee) {
+                        Some(signature) => signature,
+                        None => {
+                            self.error(VerifyErrorKind::UnknownCallTarget(callee.clone()));
+                            return;
+                        }
+                    }
+                };
+
+                if signature.params.len() != args.len() {
+                    self.error(VerifyErrorKind::WrongArgumentCount {
+                        callee: callee.clone(),
+                        expected: signature.params.len(),
+                        found: args.len()
+                    });
+                } else {
+                    for (i, (arg, param)) in args.iter().zip(&signature.params).enumerate() {
+                        if let Some(ty) = self.value_type(arg) {
+                            self.expect_type(|| format!("argument {} to @{}", i, callee), param, ty);
+                        }
+                    }
+                }
+
+                if let Some(result) = result && let Some(ty) = self.result_type(*result) {
+                    let ok = signature.return_type != Type::Void;
+                    self.expect_kind(|| format!("callee @{} of a call with a result", callee), "non-void", Type::Void, ok);
+                    if ok {
+                        self.expect_type(|| format!("result %{}", result.0), &signature.return_type, ty);
+                    }
+                }
+            },
+            Instruction::GlobalAddr { result, global } => {
+                if self.translation_unit.global(global).is_none() {
+                    self.error(VerifyErrorKind::UnknownGlobal(global.clone()));
+                }
+
+                if let Some(ty) = self.result_type(*result) {
+                    self.expect_type(|| format!("result %{}", result.0), &Type::Ptr, ty);
+                }
+            },
+            Instruction::StackAddr { result, slot } => {
+                if slot.0 >= self.function.stack_slots.len() {
+                    self.error(VerifyErrorKind::UnknownStackSlot(*slot));
+                }
+
+                if let Some(ty) = self.result_type(*result) {
+                    self.expect_type(|| format!("result %{}", result.0), &Type::Ptr, ty);
+                }
+            },
+            Instruction::Load { result, ptr, align } => {
+                self.verify_align(*align);
+
+                if let Some(ptr_ty) = self.value_type(ptr) {
+                    self.expect_type(|| "load address".to_owned(), &Type::Ptr, ptr_ty);
+                }
+
+                if let Some(ty) = self.result_type(*result) {
+                    let ok = !ty.is_aggregate() && ty != Type::Void;
+                    self.expect_kind(|| format!("loaded value %{}", result.0), "a scalar type", ty, ok);
+                }
+            },
+            Instruction::Store { value, ptr, align } => {
+                self.verify_align(*align);
+
+                if let Some(ptr_ty) = self.value_type(ptr) {
+                    self.expect_type(|| "store address".to_owned(), &Type::Ptr, ptr_ty);
+                }
+
+                if let Some(ty) = self.value_type(value) {
+                    let ok = !ty.is_aggregate() && ty != Type::Void;
+                    self.expect_kind(|| "stored value".to_owned(), "a scalar type", ty, ok);
+                }
+            },
+            Instruction::Gep { result, ty, ptr, indices } => {
+                if let Some(ptr_ty) = self.value_type(ptr) {
+                    self.expect_type(|| "gep base".to_owned(), &Type::Ptr, ptr_ty);
+                }
+
+                let mut current = ty.clone();
+
+                for (i, index) in indices.iter().enumerate() {
+                    let Some(index_ty) = self.value_type(index) else { continue };
+                    self.expect_kind(|| format!("gep index {}", i), "an integer", index_ty.clone(), index_ty.is_integer());
+
+                    if i == 0 {
+                        continue;
+                    }
+
+                    match current {
+                        Type::Array(elem, _) => current = *elem,
+                        Type::Struct(fields) => {
+                            let field = match index {
+                                Value::Const(val) => val.as_i64(),
+                                _ => None
+                            };
+
+                            match field {
+                                Some(field) if field >= 0 && (field as usize) < fields.len() => current = fields[field as usize].clone(),
+                                Some(field) => {
+                                    self.error(VerifyErrorKind::InvalidGepIndex(format!("struct has no field {}", field)));
+                                    return;
+                                },
+                                None => {
+                                    self.error(VerifyErrorKind::InvalidGepIndex("struct indices must be constant".to_owned()));
+                                    return;
+                                }
+                            }
+                        },
+                        other => {
+                            self.error(VerifyErrorKind::InvalidGepIndex(format!("can't index into {}", other)));
+                            return;
+                        }
+                    }
+                }
+
+                if let Some(result_ty) = self.result_type(*result) {
+                    self.expect_type(|| format!("result %{}", result.0), &Type::Ptr, result_ty);
+                }
+            }
+        }
+    }
+
+    // `values` are switch cases truncated to the scrutinee's width
+    fn verify_distinct(&mut self, values: &mut [i64]) {
+        values.sort();
+
+        for i in 1..values.len() {
+            if values[i] == values[i - 1] && (i == 1 || values[i - 2] != values[i]) {
+                self.error(VerifyErrorKind::DuplicateSwitchCase(values[i]));
+            }
+        }
+    }
+
+    fn verify_terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Jump(_) => {},
+            Terminator::Branch { cond, .. } => {
+                if let Some(ty) = self.value_type(cond) {
+                    self.expect_type(|| "branch condition".to_owned(), &Type::I1, ty);
+                }
+            },
+            Terminator::Switch { value, cases, .. } => {
+                let Some(ty) = self.value_type(value) else { return };
+                self.expect_kind(|| "switch value".to_owned(), "an integer", ty.clone(), ty.is_integer());
+
+                // cases may be written signed or unsigned, so an i8 takes -128 to 255
+                let (min, mask) = match ty.bits() {
+                    bits @ 1..64 => (-(1i64 << (bits - 1)), (1i64 << bits) - 1),
+                    _ => (i64::MIN, -1)
+                };
+                let max = if mask == -1 { i64::MAX } else { mask };
+                let mut seen = vec![];
+
+                for &(case, _) in cases {
+                    if case < min || case > max {
+                        self.error(VerifyErrorKind::SwitchCaseOutOfRange { value: case, ty: ty.clone() });
+                    } else {
+                        seen.push(case & mask);
+                    }
+                }
+                self.verify_distinct(&mut seen);
+            },
+            Terminator::Return(value) => {
+                let return_type = self.function.signature.return_type.clone();
+
+                match value {
+                    Some(value) => {
+                        if let Some(ty) = self.value_type(value) {
+                            self.expect_type(|| "returned value".to_owned(), &return_type, ty);
+                        }
+                    },
+                    None => self.expect_type(|| "returned value".to_owned(), &return_type, Type::Void)
+                }
+            }
+        }
+    }
+}
+
+// aggregates can't be passed or returned by value
+fn signature_errors(signature: &Signature, context: &str) -> Vec<VerifyErrorKind> {
+    let mut errors = vec![];
+
+    for param in &signature.params {
+        if param.is_aggregate() || *param == Type::Void {
+            errors.push(VerifyErrorKind::TypeMismatch {
+                context: format!("parameter type in {}", context),
+                expected: "a scalar type".to_owned(),
+                found: param.clone()
+            });
+        }
+    }
+
+    if signature.return_type.is_aggregate() {
+        errors.push(VerifyErrorKind::TypeMismatch {
+            context: format!("return type in {}", context),
+            expected: "a scalar type or void".to_owned(),
+            found: signature.return_type.clone()
+        });
+    }
+
+    errors
+}
@@ -0,0 +1,7 @@
+pub mod error;
+pub mod ir;
+pub mod linking;
+pub mod codegen;
+pub mod outputs;
+
+pub use crate::error::ChairError;
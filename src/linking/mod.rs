@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use crate::error::{ChairError, ErrorContext};
+use crate::outputs::elf::*;
+
+const SHT_INIT_ARRAY: u32 = 14;
+const SHT_FINI_ARRAY: u32 = 15;
+const SHT_PREINIT_ARRAY: u32 = 16;
+
+// sections named like these are merged into the one they start with, the way the usual linker scripts do
+const MERGED_PREFIXES: [&str; 5] = [".text", ".rodata", ".data.rel.ro", ".data", ".bss"];
+
+struct InputObject {
+    name: String,
+    elf: ParsedElf
+}
+
+struct OutputSection {
+    name: String,
+    sh_type: u32,
+    flags: u64,
+    align: u64,
+    contents: Vec<u8>,
+    size: u64
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Strength {
+    Weak,
+    Common,
+    Strong
+}
+
+// what a global name resolved to across every object
+struct GlobalSymbol {
+    // the object and symbol index of the definition that won, if any
+    definition: Option<(usize, usize)>,
+    strength: Strength,
+    // objects with a strong reference, which need a definition to exist
+    referenced_by: Vec<usize>,
+    // for common symbols, the largest size and alignment asked for and where they were put in .bss
+    common_size: u64,
+    common_align: u64,
+    common_placement: Option<(usize, u64)>
+}
+
+// links relocatable x86-64 objects, ours or other compilers', into a static executable
+pub struct Linker {
+    objects: Vec<InputObject>
+}
+
+impl Default for Linker {
+    fn default() -> Linker {
+        Linker::new()
+    }
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker {
+            objects: vec![]
+        }
+    }
+
+    // `name` is what the object is called in errors
+    pub fn add_object(&mut self, name: &str, elf: ParsedElf) -> Result<(), ChairError> {
+        let invalid = |message: &str| Err(ChairError::InvalidObject {
+            object: name.to_owned(),
+            message: message.to_owned()
+        });
+
+        if elf.header.e_type != ET_REL {
+            return invalid("only relocatable objects can be linked");
+        }
+        if elf.header.e_machine != EM_X86_64 {
+            return invalid("only x86-64 objects can be linked");
+        }
+        if elf.sections.iter().any(|section| section.header.sh_type == SHT_REL) {
+            return invalid("SHT_REL relocations aren't supported, x86-64 objects use SHT_RELA");
+        }
+        // executables have no TLS segment, so thread-locals would have nowhere to live
+        if let Some(section) = elf.sections.iter().find(|section| section.header.sh_flags & SHF_TLS != 0) {
+            return Err(ChairError::Unsupported {
+                target: "x86_64-elf",
+                context: ErrorContext::default(),
+                feature: format!("thread-local section {} in {}", section.name, name)
+            });
+        }
+
+        self.objects.push(InputObject {
+            name: name.to_owned(),
+            elf
+        });
+        Ok(())
+    }
+
+    pub fn link(self, entry: &str) -> Result<ElfFile, ChairError> {
+        let mut outputs: Vec<OutputSection> = vec![];
+        // for every object, where each of its sections went as (output section, offset), if it's kept
+        let mut placements: Vec<Vec<Option<(usize, u64)>>> = vec![];
+
+        for object in &self.objects {
+            let placed = object.elf.sections.iter().map(|section| {
+                if !is_loaded(&section.header) {
+                    return None;
+                }
+
+                let name = output_section_name(&section.name);
+                let output = match outputs.iter().position(|output| output.name == name) {
+                    Some(output) => output,
+                    None => {
+                        outputs.push(OutputSection {
+                            name: name.to_owned(),
+                            sh_type: SHT_NOBITS,
+                            flags: 0,
+                            align: 1,
+                            contents: vec![],
+                            size: 0
+                        });
+                        outputs.len() - 1
+                    }
+                };
+
+                Some((output, outputs[output].append(&section.header, &section.contents)))
+            }).collect();
+
+            placements.push(placed);
+        }
+
+        let (names, mut globals) = self.resolve_globals()?;
+
+        // common symbols that weren't defined anywhere get space in .bss
+        for name in &names {
+            let global = globals.get_mut(name).unwrap();
+            if global.strength != Strength::Common {
+                continue;
+            }
+
+            let bss = match outputs.iter().position(|output| output.name == ".bss") {
+                Some(bss) => bss,
+                None => {
+                    outputs.push(OutputSection {
+                        name: ".bss".to_owned(),
+                        sh_type: SHT_NOBITS,
+                        flags: SHF_WRITE | SHF_ALLOC,
+                        align: 1,
+                        contents: vec![],
+                        size: 0
+                    });
+                    outputs.len() - 1
+                }
+            };
+
+            let header = ElfSectionHeader {
+                sh_type: SHT_NOBITS,
+                sh_flags: SHF_WRITE | SHF_ALLOC,
+                sh_size: global.common_size,
+                sh_addralign: global.common_align,
+                ..ElfSectionHeader::default()
+            };
+            global.common_placement = Some((bss, outputs[bss].append(&header, &[])));
+        }
+
+        let mut builder = ElfObjectBuilder::new(EM_X86_64);
+        let section_ids: Vec<ElfSectionId> = outputs.into_iter().map(|output| {
+            if output.sh_type == SHT_NOBITS {
+                builder.add_nobits_section(&output.name, output.flags, output.align, output.size)
+            } else {
+                builder.add_section(&output.name, output.sh_type, output.flags, output.align, output.contents)
+            }
+        }).collect();
+
+        let place = |object: usize, shndx: u16, value: u64| {
+            placements[object].get(shndx as usize).copied().flatten().map(|(output, offset)| (section_ids[output], offset + value))
+        };
+
+        let mut global_ids: HashMap<&str, ElfSymbolId> = HashMap::new();
+        for name in &names {
+            let global = &globals[name];
+
+            let id = match (global.common_placement, global.definition) {
+                (Some((bss, offset)), _) => builder.add_symbol(Some(name), Some(section_ids[bss]), offset, global.common_size, STB_GLOBAL, STT_OBJECT),
+                (None, Some((object, index))) => {
+                    let symbol = &self.objects[object].elf.symbols[index].symbol;
+                    self.define(&mut builder, Some(name), symbol, place(object, symbol.st_shndx, symbol.st_value))
+                },
+                // only weak references are left undefined, the rest were reported by resolve_globals
+                (None, None) => builder.add_symbol(Some(name), None, 0, 0, STB_WEAK, STT_NOTYPE)
+            };
+
+            global_ids.insert(name, id);
+        }
+
+        for (i, object) in self.objects.iter().enumerate() {
+            // the builder's symbol for each of this object's symbols
+            let symbol_ids: Vec<Option<ElfSymbolId>> = object.elf.symbols.iter().enumerate().map(|(index, symbol)| {
+                let sym = &symbol.symbol;
+
+                if index == 0 || sym.sym_type() == STT_FILE {
+                    None
+                } else if sym.binding() != STB_LOCAL {
+                    Some(global_ids[symbol.name.as_str()])
+                } else {
+                    let name = (sym.sym_type() != STT_SECTION && !symbol.name.is_empty()).then_some(symbol.name.as_str());
+                    let placed = place(i, sym.st_shndx, sym.st_value);
+
+                    (placed.is_some() || sym.st_shndx == SHN_ABS).then(|| self.define(&mut builder, name, sym, placed))
+                }
+            }).collect();
+
+            for section in object.elf.sections.iter().filter(|section| section.header.sh_type == SHT_RELA) {
+                // relocations for sections that were left out, like debug info, go with them
+                let Some((output, base)) = placements[i].get(section.header.sh_info as usize).copied().flatten() else { continue };
+
+                for relocation in &section.relocations {
+                    if relocation.kind() == 0 {
+                        continue;
+                    }
+
+                    let symbol = match symbol_ids.get(relocation.symbol() as usize).copied().flatten() {
+                        Some(symbol) => symbol,
+                        // symbol 0 stands for an address of 0
+                        None if relocation.symbol() == 0 => builder.add_absolute_symbol(None, 0, 0, STB_LOCAL, STT_NOTYPE),
+                        None => return Err(ChairError::InvalidObject {
+                            object: object.name.clone(),
+                            message: format!("relocation in {} refers to a symbol in a section that isn't linked", section.name)
+                        })
+                    };
+
+                    builder.add_relocation(section_ids[output], base + relocation.r_offset, symbol, relocation.kind(), relocation.r_addend);
+                }
+            }
+        }
+
+        builder.build_executable(entry)
+    }
+}
+
+impl Linker {
+    // picks a definition for every global name, in the order the names first appear
+    fn resolve_globals(&self) -> Result<(Vec<String>, HashMap<String, GlobalSymbol>), ChairError> {
+        let mut names: Vec<String> = vec![];
+        let mut globals: HashMap<String, GlobalSymbol> = HashMap::new();
+
+        for (i, object) in self.objects.iter().enumerate() {
+            for (index, symbol) in object.elf.symbols.iter().enumerate().skip(1) {
+                let sym = &symbol.symbol;
+                if sym.binding() == STB_LOCAL {
+                    continue;
+                }
+
+                let global = globals.entry(symbol.name.clone()).or_insert_with(|| {
+                    names.push(symbol.name.clone());
+                    GlobalSymbol {
+                        definition: None,
+                        strength: Strength::Weak,
+                        referenced_by: vec![],
+                        common_size: 0,
+                        common_align: 1,
+                        common_placement: None
+                    }
+                });
+
+                let strength = match (sym.st_shndx, sym.binding()) {
+                    (SHN_UNDEF, binding) => {
+                        if binding != STB_WEAK && !global.referenced_by.contains(&i) {
+                            global.referenced_by.push(i);
+                        }
+                        continue;
+                    },
+                    (SHN_COMMON, _) => Strength::Common,
+                    (_, STB_WEAK) => Strength::Weak,
+                    _ => Strength::Strong
+                };
+
+                match (global.definition.map(|_| global.strength), strength) {
+                    (Some(Strength::Strong), Strength::Strong) => {
+                        let (first, _) = global.definition.unwrap();
+                        return Err(ChairError::DuplicateSymbol {
+                            symbol: symbol.name.clone(),
+                            objects: vec![self.objects[first].name.clone(), object.name.clone()]
+                        });
+                    },
+                    (Some(Strength::Common), Strength::Common) => {
+                        global.common_size = global.common_size.max(sym.st_size);
+                        global.common_align = global.common_align.max(sym.st_value);
+                    },
+                    // a strong definition beats common and weak ones, and common beats weak
+                    (None, _) | (Some(Strength::Weak), Strength::Common | Strength::Strong) | (Some(Strength::Common), Strength::Strong) => {
+                        global.definition = Some((i, index));
+                        global.strength = strength;
+
+                        if strength == Strength::Common {
+                            global.common_size = sym.st_size;
+                            global.common_align = sym.st_value.max(1);
+                        }
+                    },
+                    _ => ()
+                }
+            }
+        }
+
+        for name in &names {
+            let global = &globals[name];
+
+            // the executable's .got defines _GLOBAL_OFFSET_TABLE_
+            if global.definition.is_none() && !global.referenced_by.is_empty() && name != GLOBAL_OFFSET_TABLE {
+                return Err(ChairError::UndefinedSymbol {
+                    symbol: name.clone(),
+                    objects: global.referenced_by.iter().map(|object| self.objects[*object].name.clone()).collect()
+                });
+            }
+        }
+
+        Ok((names, globals))
+    }
+
+    // adds a symbol defined at `placed`, or an absolute one
+    fn define(&self, builder: &mut ElfObjectBuilder, name: Option<&str>, symbol: &ElfSymbol, placed: Option<(ElfSectionId, u64)>) -> ElfSymbolId {
+        let binding = symbol.binding();
+        let sym_type = if symbol.sym_type() == STT_SECTION { STT_NOTYPE } else { symbol.sym_type() };
+
+        match placed {
+            Some((section, value)) => builder.add_symbol(name, Some(section), value, symbol.st_size, binding, sym_type),
+            None => builder.add_absolute_symbol(name, symbol.st_value, symbol.st_size, binding, sym_type)
+        }
+    }
+}
+
+impl OutputSection {
+    // adds an input section's contents at its alignment and returns the offset it went to
+    fn append(&mut self, header: &ElfSectionHeader, contents: &[u8]) -> u64 {
+        let align = header.sh_addralign.max(1);
+        let offset = self.size.next_multiple_of(align);
+
+        self.align = self.align.max(align);
+        self.flags |= header.sh_flags & (SHF_WRITE | SHF_ALLOC | SHF_EXECINSTR);
+        self.size = offset + header.sh_size;
+
+        // one section with contents means the whole output needs them, zero filled where there weren't any
+        if header.sh_type != SHT_NOBITS {
+            if self.sh_type == SHT_NOBITS {
+                self.sh_type = SHT_PROGBITS;
+            }
+            self.contents.resize(offset as usize, 0);
+            self.contents.extend(contents);
+        }
+
+        if self.sh_type != SHT_NOBITS {
+            self.contents.resize(self.size as usize, 0);
+        }
+
+        offset
+    }
+}
+
+// whether a section ends up in the executable. Notes, debug info and the like are left out.
+fn is_loaded(header: &ElfSectionHeader) -> bool {
+    header.sh_flags & SHF_ALLOC != 0
+        && matches!(header.sh_type, SHT_PROGBITS | SHT_NOBITS | SHT_INIT_ARRAY | SHT_FINI_ARRAY | SHT_PREINIT_ARRAY)
+}
+
+fn output_section_name(name: &str) -> &str {
+    MERGED_PREFIXES.iter()
+        .find(|prefix| name == **prefix || name.strip_prefix(**prefix).is_some_and(|rest| rest.starts_with('.')))
+        .copied()
+        .unwrap_or(name)
+}
@@ -1,107 +1,422 @@
-use std::fs::write;
-
-mod ir;
-mod linking;
-mod codegen;
-mod outputs;
-
-use crate::outputs::serialization::*;
-use crate::codegen::Codegen;
-use crate::codegen::x64_elf::CompilerX64Elf;
-use crate::ir::sample::get_example_translation_unit;
-
-fn main() {
-
-    let elf = CompilerX64Elf::new().compile_translation_unit(get_example_translation_unit());
-
-    write("a.o", elf.serialize(false)).expect("file write shit fuck");
-    println!("written program to a.o");
-}
-
-/*
-fn main() {
-
-    println!("Hello, world!");
-    let mut elf = ElfHeader {
-        e_ident_magic: [0x7F, 0x45, 0x4c, 0x46],
-        e_ident_class: 2,
-        e_ident_data: 1,
-        e_ident_version: 1,
-        e_ident_abi: 3,
-        e_ident_abi_version: 67,
-        e_ident_pad: [0,0,0,0,0,0,0],
-        e_type: 2,
-        e_machine: 0x3E,
-        e_version: 1,
-        e_entry: 0,
-        e_phoff: 0x40,
-        e_shoff: 0,
-        e_flags: 0,
-        e_ehsize: 0x40,
-        e_phentsize: 0x38,
-        e_phnum: 2,
-        e_shentsize: 0x40,
-        e_shnum: 0,
-        e_shstrndx: 0
-    };
-
-    let mut prog = vec![
-        0x6Au8, 0x1,                    // push 1
-        0x58,                           // pop rax
-        0x6A, 0x1,                      // push 1
-        0x5F,                           // pop rdi
-        0x68, 0x00, 0x20, 0x00, 0x40,   // push addr to string
-        0x5e,                           // pop rsi
-        0x6a, 14,                       // push 14
-        0x5a,                           // pop rdx
-        0x0f, 0x05,                     // syscall
-        0x6a, 0x3c,                     // push 60
-        0x58,                           // pop rax
-        0x6a, 0,                        // push 0
-        0x5f,                           // pop rdi
-        0x0f, 0x05                      // syscall
-    ];
-
-    let mut rodata = vec![0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x21, 0x0A, 0x00];
-
-    let elf_ph_text = ElfProgramHeader {
-        p_type: 1,
-        p_flags: 1 | 2 | 4,
-        p_offset: 0x1000,
-        p_vaddr: 0x40001000,
-        p_paddr: 0,
-        p_filesz: prog.len() as u64,
-        p_memsz: prog.len() as u64,
-        p_align: 0x1000
-    };
-
-    let mut final_vec = Vec::new();
-
-    let elf_ph_rodata = ElfProgramHeader {
-        p_type: 1,
-        p_flags: 4,
-        p_offset: 0x2000,
-        p_vaddr: 0x40002000,
-        p_paddr: 0,
-        p_filesz: rodata.len() as u64,
-        p_memsz: rodata.len() as u64,
-        p_align: 0x1000
-    };
-
-    elf.e_entry = 0x40001000;
-
-
-    final_vec.append(&mut elf.serialize(false));
-    final_vec.append(&mut elf_ph_text.serialize(false));
-    final_vec.append(&mut elf_ph_rodata.serialize(false));
-    final_vec.resize(0x1000, 0);
-    final_vec.append(&mut prog);
-    final_vec.resize(0x2000, 0);
-    final_vec.append(&mut rodata);
-
-
-    write("a.out", final_vec).expect("file write shit fuck");
-    println!("written program to a.out");
-
-}
-*/
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::exit;
+
+use chair::ChairError;
+use chair::outputs::elf::ElfFile;
+use chair::outputs::inspect::describe;
+use chair::outputs::serialization::*;
+use chair::codegen::{Addressing, Codegen, CodegenOptions, RegisterAllocator};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::TranslationUnit;
+use chair::ir::opt::{optimize, OptLevel};
+use chair::ir::text::parser::parse_translation_unit;
+use chair::ir::verify::verify_translation_unit;
+use chair::linking::Linker;
+
+// exit codes
+const EXIT_INVALID_INPUT: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+const EXIT_UNSUPPORTED: i32 = 4;
+const EXIT_CODEGEN: i32 = 5;
+const EXIT_LINK: i32 = 6;
+
+const TARGETS: [&str; 1] = ["x86_64-elf"];
+
+const USAGE: &str = "\
+usage: chair [options] [file.chir ...]
+       chair inspect <file> ...
+
+Compiles textual IR. With no files, or a file named -, the IR is read from stdin.
+With --emit=exe, every input is compiled and linked into one static executable,
+and object files can be given alongside the IR.
+`chair inspect` prints the headers, sections, symbols, relocations and contents of ELF files.
+
+options:
+  -o <path>          write the output to <path>, - for stdout
+  --emit=<kind>      obj (default), asm, exe, ir or ir-optimized
+  --target=<target>  x86_64-elf (default)
+  --entry=<symbol>   where --emit=exe programs start, _start by default
+  -O0, -O1, -O2      optimization level, -O0 by default
+  --regalloc=<kind>  linear-scan, or coloring which -O2 uses by default
+  --frame-pointer=<when>
+                     keep, or omit which -O2 does by default
+  --no-red-zone      don't keep locals of leaf functions below the stack pointer
+  --addressing=<kind>
+                     rip-relative (default), got for shared libraries, or absolute
+  -h, --help         show this message
+
+exit codes:
+  1  the IR doesn't parse or verify, or an ELF file to inspect is malformed
+  2  bad command line
+  3  reading or writing a file failed
+  4  the target doesn't support what was asked for
+  5  code generation failed, like a jump too far to encode
+  6  linking failed, like a call to a function that isn't defined";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    Obj,
+    Asm,
+    Exe,
+    Ir,
+    IrOptimized
+}
+
+struct Options {
+    inputs: Vec<String>,
+    output: Option<String>,
+    emit: Emit,
+    target: String,
+    entry: String,
+    opt_level: OptLevel,
+    register_allocator: Option<RegisterAllocator>,
+    frame_pointer: Option<bool>,
+    red_zone: bool,
+    addressing: Option<Addressing>
+}
+
+struct Failure {
+    code: i32,
+    messages: Vec<String>
+}
+
+impl Failure {
+    fn new(code: i32, message: String) -> Failure {
+        Failure {
+            code,
+            messages: vec![message]
+        }
+    }
+}
+
+impl From<ChairError> for Failure {
+    fn from(error: ChairError) -> Failure {
+        let code = match error {
+            ChairError::Parse(_) | ChairError::Verify(_) | ChairError::Deserialize(_) | ChairError::InvalidIr { .. } => EXIT_INVALID_INPUT,
+            ChairError::Unsupported { .. } => EXIT_UNSUPPORTED,
+            ChairError::RelocationOverflow { .. } => EXIT_CODEGEN,
+            ChairError::InvalidObject { .. } | ChairError::DuplicateSymbol { .. } | ChairError::UndefinedSymbol { .. } => EXIT_LINK,
+            ChairError::Io { .. } => EXIT_IO
+        };
+
+        Failure {
+            code,
+            messages: error.to_string().lines().map(str::to_owned).collect()
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let result = match args.split_first() {
+        Some((command, files)) if command == "inspect" => inspect(files),
+        _ => parse_args(&args).and_then(|options| run(&options))
+    };
+
+    if let Err(failure) = result {
+        for message in failure.messages {
+            eprintln!("error: {}", message);
+        }
+
+        if failure.code == EXIT_USAGE {
+            eprintln!("run `chair --help` for usage");
+        }
+
+        exit(failure.code);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, Failure> {
+    let mut options = Options {
+        inputs: vec![],
+        output: None,
+        emit: Emit::Obj,
+        target: TARGETS[0].to_owned(),
+        entry: "_start".to_owned(),
+        opt_level: OptLevel::O0,
+        register_allocator: None,
+        frame_pointer: None,
+        red_zone: true,
+        addressing: None
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        // options taking a value accept it either attached or as the next argument
+        let mut value = |name: &str, attached: Option<&str>| -> Result<String, Failure> {
+            match attached {
+                Some(value) if !value.is_empty() => Ok(value.to_owned()),
+                _ => args.next().cloned().ok_or_else(|| Failure::new(EXIT_USAGE, format!("{} needs a value", name)))
+            }
+        };
+
+        if arg == "-o" || (arg.starts_with("-o") && arg.len() > 2) {
+            options.output = Some(value("-o", arg.strip_prefix("-o"))?);
+        } else if arg == "--emit" || arg.starts_with("--emit=") {
+            let emit = value("--emit", arg.strip_prefix("--emit="))?;
+
+            options.emit = match emit.as_str() {
+                "obj" => Emit::Obj,
+                "asm" => Emit::Asm,
+                "exe" => Emit::Exe,
+                "ir" => Emit::Ir,
+                "ir-optimized" => Emit::IrOptimized,
+                _ => return Err(Failure::new(EXIT_USAGE, format!("unknown --emit kind '{}'", emit)))
+            };
+        } else if arg == "--target" || arg.starts_with("--target=") {
+            options.target = value("--target", arg.strip_prefix("--target="))?;
+        } else if arg == "--entry" || arg.starts_with("--entry=") {
+            options.entry = value("--entry", arg.strip_prefix("--entry="))?;
+        } else if arg == "--regalloc" || arg.starts_with("--regalloc=") {
+            let allocator = value("--regalloc", arg.strip_prefix("--regalloc="))?;
+
+            options.register_allocator = match allocator.as_str() {
+                "linear-scan" => Some(RegisterAllocator::LinearScan),
+                "coloring" => Some(RegisterAllocator::GraphColoring),
+                _ => return Err(Failure::new(EXIT_USAGE, format!("unknown --regalloc kind '{}'", allocator)))
+            };
+        } else if arg == "--frame-pointer" || arg.starts_with("--frame-pointer=") {
+            let when = value("--frame-pointer", arg.strip_prefix("--frame-pointer="))?;
+
+            options.frame_pointer = match when.as_str() {
+                "keep" => Some(true),
+                "omit" => Some(false),
+                _ => return Err(Failure::new(EXIT_USAGE, format!("unknown --frame-pointer choice '{}'", when)))
+            };
+        } else if arg == "--no-red-zone" {
+            options.red_zone = false;
+        } else if arg == "--addressing" || arg.starts_with("--addressing=") {
+            let addressing = value("--addressing", arg.strip_prefix("--addressing="))?;
+
+            options.addressing = match addressing.as_str() {
+                "rip-relative" => Some(Addressing::RipRelative),
+                "got" => Some(Addressing::Got),
+                "absolute" => Some(Addressing::Absolute),
+                _ => return Err(Failure::new(EXIT_USAGE, format!("unknown --addressing kind '{}'", addressing)))
+            };
+        } else if let Some(level) = arg.strip_prefix("-O") {
+            options.opt_level = match level {
+                "0" => OptLevel::O0,
+                "1" => OptLevel::O1,
+                "2" => OptLevel::O2,
+                _ => return Err(Failure::new(EXIT_USAGE, format!("unknown optimization level '{}'", arg)))
+            };
+        } else if arg.starts_with('-') && arg != "-" {
+            return Err(Failure::new(EXIT_USAGE, format!("unknown option '{}'", arg)));
+        } else {
+            options.inputs.push(arg.clone());
+        }
+    }
+
+    if options.inputs.is_empty() {
+        options.inputs.push("-".to_owned());
+    }
+
+    if options.output.is_some() && options.inputs.len() > 1 && options.emit != Emit::Exe {
+        return Err(Failure::new(EXIT_USAGE, "-o can't be used with more than one input".to_owned()));
+    }
+
+    if !TARGETS.contains(&options.target.as_str()) {
+        return Err(Failure::new(EXIT_UNSUPPORTED, format!("unsupported target '{}', supported targets are: {}", options.target, TARGETS.join(", "))));
+    }
+
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), Failure> {
+    if options.emit == Emit::Exe {
+        return link_executable(options);
+    }
+
+    for input in &options.inputs {
+        let mut translation_unit = read_input(input)?;
+
+        if options.emit != Emit::Ir {
+            optimize(&mut translation_unit, options.opt_level)
+                .map_err(|error| in_input(error, format!("{}: ", input_name(input))))?;
+        }
+
+        let path = match (&options.output, options.emit) {
+            (Some(path), _) => path.clone(),
+            (None, Emit::Obj) if input == "-" => "a.o".to_owned(),
+            (None, Emit::Obj) => Path::new(input).with_extension("o").file_name().unwrap().to_string_lossy().into_owned(),
+            (None, _) => "-".to_owned()
+        };
+
+        match options.emit {
+            Emit::Ir | Emit::IrOptimized => write_output(&path, translation_unit.to_string().as_bytes())?,
+            Emit::Asm => {
+                let listing = CompilerX64Elf::with_options(codegen_options(options)).compile_listing(translation_unit)
+                    .map_err(|error| in_input(error, format!("{}: ", input_name(input))))?;
+                write_output(&path, listing.as_bytes())?;
+            },
+            _ => {
+                let elf = CompilerX64Elf::with_options(codegen_options(options)).compile_translation_unit(translation_unit)
+                    .map_err(|error| in_input(error, format!("{}: ", input_name(input))))?;
+
+                if path == "-" {
+                    write_output(&path, &elf.serialize(false))?;
+                } else {
+                    elf.write_to_file(&path)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// compiles the IR inputs and links them with the object file inputs into one executable
+fn link_executable(options: &Options) -> Result<(), Failure> {
+    let mut linker = Linker::new();
+
+    for input in &options.inputs {
+        let name = input_name(input);
+        let bytes = read_bytes(input)?;
+
+        let elf = if bytes.starts_with(b"\x7FELF") {
+            ElfFile::parse(&bytes).map_err(|error| in_input(error.into(), format!("{}: ", name)))?
+        } else {
+            let mut translation_unit = parse_input(name, bytes)?;
+            optimize(&mut translation_unit, options.opt_level)
+                .map_err(|error| in_input(error, format!("{}: ", name)))?;
+
+            let object = CompilerX64Elf::with_options(codegen_options(options)).compile_translation_unit(translation_unit)
+                .map_err(|error| in_input(error, format!("{}: ", name)))?;
+            // an object that doesn't read back is codegen's fault rather than the input's
+            ElfFile::parse(&object.serialize(false))
+                .map_err(|error| Failure { code: EXIT_CODEGEN, ..in_input(error.into(), format!("{}: ", name)) })?
+        };
+
+        linker.add_object(name, elf)?;
+    }
+
+    let path = match (&options.output, options.inputs.as_slice()) {
+        (Some(path), _) => path.clone(),
+        (None, [input]) if input != "-" => Path::new(input).with_extension("").file_name().unwrap().to_string_lossy().into_owned(),
+        (None, _) => "a.out".to_owned()
+    };
+
+    let elf = linker.link(&options.entry)?;
+
+    if path == "-" {
+        write_output(&path, &elf.serialize(false))?;
+    } else {
+        elf.write_to_file(&path)?;
+        make_executable(&path)?;
+    }
+
+    Ok(())
+}
+
+fn inspect(files: &[String]) -> Result<(), Failure> {
+    if files.is_empty() {
+        return Err(Failure::new(EXIT_USAGE, "inspect needs at least one file".to_owned()));
+    }
+
+    for (i, file) in files.iter().enumerate() {
+        let bytes = fs::read(file).map_err(|error| ChairError::Io { path: file.clone(), error })?;
+        let elf = ElfFile::parse(&bytes).map_err(|error| in_input(error.into(), format!("{}: ", file)))?;
+
+        let mut output = String::new();
+        if files.len() > 1 {
+            output.push_str(&format!("{}{}:\n\n", if i > 0 { "\n" } else { "" }, file));
+        }
+        output.push_str(&describe(&elf));
+
+        write_output("-", output.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn codegen_options(options: &Options) -> CodegenOptions {
+    let mut codegen_options = CodegenOptions::for_level(options.opt_level);
+    if let Some(allocator) = options.register_allocator {
+        codegen_options.register_allocator = allocator;
+    }
+    if let Some(frame_pointer) = options.frame_pointer {
+        codegen_options.frame_pointer = frame_pointer;
+    }
+    codegen_options.red_zone &= options.red_zone;
+    if let Some(addressing) = options.addressing {
+        codegen_options.addressing = addressing;
+    }
+    codegen_options
+}
+
+// the failure for `error`, with every message prefixed to say which input it's about
+fn in_input(error: ChairError, prefix: String) -> Failure {
+    let mut failure = Failure::from(error);
+    for message in &mut failure.messages {
+        message.insert_str(0, &prefix);
+    }
+    failure
+}
+
+fn read_bytes(input: &str) -> Result<Vec<u8>, Failure> {
+    let mut bytes = vec![];
+
+    let read = if input == "-" {
+        io::stdin().read_to_end(&mut bytes).map(|_| ())
+    } else {
+        fs::read(input).map(|read| bytes = read)
+    };
+
+    read.map_err(|error| ChairError::Io { path: input_name(input).to_owned(), error })?;
+    Ok(bytes)
+}
+
+// reads, parses and verifies one input
+fn read_input(input: &str) -> Result<TranslationUnit, Failure> {
+    parse_input(input_name(input), read_bytes(input)?)
+}
+
+fn parse_input(name: &str, bytes: Vec<u8>) -> Result<TranslationUnit, Failure> {
+    let source = String::from_utf8(bytes)
+        .map_err(|_| Failure::new(EXIT_UNSUPPORTED, format!("{} isn't textual IR, binary IR isn't supported", name)))?;
+
+    let translation_unit = parse_translation_unit(&source)
+        .map_err(|error| in_input(error.into(), format!("{}:", name)))?;
+
+    verify_translation_unit(&translation_unit)
+        .map_err(|errors| in_input(errors.into(), format!("{}: ", name)))?;
+
+    Ok(translation_unit)
+}
+
+fn input_name(input: &str) -> &str {
+    if input == "-" { "<stdin>" } else { input }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &str) -> Result<(), ChairError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(|error| ChairError::Io { path: path.to_owned(), error })
+}
+
+#[cfg(not(unix))]
+fn make_executable(_: &str) -> Result<(), ChairError> {
+    Ok(())
+}
+
+fn write_output(path: &str, output: &[u8]) -> Result<(), ChairError> {
+    let (path, result) = if path == "-" {
+        ("<stdout>", io::stdout().write_all(output))
+    } else {
+        (path, fs::write(path, output))
+    };
+
+    result.map_err(|error| ChairError::Io { path: path.to_owned(), error })
+}
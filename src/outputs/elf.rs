@@ -1,188 +1,1176 @@
-use crate::outputs::serialization::{add_bytes, Serializable};
-
-pub struct ElfHeader {
-    pub e_ident_magic: [u8; 4],
-    pub e_ident_class: u8,
-    pub e_ident_data: u8,
-    pub e_ident_version: u8,
-    pub e_ident_abi: u8,
-    pub e_ident_abi_version: u8,
-    pub e_ident_pad: [u8; 7],
-    pub e_type: u16,
-    pub e_machine: u16,
-    pub e_version: u32,
-    pub e_entry: u64,
-    pub e_phoff: u64,
-    pub e_shoff: u64,
-    pub e_flags: u32,
-    pub e_ehsize: u16,
-    pub e_phentsize: u16,
-    pub e_phnum: u16,
-    pub e_shentsize: u16,
-    pub e_shnum: u16,
-    pub e_shstrndx: u16,
-}
-
-pub struct ElfProgramHeader {
-    pub p_type: u32,
-    pub p_flags: u32,
-    pub p_offset: u64,
-    pub p_vaddr: u64,
-    pub p_paddr: u64,
-    pub p_filesz: u64,
-    pub p_memsz: u64,
-    pub p_align: u64
-}
-
-pub struct ElfSectionHeader {
-    pub sh_name: u32,
-    pub sh_type: u32,
-    pub sh_flags: u64,
-    pub sh_addr: u64,
-    pub sh_offset: u64,
-    pub sh_size: u64,
-    pub sh_link: u32,
-    pub sh_info: u32,
-    pub sh_addralign: u64,
-    pub sh_entsize: u64
-}
-
-pub struct ElfFile {
-    pub elf_header: ElfHeader,
-    pub elf_program_headers: Vec<ElfProgramHeader>,
-    pub elf_section_headers: Vec<ElfSectionHeader>,
-    pub data: Vec<u8>
-}
-
-pub struct ElfSymbol {
-    pub st_name: u32,
-    pub st_info: u8,
-    pub st_other: u8,
-    pub st_shndx: u16,
-    pub st_value: u64,
-    pub st_size: u64
-}
-
-pub struct ElfRelocation {
-    pub r_offset: u64,
-    pub r_info: u64
-}
-
-impl Serializable for ElfHeader {
-    fn serialize(&self, be: bool) -> Vec<u8> {
-        let mut vec = Vec::new();
-
-        vec.extend(&self.e_ident_magic);
-        add_bytes(&mut vec, self.e_ident_class, be);
-        add_bytes(&mut vec, self.e_ident_data, be);
-        add_bytes(&mut vec, self.e_ident_version, be);
-        add_bytes(&mut vec, self.e_ident_abi, be);
-        add_bytes(&mut vec, self.e_ident_abi_version, be);
-        vec.extend(&self.e_ident_pad);
-        add_bytes(&mut vec, self.e_type, be);
-        add_bytes(&mut vec, self.e_machine, be);
-        add_bytes(&mut vec, self.e_version, be);
-        add_bytes(&mut vec, self.e_entry, be);
-        add_bytes(&mut vec, self.e_phoff, be);
-        add_bytes(&mut vec, self.e_shoff, be);
-        add_bytes(&mut vec, self.e_flags, be);
-        add_bytes(&mut vec, self.e_ehsize, be);
-        add_bytes(&mut vec, self.e_phentsize, be);
-        add_bytes(&mut vec, self.e_phnum, be);
-        add_bytes(&mut vec, self.e_shentsize, be);
-        add_bytes(&mut vec, self.e_shnum, be);
-        add_bytes(&mut vec, self.e_shstrndx, be);
-        vec
-    }
-
-    fn serialized_length(&self) -> usize {
-        0x40
-    }
-}
-
-impl Serializable for ElfProgramHeader {
-    fn serialize(&self, be: bool) -> Vec<u8> {
-        let mut vec = Vec::new();
-
-        add_bytes(&mut vec, self.p_type, be);
-        add_bytes(&mut vec, self.p_flags, be);
-        add_bytes(&mut vec, self.p_offset, be);
-        add_bytes(&mut vec, self.p_vaddr, be);
-        add_bytes(&mut vec, self.p_paddr, be);
-        add_bytes(&mut vec, self.p_filesz, be);
-        add_bytes(&mut vec, self.p_memsz, be);
-        add_bytes(&mut vec, self.p_align, be);
-
-        vec
-    }
-
-    fn serialized_length(&self) -> usize {
-        0x38
-    }
-}
-
-impl Serializable for ElfSymbol {
-    fn serialize(&self, be: bool) -> Vec<u8> {
-        let mut vec = Vec::new();
-
-        add_bytes(&mut vec, self.st_name, be);
-        add_bytes(&mut vec, self.st_info, be);
-        add_bytes(&mut vec, self.st_other, be);
-        add_bytes(&mut vec, self.st_shndx, be);
-        add_bytes(&mut vec, self.st_value, be);
-        add_bytes(&mut vec, self.st_size, be);
-
-        vec
-    }
-
-    fn serialized_length(&self) -> usize {
-        0x18
-    }
-}
-
-impl Serializable for ElfSectionHeader {
-    fn serialize(&self, be: bool) -> Vec<u8> {
-        let mut vec = Vec::new();
-
-        add_bytes(&mut vec, self.sh_name, be);
-        add_bytes(&mut vec, self.sh_type, be);
-        add_bytes(&mut vec, self.sh_flags, be);
-        add_bytes(&mut vec, self.sh_addr, be);
-        add_bytes(&mut vec, self.sh_offset, be);
-        add_bytes(&mut vec, self.sh_size, be);
-        add_bytes(&mut vec, self.sh_link, be);
-        add_bytes(&mut vec, self.sh_info, be);
-        add_bytes(&mut vec, self.sh_addralign, be);
-        add_bytes(&mut vec, self.sh_entsize, be);
-
-        vec
-    }
-
-    fn serialized_length(&self) -> usize {
-        0x40
-    }
-}
-
-impl Serializable for ElfRelocation {
-    fn serialize(&self, be: bool) -> Vec<u8> {
-        let mut vec = vec![];
-
-        add_bytes(&mut vec, self.r_offset, be);
-        add_bytes(&mut vec, self.r_info, be);
-
-        vec
-    }
-}
-
-impl Serializable for ElfFile {
-    fn serialize(&self, be: bool) -> Vec<u8> {
-        let mut vec = Vec::new();
-
-        vec.extend(self.elf_header.serialize(be));
-        vec.extend(self.elf_program_headers.serialize(be));
-        vec.extend(self.elf_section_headers.serialize(be));
-        vec.extend(&self.data);
-
-        vec
-    }
-}
+use std::fs;
+use std::path::Path;
+
+use crate::error::{ChairError, ErrorContext};
+use crate::outputs::serialization::{add_bytes, Deserializable, DeserializeError, DeserializeErrorKind, Reader, Serializable};
+
+pub const ET_REL: u16 = 1;
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+pub const EM_X86_64: u16 = 0x3E;
+
+pub const ELFOSABI_NONE: u8 = 0;
+
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOBITS: u32 = 8;
+pub const SHT_REL: u32 = 9;
+pub const SHT_DYNSYM: u32 = 11;
+
+pub const SHF_WRITE: u64 = 0x1;
+pub const SHF_ALLOC: u64 = 0x2;
+pub const SHF_EXECINSTR: u64 = 0x4;
+pub const SHF_INFO_LINK: u64 = 0x40;
+pub const SHF_TLS: u64 = 0x400;
+
+pub const STB_LOCAL: u8 = 0;
+pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
+
+pub const STT_NOTYPE: u8 = 0;
+pub const STT_OBJECT: u8 = 1;
+pub const STT_FUNC: u8 = 2;
+pub const STT_SECTION: u8 = 3;
+pub const STT_FILE: u8 = 4;
+
+pub const R_X86_64_64: u32 = 1;
+pub const R_X86_64_PC32: u32 = 2;
+pub const R_X86_64_PLT32: u32 = 4;
+pub const R_X86_64_GOTPCREL: u32 = 9;
+pub const R_X86_64_32: u32 = 10;
+pub const R_X86_64_32S: u32 = 11;
+pub const R_X86_64_PC64: u32 = 24;
+pub const R_X86_64_GOTPCRELX: u32 = 41;
+pub const R_X86_64_REX_GOTPCRELX: u32 = 42;
+
+pub const SHN_UNDEF: u16 = 0;
+pub const SHN_LORESERVE: u16 = 0xFF00;
+pub const SHN_ABS: u16 = 0xFFF1;
+pub const SHN_COMMON: u16 = 0xFFF2;
+
+pub const PT_LOAD: u32 = 1;
+
+pub const PF_X: u32 = 0x1;
+pub const PF_W: u32 = 0x2;
+pub const PF_R: u32 = 0x4;
+
+const ELF_HEADER_SIZE: u64 = 0x40;
+const PROGRAM_HEADER_SIZE: u64 = 0x38;
+const SECTION_HEADER_SIZE: u64 = 0x40;
+const SYMBOL_SIZE: u64 = 0x18;
+const RELA_SIZE: u64 = 0x18;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElfHeader {
+    pub e_ident_magic: [u8; 4],
+    pub e_ident_class: u8,
+    pub e_ident_data: u8,
+    pub e_ident_version: u8,
+    pub e_ident_abi: u8,
+    pub e_ident_abi_version: u8,
+    pub e_ident_pad: [u8; 7],
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub e_version: u32,
+    pub e_entry: u64,
+    pub e_phoff: u64,
+    pub e_shoff: u64,
+    pub e_flags: u32,
+    pub e_ehsize: u16,
+    pub e_phentsize: u16,
+    pub e_phnum: u16,
+    pub e_shentsize: u16,
+    pub e_shnum: u16,
+    pub e_shstrndx: u16,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElfProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_paddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+    pub p_align: u64
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ElfSectionHeader {
+    pub sh_name: u32,
+    pub sh_type: u32,
+    pub sh_flags: u64,
+    pub sh_addr: u64,
+    pub sh_offset: u64,
+    pub sh_size: u64,
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_addralign: u64,
+    pub sh_entsize: u64
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElfFile {
+    pub elf_header: ElfHeader,
+    pub elf_program_headers: Vec<ElfProgramHeader>,
+    pub elf_section_headers: Vec<ElfSectionHeader>,
+    pub data: Vec<u8>
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ElfSymbol {
+    pub st_name: u32,
+    pub st_info: u8,
+    pub st_other: u8,
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElfRelocation {
+    pub r_offset: u64,
+    pub r_info: u64,
+    pub r_addend: i64
+}
+
+impl ElfRelocation {
+    pub fn new(offset: u64, symbol: u32, kind: u32, addend: i64) -> ElfRelocation {
+        ElfRelocation {
+            r_offset: offset,
+            r_info: ((symbol as u64) << 32) | kind as u64,
+            r_addend: addend
+        }
+    }
+
+    // the index of the symbol in the linked symbol table
+    pub fn symbol(&self) -> u32 {
+        (self.r_info >> 32) as u32
+    }
+
+    pub fn kind(&self) -> u32 {
+        self.r_info as u32
+    }
+}
+
+// an ELF file read back from bytes, with section and symbol names looked up
+#[derive(Clone, Debug)]
+pub struct ParsedElf {
+    pub header: ElfHeader,
+    pub program_headers: Vec<ElfProgramHeader>,
+    // every section including the null one, so indices match the section header table
+    pub sections: Vec<ParsedSection>,
+    // the entries of .symtab including the null one, empty if there isn't one
+    pub symbols: Vec<ParsedSymbol>
+}
+
+#[derive(Clone, Debug)]
+pub struct ParsedSection {
+    pub name: String,
+    pub header: ElfSectionHeader,
+    // empty for SHT_NOBITS
+    pub contents: Vec<u8>,
+    // the entries of SHT_RELA sections
+    pub relocations: Vec<ElfRelocation>
+}
+
+#[derive(Clone, Debug)]
+pub struct ParsedSymbol {
+    pub name: String,
+    pub symbol: ElfSymbol
+}
+
+impl ParsedElf {
+    pub fn section(&self, name: &str) -> Option<&ParsedSection> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    pub fn section_index(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|section| section.name == name)
+    }
+}
+
+impl ElfSymbol {
+    pub fn binding(&self) -> u8 {
+        self.st_info >> 4
+    }
+
+    pub fn sym_type(&self) -> u8 {
+        self.st_info & 0xF
+    }
+}
+
+// the NUL-terminated string at `offset` in a string table that starts at `table_offset` in the file
+fn string_at(table: &[u8], table_offset: u64, offset: u32) -> Result<String, DeserializeError> {
+    let error = |message: String| DeserializeError {
+        offset: (table_offset + offset as u64) as usize,
+        kind: DeserializeErrorKind::Invalid(message)
+    };
+
+    let bytes = table.get(offset as usize..).ok_or_else(|| error(format!("string offset {} is outside a {} byte string table", offset, table.len())))?;
+    let len = bytes.iter().position(|byte| *byte == 0).ok_or_else(|| error("string isn't NUL-terminated".to_owned()))?;
+
+    Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+impl ElfFile {
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), ChairError> {
+        let path = path.as_ref();
+
+        fs::write(path, self.serialize(false)).map_err(|error| ChairError::Io {
+            path: path.display().to_string(),
+            error
+        })
+    }
+
+    // reads a 64-bit ELF file of either byte order, checking that every table it refers to is in bounds
+    pub fn parse(bytes: &[u8]) -> Result<ParsedElf, DeserializeError> {
+        if bytes.len() >= 6 && bytes[5] == 2 {
+            ElfFile::parse_with(bytes, true)
+        } else {
+            ElfFile::parse_with(bytes, false)
+        }
+    }
+
+    fn parse_with(bytes: &[u8], big_endian: bool) -> Result<ParsedElf, DeserializeError> {
+        let header = ElfHeader::deserialize(&mut Reader::new(bytes, big_endian))?;
+        let header_error = |message: String| DeserializeError {
+            offset: 0,
+            kind: DeserializeErrorKind::Invalid(message)
+        };
+
+        if header.e_phnum > 0 && header.e_phentsize as u64 != PROGRAM_HEADER_SIZE {
+            return Err(header_error(format!("program headers are {} bytes, expected {}", header.e_phentsize, PROGRAM_HEADER_SIZE)));
+        }
+        if header.e_shnum > 0 && header.e_shentsize as u64 != SECTION_HEADER_SIZE {
+            return Err(header_error(format!("section headers are {} bytes, expected {}", header.e_shentsize, SECTION_HEADER_SIZE)));
+        }
+        if header.e_shnum > 0 && header.e_shstrndx >= header.e_shnum {
+            return Err(header_error(format!("section name table {} is past the last section {}", header.e_shstrndx, header.e_shnum - 1)));
+        }
+
+        let table_start = |offset: u64| usize::try_from(offset).map_err(|_| header_error(format!("offset 0x{:x} is too large", offset)));
+
+        let mut reader = Reader::at(bytes, table_start(header.e_phoff)?, big_endian);
+        let program_headers = (0..header.e_phnum)
+            .map(|_| ElfProgramHeader::deserialize(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut reader = Reader::at(bytes, table_start(header.e_shoff)?, big_endian);
+        let section_headers = (0..header.e_shnum)
+            .map(|_| ElfSectionHeader::deserialize(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let contents = section_headers.iter().map(|section| {
+            if section.sh_type == SHT_NOBITS || section.sh_type == 0 {
+                return Ok(&[][..]);
+            }
+
+            let mut reader = Reader::at(bytes, table_start(section.sh_offset)?, big_endian);
+            let len = usize::try_from(section.sh_size).map_err(|_| reader.error(format!("section size 0x{:x} is too large", section.sh_size)))?;
+            reader.read_bytes(len, "section contents")
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let string_table = |index: u32| {
+            match section_headers.get(index as usize) {
+                Some(table) if table.sh_type == SHT_STRTAB => Ok((contents[index as usize], table.sh_offset)),
+                _ => Err(header_error(format!("section {} isn't a string table", index)))
+            }
+        };
+
+        let names = match header.e_shstrndx {
+            0 => vec![String::new(); section_headers.len()],
+            index => {
+                let (shstrtab, shstrtab_offset) = string_table(index as u32)?;
+                section_headers.iter()
+                    .map(|section| string_at(shstrtab, shstrtab_offset, section.sh_name))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+        };
+
+        // every symbol table's entry count, for checking relocations against the one they're linked to
+        let symbol_counts: Vec<u64> = section_headers.iter().map(|section| section.sh_size / SYMBOL_SIZE).collect();
+
+        let mut symbols = vec![];
+        let mut sections = vec![];
+
+        for ((section, contents), name) in section_headers.iter().zip(&contents).zip(names) {
+            let offset = section.sh_offset as usize;
+
+            if section.sh_type == SHT_SYMTAB && symbols.is_empty() {
+                if section.sh_entsize != SYMBOL_SIZE {
+                    return Err(header_error(format!("{} has {} byte entries, expected {}", name, section.sh_entsize, SYMBOL_SIZE)));
+                }
+
+                let (strtab, strtab_offset) = string_table(section.sh_link)?;
+                let mut reader = Reader::at(bytes, offset, big_endian);
+
+                for _ in 0..section.sh_size / SYMBOL_SIZE {
+                    let symbol = ElfSymbol::deserialize(&mut reader)?;
+                    if symbol.st_shndx < SHN_LORESERVE && symbol.st_shndx >= header.e_shnum {
+                        return Err(reader.error(format!("symbol is in section {}, past the last section {}", symbol.st_shndx, header.e_shnum.saturating_sub(1))));
+                    }
+
+                    symbols.push(ParsedSymbol {
+                        name: string_at(strtab, strtab_offset, symbol.st_name)?,
+                        symbol
+                    });
+                }
+            }
+
+            let mut relocations = vec![];
+            if section.sh_type == SHT_RELA {
+                if section.sh_entsize != RELA_SIZE {
+                    return Err(header_error(format!("{} has {} byte entries, expected {}", name, section.sh_entsize, RELA_SIZE)));
+                }
+
+                let symbol_count = match section_headers.get(section.sh_link as usize) {
+                    Some(table) if table.sh_type == SHT_SYMTAB || table.sh_type == SHT_DYNSYM => symbol_counts[section.sh_link as usize],
+                    _ => return Err(header_error(format!("{} links to {}, which isn't a symbol table", name, section.sh_link)))
+                };
+
+                let mut reader = Reader::at(bytes, offset, big_endian);
+                for _ in 0..section.sh_size / RELA_SIZE {
+                    let relocation = ElfRelocation::deserialize(&mut reader)?;
+                    if relocation.symbol() as u64 >= symbol_count {
+                        return Err(reader.error(format!("relocation refers to symbol {}, but there are only {}", relocation.symbol(), symbol_count)));
+                    }
+
+                    relocations.push(relocation);
+                }
+            }
+
+            sections.push(ParsedSection {
+                name,
+                header: section.clone(),
+                contents: contents.to_vec(),
+                relocations
+            });
+        }
+
+        Ok(ParsedElf {
+            header,
+            program_headers,
+            sections,
+            symbols
+        })
+    }
+}
+
+impl Deserializable for ElfHeader {
+    fn deserialize(reader: &mut Reader) -> Result<ElfHeader, DeserializeError> {
+        let start = reader.offset();
+        let e_ident_magic: [u8; 4] = reader.read_bytes(4, "ELF header")?.try_into().unwrap();
+        if e_ident_magic != [0x7F, 0x45, 0x4c, 0x46] {
+            return Err(DeserializeError {
+                offset: start,
+                kind: DeserializeErrorKind::Invalid("not an ELF file".to_owned())
+            });
+        }
+
+        let e_ident_class = reader.read("ELF header")?;
+        if e_ident_class != 2 {
+            return Err(reader.error(format!("only 64-bit ELF files are supported, found class {}", e_ident_class)));
+        }
+
+        let e_ident_data = reader.read("ELF header")?;
+        if e_ident_data != if reader.big_endian() { 2 } else { 1 } {
+            return Err(reader.error(format!("unknown byte order {}", e_ident_data)));
+        }
+
+        Ok(ElfHeader {
+            e_ident_magic,
+            e_ident_class,
+            e_ident_data,
+            e_ident_version: reader.read("ELF header")?,
+            e_ident_abi: reader.read("ELF header")?,
+            e_ident_abi_version: reader.read("ELF header")?,
+            e_ident_pad: reader.read_bytes(7, "ELF header")?.try_into().unwrap(),
+            e_type: reader.read("ELF header")?,
+            e_machine: reader.read("ELF header")?,
+            e_version: reader.read("ELF header")?,
+            e_entry: reader.read("ELF header")?,
+            e_phoff: reader.read("ELF header")?,
+            e_shoff: reader.read("ELF header")?,
+            e_flags: reader.read("ELF header")?,
+            e_ehsize: reader.read("ELF header")?,
+            e_phentsize: reader.read("ELF header")?,
+            e_phnum: reader.read("ELF header")?,
+            e_shentsize: reader.read("ELF header")?,
+            e_shnum: reader.read("ELF header")?,
+            e_shstrndx: reader.read("ELF header")?
+        })
+    }
+}
+
+impl Deserializable for ElfProgramHeader {
+    fn deserialize(reader: &mut Reader) -> Result<ElfProgramHeader, DeserializeError> {
+        Ok(ElfProgramHeader {
+            p_type: reader.read("program header")?,
+            p_flags: reader.read("program header")?,
+            p_offset: reader.read("program header")?,
+            p_vaddr: reader.read("program header")?,
+            p_paddr: reader.read("program header")?,
+            p_filesz: reader.read("program header")?,
+            p_memsz: reader.read("program header")?,
+            p_align: reader.read("program header")?
+        })
+    }
+}
+
+impl Deserializable for ElfSectionHeader {
+    fn deserialize(reader: &mut Reader) -> Result<ElfSectionHeader, DeserializeError> {
+        Ok(ElfSectionHeader {
+            sh_name: reader.read("section header")?,
+            sh_type: reader.read("section header")?,
+            sh_flags: reader.read("section header")?,
+            sh_addr: reader.read("section header")?,
+            sh_offset: reader.read("section header")?,
+            sh_size: reader.read("section header")?,
+            sh_link: reader.read("section header")?,
+            sh_info: reader.read("section header")?,
+            sh_addralign: reader.read("section header")?,
+            sh_entsize: reader.read("section header")?
+        })
+    }
+}
+
+impl Deserializable for ElfSymbol {
+    fn deserialize(reader: &mut Reader) -> Result<ElfSymbol, DeserializeError> {
+        Ok(ElfSymbol {
+            st_name: reader.read("symbol")?,
+            st_info: reader.read("symbol")?,
+            st_other: reader.read("symbol")?,
+            st_shndx: reader.read("symbol")?,
+            st_value: reader.read("symbol")?,
+            st_size: reader.read("symbol")?
+        })
+    }
+}
+
+impl Deserializable for ElfRelocation {
+    fn deserialize(reader: &mut Reader) -> Result<ElfRelocation, DeserializeError> {
+        Ok(ElfRelocation {
+            r_offset: reader.read("relocation")?,
+            r_info: reader.read("relocation")?,
+            r_addend: reader.read("relocation")?
+        })
+    }
+}
+
+impl Serializable for ElfHeader {
+    fn serialize(&self, be: bool) -> Vec<u8> {
+        let mut vec = Vec::new();
+
+        vec.extend(&self.e_ident_magic);
+        add_bytes(&mut vec, self.e_ident_class, be);
+        add_bytes(&mut vec, self.e_ident_data, be);
+        add_bytes(&mut vec, self.e_ident_version, be);
+        add_bytes(&mut vec, self.e_ident_abi, be);
+        add_bytes(&mut vec, self.e_ident_abi_version, be);
+        vec.extend(&self.e_ident_pad);
+        add_bytes(&mut vec, self.e_type, be);
+        add_bytes(&mut vec, self.e_machine, be);
+        add_bytes(&mut vec, self.e_version, be);
+        add_bytes(&mut vec, self.e_entry, be);
+        add_bytes(&mut vec, self.e_phoff, be);
+        add_bytes(&mut vec, self.e_shoff, be);
+        add_bytes(&mut vec, self.e_flags, be);
+        add_bytes(&mut vec, self.e_ehsize, be);
+        add_bytes(&mut vec, self.e_phentsize, be);
+        add_bytes(&mut vec, self.e_phnum, be);
+        add_bytes(&mut vec, self.e_shentsize, be);
+        add_bytes(&mut vec, self.e_shnum, be);
+        add_bytes(&mut vec, self.e_shstrndx, be);
+        vec
+    }
+
+    fn serialized_length(&self) -> usize {
+        0x40
+    }
+}
+
+impl Serializable for ElfProgramHeader {
+    fn serialize(&self, be: bool) -> Vec<u8> {
+        let mut vec = Vec::new();
+
+        add_bytes(&mut vec, self.p_type, be);
+        add_bytes(&mut vec, self.p_flags, be);
+        add_bytes(&mut vec, self.p_offset, be);
+        add_bytes(&mut vec, self.p_vaddr, be);
+        add_bytes(&mut vec, self.p_paddr, be);
+        add_bytes(&mut vec, self.p_filesz, be);
+        add_bytes(&mut vec, self.p_memsz, be);
+        add_bytes(&mut vec, self.p_align, be);
+
+        vec
+    }
+
+    fn serialized_length(&self) -> usize {
+        0x38
+    }
+}
+
+impl Serializable for ElfSymbol {
+    fn serialize(&self, be: bool) -> Vec<u8> {
+        let mut vec = Vec::new();
+
+        add_bytes(&mut vec, self.st_name, be);
+        add_bytes(&mut vec, self.st_info, be);
+        add_bytes(&mut vec, self.st_other, be);
+        add_bytes(&mut vec, self.st_shndx, be);
+        add_bytes(&mut vec, self.st_value, be);
+        add_bytes(&mut vec, self.st_size, be);
+
+        vec
+    }
+
+    fn serialized_length(&self) -> usize {
+        0x18
+    }
+}
+
+impl Serializable for ElfSectionHeader {
+    fn serialize(&self, be: bool) -> Vec<u8> {
+        let mut vec = Vec::new();
+
+        add_bytes(&mut vec, self.sh_name, be);
+        add_bytes(&mut vec, self.sh_type, be);
+        add_bytes(&mut vec, self.sh_flags, be);
+        add_bytes(&mut vec, self.sh_addr, be);
+        add_bytes(&mut vec, self.sh_offset, be);
+        add_bytes(&mut vec, self.sh_size, be);
+        add_bytes(&mut vec, self.sh_link, be);
+        add_bytes(&mut vec, self.sh_info, be);
+        add_bytes(&mut vec, self.sh_addralign, be);
+        add_bytes(&mut vec, self.sh_entsize, be);
+
+        vec
+    }
+
+    fn serialized_length(&self) -> usize {
+        0x40
+    }
+}
+
+impl Serializable for ElfRelocation {
+    fn serialize(&self, be: bool) -> Vec<u8> {
+        let mut vec = vec![];
+
+        add_bytes(&mut vec, self.r_offset, be);
+        add_bytes(&mut vec, self.r_info, be);
+        add_bytes(&mut vec, self.r_addend, be);
+
+        vec
+    }
+}
+
+impl Serializable for ElfFile {
+    fn serialize(&self, be: bool) -> Vec<u8> {
+        let mut vec = Vec::new();
+
+        vec.extend(self.elf_header.serialize(be));
+        vec.extend(self.elf_program_headers.serialize(be));
+        vec.extend(self.elf_section_headers.serialize(be));
+        vec.extend(&self.data);
+
+        vec
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ElfSectionId(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ElfSymbolId(usize);
+
+struct BuilderSection {
+    name: String,
+    sh_type: u32,
+    flags: u64,
+    align: u64,
+    contents: Vec<u8>,
+    // the size of SHT_NOBITS sections, which have no contents
+    size: u64
+}
+
+struct BuilderSymbol {
+    name: Option<String>,
+    // None for undefined and absolute symbols
+    section: Option<ElfSectionId>,
+    // whether `value` is an address rather than an offset into a section
+    absolute: bool,
+    value: u64,
+    size: u64,
+    binding: u8,
+    sym_type: u8
+}
+
+struct BuilderRelocation {
+    section: ElfSectionId,
+    offset: u64,
+    symbol: ElfSymbolId,
+    kind: u32,
+    addend: i64
+}
+
+// builds a relocatable object from named sections, symbols and relocations, working out
+// the string tables, symbol and section indices and file layout
+pub struct ElfObjectBuilder {
+    machine: u16,
+    sections: Vec<BuilderSection>,
+    symbols: Vec<BuilderSymbol>,
+    relocations: Vec<BuilderRelocation>
+}
+
+impl ElfObjectBuilder {
+    pub fn new(machine: u16) -> ElfObjectBuilder {
+        ElfObjectBuilder {
+            machine,
+            sections: vec![],
+            symbols: vec![],
+            relocations: vec![]
+        }
+    }
+
+    pub fn add_section(&mut self, name: &str, sh_type: u32, flags: u64, align: u64, contents: Vec<u8>) -> ElfSectionId {
+        self.sections.push(BuilderSection {
+            name: name.to_owned(),
+            sh_type,
+            flags,
+            align,
+            size: contents.len() as u64,
+            contents
+        });
+        ElfSectionId(self.sections.len() - 1)
+    }
+
+    // a section that takes `size` bytes in memory but none in the file, like .bss
+    pub fn add_nobits_section(&mut self, name: &str, flags: u64, align: u64, size: u64) -> ElfSectionId {
+        self.sections.push(BuilderSection {
+            name: name.to_owned(),
+            sh_type: SHT_NOBITS,
+            flags,
+            align,
+            contents: vec![],
+            size
+        });
+        ElfSectionId(self.sections.len() - 1)
+    }
+
+    // `section` of None makes an undefined symbol, for the linker to find elsewhere
+    pub fn add_symbol(&mut self, name: Option<&str>, section: Option<ElfSectionId>, value: u64, size: u64, binding: u8, sym_type: u8) -> ElfSymbolId {
+        self.symbols.push(BuilderSymbol {
+            name: name.map(str::to_owned),
+            section,
+            absolute: false,
+            value,
+            size,
+            binding,
+            sym_type
+        });
+        ElfSymbolId(self.symbols.len() - 1)
+    }
+
+    // a symbol whose value is an address, not relative to any section
+    pub fn add_absolute_symbol(&mut self, name: Option<&str>, value: u64, size: u64, binding: u8, sym_type: u8) -> ElfSymbolId {
+        self.symbols.push(BuilderSymbol {
+            name: name.map(str::to_owned),
+            section: None,
+            absolute: true,
+            value,
+            size,
+            binding,
+            sym_type
+        });
+        ElfSymbolId(self.symbols.len() - 1)
+    }
+
+    pub fn set_symbol_value(&mut self, symbol: ElfSymbolId, value: u64, size: u64) {
+        self.symbols[symbol.0].value = value;
+        self.symbols[symbol.0].size = size;
+    }
+
+    pub fn add_relocation(&mut self, section: ElfSectionId, offset: u64, symbol: ElfSymbolId, kind: u32, addend: i64) {
+        self.relocations.push(BuilderRelocation {
+            section,
+            offset,
+            symbol,
+            kind,
+            addend
+        });
+    }
+
+    // the symbol table and its string table, with each symbol's section index and value given by the caller.
+    // Also returns each symbol's index in the table and how many locals come first.
+    fn symbol_tables(&self, shndx: impl Fn(&BuilderSymbol) -> u16, value: impl Fn(&BuilderSymbol) -> u64) -> (Vec<u8>, Vec<u8>, Vec<u32>, u32) {
+        // ELF wants every local symbol before the first global one
+        let mut symbol_order: Vec<usize> = (0..self.symbols.len()).filter(|i| self.symbols[*i].binding == STB_LOCAL).collect();
+        let local_count = symbol_order.len();
+        symbol_order.extend((0..self.symbols.len()).filter(|i| self.symbols[*i].binding != STB_LOCAL));
+
+        let mut symbol_indices = vec![0; self.symbols.len()];
+        for (symtab_index, symbol) in symbol_order.iter().enumerate() {
+            symbol_indices[*symbol] = symtab_index as u32 + 1;
+        }
+
+        let mut strtab = vec![0];
+        let mut symtab = ElfSymbol::default().serialize(false);
+
+        for symbol in symbol_order.iter().map(|i| &self.symbols[*i]) {
+            let st_name = match &symbol.name {
+                Some(name) => {
+                    let offset = strtab.len();
+                    strtab.extend(name.clone().serialize(false));
+                    offset as u32
+                },
+                None => 0
+            };
+
+            symtab.extend(ElfSymbol {
+                st_name,
+                st_info: (symbol.binding << 4) | (symbol.sym_type & 0xF),
+                st_other: 0,
+                st_shndx: shndx(symbol),
+                st_value: value(symbol),
+                st_size: symbol.size
+            }.serialize(false));
+        }
+
+        (symtab, strtab, symbol_indices, local_count as u32)
+    }
+
+    pub fn build(self) -> ElfFile {
+        // section 0 is the null section, then the added ones, one .rela section for each that has relocations,
+        // and the symbol and string tables
+        let relocated: Vec<usize> = (0..self.sections.len())
+            .filter(|i| self.relocations.iter().any(|reloc| reloc.section.0 == *i))
+            .collect();
+
+        let symtab_index = 1 + self.sections.len() + relocated.len();
+        let section_count = symtab_index + 3;
+
+        let (symtab, strtab, symbol_indices, local_count) = self.symbol_tables(
+            |symbol| match symbol.section {
+                Some(section) => section.0 as u16 + 1,
+                None if symbol.absolute => SHN_ABS,
+                None => SHN_UNDEF
+            },
+            |symbol| symbol.value
+        );
+
+        let mut shstrtab = SectionNames::new();
+        let mut sections: Vec<(ElfSectionHeader, Vec<u8>)> = vec![];
+
+        for section in &self.sections {
+            sections.push((ElfSectionHeader {
+                sh_name: shstrtab.add(&section.name),
+                sh_type: section.sh_type,
+                sh_flags: section.flags,
+                sh_size: section.size,
+                sh_addralign: section.align,
+                ..ElfSectionHeader::default()
+            }, section.contents.clone()));
+        }
+
+        for &target in &relocated {
+            let contents: Vec<u8> = self.relocations.iter()
+                .filter(|reloc| reloc.section.0 == target)
+                .flat_map(|reloc| ElfRelocation::new(reloc.offset, symbol_indices[reloc.symbol.0], reloc.kind, reloc.addend).serialize(false))
+                .collect();
+
+            sections.push((ElfSectionHeader {
+                sh_name: shstrtab.add(&format!(".rela{}", self.sections[target].name)),
+                sh_type: SHT_RELA,
+                sh_flags: SHF_INFO_LINK,
+                sh_size: contents.len() as u64,
+                sh_link: symtab_index as u32,
+                sh_info: target as u32 + 1,
+                sh_addralign: 8,
+                sh_entsize: RELA_SIZE,
+                ..ElfSectionHeader::default()
+            }, contents));
+        }
+
+        sections.extend(symbol_sections(&mut shstrtab, symtab, strtab, symtab_index as u32, local_count));
+
+        // the section header table comes right after the ELF header, then every section's contents
+        let mut layout = FileLayout::new(ELF_HEADER_SIZE + section_count as u64 * SECTION_HEADER_SIZE);
+        let mut section_headers = vec![ElfSectionHeader::default()];
+
+        for (mut header, contents) in sections {
+            header.sh_offset = layout.place(header.sh_addralign, contents);
+            section_headers.push(header);
+        }
+
+        ElfFile {
+            elf_header: elf_header(ET_REL, self.machine, 0, 0, section_count as u16),
+            elf_program_headers: vec![],
+            elf_section_headers: section_headers,
+            data: layout.data
+        }
+    }
+
+    // links the sections into a static executable loaded at EXECUTABLE_BASE, starting at the symbol `entry`.
+    // Every relocation is resolved here, so all referenced symbols have to be defined.
+    pub fn build_executable(mut self, entry: &str) -> Result<ElfFile, ChairError> {
+        let got = self.add_got();
+
+        // the loaded sections, grouped into read+execute, read-only and read+write segments
+        let segment_flags = |section: &BuilderSection| {
+            if section.flags & SHF_EXECINSTR != 0 {
+                PF_R | PF_X
+            } else if section.flags & SHF_WRITE != 0 {
+                PF_R | PF_W
+            } else {
+                PF_R
+            }
+        };
+
+        let mut segments: Vec<(u32, Vec<usize>)> = vec![];
+        for flags in [PF_R | PF_X, PF_R, PF_R | PF_W] {
+            let mut members: Vec<usize> = (0..self.sections.len())
+                .filter(|i| self.sections[*i].flags & SHF_ALLOC != 0 && segment_flags(&self.sections[*i]) == flags)
+                .collect();
+
+            // sections without file contents go last, so they only take up memory past the end of the file part
+            members.sort_by_key(|i| self.sections[*i].sh_type == SHT_NOBITS);
+
+            if !members.is_empty() {
+                segments.push((flags, members));
+            }
+        }
+
+        let unloaded: Vec<usize> = (0..self.sections.len()).filter(|i| self.sections[*i].flags & SHF_ALLOC == 0).collect();
+        let order: Vec<usize> = segments.iter().flat_map(|(_, members)| members.clone()).chain(unloaded.iter().copied()).collect();
+
+        // sections keep their place in `order`, after the null section
+        let mut section_indices = vec![0u16; self.sections.len()];
+        for (index, section) in order.iter().enumerate() {
+            section_indices[*section] = index as u16 + 1;
+        }
+
+        // segments with nothing in them still place their sections, but don't get a program header
+        let is_empty = |members: &[usize]| members.iter().all(|i| self.sections[*i].size == 0);
+        let program_header_count = segments.iter().filter(|(_, members)| !is_empty(members)).count() as u64;
+
+        let symtab_index = order.len() + 1;
+        let section_count = symtab_index + 3;
+        let headers_size = ELF_HEADER_SIZE + program_header_count * PROGRAM_HEADER_SIZE + section_count as u64 * SECTION_HEADER_SIZE;
+
+        // a section's address is always EXECUTABLE_BASE plus its file offset, which keeps each segment's
+        // offset and address congruent modulo the page size
+        let mut offsets = vec![0u64; self.sections.len()];
+        let mut program_headers = vec![];
+        let mut file_end = headers_size;
+
+        for (flags, members) in &segments {
+            let start = file_end.next_multiple_of(PAGE_SIZE);
+            let mut file_size = 0;
+            let mut memory_end = start;
+
+            for &i in members {
+                let section = &self.sections[i];
+                let offset = memory_end.next_multiple_of(section.align.max(1));
+                offsets[i] = offset;
+                memory_end = offset + section.size;
+
+                if section.sh_type != SHT_NOBITS {
+                    file_size = memory_end - start;
+                }
+            }
+
+            if is_empty(members) {
+                continue;
+            }
+
+            program_headers.push(ElfProgramHeader {
+                p_type: PT_LOAD,
+                p_flags: *flags,
+                p_offset: start,
+                p_vaddr: EXECUTABLE_BASE + start,
+                p_paddr: EXECUTABLE_BASE + start,
+                p_filesz: file_size,
+                p_memsz: memory_end - start,
+                p_align: PAGE_SIZE
+            });
+
+            // the next segment starts past this one's memory, not just its file contents
+            file_end = memory_end;
+        }
+
+        // sections that aren't loaded keep an address of 0
+        let addresses: Vec<u64> = self.sections.iter().zip(&offsets)
+            .map(|(section, offset)| if section.flags & SHF_ALLOC != 0 { EXECUTABLE_BASE + offset } else { 0 })
+            .collect();
+        let address = |section: usize| addresses[section];
+        let symbol_address = |symbol: &BuilderSymbol| match symbol.section {
+            Some(section) => Some(address(section.0) + symbol.value),
+            None if symbol.absolute => Some(symbol.value),
+            None => None
+        };
+
+        if let Some((got, entries)) = &got {
+            for (i, symbol) in entries.iter().enumerate() {
+                let value = self.resolve(*symbol, &symbol_address)?;
+                self.sections[got.0].contents[i * 8..i * 8 + 8].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        for reloc in std::mem::take(&mut self.relocations) {
+            let target = self.resolve(reloc.symbol, &symbol_address)?;
+            let place = address(reloc.section.0) + reloc.offset;
+            let got_entry = got.as_ref()
+                .and_then(|(got, entries)| entries.iter().position(|symbol| *symbol == reloc.symbol).map(|i| address(got.0) + i as u64 * 8));
+
+            let name = || self.symbols[reloc.symbol.0].name.clone().unwrap_or_else(|| "a local symbol".to_owned());
+            let overflow = |value: i64, bits: u32| ChairError::RelocationOverflow {
+                context: ErrorContext::default(),
+                target: name(),
+                value,
+                bits
+            };
+
+            let sum = target.wrapping_add(reloc.addend as u64) as i64;
+            let relative = sum.wrapping_sub(place as i64);
+
+            let bytes = match reloc.kind {
+                R_X86_64_64 => sum.to_le_bytes().to_vec(),
+                R_X86_64_PC64 => relative.to_le_bytes().to_vec(),
+                R_X86_64_PC32 | R_X86_64_PLT32 => i32::try_from(relative).map_err(|_| overflow(relative, 32))?.to_le_bytes().to_vec(),
+                R_X86_64_32 => u32::try_from(sum).map_err(|_| overflow(sum, 32))?.to_le_bytes().to_vec(),
+                R_X86_64_32S => i32::try_from(sum).map_err(|_| overflow(sum, 32))?.to_le_bytes().to_vec(),
+                R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX => {
+                    let relative = (got_entry.unwrap() as i64).wrapping_add(reloc.addend).wrapping_sub(place as i64);
+                    i32::try_from(relative).map_err(|_| overflow(relative, 32))?.to_le_bytes().to_vec()
+                },
+                kind => return Err(ChairError::Unsupported {
+                    target: "x86_64-elf",
+                    context: ErrorContext::default(),
+                    feature: format!("relocation type {} against {}", kind, name())
+                })
+            };
+
+            let contents = &mut self.sections[reloc.section.0].contents;
+            let offset = reloc.offset as usize;
+            if offset + bytes.len() > contents.len() {
+                return Err(ChairError::InvalidIr {
+                    context: ErrorContext::default(),
+                    message: format!("relocation at 0x{:x} is outside {}", reloc.offset, self.sections[reloc.section.0].name)
+                });
+            }
+            contents[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        let entry_symbol = self.symbols.iter()
+            .filter(|symbol| symbol.name.as_deref() == Some(entry) && symbol_address(symbol).is_some())
+            .min_by_key(|symbol| symbol.binding == STB_LOCAL)
+            .ok_or_else(|| ChairError::UndefinedSymbol { symbol: entry.to_owned(), objects: vec![] })?;
+        let entry_address = symbol_address(entry_symbol).unwrap();
+
+        let (symtab, strtab, _, local_count) = self.symbol_tables(
+            |symbol| match symbol.section {
+                Some(section) => section_indices[section.0],
+                None if symbol.absolute => SHN_ABS,
+                None => SHN_UNDEF
+            },
+            |symbol| symbol_address(symbol).unwrap_or(0)
+        );
+
+        let mut shstrtab = SectionNames::new();
+        let mut layout = FileLayout::new(headers_size);
+        let mut section_headers = vec![ElfSectionHeader::default()];
+
+        for &i in &order {
+            let section = &self.sections[i];
+            let loaded = section.flags & SHF_ALLOC != 0;
+
+            let offset = if loaded {
+                // padding up to the offset worked out above, which may skip to a new page
+                layout.data.resize((offsets[i] - headers_size) as usize, 0);
+                layout.place(1, section.contents.clone())
+            } else {
+                layout.place(section.align, section.contents.clone())
+            };
+
+            section_headers.push(ElfSectionHeader {
+                sh_name: shstrtab.add(&section.name),
+                sh_type: section.sh_type,
+                sh_flags: section.flags,
+                sh_addr: address(i),
+                sh_offset: offset,
+                sh_size: section.size,
+                sh_addralign: section.align,
+                ..ElfSectionHeader::default()
+            });
+        }
+
+        for (mut header, contents) in symbol_sections(&mut shstrtab, symtab, strtab, symtab_index as u32, local_count) {
+            header.sh_offset = layout.place(header.sh_addralign, contents);
+            section_headers.push(header);
+        }
+
+        let mut elf_header = elf_header(ET_EXEC, self.machine, entry_address, program_headers.len() as u16, section_count as u16);
+        elf_header.e_phoff = ELF_HEADER_SIZE;
+        elf_header.e_shoff = ELF_HEADER_SIZE + program_headers.len() as u64 * PROGRAM_HEADER_SIZE;
+
+        Ok(ElfFile {
+            elf_header,
+            elf_program_headers: program_headers,
+            elf_section_headers: section_headers,
+            data: layout.data
+        })
+    }
+
+    // a .got section with an entry for every symbol a GOT-relative relocation refers to, if there are any.
+    // An undefined _GLOBAL_OFFSET_TABLE_ is defined as its start, as PIC compilers refer to it.
+    fn add_got(&mut self) -> Option<(ElfSectionId, Vec<ElfSymbolId>)> {
+        let mut entries = vec![];
+        for reloc in &self.relocations {
+            if matches!(reloc.kind, R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX) && !entries.contains(&reloc.symbol) {
+                entries.push(reloc.symbol);
+            }
+        }
+
+        let is_got_symbol = |symbol: &BuilderSymbol| {
+            symbol.name.as_deref() == Some(GLOBAL_OFFSET_TABLE) && symbol.section.is_none() && !symbol.absolute
+        };
+
+        if entries.is_empty() && !self.symbols.iter().any(is_got_symbol) {
+            return None;
+        }
+
+        let got = self.add_section(".got", SHT_PROGBITS, SHF_WRITE | SHF_ALLOC, 8, vec![0; entries.len() * 8]);
+        for symbol in self.symbols.iter_mut().filter(|symbol| is_got_symbol(symbol)) {
+            symbol.section = Some(got);
+            symbol.value = 0;
+        }
+
+        Some((got, entries))
+    }
+
+    // the address of a symbol, where undefined weak symbols are 0
+    fn resolve(&self, symbol: ElfSymbolId, address: &impl Fn(&BuilderSymbol) -> Option<u64>) -> Result<u64, ChairError> {
+        let builder_symbol = &self.symbols[symbol.0];
+
+        match address(builder_symbol) {
+            Some(address) => Ok(address),
+            None if builder_symbol.binding == STB_WEAK => Ok(0),
+            None => Err(ChairError::UndefinedSymbol {
+                symbol: builder_symbol.name.clone().unwrap_or_default(),
+                objects: vec![]
+            })
+        }
+    }
+}
+
+// where executables are loaded, the usual address for non-PIE x86-64 programs
+pub const EXECUTABLE_BASE: u64 = 0x400000;
+pub const GLOBAL_OFFSET_TABLE: &str = "_GLOBAL_OFFSET_TABLE_";
+const PAGE_SIZE: u64 = 0x1000;
+// the System V ABI has no versions
+const ABI_VERSION: u8 = 0;
+
+fn elf_header(e_type: u16, machine: u16, entry: u64, program_header_count: u16, section_count: u16) -> ElfHeader {
+    ElfHeader {
+        e_ident_magic: [0x7F, 0x45, 0x4c, 0x46],
+        e_ident_class: 2,
+        e_ident_data: 1,
+        e_ident_version: 1,
+        e_ident_abi: ELFOSABI_NONE,
+        e_ident_abi_version: ABI_VERSION,
+        e_ident_pad: [0, 0, 0, 0, 0, 0, 0],
+        e_type,
+        e_machine: machine,
+        e_version: 1,
+        e_entry: entry,
+        e_phoff: 0,
+        e_shoff: ELF_HEADER_SIZE,
+        e_flags: 0,
+        e_ehsize: ELF_HEADER_SIZE as u16,
+        e_phentsize: PROGRAM_HEADER_SIZE as u16,
+        e_phnum: program_header_count,
+        e_shentsize: SECTION_HEADER_SIZE as u16,
+        e_shnum: section_count,
+        e_shstrndx: section_count - 1
+    }
+}
+
+// .shstrtab as it's being built
+struct SectionNames {
+    contents: Vec<u8>
+}
+
+impl SectionNames {
+    fn new() -> SectionNames {
+        SectionNames {
+            contents: vec![0]
+        }
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.contents.len() as u32;
+        self.contents.extend(name.to_owned().serialize(false));
+        offset
+    }
+}
+
+// .symtab, .strtab and .shstrtab, which always come last and in that order
+fn symbol_sections(shstrtab: &mut SectionNames, symtab: Vec<u8>, strtab: Vec<u8>, symtab_index: u32, local_count: u32) -> Vec<(ElfSectionHeader, Vec<u8>)> {
+    let symtab_header = ElfSectionHeader {
+        sh_name: shstrtab.add(".symtab"),
+        sh_type: SHT_SYMTAB,
+        sh_size: symtab.len() as u64,
+        sh_link: symtab_index + 1,
+        sh_info: local_count + 1,
+        sh_addralign: 8,
+        sh_entsize: SYMBOL_SIZE,
+        ..ElfSectionHeader::default()
+    };
+
+    let strtab_header = ElfSectionHeader {
+        sh_name: shstrtab.add(".strtab"),
+        sh_type: SHT_STRTAB,
+        sh_size: strtab.len() as u64,
+        sh_addralign: 1,
+        ..ElfSectionHeader::default()
+    };
+
+    let shstrtab_header = ElfSectionHeader {
+        sh_name: shstrtab.add(".shstrtab"),
+        sh_type: SHT_STRTAB,
+        sh_size: shstrtab.contents.len() as u64,
+        sh_addralign: 1,
+        ..ElfSectionHeader::default()
+    };
+
+    vec![(symtab_header, symtab), (strtab_header, strtab), (shstrtab_header, std::mem::take(&mut shstrtab.contents))]
+}
+
+// the file contents after the headers, padded so each section starts at its alignment
+struct FileLayout {
+    start: u64,
+    data: Vec<u8>
+}
+
+impl FileLayout {
+    fn new(start: u64) -> FileLayout {
+        FileLayout {
+            start,
+            data: vec![]
+        }
+    }
+
+    // appends `contents` and returns its file offset
+    fn place(&mut self, align: u64, contents: Vec<u8>) -> u64 {
+        let end = self.start + self.data.len() as u64;
+        self.data.resize((end.next_multiple_of(align.max(1)) - self.start) as usize, 0);
+
+        let offset = self.start + self.data.len() as u64;
+        self.data.extend(contents);
+        offset
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt::{self, Write};
+
+use crate::outputs::elf::*;
+
+fn file_type_name(e_type: u16) -> &'static str {
+    match e_type {
+        ET_REL => "REL (relocatable file)",
+        ET_EXEC => "EXEC (executable file)",
+        ET_DYN => "DYN (shared object file)",
+        _ => "unknown"
+    }
+}
+
+fn machine_name(machine: u16) -> &'static str {
+    match machine {
+        EM_X86_64 => "x86-64",
+        0x03 => "x86",
+        0xB7 => "AArch64",
+        0xF3 => "RISC-V",
+        _ => "unknown"
+    }
+}
+
+fn segment_type_name(p_type: u32) -> String {
+    match p_type {
+        0 => "NULL".to_owned(),
+        PT_LOAD => "LOAD".to_owned(),
+        2 => "DYNAMIC".to_owned(),
+        3 => "INTERP".to_owned(),
+        4 => "NOTE".to_owned(),
+        6 => "PHDR".to_owned(),
+        7 => "TLS".to_owned(),
+        0x6474E550 => "GNU_EH_FRAME".to_owned(),
+        0x6474E551 => "GNU_STACK".to_owned(),
+        0x6474E552 => "GNU_RELRO".to_owned(),
+        0x6474E553 => "GNU_PROPERTY".to_owned(),
+        _ => format!("0x{:x}", p_type)
+    }
+}
+
+fn section_type_name(sh_type: u32) -> String {
+    match sh_type {
+        0 => "NULL".to_owned(),
+        SHT_PROGBITS => "PROGBITS".to_owned(),
+        SHT_SYMTAB => "SYMTAB".to_owned(),
+        SHT_STRTAB => "STRTAB".to_owned(),
+        SHT_RELA => "RELA".to_owned(),
+        5 => "HASH".to_owned(),
+        6 => "DYNAMIC".to_owned(),
+        7 => "NOTE".to_owned(),
+        SHT_NOBITS => "NOBITS".to_owned(),
+        SHT_REL => "REL".to_owned(),
+        SHT_DYNSYM => "DYNSYM".to_owned(),
+        14 => "INIT_ARRAY".to_owned(),
+        15 => "FINI_ARRAY".to_owned(),
+        0x6FFFFFF6 => "GNU_HASH".to_owned(),
+        0x6FFFFFFE => "VERNEED".to_owned(),
+        0x6FFFFFFF => "VERSYM".to_owned(),
+        0x70000001 => "X86_64_UNWIND".to_owned(),
+        _ => format!("0x{:x}", sh_type)
+    }
+}
+
+// the same letters readelf uses
+fn section_flags(flags: u64) -> String {
+    let letters = [(SHF_WRITE, 'W'), (SHF_ALLOC, 'A'), (SHF_EXECINSTR, 'X'), (0x10, 'M'), (0x20, 'S'), (SHF_INFO_LINK, 'I'), (0x80, 'L'), (0x200, 'G'), (SHF_TLS, 'T')];
+
+    letters.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, letter)| *letter).collect()
+}
+
+fn segment_flags(flags: u32) -> String {
+    [(PF_R, 'R'), (PF_W, 'W'), (PF_X, 'E')].iter()
+        .map(|(flag, letter)| if flags & flag != 0 { *letter } else { ' ' })
+        .collect()
+}
+
+fn binding_name(binding: u8) -> String {
+    match binding {
+        STB_LOCAL => "LOCAL".to_owned(),
+        STB_GLOBAL => "GLOBAL".to_owned(),
+        STB_WEAK => "WEAK".to_owned(),
+        _ => binding.to_string()
+    }
+}
+
+fn symbol_type_name(sym_type: u8) -> String {
+    match sym_type {
+        STT_NOTYPE => "NOTYPE".to_owned(),
+        STT_OBJECT => "OBJECT".to_owned(),
+        STT_FUNC => "FUNC".to_owned(),
+        STT_SECTION => "SECTION".to_owned(),
+        STT_FILE => "FILE".to_owned(),
+        5 => "COMMON".to_owned(),
+        6 => "TLS".to_owned(),
+        _ => sym_type.to_string()
+    }
+}
+
+fn visibility_name(st_other: u8) -> &'static str {
+    match st_other & 3 {
+        0 => "DEFAULT",
+        1 => "INTERNAL",
+        2 => "HIDDEN",
+        _ => "PROTECTED"
+    }
+}
+
+fn section_index_name(shndx: u16) -> String {
+    match shndx {
+        SHN_UNDEF => "UND".to_owned(),
+        SHN_ABS => "ABS".to_owned(),
+        SHN_COMMON => "COM".to_owned(),
+        _ => shndx.to_string()
+    }
+}
+
+pub fn relocation_name(machine: u16, kind: u32) -> String {
+    let name = match (machine, kind) {
+        (EM_X86_64, 0) => "R_X86_64_NONE",
+        (EM_X86_64, R_X86_64_64) => "R_X86_64_64",
+        (EM_X86_64, R_X86_64_PC32) => "R_X86_64_PC32",
+        (EM_X86_64, 3) => "R_X86_64_GOT32",
+        (EM_X86_64, R_X86_64_PLT32) => "R_X86_64_PLT32",
+        (EM_X86_64, 5) => "R_X86_64_COPY",
+        (EM_X86_64, 6) => "R_X86_64_GLOB_DAT",
+        (EM_X86_64, 7) => "R_X86_64_JUMP_SLOT",
+        (EM_X86_64, 8) => "R_X86_64_RELATIVE",
+        (EM_X86_64, R_X86_64_GOTPCREL) => "R_X86_64_GOTPCREL",
+        (EM_X86_64, 10) => "R_X86_64_32",
+        (EM_X86_64, R_X86_64_32S) => "R_X86_64_32S",
+        (EM_X86_64, 24) => "R_X86_64_PC64",
+        (EM_X86_64, 41) => "R_X86_64_GOTPCRELX",
+        (EM_X86_64, 42) => "R_X86_64_REX_GOTPCRELX",
+        _ => return format!("type {}", kind)
+    };
+
+    name.to_owned()
+}
+
+// what a relocation refers to: the symbol's name, or where it is for section symbols and unnamed ones
+fn symbol_label(elf: &ParsedElf, index: usize) -> String {
+    let Some(symbol) = elf.symbols.get(index) else {
+        return format!("#{}", index);
+    };
+
+    let sym = &symbol.symbol;
+    match elf.sections.get(sym.st_shndx as usize) {
+        Some(section) if sym.sym_type() == STT_SECTION => section.name.clone(),
+        Some(section) if symbol.name.is_empty() && sym.st_shndx != SHN_UNDEF => format!("{}+0x{:x}", section.name, sym.st_value),
+        _ => symbol.name.clone()
+    }
+}
+
+fn write_header(f: &mut impl Write, elf: &ParsedElf) -> fmt::Result {
+    let header = &elf.header;
+
+    writeln!(f, "ELF header:")?;
+    writeln!(f, "  class:               ELF64")?;
+    writeln!(f, "  data:                {}", if header.e_ident_data == 2 { "big endian" } else { "little endian" })?;
+    writeln!(f, "  version:             {}", header.e_ident_version)?;
+    writeln!(f, "  OS/ABI:              {} (ABI version {})", header.e_ident_abi, header.e_ident_abi_version)?;
+    writeln!(f, "  type:                {}", file_type_name(header.e_type))?;
+    writeln!(f, "  machine:             {}", machine_name(header.e_machine))?;
+    writeln!(f, "  entry point:         0x{:x}", header.e_entry)?;
+    writeln!(f, "  program headers:     {} at 0x{:x}", header.e_phnum, header.e_phoff)?;
+    writeln!(f, "  section headers:     {} at 0x{:x}", header.e_shnum, header.e_shoff)?;
+    writeln!(f, "  section name table:  {}", header.e_shstrndx)?;
+    writeln!(f, "  flags:               0x{:x}", header.e_flags)
+}
+
+fn write_program_headers(f: &mut impl Write, elf: &ParsedElf) -> fmt::Result {
+    if elf.program_headers.is_empty() {
+        return writeln!(f, "\nNo program headers.");
+    }
+
+    writeln!(f, "\nProgram headers:")?;
+    writeln!(f, "  {:<14} {:<10} {:<18} {:<10} {:<10} {:<3} Align", "Type", "Offset", "VirtAddr", "FileSiz", "MemSiz", "Flg")?;
+
+    for segment in &elf.program_headers {
+        writeln!(f, "  {:<14} 0x{:08x} 0x{:016x} 0x{:08x} 0x{:08x} {} 0x{:x}",
+            segment_type_name(segment.p_type), segment.p_offset, segment.p_vaddr, segment.p_filesz, segment.p_memsz, segment_flags(segment.p_flags), segment.p_align)?;
+    }
+
+    Ok(())
+}
+
+fn write_sections(f: &mut impl Write, elf: &ParsedElf) -> fmt::Result {
+    writeln!(f, "\nSections:")?;
+    writeln!(f, "  [Nr] {:<18} {:<14} {:<16} {:<8} {:<8} ES Flg Lk Inf Al", "Name", "Type", "Address", "Off", "Size")?;
+
+    for (i, section) in elf.sections.iter().enumerate() {
+        let header = &section.header;
+
+        writeln!(f, "  [{:>2}] {:<18} {:<14} {:016x} {:08x} {:08x} {:02x} {:>3} {:>2} {:>3} {:>2}",
+            i, section.name, section_type_name(header.sh_type), header.sh_addr, header.sh_offset, header.sh_size,
+            header.sh_entsize, section_flags(header.sh_flags), header.sh_link, header.sh_info, header.sh_addralign)?;
+    }
+
+    Ok(())
+}
+
+fn write_symbols(f: &mut impl Write, elf: &ParsedElf) -> fmt::Result {
+    if elf.symbols.is_empty() {
+        return writeln!(f, "\nNo symbol table.");
+    }
+
+    writeln!(f, "\nSymbols:")?;
+    writeln!(f, "  {:>4} {:<16} {:>5} {:<7} {:<6} {:<9} {:>3} Name", "Num", "Value", "Size", "Type", "Bind", "Vis", "Ndx")?;
+
+    for (i, symbol) in elf.symbols.iter().enumerate() {
+        let sym = &symbol.symbol;
+
+        writeln!(f, "  {:>4} {:016x} {:>5} {:<7} {:<6} {:<9} {:>3} {}",
+            i, sym.st_value, sym.st_size, symbol_type_name(sym.sym_type()), binding_name(sym.binding()),
+            visibility_name(sym.st_other), section_index_name(sym.st_shndx), symbol.name)?;
+    }
+
+    Ok(())
+}
+
+fn write_relocations(f: &mut impl Write, elf: &ParsedElf) -> fmt::Result {
+    let symtab = elf.sections.iter().position(|section| section.header.sh_type == SHT_SYMTAB);
+
+    for section in elf.sections.iter().filter(|section| section.header.sh_type == SHT_RELA) {
+        let target = elf.sections.get(section.header.sh_info as usize).map_or("", |target| target.name.as_str());
+        writeln!(f, "\nRelocations in {} for {}:", section.name, target)?;
+        writeln!(f, "  {:<16} {:<24} Symbol + addend", "Offset", "Type")?;
+
+        for relocation in &section.relocations {
+            let symbol = relocation.symbol() as usize;
+            let name = if Some(section.header.sh_link as usize) == symtab {
+                symbol_label(elf, symbol)
+            } else {
+                format!("#{}", symbol)
+            };
+
+            let addend = match relocation.r_addend {
+                0 => String::new(),
+                addend if addend < 0 => format!(" - {}", addend.unsigned_abs()),
+                addend => format!(" + {}", addend)
+            };
+
+            writeln!(f, "  {:016x} {:<24} {}{}", relocation.r_offset, relocation_name(elf.header.e_machine, relocation.kind()), name, addend)?;
+        }
+    }
+
+    Ok(())
+}
+
+// 16 bytes a line, with the address they'd be loaded at and their printable characters
+fn write_hex_dump(f: &mut impl Write, address: u64, bytes: &[u8]) -> fmt::Result {
+    for (i, line) in bytes.chunks(16).enumerate() {
+        write!(f, "  {:08x} ", address + i as u64 * 16)?;
+
+        for column in 0..16 {
+            match line.get(column) {
+                Some(byte) => write!(f, " {:02x}", byte)?,
+                None => f.write_str("   ")?
+            }
+        }
+
+        let text: String = line.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect();
+        writeln!(f, "  |{}|", text)?;
+    }
+
+    Ok(())
+}
+
+fn write_contents(f: &mut impl Write, elf: &ParsedElf) -> fmt::Result {
+    // there's no x86-64 decoder yet, so .text is dumped like everything else.
+    // Tables already decoded above are left out.
+    for section in elf.sections.iter().skip(1) {
+        if section.contents.is_empty() || matches!(section.header.sh_type, SHT_SYMTAB | SHT_DYNSYM | SHT_RELA | SHT_STRTAB) {
+            continue;
+        }
+
+        writeln!(f, "\nContents of {}:", section.name)?;
+        write_hex_dump(f, section.header.sh_addr, &section.contents)?;
+    }
+
+    Ok(())
+}
+
+// a readelf-style description of an ELF file: headers, sections, symbols, relocations and contents
+pub fn describe(elf: &ParsedElf) -> String {
+    let mut out = String::new();
+
+    write_header(&mut out, elf)
+        .and_then(|_| write_program_headers(&mut out, elf))
+        .and_then(|_| write_sections(&mut out, elf))
+        .and_then(|_| write_symbols(&mut out, elf))
+        .and_then(|_| write_relocations(&mut out, elf))
+        .and_then(|_| write_contents(&mut out, elf))
+        .expect("Writing to a String can't fail");
+
+    out
+}
@@ -1,2 +1,3 @@
-pub mod elf;
+pub mod elf;
+pub mod inspect;
 pub mod serialization;
\ No newline at end of file
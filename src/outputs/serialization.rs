@@ -1,55 +1,167 @@
-pub trait ToBytes {
-    fn to_bytes(&self, big_endian: bool) -> Vec<u8>;
-}
-
-macro_rules! impl_to_bytes {
-    ($($t:ty),*) => {
-        $(
-            impl ToBytes for $t {
-                fn to_bytes(&self, big_endian: bool) -> Vec<u8> {
-                    let mut vec = Vec::new();
-                    if (big_endian) {
-                        vec.extend_from_slice(self.to_be_bytes().as_ref());
-                    } else {
-                        vec.extend_from_slice(self.to_le_bytes().as_ref());
-                    }
-                    vec
-                }
-            }
-        )*
-    };
-}
-
-impl_to_bytes!(u8, u16, u32, u64, i64);
-
-pub trait Serializable {
-    fn serialize(&self, big_endian: bool) -> Vec<u8>;
-
-    fn serialized_length(&self) -> usize {
-        self.serialize(true).len()
-    }
-}
-
-pub fn add_bytes<T: ToBytes>(vec: &mut Vec<u8>, val: T, be: bool) {
-    vec.append(&mut val.to_bytes(be));
-}
-
-impl Serializable for String {
-    fn serialize(&self, _: bool) -> Vec<u8> {
-        let mut vec = Vec::from(self.as_bytes());
-
-        vec.push(0x00);
-
-        vec
-    }
-
-    fn serialized_length(&self) -> usize {
-        self.len() + 1
-    }
-}
-
-impl<T: Serializable> Serializable for Vec<T> {
-    fn serialize(&self, be: bool) -> Vec<u8> {
-        self.iter().flat_map(|x| x.serialize(be)).collect()
-    }
-}
+use std::fmt;
+
+pub trait ToBytes {
+    fn to_bytes(&self, big_endian: bool) -> Vec<u8>;
+}
+
+macro_rules! impl_to_bytes {
+    ($($t:ty),*) => {
+        $(
+            impl ToBytes for $t {
+                fn to_bytes(&self, big_endian: bool) -> Vec<u8> {
+                    let mut vec = Vec::new();
+                    if (big_endian) {
+                        vec.extend_from_slice(self.to_be_bytes().as_ref());
+                    } else {
+                        vec.extend_from_slice(self.to_le_bytes().as_ref());
+                    }
+                    vec
+                }
+            }
+        )*
+    };
+}
+
+impl_to_bytes!(u8, u16, u32, u64, i16, i32, i64);
+
+pub trait Serializable {
+    fn serialize(&self, big_endian: bool) -> Vec<u8>;
+
+    fn serialized_length(&self) -> usize {
+        self.serialize(true).len()
+    }
+}
+
+pub fn add_bytes<T: ToBytes>(vec: &mut Vec<u8>, val: T, be: bool) {
+    vec.append(&mut val.to_bytes(be));
+}
+
+impl Serializable for String {
+    fn serialize(&self, _: bool) -> Vec<u8> {
+        let mut vec = Vec::from(self.as_bytes());
+
+        vec.push(0x00);
+
+        vec
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.len() + 1
+    }
+}
+
+impl<T: Serializable> Serializable for Vec<T> {
+    fn serialize(&self, be: bool) -> Vec<u8> {
+        self.iter().flat_map(|x| x.serialize(be)).collect()
+    }
+}
+
+pub trait FromBytes: Sized {
+    const SIZE: usize;
+
+    fn from_bytes(bytes: &[u8], big_endian: bool) -> Self;
+}
+
+macro_rules! impl_from_bytes {
+    ($($t:ty),*) => {
+        $(
+            impl FromBytes for $t {
+                const SIZE: usize = size_of::<$t>();
+
+                fn from_bytes(bytes: &[u8], big_endian: bool) -> Self {
+                    let bytes = bytes.try_into().unwrap();
+                    if big_endian {
+                        <$t>::from_be_bytes(bytes)
+                    } else {
+                        <$t>::from_le_bytes(bytes)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_bytes!(u8, u16, u32, u64, i16, i32, i64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserializeErrorKind {
+    // the input ended `needed` bytes into something that had to be read
+    Truncated { what: &'static str, needed: usize },
+    Invalid(String)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeserializeError {
+    pub offset: usize,
+    pub kind: DeserializeErrorKind
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            DeserializeErrorKind::Truncated { what, needed } => {
+                write!(f, "at 0x{:x}: input ends before the {} ({} bytes needed)", self.offset, what, needed)
+            },
+            DeserializeErrorKind::Invalid(message) => write!(f, "at 0x{:x}: {}", self.offset, message)
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+// reads values one after another from a byte slice, keeping track of where it is for errors
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    big_endian: bool
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], big_endian: bool) -> Reader<'a> {
+        Reader::at(bytes, 0, big_endian)
+    }
+
+    pub fn at(bytes: &'a [u8], offset: usize, big_endian: bool) -> Reader<'a> {
+        Reader {
+            bytes,
+            offset,
+            big_endian
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    pub fn error(&self, message: String) -> DeserializeError {
+        DeserializeError {
+            offset: self.offset,
+            kind: DeserializeErrorKind::Invalid(message)
+        }
+    }
+
+    pub fn read_bytes(&mut self, len: usize, what: &'static str) -> Result<&'a [u8], DeserializeError> {
+        let bytes = self.offset.checked_add(len)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or(DeserializeError {
+                offset: self.offset,
+                kind: DeserializeErrorKind::Truncated { what, needed: len }
+            })?;
+
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn read<T: FromBytes>(&mut self, what: &'static str) -> Result<T, DeserializeError> {
+        let big_endian = self.big_endian;
+        self.read_bytes(T::SIZE, what).map(|bytes| T::from_bytes(bytes, big_endian))
+    }
+}
+
+pub trait Deserializable: Sized {
+    fn deserialize(reader: &mut Reader) -> Result<Self, DeserializeError>;
+}
@@ -0,0 +1,178 @@
+use chair::ChairError;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::{BinaryOp, Builder, CastOp, ConstValue, IntCondition, TranslationUnit, Value};
+use chair::ir::text::parser::parse_translation_unit;
+use chair::ir::types::{Signature, Type};
+
+// exits with triangle(@counter) plus the first byte of a string
+fn build() -> Result<TranslationUnit, ChairError> {
+    let mut builder = Builder::new("built");
+    builder.add_global("counter", Type::I64, Some(ConstValue::Int64(5)), false);
+
+    let triangle = Signature::new(vec![Type::I64], Type::I64);
+    let mut function = builder.function("triangle", triangle.clone());
+    let total = function.add_stack_slot(Type::I64, 8);
+    let left = function.add_stack_slot(Type::I64, 8);
+    let entry = function.create_block();
+    let check = function.create_block();
+    let body = function.create_block();
+    let done = function.create_block();
+
+    function.switch_to_block(entry)?;
+    let total = function.stack_addr(total)?;
+    let left = function.stack_addr(left)?;
+    function.store(Value::const_i64(0), total.clone(), 8)?;
+    function.store(function.param(0)?, left.clone(), 8)?;
+    function.jump(check)?;
+
+    function.switch_to_block(check)?;
+    let n = function.load(Type::I64, left.clone(), 8)?;
+    let more = function.icmp(IntCondition::SGt, n.clone(), Value::const_i64(0))?;
+    function.branch(more, body, done)?;
+
+    function.switch_to_block(body)?;
+    let sum = function.load(Type::I64, total.clone(), 8)?;
+    let sum = function.binary(BinaryOp::Add, sum, n.clone())?;
+    function.store(sum, total.clone(), 8)?;
+    let n = function.binary(BinaryOp::Sub, n, Value::const_i64(1))?;
+    function.store(n, left, 8)?;
+    function.jump(check)?;
+
+    function.switch_to_block(done)?;
+    let sum = function.load(Type::I64, total, 8)?;
+    function.ret(Some(sum))?;
+    function.finish();
+
+    let main = Signature::new(vec![], Type::I64);
+    let mut function = builder.function("main", main.clone());
+    let entry = function.create_block();
+    function.switch_to_block(entry)?;
+    let counter = function.global_addr("counter")?;
+    let count = function.load(Type::I64, counter, 8)?;
+    let sum = function.call("triangle", &triangle, vec![count])?.unwrap();
+    let first = function.load(Type::I8, Value::const_str("hi".to_owned()), 1)?;
+    let first = function.cast(CastOp::ZExt, first, Type::I64)?;
+    let status = function.binary(BinaryOp::Add, sum, first)?;
+    function.ret(Some(status))?;
+    function.finish();
+
+    let mut function = builder.function("_start", Signature::new(vec![], Type::Void));
+    let entry = function.create_block();
+    function.switch_to_block(entry)?;
+    function.call("main", &main, vec![])?;
+    // mov rdi, rax; mov eax, 60; syscall
+    function.asm(vec![0x48, 0x89, 0xC7, 0xB8, 0x3C, 0x00, 0x00, 0x00, 0x0F, 0x05])?;
+    function.ret(None)?;
+    function.finish();
+
+    Ok(builder.finish())
+}
+
+const PRINTED: &str = r#"unit "built"
+
+global @counter: i64 = i64 5
+
+fn @triangle(%0: i64) -> i64 {
+    $0 = slot i64, align 8
+    $1 = slot i64, align 8
+bb0:
+    %1 = stack_addr $0
+    %2 = stack_addr $1
+    store i64 0, %1, align 8
+    store %0, %2, align 8
+    jmp bb1
+bb1:
+    %3 = load i64, %2, align 8
+    %4 = icmp sgt %3, i64 0
+    br %4, bb2, bb3
+bb2:
+    %5 = load i64, %1, align 8
+    %6 = add i64 %5, %3
+    store %6, %1, align 8
+    %7 = sub i64 %3, i64 1
+    store %7, %2, align 8
+    jmp bb1
+bb3:
+    %8 = load i64, %1, align 8
+    ret %8
+}
+
+fn @main() -> i64 {
+bb0:
+    %0 = global_addr @counter
+    %1 = load i64, %0, align 8
+    %2 = call i64 @triangle(%1)
+    %3 = load i8, ref [3 x i8] c"hi\00", align 1
+    %4 = zext %3 to i64
+    %5 = add i64 %2, %4
+    ret %5
+}
+
+fn @_start() -> void {
+bb0:
+    %0 = call i64 @main()
+    asm "48 89 c7 b8 3c 00 00 00 0f 05"
+    ret
+}
+"#;
+
+#[test]
+fn built_units_print_as_text_ir() {
+    let printed = build().unwrap().to_string();
+    assert_eq!(printed, PRINTED);
+    assert_eq!(parse_translation_unit(&printed).unwrap().to_string(), printed);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn built_units_run() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("chair-builder-{}", std::process::id()));
+    CompilerX64Elf::new().compile_executable(build().unwrap(), "_start").unwrap().write_to_file(&path).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let status = std::process::Command::new(&path).status().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(status.code(), Some(15 + b'h' as i32));
+}
+
+// checks that `result` is an error from the builder of @misused
+fn invalid<T>(result: Result<T, ChairError>, expected: &str) {
+    match result {
+        Err(ChairError::InvalidIr { context, message }) => {
+            assert_eq!(context.function.as_deref(), Some("misused"));
+            assert!(message.contains(expected), "{}", message);
+        },
+        Err(error) => panic!("wrong error: {}", error),
+        Ok(_) => panic!("expected an error about {}", expected)
+    }
+}
+
+#[test]
+fn misuse_is_an_error_instead_of_a_panic() {
+    let mut builder = Builder::new("misuse");
+
+    let mut other = builder.function("other", Signature::new(vec![], Type::I64));
+    let entry = other.create_block();
+    other.create_block();
+    other.switch_to_block(entry).unwrap();
+    let one = other.binary(BinaryOp::Add, Value::const_i64(0), Value::const_i64(1)).unwrap();
+    let two = other.binary(BinaryOp::Add, one.clone(), one).unwrap();
+    let sum = other.binary(BinaryOp::Add, two.clone(), two).unwrap();
+    other.ret(Some(sum.clone())).unwrap();
+    other.finish();
+
+    let mut function = builder.function("misused", Signature::new(vec![Type::I64], Type::I64));
+    invalid(function.ret(None), "no block to insert into");
+    invalid(function.load(Type::I64, Value::const_i64(0), 8), "no block to insert into");
+    invalid(function.param(1), "no parameter 1");
+    invalid(function.switch_to_block(entry), "bb0 isn't a block");
+
+    function.create_block();
+    function.switch_to_block(entry).unwrap();
+    // @other's %2, which @misused doesn't have
+    invalid(function.value_type(&sum), "%2 isn't a value");
+    invalid(function.binary(BinaryOp::Add, sum, Value::const_i64(1)), "%2 isn't a value");
+    assert_eq!(function.value_type(&function.param(0).unwrap()).unwrap(), Type::I64);
+}
@@ -0,0 +1,92 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use std::process::Command;
+use chair::codegen::{Codegen, CodegenOptions, RegisterAllocator};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+
+// @forward passes its parameters on to C's check in the same order, so both sides have to agree on where
+// each of 8 integers and 10 floats goes, the last two of each on the stack
+const SOURCE: &str = r#"
+unit "convention"
+
+declare @check(i64, f64, i32, f32, i8, i16, i64, f64, i64, i64, f64, f64, f64, f64, f64, f64, f64, i64) -> i64
+
+fn @forward(%0: i64, %1: f64, %2: i32, %3: f32, %4: i8, %5: i16, %6: i64, %7: f64, %8: i64, %9: i64, %10: f64, %11: f64, %12: f64, %13: f64, %14: f64, %15: f64, %16: f64, %17: i64) -> i64 {
+bb0:
+    %18 = call i64 @check(%0, %1, %2, %3, %4, %5, %6, %7, %8, %9, %10, %11, %12, %13, %14, %15, %16, %17)
+    ret %18
+}
+
+fn @second(%0: f64, %1: f64) -> f64 {
+bb0:
+    ret %1
+}
+
+fn @constant() -> f32 {
+bb0:
+    ret f32 0.25
+}
+
+fn @narrow(%0: i64) -> i8 {
+bb0:
+    %1 = trunc %0 to i8
+    ret %1
+}
+"#;
+
+const MAIN: &str = r#"
+long forward(long, double, int, float, signed char, short, long, double, long, long, double, double, double, double, double, double, double, long);
+double second(double, double);
+float constant(void);
+signed char narrow(long);
+
+long check(long a, double x, int b, float y, signed char c, short d, long e, double z, long f, long g,
+           double p, double q, double r, double s, double t, double u, double v, long h) {
+    if (a != 1 || x != 2.5 || b != -3 || y != 4.5f || c != -5 || d != 6 || e != 7 || z != 8.5 || f != 9 || g != 10) {
+        return 1;
+    }
+    if (p != 11.5 || q != 12.5 || r != 13.5 || s != 14.5 || t != 15.5 || u != 16.5 || v != 17.5 || h != 18) {
+        return 2;
+    }
+    return 42;
+}
+
+int main(void) {
+    if (second(1.0, 2.0) != 2.0) {
+        return 3;
+    }
+    if (constant() != 0.25f) {
+        return 4;
+    }
+    if (narrow(0x1ff) != -1) {
+        return 5;
+    }
+    return forward(1, 2.5, -3, 4.5f, -5, 6, 7, 8.5, 9, 10, 11.5, 12.5, 13.5, 14.5, 15.5, 16.5, 17.5, 18);
+}
+"#;
+
+// skipped when gcc isn't installed
+#[test]
+fn arguments_and_results_match_c() {
+    let dir = std::env::temp_dir().join(format!("chair-convention-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.c"), MAIN).unwrap();
+
+    for register_allocator in [RegisterAllocator::LinearScan, RegisterAllocator::GraphColoring] {
+        let options = CodegenOptions { register_allocator, ..CodegenOptions::default() };
+        let object = CompilerX64Elf::with_options(options).compile_translation_unit(parse_translation_unit(SOURCE).unwrap()).unwrap();
+        object.write_to_file(dir.join("convention.o")).unwrap();
+
+        let compiled = Command::new("gcc").args(["-O1", "-no-pie", "-Wl,-z,noexecstack"])
+            .arg(dir.join("main.c")).arg(dir.join("convention.o")).arg("-o").arg(dir.join("main")).status();
+        if !compiled.is_ok_and(|status| status.success()) {
+            break;
+        }
+
+        let status = Command::new(dir.join("main")).status().unwrap();
+        assert_eq!(status.code(), Some(42), "mismatch with {:?}", register_allocator);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
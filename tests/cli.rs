@@ -0,0 +1,150 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use chair::outputs::elf::{ElfObjectBuilder, EM_X86_64, STB_GLOBAL, STT_FUNC};
+
+// @f's addition is folded from -O1 up
+const SOURCE: &str = r#"unit "cli"
+
+fn @f() -> i64 {
+bb0:
+    %0 = add i64 i64 40, i64 2
+    ret %0
+}
+
+fn @_start() -> void {
+bb0:
+    %0 = call i64 @f()
+    asm "48 89 c7 b8 3c 00 00 00 0f 05"
+    ret
+}
+"#;
+
+// a fresh directory for one test's files
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chair-cli-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn chair_in(dir: &PathBuf, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chair"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // chair may exit before it reads stdin, like when the command line is wrong
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn chair(args: &[&str], stdin: &str) -> Output {
+    chair_in(&std::env::temp_dir(), args, stdin)
+}
+
+fn stdout(output: &Output) -> String {
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn exit_codes_say_what_went_wrong() {
+    let dir = scratch("exit-codes");
+    let undefined = "unit \"x\"\n\ndeclare @nowhere() -> void\n\nfn @_start() -> void {\nbb0:\n    call void @nowhere()\n    ret\n}\n";
+
+    // an object whose @far is further from .text than a call can reach
+    let mut object = ElfObjectBuilder::new(EM_X86_64);
+    object.add_absolute_symbol(Some("far"), 0x7000_0000_0000, 0, STB_GLOBAL, STT_FUNC);
+    object.build().write_to_file(dir.join("far.o")).unwrap();
+    let far = "unit \"x\"\n\ndeclare @far() -> void\n\nfn @_start() -> void {\nbb0:\n    call void @far()\n    ret\n}\n";
+    std::fs::write(dir.join("far.chir"), far).unwrap();
+
+    let cases: [(&[&str], &str, i32, &str); 7] = [
+        (&[], "unit \"x\"\n\nfn @f(", 1, "<stdin>:"),
+        (&[], "unit \"x\"\n\nfn @f() -> i64 {\nbb0:\n    ret i32 1\n}\n", 1, "returned value should be i64, found i32"),
+        (&["--emit=bogus"], SOURCE, 2, "unknown --emit kind 'bogus'"),
+        (&["missing.chir"], "", 3, "missing.chir"),
+        (&["--target=arm-elf"], SOURCE, 4, "unsupported target 'arm-elf'"),
+        (&["--emit=exe", "-o", "far", "far.chir", "far.o"], "", 5, "far"),
+        (&["--emit=exe", "-o", "undefined"], undefined, 6, "nowhere")
+    ];
+
+    for (args, stdin, code, message) in cases {
+        let output = chair_in(&dir, args, stdin);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert_eq!(output.status.code(), Some(code), "chair {:?}: {}", args, stderr);
+        assert!(stderr.contains(message), "chair {:?}: {}", args, stderr);
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn objects_are_written_to_o_or_next_to_the_input() {
+    let dir = scratch("outputs");
+    std::fs::write(dir.join("input.chir"), SOURCE).unwrap();
+
+    stdout(&chair_in(&dir, &["input.chir"], ""));
+    assert!(std::fs::read(dir.join("input.o")).unwrap().starts_with(b"\x7FELF"));
+
+    stdout(&chair_in(&dir, &["-o", "named.o", "input.chir"], ""));
+    assert!(std::fs::read(dir.join("named.o")).unwrap().starts_with(b"\x7FELF"));
+
+    // -o - sends the object to stdout
+    let output = chair_in(&dir, &["-o", "-", "input.chir"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.starts_with(b"\x7FELF"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn stdin_is_read_without_inputs_or_for_a_dash() {
+    assert_eq!(stdout(&chair(&["--emit=ir"], SOURCE)), SOURCE);
+    assert_eq!(stdout(&chair(&["--emit=ir", "-"], SOURCE)), SOURCE);
+
+    let dir = scratch("stdin");
+    stdout(&chair_in(&dir, &[], SOURCE));
+    assert!(std::fs::read(dir.join("a.o")).unwrap().starts_with(b"\x7FELF"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn emit_kinds() {
+    // ir is printed as parsed, ir-optimized after the passes
+    assert!(stdout(&chair(&["--emit=ir", "-O2"], SOURCE)).contains("add i64 i64 40, i64 2"));
+    assert!(stdout(&chair(&["--emit=ir-optimized", "-O1"], SOURCE)).contains("ret i64 42"));
+
+    let asm = stdout(&chair(&["--emit=asm"], SOURCE));
+    assert!(asm.starts_with("f:\n    push rbp\n    mov rbp, rsp\nbb0:\n"), "{}", asm);
+    assert!(asm.contains("\n_start:\n"), "{}", asm);
+    assert!(asm.contains("    call f\n"), "{}", asm);
+    assert!(asm.contains("    .byte 0x48, 0x89, 0xc7, 0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05\n"), "{}", asm);
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    {
+        let dir = scratch("exe");
+        stdout(&chair_in(&dir, &["--emit=exe", "-o", "program"], SOURCE));
+        assert_eq!(Command::new(dir.join("program")).status().unwrap().code(), Some(42));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn optimization_levels() {
+    assert!(stdout(&chair(&["--emit=ir-optimized", "-O0"], SOURCE)).contains("add i64 i64 40, i64 2"));
+    assert!(stdout(&chair(&["--emit=ir-optimized", "-O1"], SOURCE)).contains("ret i64 42"));
+    assert!(stdout(&chair(&["--emit=ir-optimized", "-O2"], SOURCE)).contains("ret i64 42"));
+
+    // -O2 leaves out the frame pointer
+    assert!(stdout(&chair(&["--emit=asm", "-O1"], SOURCE)).contains("push rbp"));
+    assert!(!stdout(&chair(&["--emit=asm", "-O2"], SOURCE)).contains("push rbp"));
+
+    assert_eq!(chair(&["-O3"], SOURCE).status.code(), Some(2));
+}
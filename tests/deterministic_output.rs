@@ -0,0 +1,66 @@
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::serialization::Serializable;
+
+const SOURCE: &str = r#"
+unit "deterministic"
+
+declare @puts(ptr) -> i32
+declare @exit(i32) -> void
+declare @abs(i32) -> i32
+
+const @greeting: [6 x i8] = [6 x i8] c"hello\00"
+global @counter: i64 = i64 3
+global @scratch: [16 x i8]
+const @table: [4 x i16] = [4 x i16] [1, 2, 3, 4]
+
+fn @main() -> i32 {
+bb0:
+    %0 = global_addr @greeting
+    %1 = call i32 @puts(%0)
+    %2 = call i32 @twice(i32 21)
+    %3 = call i32 @abs(%2)
+    switch %3, bb1 [0: bb2, 1: bb2, 2: bb2, 3: bb2]
+bb1:
+    ret %3
+bb2:
+    call void @exit(i32 1)
+    ret i32 0
+}
+
+fn @twice(%0: i32) -> i32 {
+bb0:
+    %1 = add i32 %0, %0
+    ret %1
+}
+
+fn @bump() -> void {
+bb0:
+    %0 = global_addr @counter
+    %1 = load i64, %0, align 8
+    %2 = add i64 %1, i64 1
+    store %2, %0, align 8
+    asm_value ref [3 x i8] c"hi\00"
+    ret
+}
+
+fn @zero() -> i64 {
+bb0:
+    ret i64 0
+}
+"#;
+
+fn compile() -> Vec<u8> {
+    let translation_unit = parse_translation_unit(SOURCE).unwrap();
+    CompilerX64Elf::new().compile_translation_unit(translation_unit).unwrap().serialize(false)
+}
+
+#[test]
+fn compiling_the_same_unit_gives_the_same_bytes() {
+    let first = compile();
+
+    for _ in 0..32 {
+        assert_eq!(compile(), first);
+    }
+}
@@ -0,0 +1,128 @@
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::elf::*;
+use chair::outputs::serialization::{DeserializeErrorKind, Serializable};
+
+const SOURCE: &str = r#"
+unit "reader"
+
+declare @puts(ptr) -> i32
+
+const @message: [3 x i8] = [3 x i8] c"hi\00"
+global @counter: i64 = i64 3
+global @buffer: [64 x i8]
+
+fn @main() -> i32 {
+bb0:
+    %0 = global_addr @message
+    %1 = call i32 @puts(%0)
+    ret %1
+}
+"#;
+
+fn compile() -> ElfFile {
+    let translation_unit = parse_translation_unit(SOURCE).unwrap();
+    CompilerX64Elf::new().compile_translation_unit(translation_unit).unwrap()
+}
+
+#[test]
+fn written_objects_read_back_the_same() {
+    let elf = compile();
+    let bytes = elf.serialize(false);
+    let parsed = ElfFile::parse(&bytes).unwrap();
+
+    assert_eq!(parsed.header, elf.elf_header);
+    assert!(parsed.program_headers.is_empty());
+
+    // a little-endian ELF64 object for System V, like gcc and as write
+    assert_eq!(bytes[..16], [0x7F, b'E', b'L', b'F', 2, 1, 1, ELFOSABI_NONE, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    let headers: Vec<ElfSectionHeader> = parsed.sections.iter().map(|section| section.header.clone()).collect();
+    assert_eq!(headers, elf.elf_section_headers);
+
+    let names: Vec<&str> = parsed.sections.iter().map(|section| section.name.as_str()).collect();
+    assert_eq!(names, ["", ".text", ".rodata", ".data", ".bss", ".rela.text", ".symtab", ".strtab", ".shstrtab"]);
+
+    let bss = parsed.section(".bss").unwrap();
+    assert_eq!(bss.header.sh_size, 64);
+    assert!(bss.contents.is_empty());
+    assert_eq!(parsed.section(".data").unwrap().contents, 3i64.to_le_bytes());
+}
+
+#[test]
+fn symbols_are_named() {
+    let parsed = ElfFile::parse(&compile().serialize(false)).unwrap();
+
+    let find = |name: &str| parsed.symbols.iter().find(|symbol| symbol.name == name).unwrap();
+
+    assert_eq!(parsed.symbols[0].name, "");
+    assert_eq!(find("main").symbol.sym_type(), STT_FUNC);
+    assert_eq!(find("main").symbol.binding(), STB_GLOBAL);
+    assert_eq!(find("puts").symbol.st_shndx, SHN_UNDEF);
+    assert_eq!(find("buffer").symbol.st_shndx as usize, parsed.section_index(".bss").unwrap());
+    assert_eq!(find("message").symbol.st_size, 3);
+
+    let relocation = &parsed.section(".rela.text").unwrap().relocations[0];
+    assert_eq!(parsed.symbols[relocation.symbol() as usize].name, "message");
+}
+
+#[test]
+fn truncated_input_is_an_error() {
+    let bytes = compile().serialize(false);
+
+    for len in 0..bytes.len() {
+        assert!(ElfFile::parse(&bytes[..len]).is_err(), "{} bytes parsed", len);
+    }
+}
+
+#[test]
+fn truncated_header_says_what_was_cut_off() {
+    let bytes = compile().serialize(false);
+    let error = ElfFile::parse(&bytes[..20]).unwrap_err();
+
+    assert_eq!(error.kind, DeserializeErrorKind::Truncated { what: "ELF header", needed: 4 });
+    assert_eq!(error.offset, 20);
+}
+
+#[test]
+fn bad_headers_are_rejected() {
+    let bytes = compile().serialize(false);
+
+    let mut not_elf = bytes.clone();
+    not_elf[1] = b'X';
+    assert_eq!(ElfFile::parse(&not_elf).unwrap_err().kind, DeserializeErrorKind::Invalid("not an ELF file".to_owned()));
+
+    let mut elf32 = bytes.clone();
+    elf32[4] = 1;
+    assert!(ElfFile::parse(&elf32).is_err());
+
+    let mut bad_shstrndx = bytes.clone();
+    bad_shstrndx[0x3E] = 0x40;
+    assert!(ElfFile::parse(&bad_shstrndx).is_err());
+}
+
+#[test]
+fn out_of_range_references_are_rejected() {
+    let elf = compile();
+    let parsed = ElfFile::parse(&elf.serialize(false)).unwrap();
+    let section_header = |name: &str| 0x40 + parsed.section_index(name).unwrap() * 0x40;
+
+    // a symbol name past the end of .strtab
+    let mut bytes = elf.serialize(false);
+    let symtab = parsed.section(".symtab").unwrap().header.sh_offset as usize;
+    bytes[symtab + 0x18..symtab + 0x1C].copy_from_slice(&0xFFFFu32.to_le_bytes());
+    assert!(ElfFile::parse(&bytes).is_err());
+
+    // a relocation against a symbol that doesn't exist
+    let mut bytes = elf.serialize(false);
+    let rela = parsed.section(".rela.text").unwrap().header.sh_offset as usize;
+    bytes[rela + 12..rela + 16].copy_from_slice(&1000u32.to_le_bytes());
+    assert!(ElfFile::parse(&bytes).is_err());
+
+    // a section that runs past the end of the file
+    let mut bytes = elf.serialize(false);
+    let text = section_header(".text");
+    bytes[text + 0x20..text + 0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(ElfFile::parse(&bytes).is_err());
+}
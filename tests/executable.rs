@@ -0,0 +1,157 @@
+use chair::ChairError;
+use chair::codegen::{Addressing, CodegenOptions};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::elf::*;
+use chair::outputs::serialization::Serializable;
+
+// exits with counter + table[2] + the first byte of message, after bumping counter through .bss
+const SOURCE: &str = r#"
+unit "executable"
+
+const @message: [3 x i8] = [3 x i8] c"hi\00"
+const @table: [4 x i64] = [4 x i64] [1, 2, 30, 4]
+global @counter: i64 = i64 5
+global @scratch: i64
+
+fn @_start() -> void {
+bb0:
+    %0 = call i64 @compute()
+    asm "48 89 c7 b8 3c 00 00 00 0f 05"
+    ret
+}
+
+fn @compute() -> i64 {
+bb0:
+    %0 = global_addr @scratch
+    %1 = global_addr @counter
+    %2 = load i64, %1, align 8
+    store %2, %0, align 8
+    %3 = load i64, %0, align 8
+    %4 = global_addr @table
+    %5 = gep [4 x i64], %4, i64 0, i64 2
+    %6 = load i64, %5, align 8
+    %7 = global_addr @message
+    %8 = load i8, %7, align 1
+    %9 = zext %8 to i64
+    %10 = add i64 %3, %6
+    %11 = add i64 %10, %9
+    ret %11
+}
+"#;
+
+fn link(source: &str) -> Result<ElfFile, ChairError> {
+    CompilerX64Elf::new().compile_executable(parse_translation_unit(source).unwrap(), "_start")
+}
+
+#[test]
+fn segments_are_page_aligned_with_the_right_permissions() {
+    let elf = ElfFile::parse(&link(SOURCE).unwrap().serialize(false)).unwrap();
+
+    assert_eq!(elf.header.e_type, ET_EXEC);
+
+    let flags: Vec<u32> = elf.program_headers.iter().map(|segment| segment.p_flags).collect();
+    assert_eq!(flags, [PF_R | PF_X, PF_R, PF_R | PF_W]);
+
+    for segment in &elf.program_headers {
+        assert_eq!(segment.p_type, PT_LOAD);
+        assert_eq!(segment.p_offset % 0x1000, 0);
+        assert_eq!(segment.p_vaddr % 0x1000, 0);
+        assert!(segment.p_filesz <= segment.p_memsz);
+    }
+
+    // .bss takes memory after .data but no space in the file
+    let writable = &elf.program_headers[2];
+    let bss = elf.section(".bss").unwrap();
+    assert_eq!(writable.p_memsz - writable.p_filesz, 8);
+    assert_eq!(bss.header.sh_addr + bss.header.sh_size, writable.p_vaddr + writable.p_memsz);
+}
+
+#[test]
+fn entry_point_is_the_entry_symbol() {
+    let elf = ElfFile::parse(&link(SOURCE).unwrap().serialize(false)).unwrap();
+    let start = elf.symbols.iter().find(|symbol| symbol.name == "_start").unwrap();
+
+    assert_eq!(elf.header.e_entry, start.symbol.st_value);
+    assert_eq!(start.symbol.st_shndx as usize, elf.section_index(".text").unwrap());
+    assert!(elf.sections.iter().all(|section| section.header.sh_type != SHT_RELA));
+}
+
+#[test]
+fn relocations_are_resolved_to_addresses() {
+    let elf = ElfFile::parse(&link(SOURCE).unwrap().serialize(false)).unwrap();
+    let text = elf.section(".text").unwrap();
+    let counter = elf.symbols.iter().find(|symbol| symbol.name == "counter").unwrap();
+
+    // lea rax, [rip + disp32] with counter's distance from the end of the instruction
+    let lea = text.contents.windows(7).enumerate().any(|(offset, window)| {
+        let end = text.header.sh_addr + offset as u64 + 7;
+        let displacement = i32::from_le_bytes(window[3..].try_into().unwrap());
+        window[..3] == [0x48, 0x8D, 0x05] && end.wrapping_add_signed(displacement as i64) == counter.symbol.st_value
+    });
+    assert!(lea);
+}
+
+#[test]
+fn undefined_functions_are_reported() {
+    let source = r#"
+unit "undefined"
+
+declare @missing() -> void
+
+fn @_start() -> void {
+bb0:
+    call void @missing()
+    ret
+}
+"#;
+
+    match link(source) {
+        Err(ChairError::UndefinedSymbol { symbol, .. }) => assert_eq!(symbol, "missing"),
+        other => panic!("expected an undefined symbol, got {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn missing_entry_point_is_reported() {
+    let result = CompilerX64Elf::new().compile_executable(parse_translation_unit(SOURCE).unwrap(), "main");
+
+    assert!(matches!(result, Err(ChairError::UndefinedSymbol { symbol, .. }) if symbol == "main"));
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn runs_without_a_linker() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    // globals loaded from the GOT get entries from the linker
+    for addressing in [Addressing::RipRelative, Addressing::Got, Addressing::Absolute] {
+        let mut compiler = CompilerX64Elf::with_options(CodegenOptions { addressing, ..CodegenOptions::default() });
+        let path = std::env::temp_dir().join(format!("chair-executable-{}", std::process::id()));
+        compiler.compile_executable(parse_translation_unit(SOURCE).unwrap(), "_start").unwrap().write_to_file(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let status = Command::new(&path).status().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(status.code(), Some(5 + 30 + b'h' as i32), "wrong result with {:?}", addressing);
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn sample_says_hello() {
+    use std::os::unix::fs::PermissionsExt;
+    use chair::ir::sample::get_example_translation_unit;
+
+    let path = std::env::temp_dir().join(format!("chair-sample-{}", std::process::id()));
+    CompilerX64Elf::new().compile_executable(get_example_translation_unit().unwrap(), "_start").unwrap().write_to_file(&path).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = std::process::Command::new(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"Hello, World!\n");
+}
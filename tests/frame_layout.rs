@@ -0,0 +1,246 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+use chair::codegen::{Codegen, CodegenOptions, RegisterAllocator};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::opt::OptLevel;
+use chair::ir::text::parser::parse_translation_unit;
+
+// every combination of frame pointer, red zone and register allocator
+fn all_options() -> Vec<CodegenOptions> {
+    let mut options = vec![];
+    for register_allocator in [RegisterAllocator::LinearScan, RegisterAllocator::GraphColoring] {
+        for frame_pointer in [true, false] {
+            for red_zone in [true, false] {
+                options.push(CodegenOptions { register_allocator, frame_pointer, red_zone, ..CodegenOptions::default() });
+            }
+        }
+    }
+    options
+}
+
+// @nine reads its digits as a decimal number, the last three arriving on the stack
+const NINE: &str = r#"
+fn @nine(%0: i64, %1: i64, %2: i64, %3: i64, %4: i64, %5: i64, %6: i64, %7: i64, %8: i64) -> i64 {
+bb0:
+    %9 = mul i64 %0, i64 10
+    %10 = add i64 %9, %1
+    %11 = mul i64 %10, i64 10
+    %12 = add i64 %11, %2
+    %13 = mul i64 %12, i64 10
+    %14 = add i64 %13, %3
+    %15 = mul i64 %14, i64 10
+    %16 = add i64 %15, %4
+    %17 = mul i64 %16, i64 10
+    %18 = add i64 %17, %5
+    %19 = mul i64 %18, i64 10
+    %20 = add i64 %19, %6
+    %21 = mul i64 %20, i64 10
+    %22 = add i64 %21, %7
+    %23 = mul i64 %22, i64 10
+    %24 = add i64 %23, %8
+    ret %24
+}
+"#;
+
+// @leaf keeps differently sized and aligned slots without calling anything, and returns in two places
+const LEAF: &str = r#"
+fn @leaf(%0: i64) -> i64 {
+    $0 = slot i8, align 1
+    $1 = slot i64, align 16
+    $2 = slot i32, align 4
+bb0:
+    %1 = stack_addr $0
+    %2 = stack_addr $1
+    %3 = stack_addr $2
+    store i8 -1, %1, align 1
+    store %0, %2, align 16
+    %4 = trunc %0 to i32
+    store %4, %3, align 4
+    %5 = load i8, %1, align 1
+    %6 = load i64, %2, align 16
+    %7 = load i32, %3, align 4
+    %8 = sext %5 to i64
+    %9 = sext %7 to i64
+    %10 = add i64 %6, %8
+    %11 = add i64 %10, %9
+    %12 = icmp slt %0, i64 0
+    br %12, bb1, bb2
+bb1:
+    %13 = mul i64 %11, i64 -1
+    ret %13
+bb2:
+    ret %11
+}
+"#;
+
+const TEST: &str = r#"
+fn @test() -> i64 {
+    $0 = slot i64, align 8
+bb0:
+    %0 = stack_addr $0
+    store i64 1000000000, %0, align 8
+    %1 = call i64 @leaf(i64 5)
+    %2 = call i64 @leaf(i64 -5)
+    %3 = call i64 @nine(i64 1, i64 2, i64 3, i64 4, i64 5, i64 6, i64 7, %1, %2)
+    %4 = load i64, %0, align 8
+    %5 = add i64 %3, %4
+    ret %5
+}
+"#;
+
+const START: &str = r#"
+unit "frames"
+
+fn @_start() -> void {
+bb0:
+    %0 = call i64 @test()
+    %1 = icmp eq %0, i64 1123456801
+    br %1, bb1, bb2
+bb1:
+    asm "bf 2a 00 00 00 b8 3c 00 00 00 0f 05"
+    ret
+bb2:
+    asm "bf 01 00 00 00 b8 3c 00 00 00 0f 05"
+    ret
+}
+"#;
+
+// the intel syntax disassembly of `function`, None when objdump isn't installed
+fn disassemble(options: CodegenOptions, function: &str) -> Option<Vec<String>> {
+    let source = format!("{}{}{}{}", START, NINE, LEAF, TEST);
+    let object = CompilerX64Elf::with_options(options).compile_translation_unit(parse_translation_unit(&source).unwrap()).unwrap();
+    let path = std::env::temp_dir().join(format!("chair-frames-{}-{}.o", function, std::process::id()));
+    object.write_to_file(&path).unwrap();
+
+    let output = Command::new("objdump").args(["-d", "-M", "intel", "--no-show-raw-insn"]).arg(&path).output();
+    std::fs::remove_file(&path).unwrap();
+    let output = output.ok().filter(|output| output.status.success())?;
+
+    let header = format!("<{}>:", function);
+    Some(String::from_utf8(output.stdout).unwrap().lines()
+        .skip_while(|line| !line.ends_with(&header))
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .map(|line| line.split_once(":\t").unwrap().1.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect())
+}
+
+#[test]
+fn frames_work_with_every_option() {
+    let source = format!("{}{}{}{}", START, NINE, LEAF, TEST);
+
+    for options in all_options() {
+        let elf = CompilerX64Elf::with_options(options).compile_executable(parse_translation_unit(&source).unwrap(), "_start").unwrap();
+        let path = std::env::temp_dir().join(format!("chair-frames-{}", std::process::id()));
+        elf.write_to_file(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let status = Command::new(&path).status().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(status.code(), Some(42), "wrong result with {:?}", options);
+    }
+}
+
+#[test]
+fn optimized_builds_omit_the_frame_pointer() {
+    assert!(CodegenOptions::for_level(OptLevel::O0).frame_pointer);
+    assert!(CodegenOptions::for_level(OptLevel::O1).frame_pointer);
+    assert!(!CodegenOptions::for_level(OptLevel::O2).frame_pointer);
+    assert!(CodegenOptions::for_level(OptLevel::O2).red_zone);
+}
+
+#[test]
+fn leaf_functions_use_the_red_zone() {
+    let options = CodegenOptions { frame_pointer: false, ..CodegenOptions::default() };
+    let Some(leaf) = disassemble(options, "leaf") else {
+        return;
+    };
+
+    assert!(!leaf.iter().any(|line| line.contains("rbp")), "{:#?}", leaf);
+    assert!(!leaf.iter().any(|line| line.starts_with("sub rsp")), "{:#?}", leaf);
+    assert!(leaf.iter().any(|line| line.contains("[rsp-")), "{:#?}", leaf);
+
+    let leaf = disassemble(CodegenOptions { red_zone: false, ..options }, "leaf").unwrap();
+    assert!(leaf.iter().any(|line| line.starts_with("sub rsp")), "{:#?}", leaf);
+    assert!(!leaf.iter().any(|line| line.contains("[rsp-")), "{:#?}", leaf);
+}
+
+#[test]
+fn every_return_has_an_epilogue() {
+    for options in all_options() {
+        let Some(leaf) = disassemble(options, "leaf") else {
+            return;
+        };
+
+        let returns: Vec<usize> = leaf.iter().enumerate().filter(|(_, line)| *line == "ret").map(|(index, _)| index).collect();
+        assert_eq!(returns.len(), 2, "{:#?}", leaf);
+
+        for index in returns {
+            let before = &leaf[index - 1];
+            if options.frame_pointer {
+                assert_eq!(before, "pop rbp", "{:#?}", leaf);
+            } else if !options.red_zone {
+                assert!(before.starts_with("add rsp"), "{:#?}", leaf);
+            }
+        }
+    }
+}
+
+// gcc checks that rsp is aligned at our calls and that slots get their alignment, skipped when gcc isn't installed
+#[test]
+fn calls_from_and_to_c_see_an_aligned_stack() {
+    let dir = std::env::temp_dir().join(format!("chair-frames-gcc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.c"), r#"
+long entry(long);
+
+// the frame address is rsp after pushing rbp, so 16-byte aligned when rsp was at the call
+long aligned(long a, long b, long c, long d, long e, long f, long g, long h, long *slot) {
+    if ((unsigned long)__builtin_frame_address(0) % 16 != 0 || (unsigned long)slot % 16 != 0) {
+        return -1000;
+    }
+    *slot = a + b + c + d + e + f + g + h;
+    return 0;
+}
+
+int main(void) {
+    return entry(1) == 36 ? 42 : 1;
+}
+"#).unwrap();
+
+    let source = r#"
+unit "entry"
+
+declare @aligned(i64, i64, i64, i64, i64, i64, i64, i64, ptr) -> i64
+
+fn @entry(%0: i64) -> i64 {
+    $0 = slot i8, align 1
+    $1 = slot i64, align 16
+bb0:
+    %1 = stack_addr $1
+    %2 = call i64 @aligned(%0, i64 2, i64 3, i64 4, i64 5, i64 6, i64 7, i64 8, %1)
+    %3 = load i64, %1, align 16
+    %4 = add i64 %2, %3
+    ret %4
+}
+"#;
+
+    for options in all_options() {
+        let object = CompilerX64Elf::with_options(options).compile_translation_unit(parse_translation_unit(source).unwrap()).unwrap();
+        object.write_to_file(dir.join("entry.o")).unwrap();
+
+        let compiled = Command::new("gcc").args(["-O0", "-no-pie", "-Wl,-z,noexecstack"])
+            .arg(dir.join("main.c")).arg(dir.join("entry.o")).arg("-o").arg(dir.join("main")).status();
+        if !compiled.is_ok_and(|status| status.success()) {
+            break;
+        }
+
+        let status = Command::new(dir.join("main")).status().unwrap();
+        assert_eq!(status.code(), Some(42), "misaligned with {:?}", options);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,94 @@
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::elf::*;
+use chair::outputs::serialization::Serializable;
+
+const SOURCE: &str = r#"
+unit "globals"
+
+global @flag: i8 = i8 1
+global @count: i64 = i64 -2
+global @zeroed: [3 x i16]
+global @explicit_zero: i32 = i32 0
+global @wide: i64
+const @table: [2 x i32] = [2 x i32] [7, 8]
+const @nothing: i64 = i64 0
+"#;
+
+fn compile() -> ParsedElf {
+    let object = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(SOURCE).unwrap()).unwrap();
+    ElfFile::parse(&object.serialize(false)).unwrap()
+}
+
+// the section `name` is in and its offset there
+fn placement<'a>(object: &'a ParsedElf, name: &str) -> (&'a str, &'a ElfSymbol) {
+    let symbol = &object.symbols.iter().find(|symbol| symbol.name == name).unwrap().symbol;
+    (&object.sections[symbol.st_shndx as usize].name, symbol)
+}
+
+#[test]
+fn globals_go_to_the_section_matching_their_contents() {
+    let object = compile();
+
+    let expected = [
+        ("flag", ".data"),
+        ("count", ".data"),
+        ("zeroed", ".bss"),
+        ("explicit_zero", ".bss"),
+        ("wide", ".bss"),
+        ("table", ".rodata"),
+        ("nothing", ".rodata")
+    ];
+    for (name, section) in expected {
+        let (found, symbol) = placement(&object, name);
+        assert_eq!(found, section, "@{} is in the wrong section", name);
+        assert_eq!(symbol.binding(), STB_GLOBAL);
+        assert_eq!(symbol.sym_type(), STT_OBJECT);
+    }
+}
+
+#[test]
+fn sections_have_the_right_flags() {
+    let object = compile();
+
+    let data = &object.section(".data").unwrap().header;
+    assert_eq!(data.sh_type, SHT_PROGBITS);
+    assert_eq!(data.sh_flags, SHF_WRITE | SHF_ALLOC);
+
+    let bss = &object.section(".bss").unwrap().header;
+    assert_eq!(bss.sh_type, SHT_NOBITS);
+    assert_eq!(bss.sh_flags, SHF_WRITE | SHF_ALLOC);
+
+    let rodata = &object.section(".rodata").unwrap().header;
+    assert_eq!(rodata.sh_type, SHT_PROGBITS);
+    assert_eq!(rodata.sh_flags, SHF_ALLOC);
+}
+
+#[test]
+fn globals_are_aligned_and_initialized() {
+    let object = compile();
+
+    let contents = |name: &str, size: u64, align: u64| {
+        let (section, symbol) = placement(&object, name);
+        assert_eq!(symbol.st_size, size, "size of @{}", name);
+        assert_eq!(symbol.st_value % align, 0, "@{} is misaligned", name);
+        object.section(section).unwrap().contents.get(symbol.st_value as usize..(symbol.st_value + size) as usize).map(<[u8]>::to_vec)
+    };
+
+    assert_eq!(contents("flag", 1, 1).unwrap(), [1]);
+    assert_eq!(contents("count", 8, 8).unwrap(), (-2i64).to_le_bytes());
+    assert_eq!(contents("table", 8, 4).unwrap(), [7, 0, 0, 0, 8, 0, 0, 0]);
+    assert_eq!(contents("nothing", 8, 8).unwrap(), [0; 8]);
+
+    // .bss has a size but nothing in the file
+    contents("zeroed", 6, 2);
+    contents("explicit_zero", 4, 4);
+    contents("wide", 8, 8);
+
+    let bss = &object.section(".bss").unwrap();
+    assert!(bss.contents.is_empty());
+    assert_eq!(bss.header.sh_size, 24);
+    assert_eq!(bss.header.sh_addralign, 8);
+    assert_eq!(object.section(".data").unwrap().header.sh_addralign, 8);
+}
@@ -0,0 +1,133 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use std::fmt::Write;
+use std::process::Command;
+use chair::codegen::{Codegen, CodegenOptions, RegisterAllocator};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::opt::OptLevel;
+use chair::ir::text::parser::parse_translation_unit;
+
+// the intel syntax disassembly of `function` compiled with `register_allocator` as addresses and instructions,
+// None when objdump isn't installed
+fn disassemble(source: &str, function: &str, register_allocator: RegisterAllocator) -> Option<Vec<(u64, String)>> {
+    let mut compiler = CompilerX64Elf::with_options(CodegenOptions { register_allocator, ..CodegenOptions::default() });
+    let object = compiler.compile_translation_unit(parse_translation_unit(source).unwrap()).unwrap();
+    let path = std::env::temp_dir().join(format!("chair-coloring-{}-{:?}-{}.o", function, register_allocator, std::process::id()));
+    object.write_to_file(&path).unwrap();
+
+    let output = Command::new("objdump").args(["-d", "-M", "intel", "--no-show-raw-insn"]).arg(&path).output();
+    std::fs::remove_file(&path).unwrap();
+    let output = output.ok().filter(|output| output.status.success())?;
+
+    let header = format!("<{}>:", function);
+    Some(String::from_utf8(output.stdout).unwrap().lines()
+        .skip_while(|line| !line.ends_with(&header))
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .map(|line| {
+            let (address, instruction) = line.split_once(":\t").unwrap();
+            (u64::from_str_radix(address.trim(), 16).unwrap(), instruction.split_whitespace().collect::<Vec<_>>().join(" "))
+        })
+        .collect())
+}
+
+#[test]
+fn optimized_builds_color_the_graph() {
+    assert_eq!(CodegenOptions::for_level(OptLevel::O0).register_allocator, RegisterAllocator::LinearScan);
+    assert_eq!(CodegenOptions::for_level(OptLevel::O1).register_allocator, RegisterAllocator::LinearScan);
+    assert_eq!(CodegenOptions::for_level(OptLevel::O2).register_allocator, RegisterAllocator::GraphColoring);
+    assert_eq!(CodegenOptions::default().register_allocator, RegisterAllocator::LinearScan);
+}
+
+#[test]
+fn casts_coalesce_into_the_argument_register() {
+    let source = r#"
+unit "widen"
+
+declare @h(i64) -> void
+
+fn @widen(%0: i8) -> void {
+bb0:
+    %1 = sext %0 to i32
+    %2 = zext %1 to i64
+    call void @h(%2)
+    ret
+}
+"#;
+    let Some(lines) = disassemble(source, "widen", RegisterAllocator::GraphColoring) else {
+        return;
+    };
+
+    // moves between two different registers, where `mov edi,edi` is the zero extension itself
+    let copies: Vec<&String> = lines.iter()
+        .map(|(_, instruction)| instruction)
+        .filter(|instruction| match instruction.strip_prefix("mov ").and_then(|operands| operands.split_once(',')) {
+            Some((dst, src)) => dst != src && !src.contains('[') && !src.starts_with("0x") && !dst.contains('[') && *instruction != "mov rbp,rsp",
+            None => false
+        })
+        .collect();
+    assert!(copies.is_empty(), "register copies left in {:#?}", lines);
+}
+
+#[test]
+fn loops_keep_their_values_in_registers() {
+    // two products used inside the loop outlive twelve used only after it, which linear scan spills last-ending first
+    let mut source = "unit \"hot\"\n\nfn @hot(%0: i64) -> i64 {\n    $0 = slot i64, align 8\nbb0:\n    %1 = stack_addr $0\n    store i64 0, %1, align 8\n".to_owned();
+    for k in 2..16 {
+        writeln!(source, "    %{} = mul i64 %0, i64 {}", k, k + 1).unwrap();
+    }
+    source.push_str("    jmp bb1\nbb1:\n    %16 = load i64, %1, align 8\n    %17 = add i64 %16, %2\n    %18 = xor i64 %17, %3\n");
+    source.push_str("    store %18, %1, align 8\n    %19 = icmp slt %18, i64 1000\n    br %19, bb1, bb2\nbb2:\n");
+    let mut acc = 18;
+    for (next, value) in (20..).zip((4..16).chain(2..4)) {
+        writeln!(source, "    %{} = add i64 %{}, %{}", next, acc, value).unwrap();
+        acc = next;
+    }
+    writeln!(source, "    ret %{}\n}}", acc).unwrap();
+
+    for (register_allocator, spills_in_loop) in [(RegisterAllocator::LinearScan, true), (RegisterAllocator::GraphColoring, false)] {
+        let Some(lines) = disassemble(&source, "hot", register_allocator) else {
+            return;
+        };
+
+        // the loop runs from the backward branch's target to the branch
+        let (end, branch) = lines.iter().find(|(_, instruction)| instruction.starts_with("jne ")).unwrap();
+        let start = u64::from_str_radix(branch.split_whitespace().nth(1).unwrap(), 16).unwrap();
+        assert!(start < *end);
+
+        let spills = lines.iter()
+            .filter(|(address, _)| (start..=*end).contains(address))
+            .any(|(_, instruction)| instruction.contains("[rbp-"));
+        assert_eq!(spills, spills_in_loop, "{:?} gave {:#?}", register_allocator, lines);
+    }
+}
+
+#[test]
+fn spilled_ints_and_floats_dont_share_slots() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // %1 and the seven sums are all live across the call, which clobbers every xmm and more gprs than are left
+    let mut source = "unit \"mixed\"\n\nfn @nop() -> void {\nbb0:\n    ret\n}\n\nfn @mixed() -> i64 {\n    $0 = slot f64, align 8\nbb0:\n    %0 = stack_addr $0\n    store f64 1.5, %0, align 8\n    %1 = load f64, %0, align 8\n".to_owned();
+    for k in 2..9 {
+        writeln!(source, "    %{} = add i64 i64 {}, i64 0", k, k - 1).unwrap();
+    }
+    source.push_str("    call void @nop()\n    store %1, %0, align 8\n    %9 = load i64, %0, align 8\n    %10 = icmp eq %9, i64 4609434218613702656\n");
+    source.push_str("    %11 = zext %10 to i64\n    %12 = mul i64 %11, i64 14\n");
+    let mut acc = 12;
+    for k in 2..9 {
+        writeln!(source, "    %{} = add i64 %{}, %{}", acc + 1, acc, k).unwrap();
+        acc += 1;
+    }
+    writeln!(source, "    ret %{}\n}}\n\nfn @_start() -> void {{\nbb0:\n    %0 = call i64 @mixed()\n    asm \"48 89 c7 b8 3c 00 00 00 0f 05\"\n    ret\n}}", acc).unwrap();
+
+    for register_allocator in [RegisterAllocator::LinearScan, RegisterAllocator::GraphColoring] {
+        let mut compiler = CompilerX64Elf::with_options(CodegenOptions { register_allocator, ..CodegenOptions::default() });
+        let path = std::env::temp_dir().join(format!("chair-coloring-mixed-{:?}-{}", register_allocator, std::process::id()));
+        compiler.compile_executable(parse_translation_unit(&source).unwrap(), "_start").unwrap().write_to_file(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let status = Command::new(&path).status().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(status.code(), Some(42), "wrong result with {:?}", register_allocator);
+    }
+}
@@ -0,0 +1,65 @@
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::elf::ElfFile;
+use chair::outputs::inspect::describe;
+use chair::outputs::serialization::Serializable;
+
+const SOURCE: &str = r#"
+unit "inspect"
+
+declare @puts(ptr) -> i32
+
+const @message: [3 x i8] = [3 x i8] c"hi\00"
+
+fn @main() -> i32 {
+bb0:
+    %0 = global_addr @message
+    %1 = call i32 @puts(%0)
+    ret %1
+}
+"#;
+
+fn inspect() -> String {
+    let translation_unit = parse_translation_unit(SOURCE).unwrap();
+    let bytes = CompilerX64Elf::new().compile_translation_unit(translation_unit).unwrap().serialize(false);
+    describe(&ElfFile::parse(&bytes).unwrap())
+}
+
+#[test]
+fn header_and_sections_are_decoded() {
+    let output = inspect();
+
+    assert!(output.contains("type:                REL (relocatable file)"));
+    assert!(output.contains("machine:             x86-64"));
+    assert!(output.lines().any(|line| line.contains(".text") && line.contains("PROGBITS") && line.contains(" AX ")));
+    assert!(output.lines().any(|line| line.contains(".rela.text") && line.contains("RELA")));
+}
+
+#[test]
+fn symbols_show_binding_type_and_visibility() {
+    let output = inspect();
+
+    let main = output.lines().find(|line| line.ends_with(" main")).unwrap();
+    assert!(main.contains("FUNC    GLOBAL DEFAULT"));
+
+    let puts = output.lines().find(|line| line.ends_with(" puts")).unwrap();
+    assert!(puts.contains("NOTYPE  GLOBAL DEFAULT   UND"));
+}
+
+#[test]
+fn relocations_name_their_symbols() {
+    let output = inspect();
+
+    assert!(output.lines().any(|line| line.contains("R_X86_64_PC32") && line.ends_with(" message - 4")));
+    assert!(output.lines().any(|line| line.contains("R_X86_64_PLT32") && line.ends_with(" puts - 4")));
+}
+
+#[test]
+fn contents_are_hex_dumped() {
+    let output = inspect();
+    let rodata = output.split("Contents of .rodata:\n").nth(1).unwrap();
+
+    assert!(rodata.starts_with("  00000000  68 69 00"));
+    assert!(rodata.lines().next().unwrap().ends_with("|hi.|"));
+}
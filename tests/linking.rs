@@ -0,0 +1,270 @@
+use chair::ChairError;
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::linking::Linker;
+use chair::outputs::elf::*;
+use chair::outputs::serialization::Serializable;
+
+// exits with value() + counter, where both live in the other object
+const MAIN: &str = r#"
+unit "main"
+
+declare @value() -> i64
+
+fn @_start() -> void {
+bb0:
+    %0 = call i64 @value()
+    asm "48 89 c7 b8 3c 00 00 00 0f 05"
+    ret
+}
+"#;
+
+const LIBRARY: &str = r#"
+unit "library"
+
+global @counter: i64 = i64 40
+
+fn @value() -> i64 {
+bb0:
+    %0 = global_addr @counter
+    %1 = load i64, %0, align 8
+    %2 = add i64 %1, i64 2
+    ret %2
+}
+"#;
+
+fn object(source: &str) -> ParsedElf {
+    let elf = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(source).unwrap()).unwrap();
+    ElfFile::parse(&elf.serialize(false)).unwrap()
+}
+
+fn link(objects: Vec<(&str, ParsedElf)>, entry: &str) -> Result<ParsedElf, ChairError> {
+    let mut linker = Linker::new();
+
+    for (name, elf) in objects {
+        linker.add_object(name, elf)?;
+    }
+
+    Ok(ElfFile::parse(&linker.link(entry)?.serialize(false)).unwrap())
+}
+
+// an object defining `name` in .text with the given binding
+fn definition(name: &str, binding: u8, byte: u8) -> ParsedElf {
+    let mut builder = ElfObjectBuilder::new(EM_X86_64);
+    let text = builder.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, vec![byte; 4]);
+    builder.add_symbol(Some(name), Some(text), 0, 4, binding, STT_FUNC);
+
+    ElfFile::parse(&builder.build().serialize(false)).unwrap()
+}
+
+// an object whose _start refers to `name` through an absolute relocation in .data
+fn reference(name: &str, binding: u8) -> ParsedElf {
+    let mut builder = ElfObjectBuilder::new(EM_X86_64);
+    let text = builder.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, vec![0xC3]);
+    let data = builder.add_section(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 8, vec![0xFF; 8]);
+    builder.add_symbol(Some("_start"), Some(text), 0, 1, STB_GLOBAL, STT_FUNC);
+    let symbol = builder.add_symbol(Some(name), None, 0, 0, binding, STT_NOTYPE);
+    builder.add_relocation(data, 0, symbol, R_X86_64_64, 0);
+
+    ElfFile::parse(&builder.build().serialize(false)).unwrap()
+}
+
+fn symbol_value(elf: &ParsedElf, name: &str) -> u64 {
+    elf.symbols.iter().find(|symbol| symbol.name == name).unwrap().symbol.st_value
+}
+
+fn data_word(elf: &ParsedElf) -> u64 {
+    u64::from_le_bytes(elf.section(".data").unwrap().contents[..8].try_into().unwrap())
+}
+
+#[test]
+fn same_named_sections_are_merged() {
+    let elf = link(vec![("main.o", object(MAIN)), ("library.o", object(LIBRARY))], "_start").unwrap();
+
+    assert_eq!(elf.header.e_type, ET_EXEC);
+    assert_eq!(elf.sections.iter().filter(|section| section.name == ".text").count(), 1);
+    assert_eq!(elf.header.e_entry, symbol_value(&elf, "_start"));
+
+    // the call to value() lands on its definition in the second object
+    let text = elf.section(".text").unwrap();
+    let call = text.contents.iter().position(|&byte| byte == 0xE8).unwrap();
+    let displacement = i32::from_le_bytes(text.contents[call + 1..call + 5].try_into().unwrap());
+    let next = text.header.sh_addr + call as u64 + 5;
+    assert_eq!(next.wrapping_add(displacement as i64 as u64), symbol_value(&elf, "value"));
+}
+
+#[test]
+fn duplicate_definitions_name_both_objects() {
+    let result = link(vec![("a.o", definition("f", STB_GLOBAL, 0x90)), ("b.o", definition("f", STB_GLOBAL, 0xCC))], "f");
+
+    match result {
+        Err(ChairError::DuplicateSymbol { symbol, objects }) => {
+            assert_eq!(symbol, "f");
+            assert_eq!(objects, ["a.o", "b.o"]);
+        }
+        other => panic!("expected a duplicate symbol, got {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn undefined_symbols_name_the_referencing_object() {
+    match link(vec![("user.o", reference("missing", STB_GLOBAL))], "_start") {
+        Err(ChairError::UndefinedSymbol { symbol, objects }) => {
+            assert_eq!(symbol, "missing");
+            assert_eq!(objects, ["user.o"]);
+        }
+        other => panic!("expected an undefined symbol, got {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn strong_definitions_override_weak_ones() {
+    for objects in [
+        vec![("weak.o", definition("f", STB_WEAK, 0x90)), ("strong.o", definition("f", STB_GLOBAL, 0xCC)), ("user.o", reference("f", STB_GLOBAL))],
+        vec![("strong.o", definition("f", STB_GLOBAL, 0xCC)), ("weak.o", definition("f", STB_WEAK, 0x90)), ("user.o", reference("f", STB_GLOBAL))]
+    ] {
+        let elf = link(objects, "_start").unwrap();
+        let text = elf.section(".text").unwrap();
+        let offset = (symbol_value(&elf, "f") - text.header.sh_addr) as usize;
+
+        assert_eq!(text.contents[offset..offset + 4], [0xCC; 4]);
+        assert_eq!(data_word(&elf), symbol_value(&elf, "f"));
+    }
+}
+
+#[test]
+fn undefined_weak_references_resolve_to_zero() {
+    let elf = link(vec![("user.o", reference("optional", STB_WEAK))], "_start").unwrap();
+
+    assert_eq!(data_word(&elf), 0);
+}
+
+#[test]
+fn non_relocatable_inputs_are_rejected() {
+    let executable = link(vec![("main.o", object(MAIN)), ("library.o", object(LIBRARY))], "_start").unwrap();
+
+    let mut linker = Linker::new();
+    assert!(matches!(linker.add_object("a.out", executable), Err(ChairError::InvalidObject { object, .. }) if object == "a.out"));
+}
+
+#[test]
+fn rel_and_thread_local_sections_are_rejected() {
+    let with_section = |name: &str, sh_type: u32, flags: u64| {
+        let mut builder = ElfObjectBuilder::new(EM_X86_64);
+        builder.add_section(name, sh_type, flags, 8, vec![0; 8]);
+        ElfFile::parse(&builder.build().serialize(false)).unwrap()
+    };
+
+    let mut linker = Linker::new();
+    let rel = linker.add_object("rel.o", with_section(".rel.text", SHT_REL, 0));
+    assert!(matches!(rel, Err(ChairError::InvalidObject { object, .. }) if object == "rel.o"));
+
+    let tls = linker.add_object("tls.o", with_section(".tdata", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE | SHF_TLS));
+    assert!(matches!(tls, Err(ChairError::Unsupported { feature, .. }) if feature == "thread-local section .tdata in tls.o"));
+
+    linker.add_object("data.o", with_section(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE)).unwrap();
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn linked_objects_run() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    let mut linker = Linker::new();
+    linker.add_object("main.o", object(MAIN)).unwrap();
+    linker.add_object("library.o", object(LIBRARY)).unwrap();
+
+    let path = std::env::temp_dir().join(format!("chair-linking-{}", std::process::id()));
+    linker.link("_start").unwrap().write_to_file(&path).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let status = Command::new(&path).status().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(status.code(), Some(42));
+}
+
+// objects from other compilers link too, skipped when gcc isn't installed
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn gcc_objects_link() {
+    use std::process::Command;
+
+    let dir = std::env::temp_dir().join(format!("chair-gcc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("value.c"), "static long table[] = {1, 2, 30};\nlong bias = 10;\nlong value(void) { return table[2] + bias; }\n").unwrap();
+
+    for flags in [["-fno-pic", "-O0"], ["-fpic", "-O2"]] {
+        let compiled = Command::new("gcc").args(["-c", "-ffreestanding"]).args(flags)
+            .arg(dir.join("value.c")).arg("-o").arg(dir.join("value.o")).status();
+        if !compiled.is_ok_and(|status| status.success()) {
+            break;
+        }
+
+        let gcc_object = ElfFile::parse(&std::fs::read(dir.join("value.o")).unwrap()).unwrap();
+        let elf = link(vec![("main.o", object(MAIN)), ("value.o", gcc_object)], "_start").unwrap();
+
+        assert!(elf.symbols.iter().any(|symbol| symbol.name == "value" && symbol.symbol.st_shndx as usize == elf.section_index(".text").unwrap()));
+        assert_eq!(elf.sections.iter().filter(|section| section.name == ".data").count(), 1);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// position independent objects link into PIE executables and, loading globals from the GOT, shared libraries
+// whose globals the executable copies. Skipped when gcc isn't installed.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn gcc_links_position_independent_objects() {
+    use std::process::Command;
+    use chair::codegen::{Addressing, CodegenOptions};
+
+    let source = r#"
+unit "bump"
+
+global @counter: i64 = i64 3
+
+fn @bump(%0: i64) -> i64 {
+bb0:
+    %1 = global_addr @counter
+    %2 = load i64, %1, align 8
+    %3 = add i64 %2, %0
+    store %3, %1, align 8
+    switch %0, bb1 [0: bb2, 1: bb3, 2: bb2, 3: bb3]
+bb1:
+    ret %3
+bb2:
+    %4 = load i64, ref i64 100, align 8
+    %5 = add i64 %3, %4
+    ret %5
+bb3:
+    ret i64 7
+}
+"#;
+
+    let dir = std::env::temp_dir().join(format!("chair-pic-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.c"), "long bump(long);\nextern long counter;\nint main(void) {\n    long a = bump(0), b = bump(1), c = bump(5);\n    return a == 103 && b == 7 && c == 9 && counter == 9 ? 42 : 1;\n}\n").unwrap();
+
+    let compile = |addressing| {
+        let mut compiler = CompilerX64Elf::with_options(CodegenOptions { addressing, ..CodegenOptions::default() });
+        compiler.compile_translation_unit(parse_translation_unit(source).unwrap()).unwrap().write_to_file(dir.join("bump.o")).unwrap();
+    };
+    let gcc = |args: &[&str]| {
+        Command::new("gcc").current_dir(&dir).arg("-Wl,-z,noexecstack").args(args).status().is_ok_and(|status| status.success())
+    };
+
+    compile(Addressing::RipRelative);
+    if gcc(&["-pie", "-fPIE", "main.c", "bump.o", "-o", "pie"]) {
+        assert_eq!(Command::new(dir.join("pie")).status().unwrap().code(), Some(42));
+
+        compile(Addressing::Got);
+        assert!(gcc(&["-shared", "bump.o", "-o", "libbump.so"]));
+        assert!(gcc(&["main.c", "-L.", "-lbump", "-Wl,-rpath,$ORIGIN", "-o", "dynamic"]));
+        assert_eq!(Command::new(dir.join("dynamic")).status().unwrap().code(), Some(42));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
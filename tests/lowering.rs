@@ -0,0 +1,544 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use std::fmt::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chair::ChairError;
+use chair::codegen::{Addressing, Codegen, CodegenOptions};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::opt::OptLevel;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::elf::ElfFile;
+use chair::outputs::serialization::Serializable;
+
+// the kernel starts us with rsp 16-byte aligned instead of as if we'd been called, so it's realigned for @main
+const START: &str = r#"
+unit "lowering"
+
+fn @_start() -> void {
+bb0:
+    asm "48 83 e4 f0"
+    %0 = call i64 @main()
+    asm "48 89 c7 b8 3c 00 00 00 0f 05"
+    ret
+}
+"#;
+
+// @main makes each call in turn, returning 0 when they all give what's expected and otherwise the number of the first that didn't
+fn checks(ty: &str, cases: &[(&str, i64)]) -> String {
+    let mut main = "fn @main() -> i64 {\n".to_owned();
+    for (i, (call, expected)) in cases.iter().enumerate() {
+        writeln!(main, "bb{}:\n    %{} = call {} {}", i, 2 * i, ty, call).unwrap();
+        writeln!(main, "    %{} = icmp ne %{}, {} {}", 2 * i + 1, 2 * i, ty, expected).unwrap();
+        writeln!(main, "    br %{}, bb{}, bb{}", 2 * i + 1, cases.len() + 1 + i, i + 1).unwrap();
+    }
+    writeln!(main, "bb{}:\n    ret i64 0", cases.len()).unwrap();
+    for i in 0..cases.len() {
+        writeln!(main, "bb{}:\n    ret i64 {}", cases.len() + 1 + i, i + 1).unwrap();
+    }
+    main.push_str("}\n");
+    main
+}
+
+// the exit code of `functions` with @main run as a static executable
+fn run_with(functions: &str, options: CodegenOptions) -> i32 {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let source = format!("{}\n{}", START, functions);
+
+    let path = std::env::temp_dir().join(format!("chair-lowering-{}-{}", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed)));
+    let elf = CompilerX64Elf::with_options(options).compile_executable(parse_translation_unit(&source).unwrap(), "_start").unwrap();
+    elf.write_to_file(&path).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let status = Command::new(&path).status().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    status.code().unwrap_or(-1)
+}
+
+// exit codes with the default and the optimizing codegen options
+fn run(functions: &str) -> Vec<i32> {
+    [CodegenOptions::default(), CodegenOptions::for_level(OptLevel::O2)].into_iter().map(|options| run_with(functions, options)).collect()
+}
+
+// the loop's blocks are numbered against their layout, so jumps go both forwards and backwards
+const SUM: &str = r#"
+fn @sum(%0: i64) -> i64 {
+    $0 = slot i64, align 8
+    $1 = slot i64, align 8
+bb0:
+    %1 = stack_addr $0
+    %2 = stack_addr $1
+    store i64 0, %1, align 8
+    store i64 1, %2, align 8
+    jmp bb3
+bb1:
+    %3 = load i64, %1, align 8
+    ret %3
+bb2:
+    %4 = load i64, %1, align 8
+    %5 = load i64, %2, align 8
+    %6 = add i64 %4, %5
+    store %6, %1, align 8
+    %7 = add i64 %5, i64 1
+    store %7, %2, align 8
+    jmp bb3
+bb3:
+    %8 = load i64, %2, align 8
+    %9 = icmp sle %8, %0
+    br %9, bb2, bb1
+}
+
+fn @max(%0: i64, %1: i64) -> i64 {
+bb0:
+    %2 = icmp sgt %0, %1
+    br %2, bb1, bb2
+bb1:
+    ret %0
+bb2:
+    ret %1
+}
+"#;
+
+// @far branches over enough code that the displacements can't be 8 bits
+fn far() -> String {
+    let mut far = "fn @far(%0: i64) -> i64 {\nbb0:\n    %1 = icmp eq %0, i64 0\n    br %1, bb2, bb1\nbb1:\n    %2 = add i64 %0, i64 1\n".to_owned();
+    for k in 3..100 {
+        writeln!(far, "    %{} = mul i64 %{}, i64 3", k, k - 1).unwrap();
+    }
+    far.push_str("    jmp bb3\nbb2:\n    jmp bb1\nbb3:\n    ret %99\n}\n");
+    far
+}
+
+#[test]
+fn jumps_and_branches_reach_their_blocks() {
+    let cases = [
+        ("@sum(i64 0)", 0),
+        ("@sum(i64 1)", 1),
+        ("@sum(i64 10)", 55),
+        ("@max(i64 3, i64 -4)", 3),
+        ("@max(i64 -4, i64 3)", 3),
+        ("@far(i64 0)", 3i64.wrapping_pow(97)),
+        ("@far(i64 1)", 3i64.wrapping_pow(97).wrapping_mul(2))
+    ];
+
+    let source = format!("{}{}{}", SUM, far(), checks("i64", &cases));
+    assert_eq!(run(&source), [0, 0]);
+}
+
+#[test]
+fn block_references_are_resolved_in_the_object() {
+    let object = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(&format!("unit \"jumps\"\n{}{}", SUM, far())).unwrap()).unwrap();
+    let object = ElfFile::parse(&object.serialize(false)).unwrap();
+
+    assert!(object.section(".rela.text").is_none_or(|section| section.relocations.is_empty()));
+}
+
+// @dense and @small get jump tables, @sparse compares against each case
+const SWITCHES: &str = r#"
+fn @dense(%0: i64) -> i64 {
+bb0:
+    switch %0, bb1 [-2: bb2, -1: bb3, 0: bb4, 2: bb2, 3: bb5]
+bb1:
+    ret i64 100
+bb2:
+    ret i64 1
+bb3:
+    ret i64 2
+bb4:
+    ret i64 3
+bb5:
+    ret i64 4
+}
+
+fn @sparse(%0: i64) -> i64 {
+bb0:
+    switch %0, bb1 [0: bb2, 1000: bb3, 1099511627776: bb4]
+bb1:
+    ret i64 100
+bb2:
+    ret i64 1
+bb3:
+    ret i64 2
+bb4:
+    ret i64 3
+}
+
+fn @small(%0: i8) -> i64 {
+bb0:
+    switch %0, bb1 [-1: bb2, 0: bb3, 1: bb2, 2: bb3]
+bb1:
+    ret i64 100
+bb2:
+    ret i64 1
+bb3:
+    ret i64 2
+}
+"#;
+
+#[test]
+fn switches_jump_to_the_matching_case() {
+    let cases = [
+        ("@dense(i64 -3)", 100),
+        ("@dense(i64 -2)", 1),
+        ("@dense(i64 -1)", 2),
+        ("@dense(i64 0)", 3),
+        ("@dense(i64 1)", 100),
+        ("@dense(i64 2)", 1),
+        ("@dense(i64 3)", 4),
+        ("@dense(i64 4)", 100),
+        ("@dense(i64 4294967295)", 100),
+        ("@dense(i64 -9223372036854775808)", 100),
+        ("@sparse(i64 0)", 1),
+        ("@sparse(i64 1000)", 2),
+        ("@sparse(i64 1099511627776)", 3),
+        ("@sparse(i64 1001)", 100),
+        ("@sparse(i64 1000000)", 100),
+        ("@small(i8 -1)", 1),
+        ("@small(i8 0)", 2),
+        ("@small(i8 2)", 2),
+        ("@small(i8 3)", 100),
+        ("@small(i8 -128)", 100)
+    ];
+
+    let source = format!("{}{}", SWITCHES, checks("i64", &cases));
+    assert_eq!(run(&source), [0, 0]);
+
+    for addressing in [Addressing::Got, Addressing::Absolute] {
+        assert_eq!(run_with(&source, CodegenOptions { addressing, ..CodegenOptions::default() }), 0, "wrong case with {:?}", addressing);
+    }
+}
+
+// @{op}_{ty}(a, b) computes `a op b` in `ty` and returns it sign extended, or zero extended for comparisons
+fn binary(op: &str, ty: &str) -> String {
+    let name = format!("{}_{}", op.replace(' ', "_"), ty);
+    let body = if let Some(cond) = op.strip_prefix("icmp ") {
+        format!("%2 = icmp {} %0, %1\n    %3 = zext %2 to i64\n    ret %3", cond)
+    } else if ty == "i64" {
+        format!("%2 = {} i64 %0, %1\n    ret %2", op)
+    } else {
+        format!("%2 = {} {} %0, %1\n    %3 = sext %2 to i64\n    ret %3", op, ty)
+    };
+    format!("fn @{}(%0: {}, %1: {}) -> i64 {{\nbb0:\n    {}\n}}\n", name, ty, ty, body)
+}
+
+#[test]
+fn integer_arithmetic_wraps_at_its_width() {
+    let cases: &[(&str, &str, i64, i64, i64)] = &[
+        ("add", "i64", 5, -7, -2),
+        ("add", "i64", i64::MAX, 1, i64::MIN),
+        ("sub", "i64", 3, 10, -7),
+        ("mul", "i64", -6, 7, -42),
+        ("sdiv", "i64", -7, 2, -3),
+        ("udiv", "i64", -1, 2, i64::MAX),
+        ("srem", "i64", -7, 2, -1),
+        ("urem", "i64", -1, 10, 5),
+        ("and", "i64", 0b1100, 0b1010, 0b1000),
+        ("or", "i64", 0b1100, 0b1010, 0b1110),
+        ("xor", "i64", 0b1100, 0b1010, 0b0110),
+        ("shl", "i64", 1, 63, i64::MIN),
+        ("lshr", "i64", -1, 60, 15),
+        ("ashr", "i64", -16, 2, -4),
+        ("add", "i32", i32::MAX as i64, 1, i32::MIN as i64),
+        ("mul", "i32", 65536, 65536, 0),
+        ("sdiv", "i32", -7, 2, -3),
+        ("udiv", "i32", -1, 2, i32::MAX as i64),
+        ("urem", "i32", -1, 10, 5),
+        ("shl", "i32", 1, 31, i32::MIN as i64),
+        ("lshr", "i32", -1, 28, 15),
+        ("ashr", "i32", -16, 2, -4),
+        ("mul", "i16", 300, 300, 24464),
+        ("sdiv", "i16", -30000, 7, -4285),
+        ("add", "i8", 127, 1, -128),
+        ("sub", "i8", -128, 1, 127),
+        ("udiv", "i8", -1, 2, 127),
+        ("sdiv", "i8", -128, 2, -64),
+        ("urem", "i8", -56, 7, 4),
+        ("srem", "i8", -56, 7, 0),
+        ("lshr", "i8", -1, 4, 15),
+        ("ashr", "i8", -128, 7, -1),
+        ("icmp eq", "i64", 3, 3, 1),
+        ("icmp ne", "i64", 3, 3, 0),
+        ("icmp slt", "i64", -1, 1, 1),
+        ("icmp ult", "i64", -1, 1, 0),
+        ("icmp sle", "i64", 2, 2, 1),
+        ("icmp ule", "i64", 3, 2, 0),
+        ("icmp sgt", "i64", 1, -1, 1),
+        ("icmp ugt", "i64", 1, -1, 0),
+        ("icmp sge", "i64", -2, -1, 0),
+        ("icmp uge", "i64", -1, -2, 1),
+        ("icmp slt", "i8", -1, 1, 1),
+        ("icmp ult", "i8", -1, 1, 0),
+        ("icmp ugt", "i8", -128, 127, 1),
+        ("icmp sgt", "i32", i32::MIN as i64, 0, 0),
+        ("icmp ult", "i32", i32::MIN as i64, 0, 0),
+        ("icmp ugt", "i32", i32::MIN as i64, 0, 1)
+    ];
+
+    let mut functions = String::new();
+    let mut calls = vec![];
+    for (op, ty, lhs, rhs, expected) in cases {
+        let function = binary(op, ty);
+        if !functions.contains(&function) {
+            functions.push_str(&function);
+        }
+        calls.push((format!("@{}_{}({} {}, {} {})", op.replace(' ', "_"), ty, ty, lhs, ty, rhs), *expected));
+    }
+
+    let checks = checks("i64", &calls.iter().map(|(call, expected)| (call.as_str(), *expected)).collect::<Vec<_>>());
+    assert_eq!(run(&format!("{}{}", functions, checks)), [0, 0]);
+}
+
+const CASTS: &str = r#"
+fn @zext_i8(%0: i8) -> i64 {
+bb0:
+    %1 = zext %0 to i64
+    ret %1
+}
+
+fn @sext_i8(%0: i8) -> i64 {
+bb0:
+    %1 = sext %0 to i64
+    ret %1
+}
+
+fn @sext_i16(%0: i16) -> i64 {
+bb0:
+    %1 = sext %0 to i32
+    %2 = zext %1 to i64
+    ret %2
+}
+
+fn @trunc_i32(%0: i64) -> i64 {
+bb0:
+    %1 = trunc %0 to i32
+    %2 = sext %1 to i64
+    ret %2
+}
+
+fn @trunc_i8(%0: i64) -> i64 {
+bb0:
+    %1 = trunc %0 to i8
+    %2 = zext %1 to i64
+    ret %2
+}
+
+fn @trunc_i1(%0: i64) -> i64 {
+bb0:
+    %1 = trunc %0 to i1
+    %2 = sext %1 to i64
+    ret %2
+}
+"#;
+
+#[test]
+fn casts_extend_and_truncate() {
+    let cases = [
+        ("@zext_i8(i8 -1)", 255),
+        ("@zext_i8(i8 127)", 127),
+        ("@sext_i8(i8 -1)", -1),
+        ("@sext_i8(i8 -128)", -128),
+        ("@sext_i8(i8 127)", 127),
+        ("@sext_i16(i16 -2)", 0xFFFF_FFFE),
+        ("@trunc_i32(i64 4294967295)", -1),
+        ("@trunc_i32(i64 4294967296)", 0),
+        ("@trunc_i32(i64 2147483648)", i32::MIN as i64),
+        ("@trunc_i8(i64 -1)", 255),
+        ("@trunc_i8(i64 257)", 1),
+        ("@trunc_i1(i64 3)", -1),
+        ("@trunc_i1(i64 2)", 0)
+    ];
+
+    assert_eq!(run(&format!("{}{}", CASTS, checks("i64", &cases))), [0, 0]);
+}
+
+const FIELDS: &str = r#"
+global @record: {i8, i64, i16, {i8, i32}} = {i8, i64, i16, {i8, i32}} {-1, 1000000000000, -2, {3, -4}}
+
+fn @field(%0: i64) -> i64 {
+bb0:
+    %1 = global_addr @record
+    switch %0, bb1 [0: bb2, 1: bb3, 2: bb4, 3: bb5, 4: bb6]
+bb1:
+    ret i64 100
+bb2:
+    %2 = gep {i8, i64, i16, {i8, i32}}, %1, i64 0, i64 0
+    %3 = load i8, %2, align 1
+    %4 = sext %3 to i64
+    ret %4
+bb3:
+    %5 = gep {i8, i64, i16, {i8, i32}}, %1, i64 0, i64 1
+    %6 = load i64, %5, align 8
+    ret %6
+bb4:
+    %7 = gep {i8, i64, i16, {i8, i32}}, %1, i64 0, i64 2
+    %8 = load i16, %7, align 2
+    %9 = sext %8 to i64
+    ret %9
+bb5:
+    %10 = gep {i8, i64, i16, {i8, i32}}, %1, i64 0, i64 3, i32 0
+    %11 = load i8, %10, align 1
+    %12 = sext %11 to i64
+    ret %12
+bb6:
+    %13 = gep {i8, i64, i16, {i8, i32}}, %1, i64 0, i64 3, i32 1
+    %14 = load i32, %13, align 4
+    %15 = sext %14 to i64
+    ret %15
+}
+"#;
+
+#[test]
+fn struct_fields_are_read_at_their_offsets() {
+    let cases = [
+        ("@field(i64 0)", -1),
+        ("@field(i64 1)", 1000000000000),
+        ("@field(i64 2)", -2),
+        ("@field(i64 3)", 3),
+        ("@field(i64 4)", -4)
+    ];
+
+    assert_eq!(run(&format!("{}{}", FIELDS, checks("i64", &cases))), [0, 0]);
+}
+
+// narrow arguments are extended by their type, including the two on the stack
+const NARROW_ARGS: &str = r#"
+fn @narrow(%0: i8, %1: i16, %2: i32, %3: i1, %4: i64, %5: i64, %6: i8, %7: i32) -> i64 {
+bb0:
+    %8 = sext %0 to i64
+    %9 = sext %1 to i64
+    %10 = sext %2 to i64
+    %11 = zext %3 to i64
+    %12 = sext %6 to i64
+    %13 = sext %7 to i64
+    %14 = add i64 %8, %9
+    %15 = add i64 %14, %10
+    %16 = add i64 %15, %11
+    %17 = add i64 %16, %4
+    %18 = add i64 %17, %5
+    %19 = add i64 %18, %12
+    %20 = add i64 %19, %13
+    ret %20
+}
+
+fn @last(%0: i64, %1: i64, %2: i64, %3: i64, %4: i64, %5: i64, %6: i64, %7: i64, %8: i64) -> i64 {
+bb0:
+    %9 = sub i64 %8, %6
+    ret %9
+}
+"#;
+
+#[test]
+fn arguments_pass_through_registers_and_the_stack() {
+    let cases = [
+        ("@narrow(i8 -1, i16 -2, i32 -3, i1 true, i64 4, i64 5, i8 -6, i32 -7)", -9),
+        ("@narrow(i8 127, i16 32767, i32 2147483647, i1 false, i64 0, i64 0, i8 -128, i32 -2147483648)", 127 + 32767 - 1 - 128),
+        ("@last(i64 1, i64 2, i64 3, i64 4, i64 5, i64 6, i64 7, i64 8, i64 9)", 2),
+        ("@last(i64 0, i64 0, i64 0, i64 0, i64 0, i64 0, i64 -5, i64 0, i64 -1)", 4)
+    ];
+
+    assert_eq!(run(&format!("{}{}", NARROW_ARGS, checks("i64", &cases))), [0, 0]);
+}
+
+const SLOTS: &str = r#"
+fn @squares(%0: i64) -> i64 {
+    $0 = slot [10 x i64], align 8
+    $1 = slot i64, align 8
+bb0:
+    %1 = stack_addr $0
+    %2 = stack_addr $1
+    store i64 0, %2, align 8
+    jmp bb1
+bb1:
+    %3 = load i64, %2, align 8
+    %4 = gep [10 x i64], %1, i64 0, %3
+    %5 = mul i64 %3, %3
+    store %5, %4, align 8
+    %6 = add i64 %3, i64 1
+    store %6, %2, align 8
+    %7 = icmp slt %6, i64 10
+    br %7, bb1, bb2
+bb2:
+    %8 = gep [10 x i64], %1, i64 0, i64 5
+    %9 = gep i64, %8, %0
+    %10 = load i64, %9, align 8
+    ret %10
+}
+
+fn @records(%0: i32) -> i64 {
+    $0 = slot [4 x {i8, i32, i16}], align 4
+bb0:
+    %1 = stack_addr $0
+    %2 = gep [4 x {i8, i32, i16}], %1, i64 0, %0, i32 0
+    store i8 -1, %2, align 1
+    %3 = gep [4 x {i8, i32, i16}], %1, i64 0, %0, i32 1
+    store i32 -7, %3, align 4
+    %4 = gep [4 x {i8, i32, i16}], %1, i64 0, %0, i32 2
+    store i16 9, %4, align 2
+    %5 = mul i32 %0, i32 12
+    %6 = gep i8, %1, %5
+    %7 = load i8, %6, align 1
+    %8 = add i32 %5, i32 4
+    %9 = gep i8, %1, %8
+    %10 = load i32, %9, align 4
+    %11 = gep i8, %9, i64 4
+    %12 = load i16, %11, align 2
+    %13 = sext %7 to i64
+    %14 = sext %10 to i64
+    %15 = sext %12 to i64
+    %16 = add i64 %13, %14
+    %17 = add i64 %16, %15
+    ret %17
+}
+
+fn @misalignment() -> i64 {
+    $0 = slot i8, align 1
+    $1 = slot i64, align 16
+    $2 = slot i8, align 1
+    $3 = slot i32, align 8
+    $4 = slot ptr, align 8
+bb0:
+    %0 = stack_addr $1
+    %1 = stack_addr $3
+    %2 = stack_addr $4
+    store %0, %2, align 8
+    %3 = load i64, %2, align 8
+    %4 = and i64 %3, i64 15
+    store %1, %2, align 8
+    %5 = load i64, %2, align 8
+    %6 = and i64 %5, i64 7
+    %7 = or i64 %4, %6
+    ret %7
+}
+"#;
+
+#[test]
+fn stack_slots_are_addressed_through_gep() {
+    let cases = [
+        ("@squares(i64 0)", 25),
+        ("@squares(i64 -5)", 0),
+        ("@squares(i64 -1)", 16),
+        ("@squares(i64 4)", 81),
+        ("@records(i32 0)", 1),
+        ("@records(i32 3)", 1),
+        ("@misalignment()", 0)
+    ];
+
+    assert_eq!(run(&format!("{}{}", SLOTS, checks("i64", &cases))), [0, 0]);
+}
+
+#[test]
+fn stack_slots_aligned_past_the_frame_are_unsupported() {
+    let source = "unit \"aligned\"\n\nfn @f() -> void {\n    $0 = slot i64, align 32\nbb0:\n    ret\n}\n";
+    let result = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(source).unwrap());
+
+    assert!(matches!(result, Err(ChairError::Unsupported { feature, .. }) if feature == "a stack slot aligned to 32 bytes"));
+}
+
+#[test]
+fn gep_strides_past_32_bits_are_unsupported() {
+    let source = "unit \"huge\"\n\nfn @f(%0: ptr, %1: i64) -> ptr {\nbb0:\n    %2 = gep [2147483648 x i8], %0, %1\n    ret %2\n}\n";
+    let result = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(source).unwrap());
+
+    assert!(matches!(result, Err(ChairError::Unsupported { feature, .. }) if feature == "a gep stride of 2147483648 bytes"));
+}
@@ -0,0 +1,194 @@
+use chair::ChairError;
+use chair::ir::opt::{optimize, OptLevel};
+use chair::ir::text::parser::parse_translation_unit;
+use chair::ir::verify::verify_translation_unit;
+
+// `function` after the -O1 passes, which have to leave it valid
+fn optimized(function: &str) -> String {
+    let mut translation_unit = parse_translation_unit(&format!("unit \"opt\"\n\n{}", function)).unwrap();
+    verify_translation_unit(&translation_unit).unwrap();
+
+    optimize(&mut translation_unit, OptLevel::O1).unwrap();
+    verify_translation_unit(&translation_unit).unwrap();
+
+    translation_unit.to_string().trim_start_matches("unit \"opt\"\n\n").to_owned()
+}
+
+#[test]
+fn nothing_changes_at_o0() {
+    let source = "unit \"opt\"\n\nfn @f() -> i64 {\nbb0:\n    %0 = add i64 i64 1, i64 2\n    br i1 true, bb1, bb2\nbb1:\n    ret %0\nbb2:\n    ret i64 0\n}\n";
+    let mut translation_unit = parse_translation_unit(source).unwrap();
+
+    optimize(&mut translation_unit, OptLevel::O0).unwrap();
+    assert_eq!(translation_unit.to_string(), source);
+}
+
+// fold_constants
+#[test]
+fn constants_fold_at_their_width() {
+    let function = r#"fn @fold(%0: ptr) -> void {
+bb0:
+    %1 = add i64 i64 40, i64 2
+    %2 = mul i64 %1, i64 3
+    %3 = add i8 i8 100, i8 100
+    %4 = sext %3 to i64
+    %5 = zext %3 to i64
+    %6 = icmp slt %3, i8 0
+    %7 = icmp ult %3, i8 0
+    %8 = ashr i8 i8 128, i8 7
+    %9 = lshr i8 i8 128, i8 7
+    %10 = udiv i32 i32 -1, i32 2
+    %11 = trunc %2 to i8
+    store %2, %0, align 8
+    store %4, %0, align 8
+    store %5, %0, align 8
+    store %6, %0, align 1
+    store %7, %0, align 1
+    store %8, %0, align 1
+    store %9, %0, align 1
+    store %10, %0, align 4
+    store %11, %0, align 1
+    ret
+}
+"#;
+
+    assert_eq!(optimized(function), r#"fn @fold(%0: ptr) -> void {
+bb0:
+    store i64 126, %0, align 8
+    store i64 -56, %0, align 8
+    store i64 200, %0, align 8
+    store i1 true, %0, align 1
+    store i1 false, %0, align 1
+    store i8 255, %0, align 1
+    store i8 1, %0, align 1
+    store i32 2147483647, %0, align 4
+    store i8 126, %0, align 1
+    ret
+}
+"#);
+}
+
+// fold_constants leaves what would trap at runtime alone
+#[test]
+fn trapping_division_is_not_folded() {
+    let function = r#"fn @trap(%0: i64) -> i64 {
+bb0:
+    %1 = sdiv i64 %0, i64 0
+    %2 = udiv i64 i64 1, i64 0
+    %3 = srem i64 i64 -9223372036854775808, i64 -1
+    %4 = add i64 %2, %3
+    ret %4
+}
+"#;
+
+    assert_eq!(optimized(function), function);
+}
+
+// fold_branches, with every block still reachable so nothing else changes
+#[test]
+fn branches_and_switches_on_constants_become_jumps() {
+    let function = r#"fn @branches(%0: i64) -> i64 {
+bb0:
+    %1 = icmp eq i64 1, i64 2
+    br %1, bb3, bb1
+bb1:
+    %2 = trunc i64 255 to i8
+    switch %2, bb3 [1: bb3, -1: bb2]
+bb2:
+    switch i64 7, bb3 [1: bb1, 2: bb2]
+bb3:
+    br %1, bb3, bb4
+bb4:
+    switch %0, bb1 [0: bb4]
+}
+"#;
+
+    assert_eq!(optimized(function), r#"fn @branches(%0: i64) -> i64 {
+bb0:
+    jmp bb1
+bb1:
+    jmp bb2
+bb2:
+    jmp bb3
+bb3:
+    jmp bb4
+bb4:
+    switch %0, bb1 [0: bb4]
+}
+"#);
+}
+
+// remove_unreachable_blocks. Valid IR only jumps to an unreachable block once a branch has been folded
+#[test]
+fn unreachable_blocks_are_removed_and_the_rest_renumbered() {
+    let function = r#"fn @reachable(%0: i1) -> i64 {
+bb0:
+    br i1 true, bb2, bb1
+bb1:
+    jmp bb3
+bb2:
+    br %0, bb4, bb6
+bb3:
+    jmp bb1
+bb4:
+    ret i64 4
+bb5:
+    ret i64 5
+bb6:
+    jmp bb4
+}
+"#;
+
+    assert_eq!(optimized(function), r#"fn @reachable(%0: i1) -> i64 {
+bb0:
+    jmp bb1
+bb1:
+    br %0, bb2, bb3
+bb2:
+    ret i64 4
+bb3:
+    jmp bb2
+}
+"#);
+}
+
+// compact_values, filling the holes left by folded values and removed blocks
+#[test]
+fn values_are_renumbered_densely() {
+    let function = r#"fn @compact(%0: i64, %1: i64) -> i64 {
+bb0:
+    %2 = add i64 i64 1, i64 2
+    %3 = add i64 %0, %2
+    jmp bb2
+bb1:
+    %4 = mul i64 %3, %3
+    ret %4
+bb2:
+    %5 = sub i64 %3, %1
+    %6 = icmp sgt %5, %2
+    br %6, bb3, bb3
+bb3:
+    ret %5
+}
+"#;
+
+    assert_eq!(optimized(function), r#"fn @compact(%0: i64, %1: i64) -> i64 {
+bb0:
+    %2 = add i64 %0, i64 3
+    jmp bb1
+bb1:
+    %3 = sub i64 %2, %1
+    %4 = icmp sgt %3, i64 3
+    br %4, bb2, bb2
+bb2:
+    ret %3
+}
+"#);
+}
+
+#[test]
+fn unverified_units_are_rejected() {
+    let mut translation_unit = parse_translation_unit("unit \"opt\"\n\nfn @f() -> void {\n}\n").unwrap();
+
+    assert!(matches!(optimize(&mut translation_unit, OptLevel::O1), Err(ChairError::Verify(_))));
+}
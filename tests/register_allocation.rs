@@ -0,0 +1,316 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use std::fmt::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+use chair::codegen::{Codegen, CodegenOptions, RegisterAllocator};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+
+// runs `functions` as an executable with each register allocator, exiting with 42 if @test() returns `expected` and 1 otherwise
+fn check(name: &str, functions: &str, expected: i64) {
+    let source = format!(r#"
+unit "{name}"
+
+fn @_start() -> void {{
+bb0:
+    %0 = call i64 @test()
+    %1 = icmp eq %0, i64 {expected}
+    br %1, bb1, bb2
+bb1:
+    asm "bf 2a 00 00 00 b8 3c 00 00 00 0f 05"
+    ret
+bb2:
+    asm "bf 01 00 00 00 b8 3c 00 00 00 0f 05"
+    ret
+}}
+{functions}"#);
+
+    for register_allocator in [RegisterAllocator::LinearScan, RegisterAllocator::GraphColoring] {
+        let mut compiler = CompilerX64Elf::with_options(CodegenOptions { register_allocator, ..CodegenOptions::default() });
+        let elf = compiler.compile_executable(parse_translation_unit(&source).unwrap(), "_start").unwrap();
+        let path = std::env::temp_dir().join(format!("chair-regalloc-{}-{}", name, std::process::id()));
+        elf.write_to_file(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let status = Command::new(&path).status().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(status.code(), Some(42), "@test() of {} didn't return {} with {:?}", name, expected, register_allocator);
+    }
+}
+
+// @pressure(%0) keeps `count` products of its parameter live at once, then folds them together
+fn pressure(count: usize) -> String {
+    let mut source = "fn @pressure(%0: i64) -> i64 {\nbb0:\n".to_owned();
+    for k in 1..=count {
+        writeln!(source, "    %{} = mul i64 %0, i64 {}", k, k + 1).unwrap();
+    }
+
+    let mut acc = 1;
+    let mut next = count + 1;
+    for k in 2..=count {
+        writeln!(source, "    %{} = mul i64 %{}, i64 3", next, acc).unwrap();
+        writeln!(source, "    %{} = add i64 %{}, %{}", next + 1, next, k).unwrap();
+        acc = next + 1;
+        next += 2;
+    }
+    writeln!(source, "    ret %{}\n}}", acc).unwrap();
+
+    source
+}
+
+fn pressure_result(x: i64, count: i64) -> i64 {
+    (2..=count).fold(x.wrapping_mul(2), |acc, k| acc.wrapping_mul(3).wrapping_add(x.wrapping_mul(k + 1)))
+}
+
+#[test]
+fn more_live_values_than_registers() {
+    let functions = format!("
+fn @test() -> i64 {{
+bb0:
+    %0 = call i64 @pressure(i64 1234567)
+    ret %0
+}}
+{}", pressure(40));
+
+    check("pressure", &functions, pressure_result(1234567, 40));
+}
+
+#[test]
+fn values_survive_calls() {
+    // %1-%20 are live across a call to a function that needs every register itself
+    let mut functions = "fn @test() -> i64 {\nbb0:\n    %0 = call i64 @pressure(i64 5)\n".to_owned();
+    for k in 1..=20 {
+        writeln!(functions, "    %{} = add i64 %0, i64 {}", k, k * 1000).unwrap();
+    }
+    writeln!(functions, "    %21 = call i64 @pressure(i64 7)").unwrap();
+
+    let mut acc = 21;
+    for k in 1..=20 {
+        writeln!(functions, "    %{} = mul i64 %{}, i64 5", 20 + 2 * k, acc).unwrap();
+        writeln!(functions, "    %{} = xor i64 %{}, %{}", 21 + 2 * k, 20 + 2 * k, k).unwrap();
+        acc = 21 + 2 * k;
+    }
+    writeln!(functions, "    ret %{}\n}}\n{}", acc, pressure(30)).unwrap();
+
+    let base = pressure_result(5, 30);
+    let expected = (1..=20).fold(pressure_result(7, 30), |acc, k| acc.wrapping_mul(5) ^ base.wrapping_add(k * 1000));
+    check("calls", &functions, expected);
+}
+
+#[test]
+fn arguments_are_shuffled_between_registers() {
+    // every register argument moves to the register of its neighbour, and two go to the stack
+    let functions = "
+fn @test() -> i64 {
+bb0:
+    %0 = call i64 @rotate(i64 1, i64 2, i64 3, i64 4, i64 5, i64 6, i64 7, i64 8)
+    ret %0
+}
+
+fn @rotate(%0: i64, %1: i64, %2: i64, %3: i64, %4: i64, %5: i64, %6: i64, %7: i64) -> i64 {
+bb0:
+    %8 = call i64 @weigh(%1, %2, %3, %4, %5, %0, %7, %6)
+    ret %8
+}
+
+fn @weigh(%0: i64, %1: i64, %2: i64, %3: i64, %4: i64, %5: i64, %6: i64, %7: i64) -> i64 {
+bb0:
+    %8 = mul i64 %0, i64 10
+    %9 = add i64 %8, %1
+    %10 = mul i64 %9, i64 10
+    %11 = add i64 %10, %2
+    %12 = mul i64 %11, i64 10
+    %13 = add i64 %12, %3
+    %14 = mul i64 %13, i64 10
+    %15 = add i64 %14, %4
+    %16 = mul i64 %15, i64 10
+    %17 = add i64 %16, %5
+    %18 = mul i64 %17, i64 10
+    %19 = add i64 %18, %6
+    %20 = mul i64 %19, i64 10
+    %21 = add i64 %20, %7
+    ret %21
+}";
+
+    check("rotate", functions, 23456187);
+}
+
+#[test]
+fn division_and_shifts_keep_their_fixed_registers() {
+    // the divisor arrives in rdx and the shift amount in rcx, and both outlive the instructions that need those registers
+    let functions = "
+fn @test() -> i64 {
+bb0:
+    %0 = call i64 @arith(i64 1000003, i64 -77, i64 7, i64 3)
+    ret %0
+}
+
+fn @arith(%0: i64, %1: i64, %2: i64, %3: i64) -> i64 {
+bb0:
+    %4 = sdiv i64 %0, %2
+    %5 = srem i64 %1, %2
+    %6 = udiv i64 %0, %3
+    %7 = urem i64 %0, %2
+    %8 = shl i64 %0, %3
+    %9 = ashr i64 %1, %2
+    %10 = lshr i64 %0, %2
+    %11 = trunc %3 to i8
+    %12 = shl i8 i8 5, %11
+    %13 = sext %12 to i64
+    %14 = add i64 %4, %5
+    %15 = add i64 %14, %6
+    %16 = add i64 %15, %7
+    %17 = add i64 %16, %8
+    %18 = add i64 %17, %9
+    %19 = add i64 %18, %10
+    %20 = add i64 %19, %13
+    %21 = mul i64 %20, %2
+    %22 = add i64 %21, %3
+    ret %22
+}";
+
+    let (a, b, c, d) = (1000003i64, -77i64, 7i64, 3i64);
+    let sum = a / c + b % c + (a as u64 / d as u64) as i64 + (a as u64 % c as u64) as i64
+        + (a << d) + (b >> c) + (a as u64 >> c) as i64 + (5i8 << d) as i64;
+    check("arith", functions, sum * c + d);
+}
+
+#[test]
+fn values_live_around_loops() {
+    // %2-%13 are defined before the loop and used in it and after it
+    let mut functions = "
+fn @test() -> i64 {
+    $0 = slot i64, align 8
+    $1 = slot i64, align 8
+bb0:
+    %0 = stack_addr $0
+    %1 = stack_addr $1
+    store i64 0, %0, align 8
+    store i64 0, %1, align 8
+".to_owned();
+    for k in 2..=13 {
+        writeln!(functions, "    %{} = call i64 @pressure(i64 {})", k, k).unwrap();
+    }
+    functions.push_str("    jmp bb1\nbb1:\n    %14 = load i64, %1, align 8\n");
+
+    let mut acc = 14;
+    for k in 2..=13 {
+        writeln!(functions, "    %{} = mul i64 %{}, i64 3", 11 + 2 * k, acc).unwrap();
+        writeln!(functions, "    %{} = add i64 %{}, %{}", 12 + 2 * k, 11 + 2 * k, k).unwrap();
+        acc = 12 + 2 * k;
+    }
+    write!(functions, "    store %{acc}, %1, align 8
+    %39 = load i64, %0, align 8
+    %40 = add i64 %39, i64 1
+    store %40, %0, align 8
+    %41 = icmp slt %40, i64 10
+    br %41, bb1, bb2
+bb2:
+    %42 = load i64, %1, align 8
+    %43 = sub i64 %42, %2
+    %44 = sub i64 %43, %13
+    ret %44
+}}
+{}", pressure(16)).unwrap();
+
+    let values: Vec<i64> = (2..=13).map(|k| pressure_result(k, 16)).collect();
+    let total = (0..10).fold(0i64, |acc, _| values.iter().fold(acc, |acc, value| acc.wrapping_mul(3).wrapping_add(*value)));
+    check("loops", &functions, total.wrapping_sub(values[0]).wrapping_sub(values[11]));
+}
+
+#[test]
+fn float_values_pass_through_registers_and_calls() {
+    // the floats are reversed across ten arguments, eight in xmm registers and two on the stack,
+    // and stay live across a call that clobbers every xmm register
+    let floats: Vec<f64> = (1..=10).map(|k| k as f64 * 1.25 - 3.0).collect();
+    let args: Vec<String> = floats.iter().map(|float| format!("f64 {:?}", float)).collect();
+    let params: Vec<String> = (0..10).map(|k| format!("%{}: f64", k)).collect();
+    let reversed: Vec<String> = (0..10).rev().map(|k| format!("%{}", k)).collect();
+
+    let mut functions = format!("
+fn @test() -> i64 {{
+bb0:
+    %0 = call i64 @spread({})
+    ret %0
+}}
+
+fn @spread({}) -> i64 {{
+bb0:
+    %10 = call i64 @bits({})
+    %11 = call i64 @pressure(i64 3)
+    %12 = call i64 @bits({})
+    %13 = sub i64 %12, %10
+    %14 = add i64 %13, %11
+    ret %14
+}}
+
+fn @bits({}) -> i64 {{
+    $0 = slot f64, align 8
+bb0:
+    %10 = stack_addr $0
+    store %0, %10, align 8
+    %11 = load i64, %10, align 8
+", args.join(", "), params.join(", "), reversed.join(", "), (0..10).map(|k| format!("%{}", k)).collect::<Vec<_>>().join(", "), params.join(", "));
+
+    let mut acc = 11;
+    for k in 1..10 {
+        writeln!(functions, "    store %{}, %10, align 8", k).unwrap();
+        writeln!(functions, "    %{} = load i64, %10, align 8", acc + 1).unwrap();
+        writeln!(functions, "    %{} = mul i64 %{}, i64 31", acc + 2, acc).unwrap();
+        writeln!(functions, "    %{} = xor i64 %{}, %{}", acc + 3, acc + 2, acc + 1).unwrap();
+        acc += 3;
+    }
+    writeln!(functions, "    ret %{}\n}}\n{}", acc, pressure(20)).unwrap();
+
+    let bits = |floats: &mut dyn Iterator<Item = &f64>| {
+        let first = floats.next().unwrap().to_bits() as i64;
+        floats.fold(first, |acc, float| acc.wrapping_mul(31) ^ float.to_bits() as i64)
+    };
+    let expected = bits(&mut floats.iter()).wrapping_sub(bits(&mut floats.iter().rev())).wrapping_add(pressure_result(3, 20));
+    check("floats", &functions, expected);
+}
+
+// gcc keeps its own values in callee-saved registers across calls, so this fails if generated code
+// doesn't preserve them. Skipped when gcc isn't installed.
+#[test]
+fn callee_saved_registers_survive_calls_from_c() {
+    let dir = std::env::temp_dir().join(format!("chair-regalloc-gcc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    std::fs::write(dir.join("main.c"), "
+long pressure(long);
+
+static long reference(long x) {
+    long acc = x * 2;
+    for (long k = 2; k <= 40; k++) acc = acc * 3 + x * (k + 1);
+    return acc;
+}
+
+int main(void) {
+    long a = 0, b = 0;
+    for (long i = 0; i < 20; i++) {
+        a = a * 7 + pressure(i);
+        b = b * 7 + reference(i);
+    }
+    return a == b ? 42 : 1;
+}
+").unwrap();
+
+    for register_allocator in [RegisterAllocator::LinearScan, RegisterAllocator::GraphColoring] {
+        let source = format!("unit \"pressure\"\n{}", pressure(40));
+        let elf = CompilerX64Elf::with_options(CodegenOptions { register_allocator, ..CodegenOptions::default() }).compile_translation_unit(parse_translation_unit(&source).unwrap()).unwrap();
+        elf.write_to_file(dir.join("pressure.o")).unwrap();
+
+        let compiled = Command::new("gcc").args(["-O2", "-no-pie", "-Wl,-z,noexecstack"])
+            .arg(dir.join("main.c")).arg(dir.join("pressure.o")).arg("-o").arg(dir.join("main")).status();
+        if !compiled.is_ok_and(|status| status.success()) {
+            break;
+        }
+
+        assert_eq!(Command::new(dir.join("main")).status().unwrap().code(), Some(42), "with {:?}", register_allocator);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
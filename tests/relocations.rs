@@ -0,0 +1,260 @@
+use chair::codegen::{Addressing, Codegen, CodegenOptions};
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::elf::*;
+use chair::outputs::serialization::Serializable;
+
+const SOURCE: &str = r#"
+unit "relocations"
+
+declare @puts(ptr) -> i32
+
+global @counter: i64 = i64 3
+
+fn @main(%0: i64) -> i32 {
+bb0:
+    %1 = global_addr @counter
+    %2 = call i32 @puts(%1)
+    switch %0, bb1 [0: bb2, 1: bb1, 2: bb2, 3: bb1]
+bb1:
+    ret %2
+bb2:
+    ret i32 0
+}
+"#;
+
+fn compile() -> ParsedElf {
+    compile_with(Addressing::default())
+}
+
+fn compile_with(addressing: Addressing) -> ParsedElf {
+    let translation_unit = parse_translation_unit(SOURCE).unwrap();
+    let mut compiler = CompilerX64Elf::with_options(CodegenOptions { addressing, ..CodegenOptions::default() });
+    let bytes = compiler.compile_translation_unit(translation_unit).unwrap().serialize(false);
+    ElfFile::parse(&bytes).unwrap()
+}
+
+fn index(object: &ParsedElf, name: &str) -> u32 {
+    object.section_index(name).unwrap() as u32
+}
+
+#[test]
+fn relocation_sections_are_rela() {
+    let object = compile();
+
+    assert!(object.sections.iter().all(|section| section.header.sh_type != SHT_REL), "found an SHT_REL section");
+
+    let rela_text = &object.section(".rela.text").unwrap().header;
+    assert_eq!(rela_text.sh_type, SHT_RELA);
+    assert_eq!(rela_text.sh_entsize, 24);
+    assert_eq!(rela_text.sh_link, index(&object, ".symtab"));
+    assert_eq!(rela_text.sh_info, index(&object, ".text"));
+
+    let rela_rodata = &object.section(".rela.rodata").unwrap().header;
+    assert_eq!(rela_rodata.sh_type, SHT_RELA);
+    assert_eq!(rela_rodata.sh_info, index(&object, ".rodata"));
+}
+
+#[test]
+fn text_relocations_name_the_right_symbols() {
+    let object = compile();
+    let symbols = &object.symbols;
+    let text = &object.section(".text").unwrap().contents;
+    let relocations = &object.section(".rela.text").unwrap().relocations;
+
+    // lea rax, [rip + counter], with the displacement taken from the end of the instruction
+    let counter = relocations.iter().find(|reloc| symbols[reloc.symbol() as usize].name == "counter").unwrap();
+    assert_eq!(counter.kind(), R_X86_64_PC32);
+    assert_eq!(counter.r_addend, -4);
+    assert_eq!(&text[counter.r_offset as usize - 3..counter.r_offset as usize], &[0x48, 0x8D, 0x05]);
+    assert_eq!(&text[counter.r_offset as usize..counter.r_offset as usize + 4], &[0; 4]);
+
+    let puts = relocations.iter().find(|reloc| symbols[reloc.symbol() as usize].name == "puts").unwrap();
+    assert_eq!(puts.kind(), R_X86_64_PLT32);
+    assert_eq!(puts.r_addend, -4);
+    assert_eq!(text[puts.r_offset as usize - 1], 0xE8);
+    assert_eq!(symbols[puts.symbol() as usize].symbol.st_shndx, 0);
+}
+
+#[test]
+fn jump_table_entries_point_into_text() {
+    let object = compile();
+    let symbols = &object.symbols;
+    let text_index = index(&object, ".text") as u16;
+    let relocations = &object.section(".rela.rodata").unwrap().relocations;
+
+    // each entry is its target's distance from the start of the table
+    assert_eq!(relocations.len(), 4);
+    for (i, reloc) in relocations.iter().enumerate() {
+        assert_eq!(reloc.kind(), R_X86_64_PC32);
+        assert_eq!(reloc.r_offset, i as u64 * 4);
+        assert_eq!(reloc.r_addend, i as i64 * 4);
+        assert_eq!(symbols[reloc.symbol() as usize].symbol.st_shndx, text_index);
+    }
+
+    assert_eq!(symbols[relocations[1].symbol() as usize].symbol.st_value, symbols[relocations[3].symbol() as usize].symbol.st_value);
+    assert_ne!(symbols[relocations[0].symbol() as usize].symbol.st_value, symbols[relocations[1].symbol() as usize].symbol.st_value);
+}
+
+#[test]
+fn absolute_addressing_uses_64_bit_addresses() {
+    let object = compile_with(Addressing::Absolute);
+    let symbols = &object.symbols;
+    let text = &object.section(".text").unwrap().contents;
+
+    // movabs rax, counter
+    let relocations = &object.section(".rela.text").unwrap().relocations;
+    let counter = relocations.iter().find(|reloc| symbols[reloc.symbol() as usize].name == "counter").unwrap();
+    assert_eq!(counter.kind(), R_X86_64_64);
+    assert_eq!(counter.r_addend, 0);
+    assert_eq!(&text[counter.r_offset as usize - 2..counter.r_offset as usize], &[0x48, 0xB8]);
+    assert_eq!(&text[counter.r_offset as usize..counter.r_offset as usize + 8], &[0; 8]);
+
+    let relocations = &object.section(".rela.rodata").unwrap().relocations;
+    assert_eq!(relocations.len(), 4);
+    for (i, reloc) in relocations.iter().enumerate() {
+        assert_eq!(reloc.kind(), R_X86_64_64);
+        assert_eq!(reloc.r_offset, i as u64 * 8);
+        assert_eq!(reloc.r_addend, 0);
+    }
+}
+
+#[test]
+fn got_addressing_loads_exported_globals_from_the_got() {
+    let object = compile_with(Addressing::Got);
+    let symbols = &object.symbols;
+    let text = &object.section(".text").unwrap().contents;
+    let relocations = &object.section(".rela.text").unwrap().relocations;
+
+    // mov rax, [rip + counter@GOTPCREL]
+    let counter = relocations.iter().find(|reloc| symbols[reloc.symbol() as usize].name == "counter").unwrap();
+    assert_eq!(counter.kind(), R_X86_64_REX_GOTPCRELX);
+    assert_eq!(counter.r_addend, -4);
+    assert_eq!(&text[counter.r_offset as usize - 3..counter.r_offset as usize], &[0x48, 0x8B, 0x05]);
+
+    // the jump table is local, so it's still reached with a lea
+    let table = relocations.iter().find(|reloc| symbols[reloc.symbol() as usize].name.is_empty() && reloc.kind() == R_X86_64_PC32).unwrap();
+    assert_eq!(&text[table.r_offset as usize - 3..table.r_offset as usize], &[0x4C, 0x8D, 0x1D]);
+    assert!(object.section(".rela.rodata").unwrap().relocations.iter().all(|reloc| reloc.kind() == R_X86_64_PC32));
+}
+
+#[test]
+fn builder_keeps_every_relocation_kind_and_addend() {
+    let mut builder = ElfObjectBuilder::new(EM_X86_64);
+    let text = builder.add_section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 16, vec![0x90; 32]);
+    let data = builder.add_section(".data", SHT_PROGBITS, SHF_WRITE | SHF_ALLOC, 8, vec![0; 8]);
+    let local = builder.add_symbol(None, Some(data), 0, 8, STB_LOCAL, STT_OBJECT);
+    let external = builder.add_symbol(Some("external"), None, 0, 0, STB_GLOBAL, STT_NOTYPE);
+
+    let expected = [
+        (0, local, R_X86_64_64, 16),
+        (8, external, R_X86_64_PC32, -4),
+        (12, external, R_X86_64_PLT32, -4),
+        (16, external, R_X86_64_GOTPCREL, -8),
+        (20, local, R_X86_64_32S, 0x7FFF_0000)
+    ];
+    for (offset, symbol, kind, addend) in expected {
+        builder.add_relocation(text, offset, symbol, kind, addend);
+    }
+
+    let object = ElfFile::parse(&builder.build().serialize(false)).unwrap();
+    let symbols = &object.symbols;
+    let relocations = &object.section(".rela.text").unwrap().relocations;
+
+    assert_eq!(relocations.len(), expected.len());
+    for (reloc, (offset, symbol, kind, addend)) in relocations.iter().zip(expected) {
+        let name = if symbol == external { "external" } else { "" };
+
+        assert_eq!(reloc.r_offset, offset);
+        assert_eq!(reloc.kind(), kind);
+        assert_eq!(reloc.r_addend, addend);
+        assert_eq!(symbols[reloc.symbol() as usize].name, name);
+    }
+
+    // the local symbol has to come before the global one
+    assert_eq!(relocations[0].symbol(), 1);
+    assert_eq!(relocations[1].symbol(), 2);
+}
+
+#[test]
+fn only_undefined_callees_are_called_through_the_plt() {
+    let source = r#"
+unit "calls"
+
+declare @external(i64) -> i64
+declare @other() -> void
+
+fn @caller(%0: i64) -> i64 {
+bb0:
+    %1 = call i64 @external(%0)
+    %2 = call i64 @defined(%1)
+    call void @other()
+    %3 = call i64 @external(%2)
+    ret %3
+}
+
+fn @defined(%0: i64) -> i64 {
+bb0:
+    ret %0
+}
+"#;
+    let bytes = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(source).unwrap()).unwrap().serialize(false);
+    let object = ElfFile::parse(&bytes).unwrap();
+    let symbols = &object.symbols;
+    let text = &object.section(".text").unwrap().contents;
+    let relocations = &object.section(".rela.text").unwrap().relocations;
+
+    // every call to an undefined function is a `call rel32` the linker points at a PLT entry or the function itself
+    let names: Vec<&str> = relocations.iter().map(|reloc| symbols[reloc.symbol() as usize].name.as_str()).collect();
+    assert_eq!(names, ["external", "other", "external"]);
+    for reloc in relocations {
+        assert_eq!(reloc.kind(), R_X86_64_PLT32);
+        assert_eq!(reloc.r_addend, -4);
+        assert_eq!(text[reloc.r_offset as usize - 1], 0xE8);
+        assert_eq!(&text[reloc.r_offset as usize..reloc.r_offset as usize + 4], &[0; 4]);
+    }
+
+    for name in ["external", "other"] {
+        let symbol = &symbols.iter().find(|symbol| symbol.name == name).unwrap().symbol;
+        assert_eq!(symbol.st_shndx, SHN_UNDEF);
+        assert_eq!(symbol.binding(), STB_GLOBAL);
+    }
+
+    // the call to @defined is already pointed at it
+    let defined = symbols.iter().find(|symbol| symbol.name == "defined").unwrap().symbol.st_value;
+    let calls = text.windows(5).enumerate().filter(|(offset, window)| {
+        let displacement = i32::from_le_bytes(window[1..].try_into().unwrap());
+        window[0] == 0xE8 && (*offset as u64 + 5).wrapping_add_signed(displacement as i64) == defined
+    });
+    assert_eq!(calls.count(), 1);
+}
+
+// the bytes in front of an asm_value constant reference have to fit the addressing
+#[test]
+fn asm_value_references_follow_the_addressing() {
+    let source = "unit \"asm\"\n\nfn @f() -> void {\nbb0:\n    asm \"48 8d 35\"\n    asm_value ref [3 x i8] c\"hi\\00\"\n    ret\n}\n";
+
+    for (addressing, kind, addend) in [
+        (Addressing::RipRelative, R_X86_64_PC32, -4),
+        (Addressing::Got, R_X86_64_PC32, -4),
+        (Addressing::Absolute, R_X86_64_64, 0)
+    ] {
+        let mut compiler = CompilerX64Elf::with_options(CodegenOptions { addressing, ..CodegenOptions::default() });
+        let bytes = compiler.compile_translation_unit(parse_translation_unit(source).unwrap()).unwrap().serialize(false);
+        let object = ElfFile::parse(&bytes).unwrap();
+
+        let relocations = &object.section(".rela.text").unwrap().relocations;
+        assert_eq!(relocations.len(), 1);
+        assert_eq!(relocations[0].kind(), kind, "wrong relocation with {:?}", addressing);
+        assert_eq!(relocations[0].r_addend, addend, "wrong addend with {:?}", addressing);
+    }
+}
+
+#[test]
+fn got_loads_are_listed() {
+    let mut compiler = CompilerX64Elf::with_options(CodegenOptions { addressing: Addressing::Got, ..CodegenOptions::default() });
+    let listing = compiler.compile_listing(parse_translation_unit(SOURCE).unwrap()).unwrap();
+
+    assert!(listing.contains("    mov rax, qword ptr [rip + counter@GOTPCREL]\n"), "{}", listing);
+    assert!(listing.contains("    lea r11, [rip + .L"), "{}", listing);
+}
@@ -0,0 +1,149 @@
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::outputs::elf::ElfFile;
+use chair::outputs::serialization::Serializable;
+
+// every instruction, terminator, operator, type and kind of constant, written the way the printer writes them
+const SOURCE: &str = r#"unit "round trip"
+
+declare @external(i64, ptr) -> i32
+
+declare @"odd name"() -> void
+
+global @counter: i64 = i64 -3
+
+global @zeroed: [4 x i16]
+
+global @flags: {i1, i8, i16, i32} = {i1, i8, i16, i32} {true, 255, -2, 2147483647}
+
+const @message: [5 x i8] = [5 x i8] c"a\"\\\0a\00"
+
+const @doubles: [5 x f64] = [5 x f64] [0x0000000000000000, 0x8000000000000000, 0x7FF0000000000000, 0x7FF8000000000001, 0x3FF8000000000000]
+
+const @floats: {f32, f32, i1} = {f32, f32, i1} {0xFFC00001, 0x3F800000, false}
+
+const @nested: [2 x {i8, [2 x i64]}] = [2 x {i8, [2 x i64]}] [{1, [2, 3]}, {4, [5, 6]}]
+
+fn @arith(%0: i64, %1: i32) -> i64 {
+bb0:
+    %2 = add i64 %0, i64 1
+    %3 = sub i64 %2, %0
+    %4 = mul i64 %3, i64 -7
+    %5 = sdiv i64 %4, i64 3
+    %6 = udiv i64 %5, i64 3
+    %7 = srem i64 %6, i64 5
+    %8 = urem i64 %7, i64 5
+    %9 = and i64 %8, i64 255
+    %10 = or i64 %9, i64 256
+    %11 = xor i64 %10, %0
+    %12 = shl i64 %11, i64 2
+    %13 = lshr i64 %12, i64 1
+    %14 = ashr i64 %13, i64 1
+    %15 = icmp eq %14, i64 0
+    %16 = icmp ne %14, i64 0
+    %17 = icmp slt %14, i64 0
+    %18 = icmp sle %14, i64 0
+    %19 = icmp sgt %14, i64 0
+    %20 = icmp sge %14, i64 0
+    %21 = icmp ult %14, i64 0
+    %22 = icmp ule %14, i64 0
+    %23 = icmp ugt %14, i64 0
+    %24 = icmp uge %14, i64 0
+    %25 = zext %1 to i64
+    %26 = sext %1 to i64
+    %27 = trunc %0 to i8
+    %28 = add i32 %1, i32 -2147483648
+    %29 = add i16 i16 -32768, i16 32767
+    ret %26
+}
+
+fn @control(%0: i64, %1: ptr) -> i32 {
+    $0 = slot [4 x i64], align 16
+    $1 = slot i8, align 1
+bb0:
+    %2 = stack_addr $0
+    %3 = gep [4 x i64], %2, i64 0, %0
+    store %0, %3, align 8
+    %4 = load i64, %3, align 8
+    %5 = global_addr @message
+    %6 = gep {i1, i8, i16, i32}, %1, i64 0, i32 3
+    store i8 255, %5, align 1
+    %7 = call i32 @external(%4, %5)
+    call void @"odd name"()
+    asm "0f 0b"
+    asm_value ref [3 x i8] c"hi\00"
+    asm_value i32 -1
+    asm_value f64 0xFFF0000000000000
+    %8 = icmp ne %4, i64 0
+    br %8, bb1, bb2
+bb1:
+    switch %0, bb3 [-1: bb2, 0: bb3, 7: bb2]
+bb2:
+    jmp bb3
+bb3:
+    ret %7
+}
+
+fn @nothing() -> void {
+bb0:
+    ret
+}
+"#;
+
+#[test]
+fn printing_a_parsed_unit_gives_back_its_source() {
+    let translation_unit = parse_translation_unit(SOURCE).unwrap();
+    assert_eq!(translation_unit.to_string(), SOURCE);
+}
+
+#[test]
+fn decimal_floats_are_printed_as_bits() {
+    let source = "unit \"floats\"\n\nconst @values: [4 x f64] = [4 x f64] [1.5, -0.0, inf, NaN]\n\nconst @single: f32 = f32 -2.5e3\n";
+    let printed = parse_translation_unit(source).unwrap().to_string();
+
+    assert_eq!(printed, "unit \"floats\"\n\nconst @values: [4 x f64] = [4 x f64] [0x3FF8000000000000, 0x8000000000000000, 0x7FF0000000000000, 0x7FF8000000000000]\n\nconst @single: f32 = f32 0xC51C4000\n");
+    assert_eq!(parse_translation_unit(&printed).unwrap().to_string(), printed);
+}
+
+#[test]
+fn float_bits_reach_the_object_unchanged() {
+    let object = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(SOURCE).unwrap()).unwrap();
+    let object = ElfFile::parse(&object.serialize(false)).unwrap();
+    let rodata = &object.section(".rodata").unwrap().contents;
+    let floats = object.symbols.iter().find(|symbol| symbol.name == "floats").unwrap().symbol.st_value as usize;
+
+    assert_eq!(&rodata[floats..floats + 8], &[0x01, 0x00, 0xC0, 0xFF, 0x00, 0x00, 0x80, 0x3F]);
+}
+
+#[test]
+fn errors_point_at_the_problem() {
+    let cases = [
+        // the lexer
+        ("unit \"x\"\n\nfn @f() -> void {\nbb0:\n    # nope\n", 5, 5, "unexpected character '#'"),
+        ("unit \"x\n", 1, 8, "unterminated string"),
+        ("unit \"x\"\nfn @f(% ", 2, 8, "expected a number after '%'"),
+        ("unit \"x\"\nglobal @: i64", 2, 9, "expected a name after '@'"),
+        // the parser
+        ("fn @f() -> void {}", 1, 1, "expected 'unit', found 'fn'"),
+        ("unit \"x\"\n\nglobal @g: i65", 3, 12, "expected a type, found 'i65'"),
+        ("unit \"x\"\n\nglobal @g: i8 = i8 256", 3, 20, "256 doesn't fit in i8"),
+        ("unit \"x\"\n\nglobal @g: f64 = f64 0xZZ", 3, 22, "invalid f64 '0xZZ'"),
+        ("unit \"x\"\n\nfn @f() -> void {\nbb1:\n    ret\n}", 4, 1, "expected block bb0"),
+        ("unit \"x\"\n\nfn @f(%1: i64) -> void {\nbb0:\n    ret\n}", 3, 7, "expected parameter %0"),
+        ("unit \"x\"\n\nfn @f() -> i64 {\nbb0:\n    %0 = add i64 i64 1, i64 2\n    %0 = add i64 i64 1, i64 2\n    ret %0\n}", 6, 5, "%0 is defined more than once"),
+        ("unit \"x\"\n\nfn @f() -> i64 {\nbb0:\n    ret %3\n}", 5, 9, "%3 is used but never defined"),
+        ("unit \"x\"\n\nfn @f() -> void {\nbb0:\n    frobnicate\n}", 5, 5, "frobnicate")
+    ];
+
+    for (source, line, column, message) in cases {
+        let error = match parse_translation_unit(source) {
+            Err(error) => error,
+            Ok(_) => panic!("{:?} parsed", source)
+        };
+
+        assert_eq!((error.line, error.column), (line, column), "wrong position for {:?}: {}", source, error);
+        assert!(error.message.contains(message), "wrong message for {:?}: {}", source, error);
+        assert!(error.to_string().starts_with(&format!("{}:{}: ", line, column)));
+    }
+}
@@ -0,0 +1,77 @@
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::text::parser::parse_translation_unit;
+use chair::ir::types::Type;
+use chair::outputs::elf::ElfFile;
+use chair::outputs::serialization::Serializable;
+
+fn fields(fields: &[Type]) -> Type {
+    Type::Struct(fields.to_vec())
+}
+
+#[test]
+fn scalars_are_naturally_aligned() {
+    for (ty, size) in [(Type::I1, 1), (Type::I8, 1), (Type::I16, 2), (Type::I32, 4), (Type::I64, 8), (Type::F32, 4), (Type::F64, 8), (Type::Ptr, 8)] {
+        assert_eq!(ty.size(), size, "size of {}", ty);
+        assert_eq!(ty.align(), size, "alignment of {}", ty);
+    }
+
+    assert_eq!(Type::Array(Box::new(Type::I32), 5).size(), 20);
+    assert_eq!(Type::Array(Box::new(Type::I32), 5).align(), 4);
+    assert_eq!(Type::Array(Box::new(Type::I64), 0).size(), 0);
+}
+
+#[test]
+fn structs_are_padded_like_c() {
+    // fields are padded up to their alignment, and the whole struct up to its largest field's
+    let cases = [
+        (fields(&[Type::I8, Type::I64, Type::I16]), 24, 8),
+        (fields(&[Type::I64, Type::I16, Type::I8]), 16, 8),
+        (fields(&[Type::I8, Type::I16, Type::I8]), 6, 2),
+        (fields(&[Type::I32, Type::Array(Box::new(Type::I8), 3)]), 8, 4),
+        (fields(&[Type::I8, fields(&[Type::I8, Type::I32])]), 12, 4),
+        (fields(&[Type::F32, Type::F64]), 16, 8),
+        (fields(&[Type::I1, Type::Ptr]), 16, 8),
+        (fields(&[]), 0, 1)
+    ];
+
+    for (ty, size, align) in cases {
+        assert_eq!(ty.size(), size, "size of {}", ty);
+        assert_eq!(ty.align(), align, "alignment of {}", ty);
+    }
+
+    // array elements keep their padding, so each one is aligned
+    let array = Type::Array(Box::new(fields(&[Type::I64, Type::I8])), 2);
+    assert_eq!(array.size(), 32);
+    assert_eq!(array.align(), 8);
+}
+
+#[test]
+fn struct_constants_are_laid_out_with_zeroed_padding() {
+    let source = r#"
+unit "layout"
+
+const @padded: {i8, i64, i16} = {i8, i64, i16} {1, 2, 3}
+const @nested: {i8, {i8, i32}, [2 x i16]} = {i8, {i8, i32}, [2 x i16]} {4, {5, 6}, [7, 8]}
+"#;
+    let object = CompilerX64Elf::new().compile_translation_unit(parse_translation_unit(source).unwrap()).unwrap();
+    let object = ElfFile::parse(&object.serialize(false)).unwrap();
+    let rodata = &object.section(".rodata").unwrap().contents;
+
+    let contents = |name: &str, align: u64| {
+        let symbol = &object.symbols.iter().find(|symbol| symbol.name == name).unwrap().symbol;
+        assert_eq!(symbol.st_value % align, 0, "@{} is misaligned", name);
+        &rodata[symbol.st_value as usize..(symbol.st_value + symbol.st_size) as usize]
+    };
+
+    assert_eq!(contents("padded", 8), [
+        1, 0, 0, 0, 0, 0, 0, 0,
+        2, 0, 0, 0, 0, 0, 0, 0,
+        3, 0, 0, 0, 0, 0, 0, 0
+    ]);
+    assert_eq!(contents("nested", 4), [
+        4, 0, 0, 0,
+        5, 0, 0, 0, 6, 0, 0, 0,
+        7, 0, 8, 0
+    ]);
+}
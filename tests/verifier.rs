@@ -0,0 +1,116 @@
+use chair::ChairError;
+use chair::codegen::Codegen;
+use chair::codegen::x64_elf::CompilerX64Elf;
+use chair::ir::{BinaryOp, Builder, TranslationUnit, Value};
+use chair::ir::text::parser::parse_translation_unit;
+use chair::ir::types::{Signature, Type};
+use chair::ir::verify::{verify_translation_unit, VerifyErrorKind};
+
+// whether an error is the one a case is meant to produce
+type Check = fn(&VerifyErrorKind) -> bool;
+
+fn errors(translation_unit: &TranslationUnit) -> Vec<VerifyErrorKind> {
+    match verify_translation_unit(translation_unit) {
+        Ok(()) => panic!("{} verified", translation_unit),
+        Err(errors) => errors.into_iter().map(|error| error.kind).collect()
+    }
+}
+
+fn source_errors(body: &str) -> Vec<VerifyErrorKind> {
+    errors(&parse_translation_unit(&format!("unit \"verify\"\n{}", body)).unwrap())
+}
+
+#[test]
+fn each_problem_in_text_ir_is_reported() {
+    let cases: [(&str, Check); 16] = [
+        ("fn @f() -> void {\nbb0:\n}", |kind| *kind == VerifyErrorKind::MissingTerminator),
+        ("fn @f() -> i64 {\nbb0:\n    %0 = add i64 %1, i64 1\n    %1 = add i64 i64 1, i64 2\n    ret %0\n}", |kind| matches!(kind, VerifyErrorKind::UseBeforeDef(_))),
+        ("fn @f() -> i64 {\nbb0:\n    ret i32 1\n}", |kind| matches!(kind, VerifyErrorKind::TypeMismatch { found: Type::I32, .. })),
+        ("declare @g(i64) -> void\nfn @f() -> void {\nbb0:\n    call void @g()\n    ret\n}", |kind| matches!(kind, VerifyErrorKind::WrongArgumentCount { expected: 1, found: 0, .. })),
+        ("fn @f() -> void {\nbb0:\n    call void @nowhere()\n    ret\n}", |kind| *kind == VerifyErrorKind::UnknownCallTarget("nowhere".to_owned())),
+        ("fn @f() -> ptr {\nbb0:\n    %0 = global_addr @nowhere\n    ret %0\n}", |kind| *kind == VerifyErrorKind::UnknownGlobal("nowhere".to_owned())),
+        ("fn @f(%0: ptr) -> ptr {\nbb0:\n    %1 = gep {i64, i64}, %0, i64 0, i64 5\n    ret %1\n}", |kind| matches!(kind, VerifyErrorKind::InvalidGepIndex(_))),
+        ("fn @f(%0: ptr, %1: i64) -> ptr {\nbb0:\n    %2 = gep {i64, i64}, %0, i64 0, %1\n    ret %2\n}", |kind| matches!(kind, VerifyErrorKind::InvalidGepIndex(_))),
+        ("global @f: i64\nfn @f() -> void {\nbb0:\n    ret\n}", |kind| *kind == VerifyErrorKind::DuplicateSymbol("f".to_owned())),
+        ("fn @f() -> void {\nbb0:\n    ret\nbb1:\n    jmp bb2\nbb2:\n    ret\n}", |kind| matches!(kind, VerifyErrorKind::UnreachableReferencedBlock(_))),
+        ("fn @f(%0: i64) -> void {\nbb0:\n    asm_value %0\n    ret\n}", |kind| *kind == VerifyErrorKind::NonConstantAsmValue),
+        ("fn @f() -> void {\n}", |kind| *kind == VerifyErrorKind::EmptyFunction),
+        ("fn @f(%0: i8) -> void {\nbb0:\n    switch %0, bb1 [-129: bb1]\nbb1:\n    ret\n}", |kind| *kind == VerifyErrorKind::SwitchCaseOutOfRange { value: -129, ty: Type::I8 }),
+        // -1 and 255 are the same i8
+        ("fn @f(%0: i8) -> void {\nbb0:\n    switch %0, bb1 [-1: bb1, 255: bb1]\nbb1:\n    ret\n}", |kind| *kind == VerifyErrorKind::DuplicateSwitchCase(255)),
+        ("fn @f() -> void {\n    $0 = slot i64, align 12\nbb0:\n    ret\n}", |kind| *kind == VerifyErrorKind::InvalidAlignment(12)),
+        ("fn @f(%0: ptr) -> i64 {\nbb0:\n    %1 = load i64, %0, align 0\n    ret %1\n}", |kind| *kind == VerifyErrorKind::InvalidAlignment(0))
+    ];
+
+    for (body, expected) in cases {
+        let errors = source_errors(body);
+        assert!(errors.iter().any(expected), "wrong errors for {:?}: {:?}", body, errors);
+    }
+}
+
+#[test]
+fn switch_cases_may_be_written_signed_or_unsigned() {
+    let source = "unit \"verify\"\nfn @f(%0: i8, %1: i1) -> void {\nbb0:\n    switch %0, bb1 [-128: bb1, 127: bb1, 200: bb2, 255: bb1]\nbb1:\n    switch %1, bb2 [0: bb2, 1: bb2]\nbb2:\n    ret\n}";
+    verify_translation_unit(&parse_translation_unit(source).unwrap()).unwrap();
+
+    assert!(source_errors("fn @f(%0: i8) -> void {\nbb0:\n    switch %0, bb1 [256: bb1]\nbb1:\n    ret\n}")
+        .contains(&VerifyErrorKind::SwitchCaseOutOfRange { value: 256, ty: Type::I8 }));
+    assert!(source_errors("fn @f(%0: i64) -> void {\nbb0:\n    switch %0, bb1 [3: bb1, 3: bb1]\nbb1:\n    ret\n}")
+        .contains(&VerifyErrorKind::DuplicateSwitchCase(3)));
+}
+
+// the parser already rejects values, slots and blocks that don't exist, but ids can leak between built functions
+#[test]
+fn ids_from_another_function_are_reported() -> Result<(), ChairError> {
+    let mut builder = Builder::new("verify");
+
+    let mut other = builder.function("other", Signature::new(vec![Type::I64, Type::I64], Type::Void));
+    let slot = other.add_stack_slot(Type::I64, 8);
+    let entry = other.create_block();
+    let exit = other.create_block();
+    other.switch_to_block(entry)?;
+    let sum = other.binary(BinaryOp::Add, other.param(0)?, other.param(1)?)?;
+    other.jump(exit)?;
+    other.switch_to_block(exit)?;
+    other.ret(None)?;
+    other.finish();
+
+    let mut function = builder.function("f", Signature::new(vec![], Type::I64));
+    let entry = function.create_block();
+    function.switch_to_block(entry)?;
+    function.stack_addr(slot)?;
+    function.ret(Some(sum))?;
+    function.finish();
+
+    let mut function = builder.function("g", Signature::new(vec![], Type::Void));
+    let entry = function.create_block();
+    function.switch_to_block(entry)?;
+    function.jump(exit)?;
+    function.finish();
+
+    let errors = errors(&builder.finish());
+    assert!(errors.iter().any(|kind| matches!(kind, VerifyErrorKind::UndefinedValue(_))), "{:?}", errors);
+    assert!(errors.iter().any(|kind| matches!(kind, VerifyErrorKind::UnknownStackSlot(_))), "{:?}", errors);
+    assert!(errors.iter().any(|kind| matches!(kind, VerifyErrorKind::UnknownBlock(_))), "{:?}", errors);
+    Ok(())
+}
+
+// MultipleDefinitions can't be reached from here: the parser rejects a second definition and the
+// builder gives every result a fresh id, so only a bad pass could produce one
+
+#[test]
+fn codegen_verifies_before_compiling() -> Result<(), ChairError> {
+    let mut builder = Builder::new("verify");
+    let mut function = builder.function("f", Signature::new(vec![], Type::I64));
+    let entry = function.create_block();
+    function.switch_to_block(entry)?;
+    function.ret(Some(Value::const_i64(1)))?;
+    function.finish();
+    builder.add_global("f", Type::I64, None, false);
+
+    match CompilerX64Elf::new().compile_translation_unit(builder.finish()) {
+        Err(ChairError::Verify(errors)) => assert_eq!(errors[0].kind, VerifyErrorKind::DuplicateSymbol("f".to_owned())),
+        other => panic!("expected a verify error, got {:?}", other.map(|_| ()))
+    }
+    Ok(())
+}
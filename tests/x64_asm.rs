@@ -0,0 +1,241 @@
+use chair::codegen::x64::asm::*;
+use chair::codegen::x64::asm::Reg::*;
+use chair::codegen::x64::asm::Xmm::*;
+
+// the expected bytes come from GNU as
+fn check(instruction: X64Inst, expected: &[u8]) {
+    let mut code = vec![];
+    let mut fixups = vec![];
+    instruction.encode(&mut code, &mut fixups);
+
+    assert_eq!(code, expected, "{:?}", instruction);
+    assert!(fixups.is_empty(), "{:?}", instruction);
+}
+
+fn reg(reg: Reg) -> Operand {
+    Operand::Reg(reg)
+}
+
+fn mem(base: Reg, disp: i32) -> Operand {
+    Operand::Mem(Mem::base(base, disp))
+}
+
+#[test]
+fn register_moves() {
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rax), src: reg(Rcx) }, &[0x48, 0x89, 0xC8]);
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(R9), src: reg(Rbx) }, &[0x49, 0x89, 0xD9]);
+    check(X64Inst::Mov { size: Size::Dword, dst: reg(Rax), src: reg(R10) }, &[0x44, 0x89, 0xD0]);
+    check(X64Inst::Mov { size: Size::Word, dst: reg(Rcx), src: reg(Rdx) }, &[0x66, 0x89, 0xD1]);
+    check(X64Inst::Movaps { dst: Xmm8, src: Xmm1 }, &[0x44, 0x0F, 0x28, 0xC1]);
+}
+
+#[test]
+fn byte_registers_four_to_seven_get_a_rex_prefix() {
+    check(X64Inst::Mov { size: Size::Byte, dst: reg(Rsi), src: reg(Rax) }, &[0x40, 0x88, 0xC6]);
+    check(X64Inst::Mov { size: Size::Byte, dst: reg(Rax), src: reg(Rdi) }, &[0x40, 0x88, 0xF8]);
+    check(X64Inst::Mov { size: Size::Byte, dst: mem(Rcx, 0), src: reg(Rsi) }, &[0x40, 0x88, 0x31]);
+    check(X64Inst::Movzx { src_size: Size::Byte, dst: Rcx, src: reg(Rsi) }, &[0x40, 0x0F, 0xB6, 0xCE]);
+    check(X64Inst::Movsx { size: Size::Dword, src_size: Size::Byte, dst: Rax, src: reg(Rdi) }, &[0x40, 0x0F, 0xBE, 0xC7]);
+    check(X64Inst::Test { size: Size::Byte, dst: reg(Rdi), src: reg(Rdi) }, &[0x40, 0x84, 0xFF]);
+    check(X64Inst::Setcc { cond: Cond::Equal, dst: reg(Rax) }, &[0x0F, 0x94, 0xC0]);
+    check(X64Inst::Setcc { cond: Cond::Less, dst: reg(Rsi) }, &[0x40, 0x0F, 0x9C, 0xC6]);
+    check(X64Inst::Setcc { cond: Cond::AboveEqual, dst: reg(R9) }, &[0x41, 0x0F, 0x93, 0xC1]);
+}
+
+#[test]
+fn memory_operands() {
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rax), src: mem(Rbp, -8) }, &[0x48, 0x8B, 0x45, 0xF8]);
+    check(X64Inst::Mov { size: Size::Qword, dst: mem(Rbp, -0x200), src: reg(R12) }, &[0x4C, 0x89, 0xA5, 0x00, 0xFE, 0xFF, 0xFF]);
+    check(X64Inst::Mov { size: Size::Word, dst: mem(Rcx, 0), src: reg(Rax) }, &[0x66, 0x89, 0x01]);
+
+    // rsp and r12 need a SIB, rbp and r13 need a displacement even when it's 0
+    check(X64Inst::Mov { size: Size::Dword, dst: reg(Rax), src: mem(Rsp, 8) }, &[0x8B, 0x44, 0x24, 0x08]);
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rdx), src: mem(R12, 0) }, &[0x49, 0x8B, 0x14, 0x24]);
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rdx), src: mem(R13, 0) }, &[0x49, 0x8B, 0x55, 0x00]);
+
+    let indexed = Mem::indexed(Rcx, Rdx, 8, 16);
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rax), src: Operand::Mem(indexed) }, &[0x48, 0x8B, 0x44, 0xD1, 0x10]);
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rax), src: Operand::Mem(Mem::indexed(R8, R9, 4, 0)) }, &[0x4B, 0x8B, 0x04, 0x88]);
+    check(X64Inst::Lea { dst: Rax, src: Mem::base(Rbp, -16) }, &[0x48, 0x8D, 0x45, 0xF0]);
+    check(X64Inst::Lea { dst: R11, src: Mem::base(Rsp, 24) }, &[0x4C, 0x8D, 0x5C, 0x24, 0x18]);
+    check(X64Inst::Lea { dst: Rcx, src: Mem::indexed(Rax, Rax, 2, 0) }, &[0x48, 0x8D, 0x0C, 0x40]);
+    check(X64Inst::JmpIndirect(Operand::Mem(Mem::indexed(Rcx, Rax, 8, 0))), &[0xFF, 0x24, 0xC1]);
+
+    // no base at all is an absolute disp32
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rax), src: Operand::Mem(Mem::Based { base: None, index: None, disp: 0x1000 }) },
+        &[0x48, 0x8B, 0x04, 0x25, 0x00, 0x10, 0x00, 0x00]);
+}
+
+#[test]
+fn immediates() {
+    check(X64Inst::Mov { size: Size::Dword, dst: reg(Rax), src: Operand::Imm(1) }, &[0xB8, 0x01, 0x00, 0x00, 0x00]);
+    check(X64Inst::Mov { size: Size::Dword, dst: reg(R11), src: Operand::Imm(0xFFFF_FFFF) }, &[0x41, 0xBB, 0xFF, 0xFF, 0xFF, 0xFF]);
+    check(X64Inst::Mov { size: Size::Qword, dst: reg(Rax), src: Operand::Imm(-1) }, &[0x48, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF]);
+    check(X64Inst::Mov { size: Size::Qword, dst: mem(Rbx, 0), src: Operand::Imm(7) }, &[0x48, 0xC7, 0x03, 0x07, 0x00, 0x00, 0x00]);
+    check(X64Inst::Mov { size: Size::Byte, dst: reg(Rcx), src: Operand::Imm(0x80) }, &[0xB1, 0x80]);
+    check(X64Inst::Mov { size: Size::Word, dst: reg(Rax), src: Operand::Imm(1000) }, &[0x66, 0xB8, 0xE8, 0x03]);
+    check(X64Inst::MovAbs { dst: Rax, src: Imm64::Value(0x1122334455667788) }, &[0x48, 0xB8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]);
+    check(X64Inst::MovAbs { dst: R15, src: Imm64::Value(1) }, &[0x49, 0xBF, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    check(X64Inst::Push(Operand::Imm(1)), &[0x6A, 0x01]);
+    check(X64Inst::Push(Operand::Imm(0x1000)), &[0x68, 0x00, 0x10, 0x00, 0x00]);
+}
+
+#[test]
+fn extending_moves() {
+    check(X64Inst::Movzx { src_size: Size::Byte, dst: Rax, src: mem(Rbp, -1) }, &[0x0F, 0xB6, 0x45, 0xFF]);
+    check(X64Inst::Movzx { src_size: Size::Word, dst: R8, src: mem(Rax, 0) }, &[0x44, 0x0F, 0xB7, 0x00]);
+    check(X64Inst::Movsx { size: Size::Qword, src_size: Size::Byte, dst: Rax, src: mem(Rcx, 0) }, &[0x48, 0x0F, 0xBE, 0x01]);
+    check(X64Inst::Movsx { size: Size::Qword, src_size: Size::Word, dst: Rdx, src: mem(Rbp, -2) }, &[0x48, 0x0F, 0xBF, 0x55, 0xFE]);
+    check(X64Inst::Movsx { size: Size::Qword, src_size: Size::Dword, dst: Rax, src: mem(Rbp, -4) }, &[0x48, 0x63, 0x45, 0xFC]);
+    check(X64Inst::Movsx { size: Size::Qword, src_size: Size::Dword, dst: R9, src: reg(Rcx) }, &[0x4C, 0x63, 0xC9]);
+}
+
+#[test]
+fn arithmetic() {
+    let alu = |op, size, dst, src| X64Inst::Alu { op, size, dst, src };
+
+    check(alu(AluOp::Add, Size::Qword, reg(Rax), reg(Rcx)), &[0x48, 0x01, 0xC8]);
+    check(alu(AluOp::Sub, Size::Qword, reg(R8), mem(Rbp, -8)), &[0x4C, 0x2B, 0x45, 0xF8]);
+    check(alu(AluOp::Cmp, Size::Qword, reg(Rax), reg(R11)), &[0x4C, 0x39, 0xD8]);
+    check(alu(AluOp::Xor, Size::Dword, reg(Rdx), reg(Rdx)), &[0x31, 0xD2]);
+
+    // small immediates take a byte, and rax has a short form for bigger ones
+    check(alu(AluOp::Add, Size::Qword, reg(Rax), Operand::Imm(1)), &[0x48, 0x83, 0xC0, 0x01]);
+    check(alu(AluOp::Add, Size::Qword, reg(Rax), Operand::Imm(0x1000)), &[0x48, 0x05, 0x00, 0x10, 0x00, 0x00]);
+    check(alu(AluOp::Add, Size::Qword, reg(Rcx), Operand::Imm(0x1000)), &[0x48, 0x81, 0xC1, 0x00, 0x10, 0x00, 0x00]);
+    check(alu(AluOp::Sub, Size::Qword, reg(Rsp), Operand::Imm(8)), &[0x48, 0x83, 0xEC, 0x08]);
+    check(alu(AluOp::And, Size::Dword, reg(Rax), Operand::Imm(1)), &[0x83, 0xE0, 0x01]);
+    check(alu(AluOp::Cmp, Size::Qword, reg(Rax), Operand::Imm(0x12345)), &[0x48, 0x3D, 0x45, 0x23, 0x01, 0x00]);
+    check(alu(AluOp::Or, Size::Byte, mem(Rcx, 0), Operand::Imm(0x80)), &[0x80, 0x09, 0x80]);
+    check(alu(AluOp::Cmp, Size::Qword, mem(Rbp, -8), Operand::Imm(0)), &[0x48, 0x83, 0x7D, 0xF8, 0x00]);
+
+    check(X64Inst::Test { size: Size::Qword, dst: reg(Rax), src: reg(Rax) }, &[0x48, 0x85, 0xC0]);
+    check(X64Inst::Test { size: Size::Dword, dst: reg(Rax), src: Operand::Imm(0x100) }, &[0xA9, 0x00, 0x01, 0x00, 0x00]);
+    check(X64Inst::Test { size: Size::Qword, dst: reg(Rcx), src: Operand::Imm(1) }, &[0x48, 0xF7, 0xC1, 0x01, 0x00, 0x00, 0x00]);
+
+    check(X64Inst::Imul { size: Size::Qword, dst: Rax, src: reg(Rcx) }, &[0x48, 0x0F, 0xAF, 0xC1]);
+    check(X64Inst::Imul { size: Size::Qword, dst: R10, src: mem(Rbp, -24) }, &[0x4C, 0x0F, 0xAF, 0x55, 0xE8]);
+    check(X64Inst::ImulImm { size: Size::Qword, dst: Rcx, src: reg(Rcx), imm: 24 }, &[0x48, 0x6B, 0xC9, 0x18]);
+    check(X64Inst::ImulImm { size: Size::Qword, dst: Rcx, src: reg(Rax), imm: 1000 }, &[0x48, 0x69, 0xC8, 0xE8, 0x03, 0x00, 0x00]);
+    check(X64Inst::Cmov { cond: Cond::Greater, size: Size::Qword, dst: Rax, src: reg(Rcx) }, &[0x48, 0x0F, 0x4F, 0xC1]);
+}
+
+#[test]
+fn shifts_and_division() {
+    check(X64Inst::Shift { op: ShiftOp::Shl, size: Size::Qword, dst: reg(Rax), amount: None }, &[0x48, 0xD3, 0xE0]);
+    check(X64Inst::Shift { op: ShiftOp::Shr, size: Size::Qword, dst: reg(R9), amount: Some(3) }, &[0x49, 0xC1, 0xE9, 0x03]);
+    check(X64Inst::Shift { op: ShiftOp::Sar, size: Size::Qword, dst: reg(Rax), amount: Some(1) }, &[0x48, 0xD1, 0xF8]);
+    check(X64Inst::Neg { size: Size::Qword, dst: reg(Rax) }, &[0x48, 0xF7, 0xD8]);
+    check(X64Inst::Not { size: Size::Qword, dst: reg(R12) }, &[0x49, 0xF7, 0xD4]);
+    check(X64Inst::Div { size: Size::Qword, signed: false, src: reg(Rcx) }, &[0x48, 0xF7, 0xF1]);
+    check(X64Inst::Div { size: Size::Qword, signed: true, src: reg(R11) }, &[0x49, 0xF7, 0xFB]);
+    check(X64Inst::Div { size: Size::Qword, signed: true, src: mem(Rbp, -8) }, &[0x48, 0xF7, 0x7D, 0xF8]);
+    check(X64Inst::Cqo, &[0x48, 0x99]);
+}
+
+#[test]
+fn stack_and_control_flow() {
+    check(X64Inst::Push(reg(Rbp)), &[0x55]);
+    check(X64Inst::Push(reg(R12)), &[0x41, 0x54]);
+    check(X64Inst::Pop(Rbx), &[0x5B]);
+    check(X64Inst::Pop(R15), &[0x41, 0x5F]);
+    check(X64Inst::CallIndirect(reg(Rax)), &[0xFF, 0xD0]);
+    check(X64Inst::CallIndirect(reg(R11)), &[0x41, 0xFF, 0xD3]);
+    check(X64Inst::Ret, &[0xC3]);
+    check(X64Inst::Leave, &[0xC9]);
+    check(X64Inst::Syscall, &[0x0F, 0x05]);
+    check(X64Inst::Ud2, &[0x0F, 0x0B]);
+    check(X64Inst::Nop, &[0x90]);
+}
+
+#[test]
+fn sse_moves() {
+    let xmm_mem = |base, disp| XmmOperand::Mem(Mem::base(base, disp));
+
+    check(X64Inst::MovFloat { double: true, dst: XmmOperand::Xmm(Xmm0), src: xmm_mem(Rbp, -8) }, &[0xF2, 0x0F, 0x10, 0x45, 0xF8]);
+    check(X64Inst::MovFloat { double: false, dst: xmm_mem(Rbp, -4), src: XmmOperand::Xmm(Xmm9) }, &[0xF3, 0x44, 0x0F, 0x11, 0x4D, 0xFC]);
+    check(X64Inst::MovFloat { double: true, dst: XmmOperand::Xmm(Xmm1), src: XmmOperand::Xmm(Xmm2) }, &[0xF2, 0x0F, 0x10, 0xCA]);
+    check(X64Inst::MovToXmm { dst: Xmm0, src: Rax }, &[0x66, 0x48, 0x0F, 0x6E, 0xC0]);
+    check(X64Inst::MovFromXmm { dst: R8, src: Xmm3 }, &[0x66, 0x49, 0x0F, 0x7E, 0xD8]);
+}
+
+#[test]
+fn branches_report_rel32_fixups() {
+    let (code, fixups) = assemble(&[
+        X64Inst::Jcc { cond: Cond::NotEqual, target: Target::Label(3) },
+        X64Inst::Jmp(Target::Label(1)),
+        X64Inst::Call(Target::Symbol(7))
+    ]);
+
+    assert_eq!(code, [0x0F, 0x85, 0, 0, 0, 0, 0xE9, 0, 0, 0, 0, 0xE8, 0, 0, 0, 0]);
+    assert_eq!(fixups, [
+        Fixup { offset: 2, target: Target::Label(3), kind: FixupKind::Branch32, addend: -4 },
+        Fixup { offset: 7, target: Target::Label(1), kind: FixupKind::Branch32, addend: -4 },
+        Fixup { offset: 12, target: Target::Symbol(7), kind: FixupKind::Branch32, addend: -4 }
+    ]);
+}
+
+#[test]
+fn rip_relative_displacements_count_from_the_end_of_the_instruction() {
+    let (code, fixups) = assemble(&[
+        X64Inst::Lea { dst: Rax, src: Mem::rip(Target::Symbol(2)) },
+        X64Inst::Mov { size: Size::Dword, dst: Operand::Mem(Mem::Rip { target: Target::Symbol(5), addend: 8 }), src: Operand::Imm(1) }
+    ]);
+
+    assert_eq!(code, [0x48, 0x8D, 0x05, 0, 0, 0, 0, 0xC7, 0x05, 0, 0, 0, 0, 0x01, 0x00, 0x00, 0x00]);
+    assert_eq!(fixups, [
+        Fixup { offset: 3, target: Target::Symbol(2), kind: FixupKind::Relative32, addend: -4 },
+        // the imm32 after the displacement takes the addend of 8 back to 0
+        Fixup { offset: 9, target: Target::Symbol(5), kind: FixupKind::Relative32, addend: 0 }
+    ]);
+}
+
+#[test]
+fn absolute_addresses_report_64_bit_fixups() {
+    let (code, fixups) = assemble(&[X64Inst::MovAbs { dst: R9, src: Imm64::Address { target: Target::Symbol(1), addend: 16 } }]);
+
+    assert_eq!(code, [0x49, 0xB9, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(fixups, [Fixup { offset: 2, target: Target::Symbol(1), kind: FixupKind::Absolute64, addend: 16 }]);
+}
+
+#[test]
+#[should_panic]
+fn memory_to_memory_moves_are_rejected() {
+    assemble(&[X64Inst::Mov { size: Size::Qword, dst: mem(Rax, 0), src: mem(Rcx, 0) }]);
+}
+
+#[test]
+#[should_panic]
+fn rsp_is_not_an_index() {
+    assemble(&[X64Inst::Lea { dst: Rax, src: Mem::indexed(Rax, Rsp, 1, 0) }]);
+}
+
+#[test]
+fn instructions_print_in_intel_syntax() {
+    let name = |target: Target| match target {
+        Target::Label(block) => format!("bb{}", block),
+        Target::Symbol(symbol) => format!("sym{}", symbol)
+    };
+    let cases = [
+        (X64Inst::Mov { size: Size::Dword, dst: reg(R9), src: Operand::Imm(-1) }, "mov r9d, -1"),
+        (X64Inst::Mov { size: Size::Byte, dst: mem(Rsp, -8), src: reg(Rsi) }, "mov byte ptr [rsp - 8], sil"),
+        (X64Inst::Movzx { src_size: Size::Word, dst: Rax, src: reg(R12) }, "movzx eax, r12w"),
+        (X64Inst::Movsx { size: Size::Qword, src_size: Size::Dword, dst: Rcx, src: reg(Rdx) }, "movsxd rcx, edx"),
+        (X64Inst::Lea { dst: Rdi, src: Mem::indexed(Rbx, R8, 4, 16) }, "lea rdi, [rbx + r8*4 + 16]"),
+        (X64Inst::Lea { dst: Rsi, src: Mem::Rip { target: Target::Symbol(3), addend: -4 } }, "lea rsi, [rip + sym3 - 4]"),
+        (X64Inst::MovAbs { dst: R11, src: Imm64::Address { target: Target::Symbol(1), addend: 8 } }, "movabs r11, sym1 + 8"),
+        (X64Inst::Alu { op: AluOp::Cmp, size: Size::Qword, dst: reg(Rax), src: mem(Rbp, 0) }, "cmp rax, qword ptr [rbp]"),
+        (X64Inst::Shift { op: ShiftOp::Sar, size: Size::Word, dst: reg(Rdx), amount: None }, "sar dx, cl"),
+        (X64Inst::Div { size: Size::Dword, signed: true, src: reg(Rcx) }, "idiv ecx"),
+        (X64Inst::Cmov { cond: Cond::LessEqual, size: Size::Qword, dst: Rax, src: reg(Rbx) }, "cmovle rax, rbx"),
+        (X64Inst::Jcc { cond: Cond::AboveEqual, target: Target::Label(2) }, "jae bb2"),
+        (X64Inst::CallIndirect(Operand::Mem(Mem::rip(Target::Symbol(0)))), "call qword ptr [rip + sym0]"),
+        (X64Inst::MovFloat { double: false, dst: XmmOperand::Xmm(Xmm3), src: XmmOperand::Mem(Mem::base(Rax, 4)) }, "movss xmm3, dword ptr [rax + 4]"),
+        (X64Inst::MovToXmm { dst: Xmm0, src: R15 }, "movq xmm0, r15"),
+        (X64Inst::Bytes(vec![0x0F, 0x0B]), ".byte 0x0f, 0x0b")
+    ];
+
+    for (instruction, expected) in cases {
+        assert_eq!(instruction.to_intel(&name), expected);
+    }
+}